@@ -0,0 +1,45 @@
+// Downloads the pdfium build pdfium-render is bound to into src-tauri/pdfium,
+// which the app bundles as a resource. Skipped if the library is already there.
+import { execFileSync } from 'node:child_process'
+import { copyFileSync, existsSync, mkdtempSync, rmSync, writeFileSync } from 'node:fs'
+import { tmpdir } from 'node:os'
+import { basename, dirname, join } from 'node:path'
+import { fileURLToPath } from 'node:url'
+
+// Must match the pdfium_* feature of the pdfium-render crate
+const RELEASE = 'chromium/7543'
+
+const PLATFORMS = {
+  linux: ['linux', 'lib/libpdfium.so'],
+  darwin: ['mac', 'lib/libpdfium.dylib'],
+  win32: ['win', 'bin/pdfium.dll'],
+}
+
+const [platform, library] = PLATFORMS[process.platform] ?? []
+if (!platform || !['x64', 'arm64'].includes(process.arch)) {
+  console.error(`No pdfium build for ${process.platform}-${process.arch}`)
+  process.exit(1)
+}
+
+const target = join(dirname(fileURLToPath(import.meta.url)), '..', 'src-tauri', 'pdfium', basename(library))
+if (existsSync(target)) {
+  process.exit(0)
+}
+
+const archive = `pdfium-${platform}-${process.arch}.tgz`
+const url = `https://github.com/bblanchon/pdfium-binaries/releases/download/${encodeURIComponent(RELEASE)}/${archive}`
+const response = await fetch(url)
+if (!response.ok) {
+  console.error(`Failed to download ${url}: ${response.status}`)
+  process.exit(1)
+}
+
+const dir = mkdtempSync(join(tmpdir(), 'pdfium-'))
+try {
+  writeFileSync(join(dir, archive), Buffer.from(await response.arrayBuffer()))
+  execFileSync('tar', ['-xzf', archive], { cwd: dir, stdio: 'inherit' })
+  copyFileSync(join(dir, library), target)
+  console.log(`pdfium ${RELEASE} saved to ${target}`)
+} finally {
+  rmSync(dir, { recursive: true, force: true })
+}
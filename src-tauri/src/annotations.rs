@@ -0,0 +1,218 @@
+//! Review comments from an annotated PDF: the sticky notes, highlights and
+//! other markup an advisor left, mapped back to source lines with SyncTeX
+
+use pdfium_render::prelude::*;
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tauri::AppHandle;
+
+use crate::{log_parser, pdf, processes, tex_path, LAST_BUILD};
+
+/// The trailer, with the document ID, is in this many bytes at the end
+const TRAILER_BYTES: usize = 64 * 1024;
+
+lazy_static::lazy_static! {
+    /// First part of a trailer's /ID, which stays the same when a viewer
+    /// saves annotations into the file
+    static ref PDF_ID: Regex = Regex::new(r"/ID\s*\[\s*<([0-9A-Fa-f]+)>").unwrap();
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReviewComment {
+    /// 1-based
+    page: u16,
+    /// "note", "highlight", "underline", "strikeout", "squiggly", "text-box",
+    /// "caret", "drawing", "shape" or "stamp"
+    kind: String,
+    /// What the reviewer wrote
+    comment: Option<String>,
+    /// The page text under a highlight or other text markup
+    marked_text: Option<String>,
+    author: Option<String>,
+    /// As written in the PDF, e.g. "D:20240312101500+01'00'"
+    modified: Option<String>,
+    /// In points from the page's top-left corner
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    /// Source position of the annotated spot, relative to the project
+    file: Option<String>,
+    line: Option<u32>,
+}
+
+/// Comment kind of an annotation, None for links, form fields and the
+/// other kinds that aren't review comments
+fn kind_of(annotation_type: PdfPageAnnotationType) -> Option<&'static str> {
+    use PdfPageAnnotationType::*;
+    Some(match annotation_type {
+        Text => "note",
+        Highlight => "highlight",
+        Underline => "underline",
+        Strikeout => "strikeout",
+        Squiggly => "squiggly",
+        FreeText => "text-box",
+        Caret => "caret",
+        Ink => "drawing",
+        Square | Circle | Line | Polygon | Polyline => "shape",
+        Stamp => "stamp",
+        _ => return None,
+    })
+}
+
+fn read_annotations(pdfium: &Pdfium, path: &Path) -> Result<Vec<ReviewComment>, String> {
+    let document = pdfium
+        .load_pdf_from_file(path, None)
+        .map_err(|e| format!("Failed to open PDF: {}", e))?;
+
+    let mut comments = Vec::new();
+    for (index, page) in document.pages().iter().enumerate() {
+        let page_height = page.height().value;
+        let text = page.text().ok();
+        for annotation in page.annotations().iter() {
+            let Some(kind) = kind_of(annotation.annotation_type()) else {
+                continue;
+            };
+            let Ok(bounds) = annotation.bounds() else {
+                continue;
+            };
+            let marks_text = matches!(kind, "highlight" | "underline" | "strikeout" | "squiggly");
+            let marked_text = if marks_text {
+                text.as_ref()
+                    .and_then(|text| text.for_annotation(&annotation).ok())
+                    .map(|marked| marked.split_whitespace().collect::<Vec<_>>().join(" "))
+                    .filter(|marked| !marked.is_empty())
+            } else {
+                None
+            };
+            comments.push(ReviewComment {
+                page: index as u16 + 1,
+                kind: kind.to_string(),
+                comment: annotation.contents().filter(|c| !c.trim().is_empty()),
+                marked_text,
+                author: annotation.creator().filter(|a| !a.trim().is_empty()),
+                modified: annotation.modification_date(),
+                x: bounds.left().value,
+                y: page_height - bounds.top().value,
+                width: bounds.width().value,
+                height: bounds.height().value,
+                file: None,
+                line: None,
+            });
+        }
+    }
+    Ok(comments)
+}
+
+/// Parse the first record of `synctex edit` output into a file and line
+fn parse_synctex_edit(output: &str) -> Option<(String, u32)> {
+    let mut input = None;
+    let mut line = None;
+    for record in output.lines() {
+        match record.split_once(':') {
+            Some(("Input", value)) if input.is_none() => input = Some(value.trim().to_string()),
+            Some(("Line", value)) if line.is_none() => line = value.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    Some((input?, line?))
+}
+
+/// What identifies a compiled PDF, also in an annotated copy: its
+/// document ID, or its content if it has none
+pub fn pdf_identity(data: &[u8]) -> Vec<u8> {
+    let tail = &data[data.len().saturating_sub(TRAILER_BYTES)..];
+    // The last trailer, written by the latest save
+    match PDF_ID.captures_iter(tail).last() {
+        Some(cap) => cap[1].to_ascii_uppercase(),
+        None => Sha256::digest(data).to_vec(),
+    }
+}
+
+/// Find the source lines of the comments with the SyncTeX data of the last
+/// compile. Fails if `pdf_data` isn't (a copy of) the PDF it produced.
+async fn locate_in_source(comments: &mut [ReviewComment], pdf_data: &[u8]) -> Result<(), String> {
+    let Some((build_dir, jobname, identity)) = LAST_BUILD.lock().ok().and_then(|last| {
+        last.as_ref().map(|last| {
+            (
+                last.dir.path().to_path_buf(),
+                last.jobname.clone(),
+                last.pdf_identity.clone(),
+            )
+        })
+    }) else {
+        return Ok(());
+    };
+    if pdf_identity(pdf_data) != identity {
+        return Err(
+            "The PDF isn't from the last compile, so its comments can't be matched to the source"
+                .to_string(),
+        );
+    }
+    let pdf_path = build_dir.join(format!("{}.pdf", jobname));
+    // Folder projects build in `<project>/.offleaf/build`, with their
+    // sources in the project itself
+    let mut roots: Vec<PathBuf> = vec![build_dir.clone()];
+    roots.extend(std::fs::canonicalize(&build_dir).ok());
+    if build_dir.ends_with(Path::new(".offleaf").join("build")) {
+        roots.extend(build_dir.parent().and_then(Path::parent).map(PathBuf::from));
+    }
+    let roots: Vec<&Path> = roots.iter().map(PathBuf::as_path).collect();
+
+    for comment in comments.iter_mut() {
+        // The start of the marked passage, or the spot a note was pinned to
+        let (x, y) = (comment.x + 1.0, comment.y + comment.height / 2.0);
+        let output = processes::output(
+            tex_path::command("synctex")
+                .arg("edit")
+                .arg("-o")
+                .arg(format!(
+                    "{}:{}:{}:{}",
+                    comment.page,
+                    x,
+                    y,
+                    pdf_path.display()
+                ))
+                .current_dir(&build_dir)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .await;
+        let Ok(output) = output else {
+            // synctex isn't installed
+            return Ok(());
+        };
+        if let Some((file, line)) = parse_synctex_edit(&String::from_utf8_lossy(&output.stdout)) {
+            comment.file = Some(log_parser::project_relative_path(&file, &roots));
+            comment.line = Some(line);
+        }
+    }
+    Ok(())
+}
+
+/// Read the notes and markup a reviewer left in a PDF, page by page, with
+/// the source lines they point at. After a compile, only its PDF is taken.
+#[tauri::command]
+pub async fn extract_pdf_annotations(
+    app: AppHandle,
+    path: String,
+) -> Result<Vec<ReviewComment>, String> {
+    let pdf_path = PathBuf::from(&path);
+    if !pdf_path.is_file() {
+        return Err(format!("PDF not found: {}", path));
+    }
+    let pdf_data = tokio::fs::read(&pdf_path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let mut comments = tokio::task::spawn_blocking(move || {
+        pdf::with_pdfium(&app, |pdfium| read_annotations(pdfium, &pdf_path))
+    })
+    .await
+    .map_err(|e| format!("Failed to read annotations: {}", e))??;
+
+    locate_in_source(&mut comments, &pdf_data).await?;
+    Ok(comments)
+}
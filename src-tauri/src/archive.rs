@@ -0,0 +1,295 @@
+//! ZIP archives of projects, for sharing, journal submission and importing
+//! Overleaf downloads
+
+use regex::Regex;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{parse_magic_comments, project, save};
+
+/// Outputs worth shipping with the sources: the PDF, and the .bbl journals
+/// ask for instead of running BibTeX themselves
+const BUILD_ARTIFACTS: &[&str] = &["pdf", "bbl"];
+
+/// Imported ZIPs may unpack to this many bytes, so a ZIP bomb can't fill
+/// the disk
+const MAX_UNCOMPRESSED: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Where a project's compiles leave their outputs: the build folder of a
+/// folder project, or the cached build directory of a managed one
+pub fn build_dirs(project_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![project_dir.join(".offleaf").join("build")];
+    if project_dir.parent() == Some(project::projects_dir().as_path()) {
+        if let Some(id) = project_dir.file_name() {
+            dirs.push(project::cached_build_dir(&id.to_string_lossy()));
+        }
+    }
+    dirs
+}
+
+/// Bundle a project's files into a ZIP at `zip_path`, optionally with its
+/// PDF and .bbl files from the last compile. OffLeaf's and version
+/// control's folders are left out.
+#[tauri::command]
+pub async fn export_project_zip(
+    project_path: String,
+    zip_path: String,
+    include_build_artifacts: bool,
+) -> Result<(), String> {
+    let dir = PathBuf::from(&project_path);
+    if !dir.is_dir() {
+        return Err(format!("Not a folder: {}", project_path));
+    }
+    let data = zip_project(&dir, include_build_artifacts).await?;
+    save::write_atomic(Path::new(&zip_path), &data).await
+}
+
+/// ZIP of a project folder's files and manifest, see `export_project_zip`
+pub async fn zip_project(dir: &Path, include_build_artifacts: bool) -> Result<Vec<u8>, String> {
+    let mut entries: Vec<(String, PathBuf)> = project::walk(dir)
+        .await?
+        .into_iter()
+        .filter(|(entry, _)| !entry.is_dir)
+        .map(|(entry, path)| (entry.path, path))
+        .collect();
+    let manifest = dir.join(project::MANIFEST);
+    if manifest.exists() {
+        entries.push((project::MANIFEST.to_string(), manifest));
+    }
+    if include_build_artifacts {
+        for build_dir in build_dirs(dir) {
+            let Ok(mut files) = fs::read_dir(&build_dir).await else {
+                continue;
+            };
+            while let Ok(Some(file)) = files.next_entry().await {
+                let path = file.path();
+                let is_artifact = path
+                    .extension()
+                    .is_some_and(|ext| BUILD_ARTIFACTS.contains(&ext.to_string_lossy().as_ref()));
+                let name = file.file_name().to_string_lossy().to_string();
+                if is_artifact && !entries.iter().any(|(n, _)| *n == name) {
+                    entries.push((name, path));
+                }
+            }
+        }
+    }
+
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, path) in entries {
+        let data = fs::read(&path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        zip.start_file(name.as_str(), options)
+            .and_then(|_| zip.write_all(&data).map_err(Into::into))
+            .map_err(|e| format!("Failed to add {} to ZIP: {}", name, e))?;
+    }
+    let archive = zip
+        .finish()
+        .map_err(|e| format!("Failed to write ZIP: {}", e))?;
+    Ok(archive.into_inner())
+}
+
+/// Path inside the ZIP with the folder everything is nested in (if any)
+/// removed. Overleaf downloads sometimes wrap the project in one folder.
+fn strip_wrapper(name: &Path, wrapper: Option<&Path>) -> PathBuf {
+    match wrapper {
+        Some(wrapper) => name.strip_prefix(wrapper).unwrap_or(name).to_path_buf(),
+        None => name.to_path_buf(),
+    }
+}
+
+/// Extract the files of a ZIP into a directory, refusing paths that would
+/// escape it
+fn extract(zip_path: &Path, dir: &Path) -> Result<(), String> {
+    let too_large = || {
+        format!(
+            "The ZIP unpacks to more than {} MB",
+            MAX_UNCOMPRESSED / 1024 / 1024
+        )
+    };
+    let file = std::fs::File::open(zip_path).map_err(|e| format!("Failed to open ZIP: {}", e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Failed to read ZIP: {}", e))?;
+
+    // macOS adds resource forks under __MACOSX
+    let names: Vec<Option<PathBuf>> = (0..archive.len())
+        .map(|i| {
+            let entry = archive.by_index(i).ok()?;
+            let name = entry.enclosed_name()?;
+            (!entry.is_dir() && !name.starts_with("__MACOSX")).then_some(name)
+        })
+        .collect();
+
+    // The folder each file is in at the top level, None for top-level files
+    let mut firsts = names.iter().flatten().map(|name| {
+        let mut components = name.components();
+        let first = components.next()?;
+        components.next().map(|_| PathBuf::from(first.as_os_str()))
+    });
+    let wrapper = match firsts.next() {
+        Some(Some(first)) if firsts.all(|f| f.as_ref() == Some(&first)) => Some(first),
+        _ => None,
+    };
+
+    // Sizes in the ZIP's directory are checked first, then the bytes
+    // actually written, since the directory may lie
+    let declared: u64 = (0..archive.len())
+        .filter_map(|i| archive.by_index(i).ok().map(|entry| entry.size()))
+        .sum();
+    if declared > MAX_UNCOMPRESSED {
+        return Err(too_large());
+    }
+    let mut remaining = MAX_UNCOMPRESSED;
+
+    for (i, name) in names.iter().enumerate() {
+        let Some(name) = name else {
+            continue;
+        };
+        let dest = dir.join(strip_wrapper(name, wrapper.as_deref()));
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create folder: {}", e))?;
+        }
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| format!("Failed to read ZIP: {}", e))?;
+        let mut out = std::fs::File::create(&dest)
+            .map_err(|e| format!("Failed to extract {}: {}", name.display(), e))?;
+        let written = std::io::copy(&mut (&mut entry).take(remaining + 1), &mut out)
+            .map_err(|e| format!("Failed to extract {}: {}", name.display(), e))?;
+        if written > remaining {
+            return Err(too_large());
+        }
+        remaining -= written;
+    }
+    Ok(())
+}
+
+/// Guess the root file: a `% !TEX root` comment wins, then main.tex, then
+/// the least nested .tex file with a \documentclass
+fn detect_root(tex_files: &[(String, String)]) -> Option<String> {
+    for (path, content) in tex_files {
+        if let Some(root) = parse_magic_comments(content).root {
+            // Relative to the file the comment is in
+            let mut parts: Vec<&str> = path.split('/').collect();
+            parts.pop();
+            for part in root.split('/') {
+                match part {
+                    ".." => {
+                        parts.pop();
+                    }
+                    "." | "" => {}
+                    _ => parts.push(part),
+                }
+            }
+            let root = parts.join("/");
+            if tex_files.iter().any(|(p, _)| *p == root) {
+                return Some(root);
+            }
+        }
+    }
+
+    let documentclass_re = Regex::new(r"(?m)^\s*\\documentclass").unwrap();
+    let mut candidates: Vec<&String> = tex_files
+        .iter()
+        .filter(|(_, content)| documentclass_re.is_match(content))
+        .map(|(path, _)| path)
+        .collect();
+    if candidates.iter().any(|p| *p == "main.tex") {
+        return Some("main.tex".to_string());
+    }
+    candidates.sort_by_key(|p| (p.matches('/').count(), p.to_string()));
+    candidates.first().map(|p| p.to_string())
+}
+
+/// Engine chosen by a latexmkrc, e.g. Overleaf's "$pdf_mode = 5;" for XeLaTeX
+fn latexmkrc_engine(rc: &str) -> Option<String> {
+    let pdf_mode_re = Regex::new(r"\$pdf_mode\s*=\s*(\d)").unwrap();
+    let engine = match pdf_mode_re.captures(rc).map(|cap| cap[1].to_string()) {
+        Some(mode) if mode == "4" => "lualatex",
+        Some(mode) if mode == "5" => "xelatex",
+        Some(mode) if mode == "1" => "pdflatex",
+        _ if rc.contains("xelatex") => "xelatex",
+        _ if rc.contains("lualatex") => "lualatex",
+        _ => return None,
+    };
+    Some(engine.to_string())
+}
+
+/// Extract a project ZIP (e.g. an Overleaf download) into the projects
+/// folder and register it as a project, detecting its root file and engine.
+/// The project's ID is its folder's name there, so `open_project` finds it.
+#[tauri::command]
+pub async fn import_project_zip(zip_path: String) -> Result<project::ProjectSummary, String> {
+    let zip_path = PathBuf::from(zip_path);
+    let name = zip_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Imported project".to_string());
+
+    let projects = project::projects_dir();
+    fs::create_dir_all(&projects)
+        .await
+        .map_err(|e| format!("Failed to create projects directory: {}", e))?;
+    let dir = projects.join(project::new_project_id(&name));
+    fs::create_dir(&dir)
+        .await
+        .map_err(|e| format!("Failed to create project: {}", e))?;
+
+    // The folder was created above, so removing it takes nothing else along
+    let target = dir.clone();
+    let extracted = tokio::task::spawn_blocking(move || extract(&zip_path, &target))
+        .await
+        .map_err(|e| format!("Failed to extract ZIP: {}", e))
+        .and_then(|result| result);
+    let imported = match extracted {
+        Ok(()) => register_folder(&dir, name).await,
+        Err(e) => Err(e),
+    };
+    if imported.is_err() {
+        let _ = fs::remove_dir_all(&dir).await;
+    }
+    imported
+}
+
+/// Write a manifest for a folder of sources that came from elsewhere,
+/// detecting its root file and engine
+pub async fn register_folder(dir: &Path, name: String) -> Result<project::ProjectSummary, String> {
+    let mut tex_files = Vec::new();
+    for (entry, path) in project::walk(dir).await? {
+        if entry.path.ends_with(".tex") {
+            if let Ok(content) = fs::read_to_string(&path).await {
+                tex_files.push((entry.path, content));
+            }
+        }
+    }
+    let root_file = detect_root(&tex_files).unwrap_or_else(|| "main.tex".to_string());
+
+    // The root's magic comment beats the latexmkrc, as when compiling
+    let root_program = tex_files
+        .iter()
+        .find(|(path, _)| *path == root_file)
+        .and_then(|(_, content)| parse_magic_comments(content).program);
+    let mut engine = root_program;
+    for rc in ["latexmkrc", ".latexmkrc"] {
+        if engine.is_none() {
+            if let Ok(text) = fs::read_to_string(dir.join(rc)).await {
+                engine = latexmkrc_engine(&text);
+            }
+        }
+    }
+
+    let manifest = project::ProjectManifest {
+        name,
+        root_file,
+        engine,
+        ..project::ProjectManifest::default()
+    };
+    project::write_manifest(dir, &manifest).await?;
+    project::summary(dir)
+        .await
+        .ok_or_else(|| "Failed to register imported project".to_string())
+}
@@ -0,0 +1,334 @@
+//! Importing images and other files into a project, converting image
+//! formats the TeX engines can't include to PNG
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{processes, project};
+
+/// Folder pasted images are saved in
+const FIGURES_DIR: &str = "figures";
+
+/// Read by the image library and written out as PNG
+const DECODABLE: &[&str] = &["webp", "tif", "tiff", "gif", "bmp"];
+
+/// Converted by an external tool, as no pure-Rust HEIF decoder exists
+const HEIF: &[&str] = &["heic", "heif", "avif"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PastedImage {
+    /// Relative to the project, with "/" separators
+    path: String,
+    /// \includegraphics line to insert at the cursor
+    snippet: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DroppedFile {
+    /// Name of the dropped file or folder
+    original: String,
+    /// Where it ended up, relative to the project
+    path: String,
+    /// Whether the name had to change
+    renamed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportedAsset {
+    /// Relative to the project, with "/" separators
+    path: String,
+    /// Format the file was converted from, if it was
+    converted_from: Option<String>,
+}
+
+/// Folder inside a project, "" or None for the project root
+pub fn target_folder(dir: &Path, folder: Option<&str>) -> Result<PathBuf, String> {
+    match folder.map(|f| f.trim_matches('/')) {
+        Some(folder) if !folder.is_empty() => project::resolve_in(dir, folder),
+        _ => Ok(dir.to_path_buf()),
+    }
+}
+
+/// `folder/stem.ext`, or `folder/stem-2.ext` and so on if that's taken
+pub fn unique_path(folder: &Path, stem: &str, extension: &str) -> PathBuf {
+    let name = |suffix: String| match extension {
+        "" => format!("{}{}", stem, suffix),
+        _ => format!("{}{}.{}", stem, suffix, extension),
+    };
+    let mut path = folder.join(name(String::new()));
+    let mut n = 2;
+    while path.exists() {
+        path = folder.join(name(format!("-{}", n)));
+        n += 1;
+    }
+    path
+}
+
+/// Project-relative form of a path inside the project
+pub fn relative_to(dir: &Path, path: &Path) -> String {
+    path.strip_prefix(dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Convert a HEIF image to PNG with whichever converter is installed
+async fn convert_heif(source: &Path, target: &Path) -> Result<(), String> {
+    let mut converters: Vec<Command> = Vec::new();
+    if cfg!(target_os = "macos") {
+        let mut sips = Command::new("sips");
+        sips.args(["-s", "format", "png"])
+            .arg(source)
+            .arg("--out")
+            .arg(target);
+        converters.push(sips);
+    }
+    let mut heif_convert = Command::new("heif-convert");
+    heif_convert.arg(source).arg(target);
+    converters.push(heif_convert);
+    let mut magick = Command::new("magick");
+    magick.arg(source).arg(target);
+    converters.push(magick);
+
+    for mut converter in converters {
+        if let Ok(output) = processes::output(&mut converter).await {
+            if output.status.success() && target.exists() {
+                return Ok(());
+            }
+        }
+    }
+    Err("Converting HEIC images needs sips, heif-convert or ImageMagick".to_string())
+}
+
+/// Copy a file into a project folder, converting images LaTeX can't include
+/// (WebP, TIFF, GIF, BMP, HEIC) to PNG. An existing file of the same name
+/// is kept and the import gets a numbered name.
+#[tauri::command]
+pub async fn import_asset(
+    path: String,
+    project: String,
+    target_dir: Option<String>,
+) -> Result<ImportedAsset, String> {
+    let source = PathBuf::from(&path);
+    if !source.is_file() {
+        return Err(format!("File not found: {}", path));
+    }
+    let dir = PathBuf::from(&project);
+    if !dir.is_dir() {
+        return Err(format!("Not a folder: {}", project));
+    }
+    let folder = target_folder(&dir, target_dir.as_deref())?;
+    fs::create_dir_all(&folder)
+        .await
+        .map_err(|e| format!("Failed to create folder: {}", e))?;
+
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "image".to_string());
+    let extension = source
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let (target, converted_from) = if DECODABLE.contains(&extension.as_str()) {
+        let target = unique_path(&folder, &stem, "png");
+        let (from, to) = (source.clone(), target.clone());
+        tokio::task::spawn_blocking(move || image::open(&from).and_then(|img| img.save(&to)))
+            .await
+            .map_err(|e| format!("Failed to convert {}: {}", path, e))?
+            .map_err(|e| format!("Failed to convert {}: {}", path, e))?;
+        (target, Some(extension))
+    } else if HEIF.contains(&extension.as_str()) {
+        let target = unique_path(&folder, &stem, "png");
+        convert_heif(&source, &target).await?;
+        (target, Some(extension))
+    } else {
+        let target = unique_path(&folder, &stem, &extension);
+        fs::copy(&source, &target)
+            .await
+            .map_err(|e| format!("Failed to import {}: {}", path, e))?;
+        (target, None)
+    };
+
+    Ok(ImportedAsset {
+        path: relative_to(&dir, &target),
+        converted_from,
+    })
+}
+
+/// File name for a figure: spaces become dashes and characters that upset
+/// LaTeX or the file system are dropped
+fn figure_stem(name: &str) -> String {
+    let stem: String = name
+        .trim()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_whitespace() => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect();
+    if stem.is_empty() {
+        "pasted-image".to_string()
+    } else {
+        stem
+    }
+}
+
+/// Save the image on the clipboard (e.g. a screenshot) as a PNG in the
+/// project's figures folder
+#[tauri::command]
+pub async fn save_clipboard_image(
+    project: String,
+    name: Option<String>,
+) -> Result<PastedImage, String> {
+    let dir = PathBuf::from(&project);
+    if !dir.is_dir() {
+        return Err(format!("Not a folder: {}", project));
+    }
+    let folder = dir.join(FIGURES_DIR);
+    fs::create_dir_all(&folder)
+        .await
+        .map_err(|e| format!("Failed to create folder: {}", e))?;
+    let name = name.map(|n| n.trim_end_matches(".png").to_string());
+    let target = unique_path(&folder, &figure_stem(name.as_deref().unwrap_or("")), "png");
+
+    let to = target.clone();
+    tokio::task::spawn_blocking(move || {
+        let image = arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.get_image())
+            .map_err(|e| format!("No image on the clipboard: {}", e))?;
+        let buffer = image::RgbaImage::from_raw(
+            image.width as u32,
+            image.height as u32,
+            image.bytes.into_owned(),
+        )
+        .ok_or_else(|| "Clipboard image is malformed".to_string())?;
+        buffer
+            .save(&to)
+            .map_err(|e| format!("Failed to save image: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Failed to read clipboard: {}", e))??;
+
+    let path = relative_to(&dir, &target);
+    Ok(PastedImage {
+        snippet: format!("\\includegraphics[width=\\linewidth]{{{}}}", path),
+        path,
+    })
+}
+
+/// Name TeX copes with on every system: composed Unicode (macOS hands out
+/// Hangul decomposed, which TeX can't match), dashes for spaces and TeX's
+/// special characters, and a lower-case extension
+fn normalize_name(name: &str, is_dir: bool) -> (String, String) {
+    let name: String = name
+        .nfc()
+        .map(|c| match c {
+            c if c.is_whitespace() => '-',
+            '#' | '%' | '&' | '~' | '$' | '{' | '}' | '^' | '\\' => '-',
+            c => c,
+        })
+        .collect();
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !is_dir && !stem.is_empty() => {
+            (stem.to_string(), extension.to_lowercase())
+        }
+        _ => (name, String::new()),
+    }
+}
+
+/// Copy a dropped folder, normalizing every name inside it, and note the
+/// entries that had to be renamed
+async fn copy_folder(
+    dir: &Path,
+    source: &Path,
+    target: &Path,
+    imported: &mut Vec<DroppedFile>,
+) -> Result<(), String> {
+    let dropped_from = source.parent().unwrap_or(source);
+    let mut pending = vec![(source.to_path_buf(), target.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        fs::create_dir_all(&to)
+            .await
+            .map_err(|e| format!("Failed to create folder: {}", e))?;
+        let mut entries = fs::read_dir(&from)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", from.display(), e))?;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            let child = entry.path();
+            let (stem, extension) = normalize_name(&name, child.is_dir());
+            // Created right away so a sibling normalizing to the same name
+            // gets a numbered one
+            let copy = unique_path(&to, &stem, &extension);
+            if child.is_dir() {
+                fs::create_dir_all(&copy)
+                    .await
+                    .map_err(|e| format!("Failed to create folder: {}", e))?;
+                pending.push((child.clone(), copy.clone()));
+            } else {
+                fs::copy(&child, &copy)
+                    .await
+                    .map_err(|e| format!("Failed to import {}: {}", name, e))?;
+            }
+            if copy.file_name() != Some(entry.file_name().as_os_str()) {
+                imported.push(DroppedFile {
+                    original: relative_to(dropped_from, &child),
+                    path: relative_to(dir, &copy),
+                    renamed: true,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy dropped files and folders into a project, renaming any whose names
+/// would trip up LaTeX. Folders keep their structure, with every name inside
+/// normalized the same way.
+#[tauri::command]
+pub async fn import_dropped_files(
+    paths: Vec<String>,
+    project: String,
+) -> Result<Vec<DroppedFile>, String> {
+    let dir = PathBuf::from(&project);
+    if !dir.is_dir() {
+        return Err(format!("Not a folder: {}", project));
+    }
+
+    let mut imported = Vec::new();
+    for path in paths {
+        let source = PathBuf::from(&path);
+        let original = source
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| format!("Invalid path: {}", path))?;
+        let (stem, extension) = normalize_name(&original, source.is_dir());
+        let target = unique_path(&dir, &stem, &extension);
+        if target.starts_with(&source) {
+            return Err(format!("Cannot import {} into itself", original));
+        }
+        let path = relative_to(&dir, &target);
+        imported.push(DroppedFile {
+            renamed: path != original,
+            original,
+            path,
+        });
+        if source.is_dir() {
+            copy_folder(&dir, &source, &target, &mut imported).await?;
+        } else {
+            fs::copy(&source, &target)
+                .await
+                .map_err(|e| format!("Failed to import {}: {}", source.display(), e))?;
+        }
+    }
+    Ok(imported)
+}
@@ -0,0 +1,277 @@
+//! Scheduled backups of the projects folder to a location of the user's
+//! choosing, e.g. an external drive or a synced folder
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::async_runtime::JoinHandle;
+use tokio::fs;
+
+use crate::{archive, history, project, settings};
+
+const MIN_INTERVAL_MINUTES: u64 = 5;
+
+const MS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupSettings {
+    /// Folder the backups go in; scheduled backups are off without one
+    pub directory: Option<String>,
+    pub interval_minutes: u64,
+    /// Store each backup as a ZIP instead of a copy of the files
+    pub compress: bool,
+    /// Backups kept per project, newest first
+    pub keep_last: usize,
+    /// Backups older than this are deleted, except a project's newest
+    pub max_age_days: Option<u64>,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            directory: None,
+            interval_minutes: 60,
+            compress: true,
+            keep_last: 10,
+            max_age_days: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupRecord {
+    project: String,
+    /// The ZIP or folder written
+    path: String,
+}
+
+lazy_static::lazy_static! {
+    static ref SCHEDULER: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+    /// Fingerprints of the projects as last backed up, by project folder, so
+    /// unchanged projects are skipped
+    static ref BACKED_UP: Mutex<HashMap<PathBuf, u64>> = Mutex::new(HashMap::new());
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Backups of one project: files or folders named by their creation time in
+/// milliseconds, newest first
+async fn existing_backups(dir: &Path) -> Vec<(u64, PathBuf)> {
+    let mut backups = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let stem = name.strip_suffix(".zip").unwrap_or(&name);
+            if let Ok(created_ms) = stem.parse() {
+                backups.push((created_ms, path));
+            }
+        }
+    }
+    backups.sort_by_key(|(created_ms, _)| std::cmp::Reverse(*created_ms));
+    backups
+}
+
+/// Delete the backups the retention settings no longer keep
+async fn prune(dir: &Path, settings: &BackupSettings) {
+    let cutoff = settings
+        .max_age_days
+        .map(|days| now_ms().saturating_sub(days * MS_PER_DAY));
+    for (index, (created_ms, path)) in existing_backups(dir).await.into_iter().enumerate() {
+        let expired = cutoff.is_some_and(|cutoff| created_ms < cutoff);
+        if index > 0 && (index >= settings.keep_last || expired) {
+            let _ = if path.is_dir() {
+                fs::remove_dir_all(&path).await
+            } else {
+                fs::remove_file(&path).await
+            };
+        }
+    }
+}
+
+async fn copy_project(source: &Path, target: &Path) -> Result<(), String> {
+    let manifest = source.join(project::MANIFEST);
+    let mut files: Vec<(String, PathBuf)> = project::walk(source)
+        .await?
+        .into_iter()
+        .filter(|(entry, _)| !entry.is_dir)
+        .map(|(entry, path)| (entry.path, path))
+        .collect();
+    if manifest.exists() {
+        files.push((project::MANIFEST.to_string(), manifest));
+    }
+    for (name, path) in files {
+        let dest = target.join(&name);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Failed to create backup folder: {}", e))?;
+        }
+        fs::copy(&path, &dest)
+            .await
+            .map_err(|e| format!("Failed to back up {}: {}", name, e))?;
+    }
+    Ok(())
+}
+
+/// Back up one project into its folder under the backup directory. The
+/// backup is written under a temporary name first, so an unplugged drive
+/// never leaves a half-written backup looking complete.
+async fn back_up(
+    project_dir: &Path,
+    backup_dir: &Path,
+    settings: &BackupSettings,
+) -> Result<PathBuf, String> {
+    let name = project_dir
+        .file_name()
+        .ok_or_else(|| format!("Not a project folder: {}", project_dir.display()))?;
+    let dir = backup_dir.join(name);
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Failed to create backup folder: {}", e))?;
+
+    let created_ms = now_ms();
+    let partial = dir.join(format!("{}.partial", created_ms));
+    let target = if settings.compress {
+        let data = archive::zip_project(project_dir, false).await?;
+        fs::write(&partial, data)
+            .await
+            .map_err(|e| format!("Failed to write backup: {}", e))?;
+        dir.join(format!("{}.zip", created_ms))
+    } else {
+        if let Err(e) = copy_project(project_dir, &partial).await {
+            let _ = fs::remove_dir_all(&partial).await;
+            return Err(e);
+        }
+        dir.join(created_ms.to_string())
+    };
+    fs::rename(&partial, &target)
+        .await
+        .map_err(|e| format!("Failed to write backup: {}", e))?;
+
+    prune(&dir, settings).await;
+    Ok(target)
+}
+
+/// Project folders in the projects folder
+async fn all_projects() -> Vec<PathBuf> {
+    let mut projects = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(project::projects_dir()).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.join(project::MANIFEST).is_file() {
+                projects.push(path);
+            }
+        }
+    }
+    projects
+}
+
+/// Back up every project that changed since its last backup
+async fn back_up_changed(settings: &BackupSettings) -> Vec<BackupRecord> {
+    let Some(backup_dir) = settings.directory.as_deref().map(PathBuf::from) else {
+        return Vec::new();
+    };
+    let mut records = Vec::new();
+    for project_dir in all_projects().await {
+        let Ok(current) = history::fingerprint(&project_dir).await else {
+            continue;
+        };
+        let unchanged = BACKED_UP
+            .lock()
+            .map(|backed_up| backed_up.get(&project_dir) == Some(&current))
+            .unwrap_or(false);
+        if unchanged {
+            continue;
+        }
+        if let Ok(path) = back_up(&project_dir, &backup_dir, settings).await {
+            if let Ok(mut backed_up) = BACKED_UP.lock() {
+                backed_up.insert(project_dir.clone(), current);
+            }
+            records.push(BackupRecord {
+                project: project_dir.to_string_lossy().to_string(),
+                path: path.to_string_lossy().to_string(),
+            });
+        }
+    }
+    records
+}
+
+/// (Re)start scheduled backups with the saved settings; called on startup
+/// and whenever the settings change
+pub async fn start() {
+    let backup = settings::load().await.backup;
+    let task = backup.directory.is_some().then(|| {
+        let interval = Duration::from_secs(backup.interval_minutes.max(MIN_INTERVAL_MINUTES) * 60);
+        tauri::async_runtime::spawn(async move {
+            loop {
+                back_up_changed(&backup).await;
+                tokio::time::sleep(interval).await;
+            }
+        })
+    });
+
+    if let Ok(mut scheduler) = SCHEDULER.lock() {
+        if let Some(previous) = std::mem::replace(&mut *scheduler, task) {
+            previous.abort();
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_backup_settings() -> Result<BackupSettings, String> {
+    Ok(settings::load().await.backup)
+}
+
+#[tauri::command]
+pub async fn set_backup_settings(backup: BackupSettings) -> Result<(), String> {
+    if let Some(directory) = &backup.directory {
+        fs::create_dir_all(directory)
+            .await
+            .map_err(|e| format!("Failed to create backup folder: {}", e))?;
+    }
+    let mut app_settings = settings::load().await;
+    app_settings.backup = backup;
+    settings::save(&app_settings).await?;
+    start().await;
+    Ok(())
+}
+
+/// Back up now, whether or not anything changed: one project folder, or
+/// every project in the projects folder
+#[tauri::command]
+pub async fn run_backup_now(project: Option<String>) -> Result<Vec<BackupRecord>, String> {
+    let backup = settings::load().await.backup;
+    let backup_dir = backup
+        .directory
+        .as_deref()
+        .map(PathBuf::from)
+        .ok_or_else(|| "No backup folder has been chosen".to_string())?;
+    let projects = match project {
+        Some(path) => vec![PathBuf::from(path)],
+        None => all_projects().await,
+    };
+
+    let mut records = Vec::new();
+    for project_dir in projects {
+        let path = back_up(&project_dir, &backup_dir, &backup).await?;
+        if let Ok(current) = history::fingerprint(&project_dir).await {
+            if let Ok(mut backed_up) = BACKED_UP.lock() {
+                backed_up.insert(project_dir.clone(), current);
+            }
+        }
+        records.push(BackupRecord {
+            project: project_dir.to_string_lossy().to_string(),
+            path: path.to_string_lossy().to_string(),
+        });
+    }
+    Ok(records)
+}
@@ -0,0 +1,1038 @@
+//! BibTeX/BibLaTeX databases parsed natively, for the bibliography panel,
+//! so nothing has to shell out to bibtex or biber to list entries. Entries
+//! can also be looked up by DOI or arXiv ID, when online lookups are allowed.
+
+use nom::branch::alt;
+use nom::bytes::complete::take_while1;
+use nom::character::complete::{char, digit1, multispace0};
+use nom::combinator::opt;
+use nom::sequence::{delimited, preceded, terminated};
+use nom::{IResult, Parser};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use crate::{encoding, includes, processes, project, proxy, save, settings};
+
+type ParseResult<'a, T> = IResult<&'a str, T>;
+
+/// Month macros BibTeX styles predefine
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BibField {
+    /// Lowercase, e.g. "author"
+    name: String,
+    /// With outer braces or quotes removed, @string macros expanded and
+    /// whitespace collapsed
+    value: String,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BibEntry {
+    key: String,
+    /// Lowercase, e.g. "article"
+    entry_type: String,
+    fields: Vec<BibField>,
+    /// Line of the entry's `@`, counting from 1
+    line: usize,
+}
+
+/// Text that couldn't be read as an entry; parsing resumes at the next `@`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BibParseError {
+    line: usize,
+    message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Bibliography {
+    entries: Vec<BibEntry>,
+    /// @string macros, as defined
+    strings: HashMap<String, String>,
+    preamble: Vec<String>,
+    errors: Vec<BibParseError>,
+}
+
+impl Bibliography {
+    pub fn entries(&self) -> &[BibEntry] {
+        &self.entries
+    }
+}
+
+/// What one `@...` block is
+enum Item {
+    Entry(String, String, Vec<(String, String, usize)>),
+    String(String, String),
+    Preamble(String),
+    Comment,
+}
+
+/// Entry types, field names and macro names: anything but whitespace and
+/// the characters BibTeX gives a meaning
+fn identifier(input: &str) -> ParseResult<'_, &str> {
+    take_while1(|c: char| !c.is_whitespace() && !"{}(),=\"#%@".contains(c)).parse(input)
+}
+
+fn key(input: &str) -> ParseResult<'_, &str> {
+    take_while1(|c: char| !c.is_whitespace() && !",{}()".contains(c)).parse(input)
+}
+
+/// Text up to the `close` that balances the braces, which stays unconsumed
+fn balanced(close: char) -> impl Fn(&str) -> ParseResult<'_, &str> {
+    move |input: &str| {
+        let mut depth = 0usize;
+        for (i, c) in input.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                c if c == close && depth == 0 => return Ok((&input[i..], &input[..i])),
+                '}' => break,
+                _ => {}
+            }
+        }
+        Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::TakeUntil,
+        )))
+    }
+}
+
+/// The `}` or `)` closing a block, after any whitespace
+fn closing(close: char) -> impl Fn(&str) -> ParseResult<'_, char> {
+    move |input: &str| preceded(multispace0, char(close)).parse(input)
+}
+
+fn braced(input: &str) -> ParseResult<'_, &str> {
+    delimited(char('{'), balanced('}'), char('}')).parse(input)
+}
+
+fn quoted(input: &str) -> ParseResult<'_, &str> {
+    delimited(char('"'), balanced('"'), char('"')).parse(input)
+}
+
+/// A field value: pieces joined with `#`, each a braced or quoted string,
+/// a number, or a macro
+fn value<'a>(
+    strings: &HashMap<String, String>,
+) -> impl Fn(&'a str) -> ParseResult<'a, String> + '_ {
+    move |input: &'a str| {
+        let piece = |input: &'a str| -> ParseResult<'a, String> {
+            if let Ok((rest, text)) = alt((braced, quoted, digit1)).parse(input) {
+                return Ok((rest, text.to_string()));
+            }
+            let (rest, name) = identifier(input)?;
+            let name = name.to_lowercase();
+            // Undefined macros expand to nothing, as in BibTeX
+            let expanded = strings.get(&name).cloned().unwrap_or_else(|| {
+                match MONTHS.iter().position(|m| *m == name) {
+                    Some(month) => (month + 1).to_string(),
+                    None => String::new(),
+                }
+            });
+            Ok((rest, expanded))
+        };
+
+        let (mut rest, mut text) = piece(input)?;
+        while let Ok((next, more)) =
+            preceded((multispace0, char('#'), multispace0), piece).parse(rest)
+        {
+            text.push_str(&more);
+            rest = next;
+        }
+        Ok((rest, text.split_whitespace().collect::<Vec<_>>().join(" ")))
+    }
+}
+
+/// Where the lines of a text start, for finding the line of a position
+struct Lines {
+    len: usize,
+    starts: Vec<usize>,
+}
+
+impl Lines {
+    fn new(content: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Lines {
+            len: content.len(),
+            starts,
+        }
+    }
+
+    /// Line of the position `rest` starts at, counting from 1
+    fn at(&self, rest: &str) -> usize {
+        let offset = self.len - rest.len();
+        self.starts.partition_point(|&start| start <= offset)
+    }
+}
+
+/// Whether `input` starts an `@type{` or `@type(` block. Anything else is
+/// text between entries, which BibTeX ignores, stray `@`s included.
+fn starts_item(input: &str) -> bool {
+    let start: ParseResult<'_, _> = (
+        char('@'),
+        multispace0,
+        identifier,
+        multispace0,
+        alt((char('{'), char('('))),
+    )
+        .parse(input);
+    start.is_ok()
+}
+
+/// One `@type{...}` or `@type(...)` block, starting at its `@`
+fn item<'a>(
+    lines: &Lines,
+    input: &'a str,
+    strings: &HashMap<String, String>,
+) -> ParseResult<'a, Item> {
+    let (input, entry_type) = preceded((char('@'), multispace0), identifier).parse(input)?;
+    let entry_type = entry_type.to_lowercase();
+    let (input, open) = preceded(multispace0, alt((char('{'), char('(')))).parse(input)?;
+    let close = if open == '{' { '}' } else { ')' };
+
+    match entry_type.as_str() {
+        "comment" => {
+            let (input, _) = terminated(balanced(close), char(close)).parse(input)?;
+            Ok((input, Item::Comment))
+        }
+        "preamble" => {
+            let (input, text) =
+                terminated(preceded(multispace0, value(strings)), closing(close)).parse(input)?;
+            Ok((input, Item::Preamble(text)))
+        }
+        "string" => {
+            let (input, (name, text)) = terminated(
+                (
+                    preceded(multispace0, identifier),
+                    preceded((multispace0, char('='), multispace0), value(strings)),
+                ),
+                closing(close),
+            )
+            .parse(input)?;
+            Ok((input, Item::String(name.to_lowercase(), text)))
+        }
+        _ => {
+            let (mut input, key) = preceded(multispace0, key).parse(input)?;
+            let mut fields = Vec::new();
+            loop {
+                // Fields follow commas; a trailing comma is allowed
+                let (rest, comma) = opt(preceded(multispace0, char(','))).parse(input)?;
+                if let Ok((rest, _)) = closing(close)(rest) {
+                    input = rest;
+                    break;
+                }
+                if comma.is_none() {
+                    return Err(nom::Err::Error(nom::error::Error::new(
+                        rest,
+                        nom::error::ErrorKind::Char,
+                    )));
+                }
+                let (rest, _) = multispace0(rest)?;
+                let line = lines.at(rest);
+                let (rest, (name, text)) = (
+                    identifier,
+                    preceded((multispace0, char('='), multispace0), value(strings)),
+                )
+                    .parse(rest)?;
+                fields.push((name.to_lowercase(), text, line));
+                input = rest;
+            }
+            Ok((input, Item::Entry(entry_type, key.to_string(), fields)))
+        }
+    }
+}
+
+/// Parse a .bib database, skipping over anything malformed
+pub fn parse(content: &str) -> Bibliography {
+    let mut bibliography = Bibliography::default();
+    let mut rest = content;
+    let lines = Lines::new(content);
+
+    while let Some(at) = rest.find('@') {
+        let start = &rest[at..];
+        if !starts_item(start) {
+            rest = &start[1..];
+            continue;
+        }
+        match item(&lines, start, &bibliography.strings) {
+            Ok((next, parsed)) => {
+                match parsed {
+                    Item::Entry(entry_type, key, fields) => {
+                        bibliography.entries.push(BibEntry {
+                            key,
+                            entry_type,
+                            fields: fields
+                                .into_iter()
+                                .map(|(name, value, line)| BibField { name, value, line })
+                                .collect(),
+                            line: lines.at(start),
+                        });
+                    }
+                    Item::String(name, text) => {
+                        bibliography.strings.insert(name, text);
+                    }
+                    Item::Preamble(text) => bibliography.preamble.push(text),
+                    Item::Comment => {}
+                }
+                rest = next;
+            }
+            Err(e) => {
+                let at = match &e {
+                    nom::Err::Error(e) | nom::Err::Failure(e) => e.input,
+                    nom::Err::Incomplete(_) => start,
+                }
+                .trim_start();
+                let found = match at.chars().next() {
+                    Some(c) => format!("'{}'", c),
+                    None => "end of file".to_string(),
+                };
+                bibliography.errors.push(BibParseError {
+                    line: lines.at(at),
+                    message: format!(
+                        "Unexpected {} in the entry starting on line {}",
+                        found,
+                        lines.at(start)
+                    ),
+                });
+                rest = &start[1..];
+            }
+        }
+    }
+
+    bibliography
+}
+
+/// Read a .bib file, converting legacy encodings to UTF-8
+pub async fn read(path: &Path) -> Result<String, String> {
+    let data = tokio::fs::read(path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(encoding::decode(&data).0)
+}
+
+/// Parse a .bib file, given its path, or a database's content
+#[tauri::command]
+pub async fn parse_bibliography(path_or_content: String) -> Result<Bibliography, String> {
+    let is_path = !path_or_content.contains('\n')
+        && !path_or_content.trim_start().starts_with('@')
+        && Path::new(path_or_content.trim()).is_file();
+    let content = if is_path {
+        read(Path::new(path_or_content.trim())).await?
+    } else {
+        path_or_content
+    };
+    Ok(parse(&content))
+}
+
+/// Fields each entry type requires; a requirement lists alternatives, e.g.
+/// BibLaTeX's `date` in place of `year`
+const REQUIRED_FIELDS: &[(&str, &[&[&str]])] = &[
+    (
+        "article",
+        &[
+            &["author"],
+            &["title"],
+            &["journal", "journaltitle"],
+            &["year", "date"],
+        ],
+    ),
+    (
+        "book",
+        &[
+            &["author", "editor"],
+            &["title"],
+            &["publisher"],
+            &["year", "date"],
+        ],
+    ),
+    ("booklet", &[&["title"]]),
+    (
+        "inbook",
+        &[
+            &["author", "editor"],
+            &["title"],
+            &["chapter", "pages"],
+            &["publisher"],
+            &["year", "date"],
+        ],
+    ),
+    (
+        "incollection",
+        &[
+            &["author"],
+            &["title"],
+            &["booktitle"],
+            &["publisher"],
+            &["year", "date"],
+        ],
+    ),
+    (
+        "inproceedings",
+        &[&["author"], &["title"], &["booktitle"], &["year", "date"]],
+    ),
+    (
+        "conference",
+        &[&["author"], &["title"], &["booktitle"], &["year", "date"]],
+    ),
+    ("manual", &[&["title"]]),
+    (
+        "mastersthesis",
+        &[
+            &["author"],
+            &["title"],
+            &["school", "institution"],
+            &["year", "date"],
+        ],
+    ),
+    (
+        "phdthesis",
+        &[
+            &["author"],
+            &["title"],
+            &["school", "institution"],
+            &["year", "date"],
+        ],
+    ),
+    ("proceedings", &[&["title"], &["year", "date"]]),
+    (
+        "techreport",
+        &[&["author"], &["title"], &["institution"], &["year", "date"]],
+    ),
+    ("unpublished", &[&["author"], &["title"], &["note"]]),
+    (
+        "thesis",
+        &[
+            &["author"],
+            &["title"],
+            &["type"],
+            &["institution", "school"],
+            &["year", "date"],
+        ],
+    ),
+    (
+        "report",
+        &[
+            &["author"],
+            &["title"],
+            &["type"],
+            &["institution"],
+            &["year", "date"],
+        ],
+    ),
+    ("online", &[&["title"], &["url", "doi", "eprint"]]),
+];
+
+/// Fields holding URLs and paths, where `_`, `%` and `#` are meant literally
+const VERBATIM_FIELDS: &[&str] = &["url", "doi", "eprint", "file", "pdf", "urlraw"];
+
+/// Fields holding name lists
+const NAME_FIELDS: &[&str] = &["author", "editor", "translator"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BibDiagnosticKind {
+    ParseError,
+    MissingField,
+    MalformedNames,
+    UnescapedCharacter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BibDiagnostic {
+    kind: BibDiagnosticKind,
+    line: usize,
+    /// Entry the diagnostic is about, unless it couldn't be parsed
+    key: Option<String>,
+    field: Option<String>,
+    message: String,
+}
+
+/// Split a name list on the `and`s outside braces
+fn split_names(value: &str) -> Vec<String> {
+    let mut names = vec![String::new()];
+    let mut depth = 0usize;
+    let words: Vec<&str> = value.split(' ').collect();
+    for word in words {
+        if depth == 0 && word.eq_ignore_ascii_case("and") {
+            names.push(String::new());
+            continue;
+        }
+        depth = (depth + word.matches('{').count()).saturating_sub(word.matches('}').count());
+        let name = names.last_mut().unwrap();
+        if !name.is_empty() {
+            name.push(' ');
+        }
+        name.push_str(word);
+    }
+    names
+}
+
+/// Text of a value outside braces, where separators count
+fn top_level(value: &str) -> String {
+    let mut depth = 0usize;
+    value
+        .chars()
+        .filter(|c| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                _ => return depth == 0,
+            }
+            false
+        })
+        .collect()
+}
+
+/// What is wrong with a name list, if anything
+fn name_list_problem(value: &str) -> Option<String> {
+    let names = split_names(value);
+    if names.iter().any(|name| name.is_empty()) {
+        return Some("Empty name; check for doubled or dangling \"and\"".to_string());
+    }
+    for name in &names {
+        let outside = top_level(name);
+        if outside.contains(';') || outside.contains('&') {
+            return Some(format!(
+                "\"{}\" looks like several names; separate names with \"and\"",
+                name
+            ));
+        }
+        // "Last, First" and "Last, Jr, First" are the only forms with commas
+        if outside.matches(',').count() > 2 {
+            return Some(format!(
+                "\"{}\" has too many commas; separate names with \"and\"",
+                name
+            ));
+        }
+    }
+    None
+}
+
+/// The first character TeX would read as special, outside math and the
+/// arguments of \url and \href
+fn unescaped_character(value: &str) -> Option<char> {
+    let mut chars = value.chars().peekable();
+    let mut math = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let mut command = String::new();
+                while let Some(&next) = chars.peek() {
+                    if !next.is_ascii_alphabetic() {
+                        break;
+                    }
+                    command.push(next);
+                    chars.next();
+                }
+                if command.is_empty() {
+                    // An escaped character, e.g. \&
+                    chars.next();
+                } else if (command == "url" || command == "href") && chars.peek() == Some(&'{') {
+                    let mut depth = 0usize;
+                    for c in chars.by_ref() {
+                        match c {
+                            '{' => depth += 1,
+                            '}' => {
+                                depth -= 1;
+                                if depth == 0 {
+                                    break;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            '$' => math = !math,
+            '&' | '%' | '#' => return Some(c),
+            '_' if !math => return Some(c),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Check a database for parse errors, missing required fields, malformed
+/// name lists and unescaped special characters
+fn validate(content: &str) -> Vec<BibDiagnostic> {
+    let bibliography = parse(content);
+    let mut diagnostics: Vec<BibDiagnostic> = bibliography
+        .errors
+        .iter()
+        .map(|error| BibDiagnostic {
+            kind: BibDiagnosticKind::ParseError,
+            line: error.line,
+            key: None,
+            field: None,
+            message: error.message.clone(),
+        })
+        .collect();
+
+    for entry in &bibliography.entries {
+        let has = |name: &str| {
+            let field = |entry: &BibEntry| {
+                entry
+                    .fields
+                    .iter()
+                    .any(|f| f.name == name && !f.value.is_empty())
+            };
+            // An entry inherits the fields of the one it crossrefs
+            field(entry)
+                || entry
+                    .fields
+                    .iter()
+                    .find(|f| f.name == "crossref")
+                    .and_then(|f| {
+                        bibliography
+                            .entries
+                            .iter()
+                            .find(|e| e.key.eq_ignore_ascii_case(&f.value))
+                    })
+                    .is_some_and(field)
+        };
+
+        let required = REQUIRED_FIELDS
+            .iter()
+            .find(|(entry_type, _)| *entry_type == entry.entry_type)
+            .map(|(_, fields)| *fields)
+            .unwrap_or_default();
+        for alternatives in required {
+            if !alternatives.iter().any(|name| has(name)) {
+                diagnostics.push(BibDiagnostic {
+                    kind: BibDiagnosticKind::MissingField,
+                    line: entry.line,
+                    key: Some(entry.key.clone()),
+                    field: Some(alternatives[0].to_string()),
+                    message: format!(
+                        "@{} {} is missing {}",
+                        entry.entry_type,
+                        entry.key,
+                        alternatives.join(" or ")
+                    ),
+                });
+            }
+        }
+
+        for field in &entry.fields {
+            if NAME_FIELDS.contains(&field.name.as_str()) {
+                if let Some(problem) = name_list_problem(&field.value) {
+                    diagnostics.push(BibDiagnostic {
+                        kind: BibDiagnosticKind::MalformedNames,
+                        line: field.line,
+                        key: Some(entry.key.clone()),
+                        field: Some(field.name.clone()),
+                        message: problem,
+                    });
+                }
+            }
+            if !VERBATIM_FIELDS.contains(&field.name.as_str()) {
+                if let Some(c) = unescaped_character(&field.value) {
+                    diagnostics.push(BibDiagnostic {
+                        kind: BibDiagnosticKind::UnescapedCharacter,
+                        line: field.line,
+                        key: Some(entry.key.clone()),
+                        field: Some(field.name.clone()),
+                        message: format!("Unescaped {} in {}; write \\{}", c, field.name, c),
+                    });
+                }
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(|d| d.line);
+    diagnostics
+}
+
+#[tauri::command]
+pub async fn validate_bib_entries(content: String) -> Vec<BibDiagnostic> {
+    validate(&content)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CitationKey {
+    key: String,
+    entry_type: String,
+    /// e.g. "Knuth", "Knuth and Lamport" or "Knuth et al."
+    authors: Option<String>,
+    year: Option<String>,
+    title: Option<String>,
+    /// .bib file relative to the project, with "/" separators
+    file: String,
+    line: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CitationKeys {
+    keys: Vec<CitationKey>,
+    /// Bibliographies the document names that aren't in the project
+    missing: Vec<String>,
+}
+
+/// Bibliography files a document names with \bibliography or
+/// \addbibresource, with the .bib extension
+fn referenced_bibliographies(document: &str) -> Vec<String> {
+    let re =
+        Regex::new(r"\\(bibliography|addbibresource)\s*(?:\[[^\]]*\])?\s*\{([^}]+)\}").unwrap();
+    let mut files = Vec::new();
+    for cap in re.captures_iter(document) {
+        // \bibliography takes a comma-separated list
+        let names: Vec<&str> = if &cap[1] == "bibliography" {
+            cap[2].split(',').collect()
+        } else {
+            vec![&cap[2]]
+        };
+        for name in names {
+            let name = name.trim();
+            // Remote resources can't be read here
+            if name.is_empty() || name.contains("://") {
+                continue;
+            }
+            let name = if name.ends_with(".bib") {
+                name.to_string()
+            } else {
+                format!("{}.bib", name)
+            };
+            if !files.contains(&name) {
+                files.push(name);
+            }
+        }
+    }
+    files
+}
+
+/// Text with braces and the backslash of escapes removed, for display
+fn plain(value: &str) -> String {
+    value
+        .replace(['{', '}'], "")
+        .replace("\\&", "&")
+        .replace("\\%", "%")
+        .replace("\\_", "_")
+}
+
+/// Last names of a name list, shortened like an author-year citation
+fn authors_summary(value: &str) -> String {
+    let last_names: Vec<String> = split_names(value)
+        .iter()
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            // "Last, First" or "First Last"; braced names stay whole
+            let last = match name.split_once(',') {
+                Some((last, _)) => last.to_string(),
+                None if name.starts_with('{') && name.ends_with('}') => name.clone(),
+                None => name.rsplit(' ').next().unwrap_or(name).to_string(),
+            };
+            plain(last.trim())
+        })
+        .collect();
+    match last_names.as_slice() {
+        [] => String::new(),
+        [one] => one.clone(),
+        [first, second] => format!("{} and {}", first, second),
+        [first, ..] => format!("{} et al.", first),
+    }
+}
+
+fn citation_key(entry: &BibEntry, file: &str) -> CitationKey {
+    let field = |name: &str| {
+        entry
+            .fields
+            .iter()
+            .find(|f| f.name == name && !f.value.is_empty())
+            .map(|f| f.value.as_str())
+    };
+    CitationKey {
+        key: entry.key.clone(),
+        entry_type: entry.entry_type.clone(),
+        authors: field("author").or(field("editor")).map(authors_summary),
+        year: field("year")
+            .or_else(|| field("date").and_then(|date| date.get(..4)))
+            .map(str::to_string),
+        title: field("title").map(plain),
+        file: file.to_string(),
+        line: entry.line,
+    }
+}
+
+/// Citation keys of the bibliographies a project's document uses, for
+/// completing \cite{. `project` is a project ID or folder; without any
+/// \bibliography or \addbibresource, every .bib file in it is read.
+#[tauri::command]
+pub async fn get_citation_keys(project: String) -> Result<CitationKeys, String> {
+    let dir = if Path::new(&project).is_dir() {
+        PathBuf::from(&project)
+    } else {
+        project::project_dir(&project)?
+    };
+    let root_file = project::read_manifest(&dir)
+        .await
+        .map(|manifest| manifest.root_file)
+        .unwrap_or_else(|| "main.tex".to_string());
+
+    let root = dir.join(&root_file);
+    let document = tokio::task::spawn_blocking(move || includes::flatten(&root))
+        .await
+        .unwrap_or_default();
+    let mut files = referenced_bibliographies(&document);
+    if files.is_empty() {
+        files = project::walk(&dir)
+            .await?
+            .into_iter()
+            .filter(|(entry, _)| !entry.is_dir && entry.path.ends_with(".bib"))
+            .map(|(entry, _)| entry.path)
+            .collect();
+    }
+
+    // Paths in the document are relative to the root file's folder
+    let root_dir = Path::new(&root_file)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let mut keys: Vec<CitationKey> = Vec::new();
+    let mut missing = Vec::new();
+    for file in files {
+        let relative = root_dir.join(&file).to_string_lossy().replace('\\', "/");
+        let path = match project::resolve_in(&dir, &relative) {
+            Ok(path) if path.is_file() => path,
+            _ => {
+                missing.push(file);
+                continue;
+            }
+        };
+        let content = read(&path).await?;
+        for entry in parse(&content).entries {
+            // BibTeX uses the first of duplicate keys
+            if !keys.iter().any(|k| k.key == entry.key) {
+                keys.push(citation_key(&entry, &relative));
+            }
+        }
+    }
+
+    Ok(CitationKeys { keys, missing })
+}
+
+/// A DOI, as written bare, with "doi:" or as a doi.org link
+fn parse_doi(identifier: &str) -> Option<String> {
+    let re = Regex::new(r"^(?i:https?://(?:dx\.)?doi\.org/|doi:\s*)?(10\.\d{4,9}/\S+)$").unwrap();
+    re.captures(identifier).map(|cap| cap[1].to_string())
+}
+
+/// An arXiv ID, new ("2101.00001v2") or old style ("math.GT/0309136"), as
+/// written bare, with "arXiv:" or as an abs/pdf link. Old-style subject
+/// classes are dropped, as the arXiv API doesn't take them.
+fn parse_arxiv_id(identifier: &str) -> Option<String> {
+    let re = Regex::new(
+        r"^(?i:arxiv:\s*|https?://(?:www\.)?arxiv\.org/(?:abs|pdf)/)?(\d{4}\.\d{4,5}(?:v\d+)?|[a-z-]+(?:\.[A-Z]{2})?/\d{7}(?:v\d+)?)(?:\.pdf)?$",
+    )
+    .unwrap();
+    re.captures(identifier).map(|cap| {
+        Regex::new(r"\.[A-Z]{2}/")
+            .unwrap()
+            .replace(&cap[1], "/")
+            .to_string()
+    })
+}
+
+async fn curl(args: &[&str]) -> Result<String, String> {
+    let output = processes::output(
+        proxy::command("curl")
+            .args(["-sSfL", "--globoff", "--max-time", "30"])
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .await
+    .map_err(|e| format!("Failed to run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Lookup failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn xml_text(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "\\&").replace('%', "\\%")
+}
+
+/// BibTeX for an entry of the arXiv API's Atom feed
+fn arxiv_bibtex(feed: &str, id: &str) -> Option<String> {
+    let entry = Regex::new(r"(?s)<entry>(.*?)</entry>")
+        .unwrap()
+        .captures(feed)?
+        .get(1)?
+        .as_str();
+    let first = |pattern: &str| {
+        Regex::new(pattern)
+            .unwrap()
+            .captures(entry)
+            .map(|cap| xml_text(&cap[1]))
+    };
+    let title = first(r"(?s)<title>(.*?)</title>")?;
+    // Unknown IDs come back as an entry titled "Error"
+    if title == "Error" {
+        return None;
+    }
+    let authors: Vec<String> = Regex::new(r"(?s)<author>\s*<name>(.*?)</name>")
+        .unwrap()
+        .captures_iter(entry)
+        .map(|cap| xml_text(&cap[1]))
+        .collect();
+    let year = first(r"<published>(\d{4})")?;
+    let category = first(r#"<arxiv:primary_category[^>]*term="([^"]+)""#);
+    let doi = first(r"(?s)<arxiv:doi[^>]*>(.*?)</arxiv:doi>");
+    let eprint = Regex::new(r"v\d+$").unwrap().replace(id, "").to_string();
+
+    // Google Scholar style: last name, year, first word of the title
+    let alphanumeric = |text: &str| -> String {
+        text.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase()
+    };
+    let last_name = authors
+        .first()
+        .and_then(|name| name.rsplit(' ').next())
+        .map(alphanumeric)
+        .unwrap_or_default();
+    let first_word = title
+        .split(' ')
+        .map(alphanumeric)
+        .find(|word| !word.is_empty())
+        .unwrap_or_default();
+    let key = format!("{}{}{}", last_name, year, first_word);
+
+    let mut fields = vec![
+        ("title", format!("{{{}}}", escape(&title))),
+        ("author", escape(&authors.join(" and "))),
+        ("year", year),
+        ("eprint", eprint.clone()),
+        ("archivePrefix", "arXiv".to_string()),
+    ];
+    if let Some(category) = category {
+        fields.push(("primaryClass", category));
+    }
+    if let Some(doi) = doi {
+        fields.push(("doi", doi));
+    }
+    fields.push(("url", format!("https://arxiv.org/abs/{}", eprint)));
+
+    let body: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("  {} = {{{}}}", name, value))
+        .collect();
+    Some(format!("@misc{{{},\n{}\n}}", key, body.join(",\n")))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FetchedEntry {
+    key: String,
+    bibtex: String,
+}
+
+#[tauri::command]
+pub async fn get_online_lookups() -> bool {
+    settings::load().await.online_lookups
+}
+
+/// Allow or forbid commands that look things up on the internet
+#[tauri::command]
+pub async fn set_online_lookups(enabled: bool) -> Result<(), String> {
+    let mut app_settings = settings::load().await;
+    app_settings.online_lookups = enabled;
+    settings::save(&app_settings).await
+}
+
+/// Look up the BibTeX entry of a DOI (through doi.org content negotiation)
+/// or an arXiv ID (through the arXiv API), and append it to `append_to`,
+/// a .bib file, if given. Needs online lookups to be allowed.
+#[tauri::command]
+pub async fn fetch_bibtex(
+    identifier: String,
+    append_to: Option<String>,
+) -> Result<FetchedEntry, String> {
+    if !settings::load().await.online_lookups {
+        return Err("Online lookups are turned off in the settings".to_string());
+    }
+    let identifier = identifier.trim();
+
+    let bibtex = if let Some(doi) = parse_doi(identifier) {
+        curl(&[
+            "-H",
+            "Accept: application/x-bibtex; charset=utf-8",
+            &format!("https://doi.org/{}", doi),
+        ])
+        .await?
+        .trim()
+        .to_string()
+    } else if let Some(id) = parse_arxiv_id(identifier) {
+        let feed = curl(&[&format!(
+            "https://export.arxiv.org/api/query?id_list={}",
+            id
+        )])
+        .await?;
+        arxiv_bibtex(&feed, &id).ok_or_else(|| format!("arXiv has no paper {}", id))?
+    } else {
+        return Err(format!("Not a DOI or arXiv ID: {}", identifier));
+    };
+
+    let key = parse(&bibtex)
+        .entries
+        .first()
+        .map(|entry| entry.key.clone())
+        .ok_or_else(|| format!("No BibTeX entry found for {}", identifier))?;
+
+    if let Some(file) = append_to {
+        let path = Path::new(&file);
+        let data = if path.exists() {
+            tokio::fs::read(path)
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", file, e))?
+        } else {
+            Vec::new()
+        };
+        let existing = encoding::decode(&data).0;
+        if parse(&existing)
+            .entries
+            .iter()
+            .any(|entry| entry.key == key)
+        {
+            return Err(format!("{} already has an entry {}", file, key));
+        }
+        let mut content = existing;
+        if !content.is_empty() && !content.ends_with("\n\n") {
+            content.push_str(if content.ends_with('\n') {
+                "\n"
+            } else {
+                "\n\n"
+            });
+        }
+        content.push_str(&bibtex);
+        content.push('\n');
+        // Saved in the encoding the file already has
+        let data = encoding::encode_like(&content, &data)
+            .map_err(|e| format!("Failed to write {}: {}", file, e))?;
+        save::write_atomic(path, &data).await?;
+    }
+
+    Ok(FetchedEntry { key, bibtex })
+}
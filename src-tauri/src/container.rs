@@ -0,0 +1,184 @@
+//! Optional Docker/Podman backend: runs compile commands inside a pinned
+//! TeX Live image with the build directory mounted
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::limits::ResourceLimits;
+
+/// TeX Live image used unless a project picks its own
+pub const DEFAULT_IMAGE: &str = "texlive/texlive:TL2024-historic";
+
+/// Where the build directory is mounted inside the container
+const WORKDIR: &str = "/work";
+
+/// Where host folders from search paths such as TEXINPUTS are mounted
+const INPUTS_DIR: &str = "/inputs";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerSettings {
+    /// "docker" or "podman"
+    pub runtime: String,
+    pub image: String,
+}
+
+impl ContainerSettings {
+    fn is_podman(&self) -> bool {
+        Path::new(&self.runtime)
+            .file_stem()
+            .is_some_and(|stem| stem == "podman")
+    }
+}
+
+impl Default for ContainerSettings {
+    fn default() -> Self {
+        ContainerSettings {
+            runtime: "docker".to_string(),
+            image: DEFAULT_IMAGE.to_string(),
+        }
+    }
+}
+
+/// `-v` argument for a bind mount. SELinux hosts, which podman setups
+/// usually are, only let a container use mounts relabelled for it.
+fn volume(settings: &ContainerSettings, source: &str, target: &str, read_only: bool) -> String {
+    let selinux = cfg!(target_os = "linux") && Path::new("/sys/fs/selinux/enforce").exists();
+    let relabel = settings.is_podman() || selinux;
+    // Folders shared with other containers get the shared label
+    let options = match (read_only, relabel) {
+        (false, false) => "",
+        (false, true) => ":Z",
+        (true, false) => ":ro",
+        (true, true) => ":ro,z",
+    };
+    format!("{}:{}{}", source, target, options)
+}
+
+/// Turn a command that runs in the build directory into one that runs the
+/// same program in a container named `name`, with `limits` enforced by the
+/// runtime. Host paths into the build directory are rewritten to the mount
+/// point; other folders in search paths are mounted read-only.
+pub fn wrap(
+    command: &Command,
+    settings: &ContainerSettings,
+    name: &str,
+    limits: Option<ResourceLimits>,
+) -> Command {
+    let inner = command.as_std();
+    let build_dir = inner
+        .get_current_dir()
+        .map(|dir| dir.to_string_lossy().to_string());
+    let map_arg = |arg: &str| match &build_dir {
+        Some(dir) if arg.starts_with(dir.as_str()) => {
+            format!("{}{}", WORKDIR, arg[dir.len()..].replace('\\', "/"))
+        }
+        _ => arg.to_string(),
+    };
+
+    // Search paths hold host folders; each becomes a container path, and
+    // folders outside the build directory get a mount of their own
+    let separator = if cfg!(windows) { ';' } else { ':' };
+    let mut input_mounts: Vec<String> = Vec::new();
+    let mut env_args = Vec::new();
+    for (key, value) in inner.get_envs() {
+        let Some(value) = value else { continue };
+        let key = key.to_string_lossy();
+        let mut value = value.to_string_lossy().to_string();
+        if key.ends_with("INPUTS") {
+            let entries: Vec<String> = value
+                .split(separator)
+                .map(|entry| {
+                    // A trailing // asks kpathsea to search subfolders too
+                    let (dir, recursive) = match entry.strip_suffix("//") {
+                        Some(dir) => (dir, "//"),
+                        None => (entry, ""),
+                    };
+                    let mapped = map_arg(dir);
+                    if mapped != dir || !Path::new(dir).is_absolute() {
+                        return format!("{}{}", mapped, recursive);
+                    }
+                    let index = match input_mounts.iter().position(|mount| mount == dir) {
+                        Some(index) => index,
+                        None => {
+                            input_mounts.push(dir.to_string());
+                            input_mounts.len() - 1
+                        }
+                    };
+                    format!("{}/{}{}", INPUTS_DIR, index, recursive)
+                })
+                .collect();
+            value = entries.join(":");
+        }
+        env_args.push(format!("{}={}", key, value));
+    }
+
+    let mut wrapped = Command::new(&settings.runtime);
+    wrapped.args(["run", "--rm", "-i", "--network", "none", "--name", name]);
+
+    // Run as the user so the build files stay theirs; rootless podman maps
+    // that id to root unless told to keep it. TeX wants a writable home
+    // for its caches, and the image has none for this user.
+    #[cfg(unix)]
+    {
+        // SAFETY: getuid and getgid cannot fail
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        if settings.is_podman() {
+            wrapped.arg("--userns=keep-id");
+        }
+        wrapped
+            .arg("--user")
+            .arg(format!("{}:{}", uid, gid))
+            .args(["-e", "HOME=/tmp"]);
+    }
+
+    if let Some(limits) = limits {
+        if limits.cpu_secs > 0 {
+            wrapped
+                .arg("--ulimit")
+                .arg(format!("cpu={}:{}", limits.cpu_secs, limits.cpu_secs + 1));
+        }
+        if limits.memory_mb > 0 {
+            wrapped
+                .arg("--memory")
+                .arg(format!("{}m", limits.memory_mb));
+        }
+        if limits.output_mb > 0 {
+            wrapped
+                .arg("--ulimit")
+                .arg(format!("fsize={}", limits.output_mb * 1024 * 1024));
+        }
+    }
+    if let Some(dir) = &build_dir {
+        wrapped
+            .arg("-v")
+            .arg(volume(settings, dir, WORKDIR, false))
+            .args(["-w", WORKDIR]);
+    }
+    for (index, dir) in input_mounts.iter().enumerate() {
+        let target = format!("{}/{}", INPUTS_DIR, index);
+        wrapped.arg("-v").arg(volume(settings, dir, &target, true));
+    }
+    for arg in env_args {
+        wrapped.arg("-e").arg(arg);
+    }
+    wrapped
+        .arg(&settings.image)
+        .arg(inner.get_program())
+        .args(inner.get_args().map(|arg| map_arg(&arg.to_string_lossy())));
+    if let Some(dir) = inner.get_current_dir() {
+        wrapped.current_dir(dir);
+    }
+    wrapped
+}
+
+/// Stop a container; killing the runtime's client process leaves it running
+pub fn kill(settings: &ContainerSettings, name: &str) {
+    let _ = std::process::Command::new(&settings.runtime)
+        .args(["kill", name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
@@ -0,0 +1,107 @@
+//! Text encodings of legacy sources, e.g. Korean files saved in EUC-KR/CP949
+//! or Western ones in Latin-1, which are converted to UTF-8 on load
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::fs;
+
+use crate::save;
+
+/// Extensions of files that are text even when they aren't valid UTF-8
+pub const TEXT_EXTENSIONS: &[&str] = &["tex", "bib", "sty", "cls", "bst", "bbx", "cbx", "txt"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncodingConversion {
+    /// Encoding the file was read as, e.g. "EUC-KR" (which covers CP949)
+    encoding: String,
+    /// Whether the file was rewritten
+    converted: bool,
+}
+
+/// Guess the encoding of some text. UTF-8 is taken as is; anything else is
+/// left to the detector, which knows the legacy Korean and Western encodings.
+pub fn detect(data: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(data) {
+        return encoding;
+    }
+    if std::str::from_utf8(data).is_ok() {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(data, true);
+    detector.guess(None, false)
+}
+
+/// Text of a file in whatever encoding it was saved in, and that encoding
+pub fn decode(data: &[u8]) -> (String, &'static Encoding) {
+    let encoding = detect(data);
+    let (text, encoding, _) = encoding.decode(data);
+    (text.into_owned(), encoding)
+}
+
+/// `text` in the encoding `original` was saved in, byte order mark included.
+/// Fails if that encoding can't represent the text.
+pub fn encode_like(text: &str, original: &[u8]) -> Result<Vec<u8>, String> {
+    let (encoding, bom) = match Encoding::for_bom(original) {
+        Some((encoding, bom_length)) => (encoding, &original[..bom_length]),
+        None => (detect(original), &original[..0]),
+    };
+    // encoding_rs only decodes UTF-16
+    if encoding.output_encoding() != encoding {
+        return Err(format!("Can't write {} text", encoding.name()));
+    }
+    let (data, _, unmappable) = encoding.encode(text);
+    if unmappable {
+        return Err(format!(
+            "The text has characters {} can't hold; convert the file to UTF-8 first",
+            encoding.name()
+        ));
+    }
+    Ok([bom, &data].concat())
+}
+
+/// Declare UTF-8 wherever the source names its input encoding, e.g.
+/// `\usepackage[latin1]{inputenc}`, which would otherwise misread the
+/// converted file
+fn declare_utf8(text: &str) -> String {
+    let package = Regex::new(r"\\usepackage\s*\[[^\]]*\]\s*\{inputenc\}").unwrap();
+    let switch = Regex::new(r"\\inputencoding\s*\{[^}]*\}").unwrap();
+    let text = package.replace_all(text, r"\usepackage[utf8]{inputenc}");
+    switch
+        .replace_all(&text, r"\inputencoding{utf8}")
+        .into_owned()
+}
+
+/// Rewrite a file as UTF-8. The encoding is detected unless given as a
+/// label such as "euc-kr", "cp949" or "latin1".
+#[tauri::command]
+pub async fn convert_file_encoding(
+    path: String,
+    from: Option<String>,
+) -> Result<EncodingConversion, String> {
+    let data = fs::read(&path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let encoding = match from {
+        Some(label) => Encoding::for_label(label.trim().as_bytes())
+            .ok_or_else(|| format!("Unknown encoding: {}", label))?,
+        None => detect(&data),
+    };
+
+    let (text, encoding, malformed) = encoding.decode(&data);
+    if malformed {
+        return Err(format!("{} is not valid {}", path, encoding.name()));
+    }
+    let text = declare_utf8(&text);
+    let converted = encoding != UTF_8 || text.as_bytes() != data.as_slice();
+    if converted {
+        save::write_atomic(Path::new(&path), text.as_bytes()).await?;
+    }
+    Ok(EncodingConversion {
+        encoding: encoding.name().to_string(),
+        converted,
+    })
+}
@@ -0,0 +1,713 @@
+//! Exports: per-page SVG and HTML for the web, PDF/A for archiving,
+//! size-optimized and cropped PDFs, extracts of some pages and pages as
+//! images
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::AppHandle;
+use tempfile::TempDir;
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::{
+    jobs, output, pdf, processes, project, queue_key, run_compile, tex_path, write_sources,
+    CompilationError, CompilationWarning, CompileRequest, Engine,
+};
+
+/// Compile the document through DVI/XDV and return one SVG per page
+#[tauri::command]
+pub async fn export_svg(
+    app: AppHandle,
+    mut request: CompileRequest,
+) -> Result<Vec<String>, String> {
+    request.output_format = Some("svg".to_string());
+    // Goes through the compile queue like any other job, but waits for it
+    let job_id = jobs::enqueue(request.project_id.as_deref(), queue_key(&request));
+    let (runnable, _queue) = jobs::start(&job_id).await;
+    let outcome = if runnable {
+        run_compile(&app, &job_id, request).await
+    } else {
+        Err(format!("Compilation {} was cancelled", job_id))
+    };
+    jobs::finish(&job_id, outcome.clone());
+
+    let result = outcome?;
+    match result.svg_pages {
+        Some(pages) if result.success => Ok(pages),
+        _ => Err(result
+            .errors
+            .first()
+            .map(|e| e.message.clone())
+            .unwrap_or_else(|| "SVG export failed".to_string())),
+    }
+}
+
+/// make4ht runs LaTeX several times over, so allow it a while
+const HTML_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HtmlExport {
+    success: bool,
+    /// Generated HTML, CSS and images, relative to the output folder
+    files: Vec<String>,
+    log: String,
+    errors: Vec<CompilationError>,
+    warnings: Vec<CompilationWarning>,
+}
+
+/// Parse make4ht's "[ERROR]" and "[WARNING]" lines
+fn parse_make4ht_log(log: &str) -> (Vec<CompilationError>, Vec<CompilationWarning>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    // e.g. "[ERROR]   htlatex: ./main.tex       5      Undefined control sequence."
+    let re = Regex::new(r"^\[(ERROR|WARNING)\]\s+[\w-]+:\s*(.*)$").unwrap();
+    let location_re = Regex::new(r"^(\S+\.tex)\s+(\d+)\s+(.*)$").unwrap();
+
+    for line in log.lines() {
+        let Some(cap) = re.captures(line.trim_end()) else {
+            continue;
+        };
+        let text = cap[2].trim();
+        let (file, line_num, message) = match location_re.captures(text) {
+            Some(loc) => (
+                Some(loc[1].trim_start_matches("./").to_string()),
+                loc[2].parse().unwrap_or(0),
+                loc[3].trim().to_string(),
+            ),
+            None => (None, 0, text.to_string()),
+        };
+
+        if &cap[1] == "ERROR" {
+            errors.push(CompilationError {
+                line: line_num,
+                message,
+                file,
+                context: None,
+                missing_font: None,
+                limit_exceeded: None,
+            });
+        } else {
+            warnings.push(CompilationWarning {
+                line: line_num,
+                message,
+                file,
+                bad_box: None,
+            });
+        }
+    }
+
+    (errors, warnings)
+}
+
+/// Convert the project to HTML with make4ht and write the generated bundle
+/// into `output_dir`
+#[tauri::command]
+pub async fn export_html(
+    project: CompileRequest,
+    output_dir: String,
+) -> Result<HtmlExport, String> {
+    let output_dir = PathBuf::from(output_dir);
+    if output::contains(&output_dir) {
+        return Err("Can't save into OffLeaf's output folder".to_string());
+    }
+    let engine_name = project.engine.as_deref().unwrap_or("xelatex");
+    let engine_flag = match Engine::from_name(engine_name) {
+        Some(Engine::Pdflatex) => None,
+        Some(Engine::Xelatex) => Some("-x"),
+        Some(Engine::Lualatex) => Some("-l"),
+        _ => return Err(format!("HTML export does not support {}", engine_name)),
+    };
+    let root_file = project
+        .root_file
+        .clone()
+        .unwrap_or_else(|| "main.tex".to_string());
+
+    let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let build_path = temp_dir.path();
+    write_sources(
+        build_path,
+        &project.content,
+        &project.files,
+        &project.binary_files,
+    )
+    .await?;
+
+    let mut command = tex_path::command("make4ht");
+    command.args(["-u", "-d", "html"]);
+    if let Some(flag) = engine_flag {
+        command.arg(flag);
+    }
+    command.arg(&root_file).current_dir(build_path);
+
+    // A job of its own, so it can be listed, cancelled and killed on exit,
+    // but without queueing behind compiles
+    let job_id = jobs::enqueue(project.project_id.as_deref(), None);
+    if let Some(limits) = project.limits {
+        jobs::set_limits(&job_id, limits);
+    }
+    jobs::start_unqueued(&job_id);
+    let result = tokio::time::timeout(HTML_TIMEOUT, jobs::run_command(&job_id, &mut command)).await;
+    if result.is_err() {
+        jobs::kill_running(&job_id);
+    }
+    jobs::forget(&job_id);
+    let output = result
+        .map_err(|_| "HTML export timed out".to_string())?
+        .map_err(|e| format!("Failed to run make4ht: {}. Is TeX Live installed?", e))?;
+
+    let log = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let (errors, warnings) = parse_make4ht_log(&log);
+
+    let mut files = Vec::new();
+    let html_dir = build_path.join("html");
+    if html_dir.is_dir() {
+        for (entry, path) in project::walk(&html_dir).await? {
+            let target = output_dir.join(&entry.path);
+            if entry.is_dir {
+                fs::create_dir_all(&target)
+                    .await
+                    .map_err(|e| format!("Failed to create folder: {}", e))?;
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| format!("Failed to create folder: {}", e))?;
+            }
+            fs::copy(&path, &target)
+                .await
+                .map_err(|e| format!("Failed to write HTML output: {}", e))?;
+            files.push(entry.path);
+        }
+    }
+
+    Ok(HtmlExport {
+        success: output.status.success()
+            && errors.is_empty()
+            && files.iter().any(|name| name.ends_with(".html")),
+        files,
+        log,
+        errors,
+        warnings,
+    })
+}
+
+/// Where an export of `source` is written: `output` when given, otherwise
+/// `default_name` next to the source. Compiled PDFs live in the managed
+/// output folder, which is cleared at every start, so exports of them need
+/// an explicit `output` and none may go into that folder.
+fn export_target(
+    source: &Path,
+    output: Option<String>,
+    default_name: &str,
+) -> Result<PathBuf, String> {
+    let target = match output.filter(|output| !output.trim().is_empty()) {
+        Some(output) => PathBuf::from(output),
+        None if output::contains(source) => {
+            return Err("Choose where to save the export".to_string())
+        }
+        None => source.with_file_name(default_name),
+    };
+    if output::contains(&target) {
+        return Err(format!(
+            "Can't save into OffLeaf's output folder: {}",
+            target.display()
+        ));
+    }
+    if target == source {
+        return Err("The export can't replace the original PDF".to_string());
+    }
+    Ok(target)
+}
+
+/// Ghostscript's command-line executable
+const GHOSTSCRIPT: &str = if cfg!(windows) { "gswin64c" } else { "gs" };
+
+/// Gives the PDF/A output intent Ghostscript requires. ICC_PROFILE is
+/// replaced with the path of the sRGB profile.
+const PDFA_DEF: &str = r#"%!
+/ICCProfile (ICC_PROFILE) def
+[/_objdef {icc_PDFA} /type /stream /OBJ pdfmark
+[{icc_PDFA} << /N 3 >> /PUT pdfmark
+[{icc_PDFA} ICCProfile (r) file /PUT pdfmark
+[/_objdef {OutputIntent_PDFA} /type /dict /OBJ pdfmark
+[{OutputIntent_PDFA} <<
+  /Type /OutputIntent
+  /S /GTS_PDFA1
+  /DestOutputProfile {icc_PDFA}
+  /OutputConditionIdentifier (sRGB)
+>> /PUT pdfmark
+[{Catalog} << /OutputIntents [ {OutputIntent_PDFA} ] >> /PUT pdfmark
+"#;
+
+/// The sRGB profile Ghostscript ships with. Its folder sits next to the
+/// Resource and lib folders in the search path `gs -h` prints; builds that
+/// keep their files in ROM have it there instead.
+async fn srgb_profile() -> PathBuf {
+    let mut command = Command::new(GHOSTSCRIPT);
+    command.arg("-h");
+    if let Ok(output) = processes::output(&mut command).await {
+        let help = String::from_utf8_lossy(&output.stdout).to_string();
+        let search_path = help
+            .split_once("Search path:")
+            .map(|(_, rest)| rest)
+            .unwrap_or("");
+        let separator = Regex::new(r"\s[:;]\s|\n").unwrap();
+        let dirs = search_path
+            .lines()
+            .take_while(|line| line.is_empty() || line.starts_with(char::is_whitespace))
+            .flat_map(|line| separator.split(line).collect::<Vec<_>>())
+            .map(|dir| dir.trim().trim_end_matches([':', ';']).trim())
+            .filter(|dir| !dir.is_empty() && !dir.starts_with('%'));
+        for dir in dirs {
+            for ancestor in Path::new(dir).ancestors().take(3) {
+                let profile = ancestor.join("iccprofiles").join("srgb.icc");
+                if profile.is_file() {
+                    return profile;
+                }
+            }
+        }
+    }
+    // Debian and Ubuntu move the profiles out of Ghostscript's folder
+    let debian = PathBuf::from("/usr/share/color/icc/ghostscript/srgb.icc");
+    if debian.is_file() {
+        return debian;
+    }
+    PathBuf::from("%rom%iccprofiles/srgb.icc")
+}
+
+/// A path as a PostScript string literal's contents
+fn postscript_string(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace('\\', "/")
+        .replace('(', "\\(")
+        .replace(')', "\\)")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfaExport {
+    success: bool,
+    /// The converted copy
+    output_path: Option<String>,
+    /// What Ghostscript had to change or couldn't make conform, e.g.
+    /// transparency or annotations it dropped
+    problems: Vec<String>,
+    log: String,
+}
+
+/// Lines of Ghostscript's output that point at a PDF/A problem
+fn pdfa_problems(log: &str) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    for line in log.lines() {
+        let line = line.trim().trim_start_matches("****").trim();
+        let relevant = line.contains("PDF/A")
+            || line.starts_with("Error")
+            || line.starts_with("WARNING")
+            || line.contains("not permitted");
+        if relevant && !problems.iter().any(|p| p == line) {
+            problems.push(line.to_string());
+        }
+    }
+    problems
+}
+
+/// Convert a compiled PDF to PDF/A-1b or PDF/A-2b with Ghostscript, as
+/// many universities require for thesis submission. The copy is written to
+/// `output`, by default `<name>-pdfa.pdf` next to the original.
+#[tauri::command]
+pub async fn export_pdfa(
+    pdf_path: String,
+    level: String,
+    output: Option<String>,
+) -> Result<PdfaExport, String> {
+    let source = PathBuf::from(&pdf_path);
+    if !source.is_file() {
+        return Err(format!("PDF not found: {}", pdf_path));
+    }
+    let (part, compatibility) = match level.to_lowercase().trim_start_matches("pdf/a-") {
+        "1b" | "1" => (1, "1.4"),
+        "2b" | "2" => (2, "1.7"),
+        _ => return Err(format!("Unsupported PDF/A level: {}", level)),
+    };
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+    let target = export_target(&source, output, &format!("{}-pdfa.pdf", stem))?;
+
+    let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let def_path = temp_dir.path().join("PDFA_def.ps");
+    let profile = srgb_profile().await;
+    let definition = PDFA_DEF.replace("ICC_PROFILE", &postscript_string(&profile));
+    fs::write(&def_path, definition)
+        .await
+        .map_err(|e| format!("Failed to write PDF/A definition: {}", e))?;
+
+    let mut command = Command::new(GHOSTSCRIPT);
+    command
+        .arg(format!("-dPDFA={}", part))
+        .args([
+            "-dBATCH",
+            "-dNOPAUSE",
+            "-dNOOUTERSAVE",
+            "-sDEVICE=pdfwrite",
+            "-sColorConversionStrategy=RGB",
+            // Drop what PDF/A forbids instead of giving up, and say so
+            "-dPDFACompatibilityPolicy=1",
+        ])
+        .arg(format!("-dCompatibilityLevel={}", compatibility))
+        .arg(format!("--permit-file-read={}", temp_dir.path().display()))
+        .arg(format!("--permit-file-read={}", profile.display()))
+        .arg(format!("-sOutputFile={}", target.display()))
+        .arg(&def_path)
+        .arg(&source);
+    let output = processes::output(&mut command)
+        .await
+        .map_err(|e| format!("Failed to run Ghostscript: {}. Is it installed?", e))?;
+
+    let log = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let success = output.status.success() && target.is_file();
+    Ok(PdfaExport {
+        success,
+        output_path: success.then(|| target.to_string_lossy().to_string()),
+        problems: pdfa_problems(&log),
+        log,
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OptimizedPdf {
+    /// The optimized copy
+    output_path: String,
+    /// In bytes
+    original_size: u64,
+    optimized_size: u64,
+    /// "ghostscript", or "qpdf" when only streams could be recompressed
+    tool: String,
+}
+
+/// Shrink a PDF to meet journal or email size limits, written to `output`,
+/// by default `<name>-optimized.pdf` next to the original. Ghostscript downsamples
+/// images to the quality ("screen", "ebook", "printer" or "prepress");
+/// without it qpdf recompresses the streams, leaving images as they are.
+#[tauri::command]
+pub async fn optimize_pdf(
+    path: String,
+    quality: String,
+    output: Option<String>,
+) -> Result<OptimizedPdf, String> {
+    let source = PathBuf::from(&path);
+    let original_size = fs::metadata(&source)
+        .await
+        .map_err(|e| format!("PDF not found: {}: {}", path, e))?
+        .len();
+    let quality = quality.to_lowercase();
+    if !["screen", "ebook", "printer", "prepress"].contains(&quality.as_str()) {
+        return Err(format!("Unknown quality: {}", quality));
+    }
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+    let target = export_target(&source, output, &format!("{}-optimized.pdf", stem))?;
+
+    let mut ghostscript = Command::new(GHOSTSCRIPT);
+    ghostscript
+        .args([
+            "-dBATCH",
+            "-dNOPAUSE",
+            "-dQUIET",
+            "-sDEVICE=pdfwrite",
+            "-dCompatibilityLevel=1.5",
+            "-dDetectDuplicateImages=true",
+        ])
+        .arg(format!("-dPDFSETTINGS=/{}", quality))
+        .arg(format!("-sOutputFile={}", target.display()))
+        .arg(&source);
+    let mut qpdf = Command::new("qpdf");
+    qpdf.args([
+        "--recompress-flate",
+        "--compression-level=9",
+        "--object-streams=generate",
+    ])
+    .arg(&source)
+    .arg(&target);
+
+    let mut failures = Vec::new();
+    for (tool, mut command) in [("ghostscript", ghostscript), ("qpdf", qpdf)] {
+        match processes::output(&mut command).await {
+            Ok(output) if output.status.success() && target.is_file() => {
+                let optimized_size = fs::metadata(&target)
+                    .await
+                    .map_err(|e| format!("Failed to read optimized PDF: {}", e))?
+                    .len();
+                return Ok(OptimizedPdf {
+                    output_path: target.to_string_lossy().to_string(),
+                    original_size,
+                    optimized_size,
+                    tool: tool.to_string(),
+                });
+            }
+            Ok(output) => failures.push(format!(
+                "{}: {}",
+                tool,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) => failures.push(format!("{}: {}", tool, e)),
+        }
+    }
+    Err(format!(
+        "Optimizing the PDF needs Ghostscript or qpdf ({})",
+        failures.join("; ")
+    ))
+}
+
+/// Page numbers in ranges like "1-3, 7, 10-" (to the end), checked
+/// against the page count
+fn parse_page_ranges(ranges: &str, page_count: u32) -> Result<BTreeSet<u32>, String> {
+    let invalid = || format!("Invalid page range: {}", ranges);
+    let page = |text: &str| -> Result<u32, String> {
+        let number: u32 = text.trim().parse().map_err(|_| invalid())?;
+        if number == 0 || number > page_count {
+            return Err(format!(
+                "Page {} is out of range (1-{})",
+                number, page_count
+            ));
+        }
+        Ok(number)
+    };
+
+    let mut pages = BTreeSet::new();
+    for part in ranges.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (first, last) = match part.split_once('-') {
+            Some((first, "")) => (page(first)?, page_count),
+            Some((first, last)) => (page(first)?, page(last)?),
+            None => (page(part)?, page(part)?),
+        };
+        if first > last {
+            return Err(invalid());
+        }
+        pages.extend(first..=last);
+    }
+    if pages.is_empty() {
+        return Err(invalid());
+    }
+    Ok(pages)
+}
+
+/// Write the given pages of a PDF (e.g. "1" for the title page or "23-41"
+/// for a chapter) to a new PDF at `output`, keeping their original order.
+/// Returns the number of pages written.
+#[tauri::command]
+pub async fn export_pdf_pages(path: String, ranges: String, output: String) -> Result<u32, String> {
+    let data = fs::read(&path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let source = Path::new(&path);
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "document".to_string());
+    let output = export_target(source, Some(output), &format!("{}-extract.pdf", stem))?;
+
+    let extract = tokio::task::spawn_blocking(move || {
+        let mut document =
+            lopdf::Document::load_mem(&data).map_err(|e| format!("Failed to open PDF: {}", e))?;
+        let page_count = document.get_pages().len() as u32;
+        let keep = parse_page_ranges(&ranges, page_count)?;
+        let dropped: Vec<u32> = (1..=page_count).filter(|p| !keep.contains(p)).collect();
+        document.delete_pages(&dropped);
+        document.prune_objects();
+
+        let mut extract = Vec::new();
+        document
+            .save_to(&mut extract)
+            .map_err(|e| format!("Failed to save PDF: {}", e))?;
+        Ok::<_, String>((extract, keep.len() as u32))
+    })
+    .await
+    .map_err(|e| format!("Failed to extract pages: {}", e))?;
+    let (extract, pages) = extract?;
+
+    fs::write(&output, extract)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    Ok(pages)
+}
+
+/// Resolutions pages may be exported at
+const MIN_DPI: u32 = 36;
+const MAX_DPI: u32 = 1200;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedImage {
+    /// 1-based
+    page: u32,
+    path: String,
+}
+
+/// Save pages of a PDF as PNG (rendered at `dpi`) or SVG (through
+/// pdftocairo) for slides, previews or reusing a figure. The images go to
+/// the `output` folder, by default `<name>-pages` next to the PDF; `pages`
+/// takes ranges like "1-3, 7" and defaults to every page.
+#[tauri::command]
+pub async fn export_pdf_as_images(
+    app: AppHandle,
+    path: String,
+    format: String,
+    dpi: u32,
+    pages: Option<String>,
+    output: Option<String>,
+) -> Result<Vec<ExportedImage>, String> {
+    let source = PathBuf::from(&path);
+    let format = format.to_lowercase();
+    if format != "png" && format != "svg" {
+        return Err(format!("Unsupported image format: {}", format));
+    }
+    if !(MIN_DPI..=MAX_DPI).contains(&dpi) {
+        return Err(format!("DPI must be between {} and {}", MIN_DPI, MAX_DPI));
+    }
+    let data = fs::read(&source)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+
+    let (page_count, data) = tokio::task::spawn_blocking(move || {
+        let document =
+            lopdf::Document::load_mem(&data).map_err(|e| format!("Failed to open PDF: {}", e))?;
+        Ok::<_, String>((document.get_pages().len() as u32, data))
+    })
+    .await
+    .map_err(|e| format!("Failed to open PDF: {}", e))??;
+    let selected = match pages.as_deref().map(str::trim) {
+        Some(ranges) if !ranges.is_empty() => parse_page_ranges(ranges, page_count)?,
+        _ => (1..=page_count).collect(),
+    };
+
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "document".to_string());
+    let folder = export_target(&source, output, &format!("{}-pages", stem))?;
+    fs::create_dir_all(&folder)
+        .await
+        .map_err(|e| format!("Failed to create folder: {}", e))?;
+    let image_path = |page: u32| folder.join(format!("{}-{}.{}", stem, page, format));
+
+    if format == "png" {
+        let targets: Vec<(u32, PathBuf)> = selected.iter().map(|&p| (p, image_path(p))).collect();
+        let written = targets.clone();
+        tokio::task::spawn_blocking(move || {
+            pdf::with_pdfium(&app, |pdfium| {
+                let document = pdfium
+                    .load_pdf_from_byte_slice(&data, None)
+                    .map_err(|e| format!("Failed to open PDF: {}", e))?;
+                let config = pdfium_render::prelude::PdfRenderConfig::new()
+                    .scale_page_by_factor(dpi as f32 / 72.0);
+                for (page, target) in &targets {
+                    document
+                        .pages()
+                        .get((page - 1) as u16)
+                        .map_err(|e| format!("Failed to load page {}: {}", page, e))?
+                        .render_with_config(&config)
+                        .map_err(|e| format!("Failed to render page {}: {}", page, e))?
+                        .as_image()
+                        .save(target)
+                        .map_err(|e| format!("Failed to save page {}: {}", page, e))?;
+                }
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e| format!("Failed to export images: {}", e))??;
+        return Ok(written
+            .into_iter()
+            .map(|(page, target)| ExportedImage {
+                page,
+                path: target.to_string_lossy().to_string(),
+            })
+            .collect());
+    }
+
+    // SVG keeps text and lines as vectors, which only pdftocairo does
+    let mut images = Vec::new();
+    for page in selected {
+        let target = image_path(page);
+        let mut command = Command::new("pdftocairo");
+        command
+            .arg("-svg")
+            .arg("-f")
+            .arg(page.to_string())
+            .arg("-l")
+            .arg(page.to_string())
+            .arg(&source)
+            .arg(&target);
+        let output = processes::output(&mut command)
+            .await
+            .map_err(|e| format!("Failed to run pdftocairo: {}. Is Poppler installed?", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "Failed to export page {}: {}",
+                page,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        images.push(ExportedImage {
+            page,
+            path: target.to_string_lossy().to_string(),
+        });
+    }
+    Ok(images)
+}
+
+/// Trim a PDF's pages to their content with pdfcrop, e.g. a figure made
+/// with the standalone class, leaving `margins` points around it: one
+/// number for every side, or four for left, top, right and bottom. The
+/// result is written to `output`, by default `<name>-crop.pdf` next to the
+/// original.
+#[tauri::command]
+pub async fn crop_pdf(
+    path: String,
+    margins: Option<Vec<f32>>,
+    output: Option<String>,
+) -> Result<String, String> {
+    let source = PathBuf::from(&path);
+    if !source.is_file() {
+        return Err(format!("PDF not found: {}", path));
+    }
+    let margins = margins.unwrap_or_default();
+    if !matches!(margins.len(), 0 | 1 | 4) || margins.iter().any(|m| !m.is_finite()) {
+        return Err("Margins must be one number or four".to_string());
+    }
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "figure".to_string());
+    let target = export_target(&source, output, &format!("{}-crop.pdf", stem))?;
+
+    let mut command = Command::new("pdfcrop");
+    if !margins.is_empty() {
+        let margins: Vec<String> = margins.iter().map(|m| m.to_string()).collect();
+        command.arg("--margins").arg(margins.join(" "));
+    }
+    command.arg(&source).arg(&target);
+    let output = processes::output(&mut command)
+        .await
+        .map_err(|e| format!("Failed to run pdfcrop: {}. Is TeX Live installed?", e))?;
+    if !output.status.success() || !target.is_file() {
+        let log = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("pdfcrop failed: {}", log.trim()));
+    }
+    Ok(target.to_string_lossy().to_string())
+}
@@ -0,0 +1,126 @@
+//! Handing compiled PDFs to the system's own viewer, for users who prefer
+//! SumatraPDF, Preview or Okular to the built-in preview
+
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+
+use crate::assets;
+
+/// Where PDFs are copied before opening, so the viewer keeps its file
+/// when the build folder is cleaned or the next compile replaces it
+fn opened_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("OffLeaf")
+        .join("opened")
+}
+
+/// Open a file with the default application for its type. Not waited on;
+/// the application outlives the launcher and the app.
+pub fn open_path(path: &Path) -> Result<(), String> {
+    #[cfg(windows)]
+    return shell_execute("open", path, None);
+
+    #[cfg(not(windows))]
+    {
+        let program = if cfg!(target_os = "macos") {
+            "open"
+        } else {
+            "xdg-open"
+        };
+        tokio::process::Command::new(program)
+            .arg(path)
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Run a shell verb ("open", "printto", ...) on a file through the shell
+/// API, which takes the path as data; `cmd /C start` would parse it as a
+/// command line
+#[cfg(windows)]
+pub fn shell_execute(verb: &str, path: &Path, parameters: Option<&str>) -> Result<(), String> {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::UI::Shell::ShellExecuteW;
+    use windows_sys::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    fn wide(s: &OsStr) -> Vec<u16> {
+        s.encode_wide().chain(std::iter::once(0)).collect()
+    }
+
+    let verb = wide(OsStr::new(verb));
+    let file = wide(path.as_os_str());
+    let parameters = parameters.map(|p| wide(OsStr::new(p)));
+    // SAFETY: nul-terminated strings that outlive the call
+    let result = unsafe {
+        ShellExecuteW(
+            std::ptr::null_mut(),
+            verb.as_ptr(),
+            file.as_ptr(),
+            parameters.as_ref().map_or(std::ptr::null(), |p| p.as_ptr()),
+            std::ptr::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+    // Values up to 32 are error codes
+    let code = result as isize;
+    if code <= 32 {
+        return Err(format!("the shell returned error {}", code));
+    }
+    Ok(())
+}
+
+/// Whether a file has the .pdf extension and starts like one
+async fn is_pdf(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+    let mut header = [0u8; 5];
+    let starts_like_pdf = match fs::File::open(path).await {
+        Ok(mut file) => file.read_exact(&mut header).await.is_ok() && &header == b"%PDF-",
+        Err(_) => false,
+    };
+    extension && starts_like_pdf
+}
+
+/// Copy a PDF out of the build folder and open it with the default viewer.
+/// Returns the path of the copy.
+#[tauri::command]
+pub async fn open_pdf_external(path: String) -> Result<String, String> {
+    let source = PathBuf::from(&path);
+    if !source.is_file() {
+        return Err(format!("PDF not found: {}", path));
+    }
+    // The viewer is whatever the system opens the file with, so anything
+    // but a PDF could be run instead of shown
+    if !is_pdf(&source).await {
+        return Err(format!("Not a PDF: {}", path));
+    }
+    let dir = opened_dir();
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Failed to create folder: {}", e))?;
+    let name = source
+        .file_name()
+        .ok_or_else(|| format!("Invalid path: {}", path))?;
+
+    // Overwrite the previous copy, unless a viewer that locks its file
+    // (e.g. Acrobat on Windows) still has it open
+    let mut target = dir.join(name);
+    if fs::copy(&source, &target).await.is_err() {
+        let stem = source
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "document".to_string());
+        target = assets::unique_path(&dir, &stem, "pdf");
+        fs::copy(&source, &target)
+            .await
+            .map_err(|e| format!("Failed to copy {}: {}", path, e))?;
+    }
+
+    open_path(&target).map_err(|e| format!("Failed to open the PDF viewer: {}", e))?;
+    Ok(target.to_string_lossy().to_string())
+}
@@ -0,0 +1,424 @@
+//! Missing font detection and suggestions
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::{tex_path, CompilationError};
+
+/// A font the engine could not find, with what to do about it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MissingFont {
+    font: String,
+    /// TeX Live package that provides the font, if there is one
+    package: Option<String>,
+    /// CJK fonts installed on the system that could be used instead
+    alternatives: Vec<String>,
+    suggestion: String,
+}
+
+/// TeX Live packages shipping fonts XeLaTeX/LuaLaTeX can load by name
+const FONT_PACKAGES: &[(&str, &str)] = &[
+    ("unbatang", "unfonts-core"),
+    ("undotum", "unfonts-core"),
+    ("ungraphic", "unfonts-extra"),
+    ("unpilgi", "unfonts-extra"),
+    ("baekmuk", "baekmuk"),
+    ("ipaex", "ipaex"),
+    ("ipa", "ipaex"),
+    ("haranoaji", "haranoaji"),
+    ("fandol", "fandol"),
+    ("texgyre", "tex-gyre"),
+    ("latinmodern", "lm"),
+    ("lmroman", "lm"),
+    ("libertinus", "libertinus-fonts"),
+    ("stixtwo", "stix2-otf"),
+    ("xits", "xits"),
+    ("firasans", "fira"),
+    ("firamono", "fira"),
+    ("sourceserif", "sourceserifpro"),
+    ("sourcesans", "sourcesanspro"),
+    ("sourcecode", "sourcecodepro"),
+    ("dejavu", "dejavu"),
+];
+
+fn font_package(font: &str) -> Option<&'static str> {
+    let name = font.to_lowercase().replace([' ', '-'], "");
+    FONT_PACKAGES
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, package)| *package)
+}
+
+/// Recognize "font not found" errors and extract the requested font, e.g.
+/// `Package fontspec Error: The font "Noto Sans KR" cannot be found.`
+/// `Font \TU/NanumMyeongjo(0)/m/n/10=NanumMyeongjo at 10pt not loadable: ...`
+pub fn missing_font(message: &str) -> Option<MissingFont> {
+    let fontspec_re = Regex::new(r#"The font "([^"]+)" cannot be found"#).unwrap();
+    let tfm_re = Regex::new(r"Font \\\S+=(\S+?)(?::\S*)? at \S+ not loadable").unwrap();
+
+    let font = fontspec_re
+        .captures(message)
+        .or_else(|| tfm_re.captures(message))
+        .map(|cap| {
+            cap[1]
+                .trim_matches(|c| c == '"' || c == '[' || c == ']')
+                .to_string()
+        })?;
+
+    Some(missing(font))
+}
+
+fn missing(font: String) -> MissingFont {
+    let package = font_package(&font).map(str::to_string);
+    let suggestion = match &package {
+        Some(package) => format!("Install the TeX Live package {} to get {}", package, font),
+        None => format!(
+            "Install {} on the system or use a font that is already installed",
+            font
+        ),
+    };
+
+    MissingFont {
+        font,
+        package,
+        alternatives: vec![],
+        suggestion,
+    }
+}
+
+/// Fonts a document selects with fontspec or xeCJK/luatexja, as (font, is
+/// CJK), e.g. `\setmainfont{TeX Gyre Pagella}[Scale=1.1]`
+fn declared_fonts(content: &str) -> Vec<(String, bool)> {
+    let re = Regex::new(
+        r"\\(set(CJK)?(?:main|sans|mono|math)font|new(CJK)?fontfamily\s*\\\w+)\s*(?:\[[^\]]*\])?\s*\{([^}]+)\}",
+    )
+    .unwrap();
+    let mut fonts: Vec<(String, bool)> = Vec::new();
+    for cap in re.captures_iter(content) {
+        let font = cap[4].trim().to_string();
+        let cjk = cap.get(2).is_some() || cap.get(3).is_some();
+        if !font.is_empty() && !fonts.iter().any(|(f, _)| *f == font) {
+            fonts.push((font, cjk));
+        }
+    }
+    fonts
+}
+
+/// Lowercase family, full and PostScript names of the fonts fontconfig knows
+#[cfg(not(windows))]
+async fn system_font_names() -> HashSet<String> {
+    let output = Command::new("fc-list")
+        .args(["--format", "%{family}\t%{fullname}\t%{postscriptname}\n"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .await;
+    let mut names = HashSet::new();
+    if let Ok(out) = output {
+        // Localized names are listed comma-separated
+        for name in String::from_utf8_lossy(&out.stdout).split(['\t', '\n', ',']) {
+            let name = name.trim();
+            if !name.is_empty() {
+                names.insert(name.to_lowercase());
+            }
+        }
+    }
+    names
+}
+
+/// Fonts GDI enumerates, as (family, style, charset), either every family
+/// or the styles of one. Blocks, so run it off the async runtime.
+#[cfg(windows)]
+fn gdi_fonts(family: Option<&str>) -> Vec<(String, String, u8)> {
+    use windows_sys::Win32::Foundation::LPARAM;
+    use windows_sys::Win32::Graphics::Gdi::{
+        EnumFontFamiliesExW, GetDC, ReleaseDC, DEFAULT_CHARSET, ENUMLOGFONTEXW, LOGFONTW,
+        TEXTMETRICW,
+    };
+
+    fn utf16(chars: &[u16]) -> String {
+        let len = chars.iter().position(|c| *c == 0).unwrap_or(chars.len());
+        String::from_utf16_lossy(&chars[..len])
+    }
+
+    unsafe extern "system" fn collect(
+        font: *const LOGFONTW,
+        _metrics: *const TEXTMETRICW,
+        _font_type: u32,
+        fonts: LPARAM,
+    ) -> i32 {
+        // SAFETY: EnumFontFamiliesExW passes an ENUMLOGFONTEXW, and `fonts`
+        // is the Vec handed to it below
+        let (font, fonts) = unsafe {
+            (
+                &*(font as *const ENUMLOGFONTEXW),
+                &mut *(fonts as *mut Vec<(String, String, u8)>),
+            )
+        };
+        // Vertical variants of CJK fonts are listed as "@Family"
+        let name = utf16(&font.elfLogFont.lfFaceName);
+        if !name.starts_with('@') {
+            fonts.push((name, utf16(&font.elfStyle), font.elfLogFont.lfCharSet));
+        }
+        1
+    }
+
+    let mut fonts = Vec::new();
+    // SAFETY: the screen DC is released before returning, and the callback
+    // only runs during EnumFontFamiliesExW
+    unsafe {
+        let dc = GetDC(std::ptr::null_mut());
+        let mut filter: LOGFONTW = std::mem::zeroed();
+        filter.lfCharSet = DEFAULT_CHARSET;
+        if let Some(family) = family {
+            for (i, c) in family.encode_utf16().take(31).enumerate() {
+                filter.lfFaceName[i] = c;
+            }
+        }
+        EnumFontFamiliesExW(
+            dc,
+            &filter,
+            Some(collect),
+            &mut fonts as *mut Vec<(String, String, u8)> as LPARAM,
+            0,
+        );
+        ReleaseDC(std::ptr::null_mut(), dc);
+    }
+    fonts
+}
+
+/// Lowercase family names of the installed fonts
+#[cfg(windows)]
+async fn system_font_names() -> HashSet<String> {
+    tokio::task::spawn_blocking(|| {
+        gdi_fonts(None)
+            .into_iter()
+            .map(|(family, _, _)| family.to_lowercase())
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Whether LuaTeX's font database, which includes the fonts in the TeX
+/// tree, finds a font
+async fn luaotfload_finds(font: &str) -> bool {
+    tex_path::command("luaotfload-tool")
+        .arg(format!("--find={}", font))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Report the fonts a fontspec document selects that aren't installed,
+/// before compiling trips over them
+#[tauri::command]
+pub async fn detect_missing_fonts(content: String) -> Vec<MissingFont> {
+    let declared = declared_fonts(&content);
+    if declared.is_empty() {
+        return Vec::new();
+    }
+
+    let names = system_font_names().await;
+    let mut cjk_alternatives = None;
+    let mut result = Vec::new();
+    for (font, cjk) in declared {
+        // Font files, e.g. "texgyrepagella-regular.otf", are looked up in
+        // the TeX tree
+        let is_file = [".otf", ".ttf", ".ttc", ".otc"]
+            .iter()
+            .any(|ext| font.to_lowercase().ends_with(ext));
+        let found = if is_file {
+            tex_path::command("kpsewhich")
+                .arg(&font)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .await
+                .map(|s| s.success())
+                .unwrap_or(false)
+        } else {
+            names.contains(&font.to_lowercase()) || luaotfload_finds(&font).await
+        };
+        if found {
+            continue;
+        }
+
+        let mut missing = missing(font);
+        if cjk {
+            if cjk_alternatives.is_none() {
+                cjk_alternatives = Some(installed_cjk_fonts().await);
+            }
+            missing.alternatives = cjk_alternatives.clone().unwrap_or_default();
+        }
+        result.push(missing);
+    }
+    result
+}
+
+/// Families of installed fonts covering Korean, Japanese or Chinese
+async fn installed_cjk_fonts() -> Vec<String> {
+    let mut fonts = Vec::new();
+    for lang in ["ko", "ja", "zh"] {
+        let output = Command::new("fc-list")
+            .arg(format!(":lang={}", lang))
+            .arg("family")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .output()
+            .await;
+        if let Ok(out) = output {
+            // Families with localized names are listed as "English,Localized"
+            for line in String::from_utf8_lossy(&out.stdout).lines() {
+                if let Some(family) = line.split(',').next().map(str::trim) {
+                    if !family.is_empty() {
+                        fonts.push(family.to_string());
+                    }
+                }
+            }
+        }
+    }
+    fonts.sort();
+    fonts.dedup();
+    fonts
+}
+
+/// Fill in installed alternatives for missing font errors
+pub async fn suggest_alternatives(errors: &mut [CompilationError]) {
+    if !errors.iter().any(|e| e.missing_font.is_some()) {
+        return;
+    }
+
+    let alternatives = installed_cjk_fonts().await;
+    for missing in errors.iter_mut().filter_map(|e| e.missing_font.as_mut()) {
+        missing.alternatives = alternatives.clone();
+        if missing.package.is_none() && !alternatives.is_empty() {
+            missing.suggestion = format!(
+                "Install {} on the system or pick an installed font such as {}",
+                missing.font, alternatives[0]
+            );
+        }
+    }
+}
+
+/// An installed font family, for picking fontspec fonts
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SystemFont {
+    family: String,
+    styles: Vec<String>,
+    korean: bool,
+    japanese: bool,
+    chinese: bool,
+}
+
+impl SystemFont {
+    fn add_style(&mut self, style: String) {
+        if !style.is_empty() && !self.styles.contains(&style) {
+            self.styles.push(style);
+        }
+    }
+}
+
+fn matches_filter(family: &str, filter: &Option<String>) -> bool {
+    match filter.as_deref().map(str::trim) {
+        Some(filter) if !filter.is_empty() => {
+            family.to_lowercase().contains(&filter.to_lowercase())
+        }
+        _ => true,
+    }
+}
+
+#[cfg(not(windows))]
+async fn enumerate_fonts(filter: Option<String>) -> Result<Vec<SystemFont>, String> {
+    use std::collections::BTreeMap;
+
+    let output = Command::new("fc-list")
+        .args(["--format", "%{family}\t%{style}\t%{lang}\n"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run fc-list: {}", e))?;
+
+    let mut fonts: BTreeMap<String, SystemFont> = BTreeMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.split('\t');
+        // Localized names follow the English one, comma-separated
+        let mut first = || {
+            fields
+                .next()
+                .and_then(|f| f.split(',').next())
+                .unwrap_or("")
+                .trim()
+                .to_string()
+        };
+        let (family, style) = (first(), first());
+        let langs: Vec<&str> = fields.next().unwrap_or("").split('|').collect();
+        if family.is_empty() || !matches_filter(&family, &filter) {
+            continue;
+        }
+
+        let font = fonts.entry(family.clone()).or_insert_with(|| SystemFont {
+            family,
+            ..Default::default()
+        });
+        font.add_style(style);
+        font.korean |= langs.contains(&"ko");
+        font.japanese |= langs.contains(&"ja");
+        font.chinese |= langs.iter().any(|l| l.starts_with("zh"));
+    }
+    Ok(fonts.into_values().collect())
+}
+
+#[cfg(windows)]
+async fn enumerate_fonts(filter: Option<String>) -> Result<Vec<SystemFont>, String> {
+    use std::collections::BTreeSet;
+    use windows_sys::Win32::Graphics::Gdi::{
+        CHINESEBIG5_CHARSET, GB2312_CHARSET, HANGUL_CHARSET, JOHAB_CHARSET, SHIFTJIS_CHARSET,
+    };
+
+    tokio::task::spawn_blocking(move || {
+        let families: BTreeSet<String> = gdi_fonts(None)
+            .into_iter()
+            .map(|(family, _, _)| family)
+            .filter(|family| matches_filter(family, &filter))
+            .collect();
+
+        // Styles are only listed when enumerating a single family
+        families
+            .into_iter()
+            .map(|family| {
+                let mut font = SystemFont {
+                    family: family.clone(),
+                    ..Default::default()
+                };
+                for (_, style, charset) in gdi_fonts(Some(&family)) {
+                    font.add_style(style);
+                    font.korean |= charset == HANGUL_CHARSET || charset == JOHAB_CHARSET;
+                    font.japanese |= charset == SHIFTJIS_CHARSET;
+                    font.chinese |= charset == GB2312_CHARSET || charset == CHINESEBIG5_CHARSET;
+                }
+                font
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Failed to list fonts: {}", e))
+}
+
+/// Installed font families whose name contains `filter`, with their styles
+/// and whether they cover Korean, Japanese and Chinese
+#[tauri::command]
+pub async fn list_system_fonts(filter: Option<String>) -> Result<Vec<SystemFont>, String> {
+    let mut fonts = enumerate_fonts(filter).await?;
+    for font in &mut fonts {
+        font.styles.sort();
+    }
+    Ok(fonts)
+}
@@ -0,0 +1,79 @@
+//! Document formatting via latexindent
+
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use tempfile::TempDir;
+use tokio::fs;
+
+use crate::{tex_path, CompilationError};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FormatResult {
+    success: bool,
+    /// Formatted document, when latexindent succeeded
+    formatted: Option<String>,
+    errors: Vec<CompilationError>,
+    log: String,
+}
+
+/// Collect latexindent's "ERROR" lines, e.g. from an invalid localSettings.yaml
+fn parse_latexindent_log(log: &str) -> Vec<CompilationError> {
+    log.lines()
+        .map(str::trim)
+        .filter(|line| line.contains("ERROR"))
+        .map(|line| CompilationError::message(line.trim_start_matches("ERROR:").trim()))
+        .collect()
+}
+
+/// Format a document with latexindent. `settings` is the project's
+/// localSettings.yaml, if it has one.
+#[tauri::command]
+pub async fn format_document(
+    content: String,
+    settings: Option<String>,
+) -> Result<FormatResult, String> {
+    let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let dir = temp_dir.path();
+
+    fs::write(dir.join("document.tex"), &content)
+        .await
+        .map_err(|e| format!("Failed to write document: {}", e))?;
+
+    let mut command = tex_path::command("latexindent");
+    command.args(["-g", "indent.log"]);
+    if let Some(settings) = &settings {
+        fs::write(dir.join("localSettings.yaml"), settings)
+            .await
+            .map_err(|e| format!("Failed to write localSettings.yaml: {}", e))?;
+        command.arg("-l=localSettings.yaml");
+    }
+
+    let output = command
+        .arg("document.tex")
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run latexindent: {}. Is TeX Live installed?", e))?;
+
+    let log = fs::read_to_string(dir.join("indent.log"))
+        .await
+        .unwrap_or_else(|_| String::from_utf8_lossy(&output.stderr).to_string());
+    let mut errors = parse_latexindent_log(&log);
+
+    if !output.status.success() && errors.is_empty() {
+        errors.push(CompilationError::message(format!(
+            "latexindent exited with {}",
+            output.status
+        )));
+    }
+
+    let success = errors.is_empty();
+    Ok(FormatResult {
+        success,
+        formatted: success.then(|| String::from_utf8_lossy(&output.stdout).to_string()),
+        errors,
+        log,
+    })
+}
@@ -0,0 +1,293 @@
+//! Autosave: periodic snapshots of a project's files in .offleaf/history,
+//! which can be listed and restored. Each snapshot is a list of files by
+//! content hash; the contents are stored once in history/objects.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::async_runtime::JoinHandle;
+use tokio::fs;
+
+use crate::{project, save};
+
+/// Oldest snapshots beyond this many are deleted
+const MAX_SNAPSHOTS: usize = 50;
+
+const MIN_INTERVAL_SECS: u64 = 5;
+
+lazy_static::lazy_static! {
+    /// Running autosave tasks and their intervals by project ID
+    static ref AUTOSAVES: Mutex<HashMap<String, (JoinHandle<()>, Duration)>> =
+        Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    id: String,
+    /// Milliseconds since the Unix epoch
+    created_ms: u64,
+    files: usize,
+}
+
+/// What a snapshot holds; file contents are looked up by hash
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SnapshotManifest {
+    dirs: Vec<String>,
+    /// Project-relative path and SHA-256 of each file
+    files: Vec<(String, String)>,
+}
+
+fn history_dir(project_dir: &Path) -> PathBuf {
+    project_dir.join(".offleaf").join("history")
+}
+
+fn objects_dir(project_dir: &Path) -> PathBuf {
+    history_dir(project_dir).join("objects")
+}
+
+/// Snapshot manifest; IDs are the creation time in milliseconds
+fn snapshot_path(project_dir: &Path, snapshot: &str) -> Result<PathBuf, String> {
+    if snapshot.is_empty() || !snapshot.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("Invalid snapshot ID: {}", snapshot));
+    }
+    Ok(history_dir(project_dir).join(format!("{}.json", snapshot)))
+}
+
+async fn read_snapshot(project_dir: &Path, snapshot: &str) -> Result<SnapshotManifest, String> {
+    let path = snapshot_path(project_dir, snapshot)?;
+    let data = fs::read(&path)
+        .await
+        .map_err(|_| format!("Snapshot not found: {}", snapshot))?;
+    serde_json::from_slice(&data).map_err(|e| format!("Invalid snapshot {}: {}", snapshot, e))
+}
+
+/// Fingerprint of the files' names, sizes and modification times, so an
+/// unchanged project isn't snapshotted again
+pub async fn fingerprint(project_dir: &Path) -> Result<u64, String> {
+    let mut hasher = DefaultHasher::new();
+    for (entry, path) in project::walk(project_dir).await? {
+        entry.path.hash(&mut hasher);
+        entry.size.hash(&mut hasher);
+        if let Ok(modified) = fs::metadata(&path).await.and_then(|m| m.modified()) {
+            modified.hash(&mut hasher);
+        }
+    }
+    Ok(hasher.finish())
+}
+
+/// Record the project's files in a new snapshot and return its ID. Only
+/// contents no earlier snapshot has are stored.
+async fn take_snapshot(project_dir: &Path) -> Result<String, String> {
+    let created_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let id = created_ms.to_string();
+    let objects = objects_dir(project_dir);
+    fs::create_dir_all(&objects)
+        .await
+        .map_err(|e| format!("Failed to create snapshot: {}", e))?;
+
+    let mut snapshot = SnapshotManifest::default();
+    for (entry, path) in project::walk(project_dir).await? {
+        if entry.is_dir {
+            snapshot.dirs.push(entry.path);
+            continue;
+        }
+        let data = fs::read(&path)
+            .await
+            .map_err(|e| format!("Failed to snapshot {}: {}", entry.path, e))?;
+        let hash = format!("{:x}", Sha256::digest(&data));
+        let object = objects.join(&hash);
+        if !object.is_file() {
+            save::write_atomic(&object, &data).await?;
+        }
+        snapshot.files.push((entry.path, hash));
+    }
+    let json =
+        serde_json::to_vec(&snapshot).map_err(|e| format!("Failed to create snapshot: {}", e))?;
+    save::write_atomic(&snapshot_path(project_dir, &id)?, &json).await?;
+
+    prune(project_dir).await;
+    Ok(id)
+}
+
+/// Delete the oldest snapshots beyond MAX_SNAPSHOTS, then the contents no
+/// remaining snapshot refers to
+async fn prune(project_dir: &Path) {
+    let mut ids = snapshot_ids(project_dir).await;
+    if ids.len() <= MAX_SNAPSHOTS {
+        return;
+    }
+    ids.sort();
+    let (old, kept) = ids.split_at(ids.len() - MAX_SNAPSHOTS);
+    for id in old {
+        if let Ok(path) = snapshot_path(project_dir, id) {
+            let _ = fs::remove_file(path).await;
+        }
+    }
+
+    let mut referenced = HashSet::new();
+    for id in kept {
+        // An unreadable snapshot could still need any object
+        let Ok(snapshot) = read_snapshot(project_dir, id).await else {
+            return;
+        };
+        referenced.extend(snapshot.files.into_iter().map(|(_, hash)| hash));
+    }
+    if let Ok(mut entries) = fs::read_dir(objects_dir(project_dir)).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !referenced.contains(&name) {
+                let _ = fs::remove_file(entry.path()).await;
+            }
+        }
+    }
+}
+
+async fn snapshot_ids(project_dir: &Path) -> Vec<String> {
+    let mut ids = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(history_dir(project_dir)).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(id) = name.strip_suffix(".json") {
+                if snapshot_path(project_dir, id).is_ok() {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+    }
+    ids
+}
+
+/// Start snapshotting a project folder, replacing any earlier autosave
+fn start(project: String, dir: PathBuf, interval: Duration) -> Result<(), String> {
+    let task = tauri::async_runtime::spawn(async move {
+        let mut last = None;
+        loop {
+            tokio::time::sleep(interval).await;
+            let Ok(current) = fingerprint(&dir).await else {
+                continue;
+            };
+            if last != Some(current) && take_snapshot(&dir).await.is_ok() {
+                last = Some(current);
+            }
+        }
+    });
+
+    let mut autosaves = AUTOSAVES
+        .lock()
+        .map_err(|_| "Autosave state is unavailable".to_string())?;
+    if let Some((previous, _)) = autosaves.insert(project, (task, interval)) {
+        previous.abort();
+    }
+    Ok(())
+}
+
+/// Move a renamed project's autosave to its new ID and folder
+pub fn rename(old_id: &str, new_id: &str) -> Result<(), String> {
+    let previous = AUTOSAVES
+        .lock()
+        .map_err(|_| "Autosave state is unavailable".to_string())?
+        .remove(old_id);
+    if let Some((task, interval)) = previous {
+        task.abort();
+        start(new_id.to_string(), project::project_dir(new_id)?, interval)?;
+    }
+    Ok(())
+}
+
+/// Snapshot the project every `interval_secs` seconds while it changes.
+/// Enabling again replaces the previous interval.
+#[tauri::command]
+pub async fn enable_autosave(project: String, interval_secs: u64) -> Result<(), String> {
+    let dir = project::project_dir(&project)?;
+    if !dir.is_dir() {
+        return Err(format!("Project not found: {}", project));
+    }
+    let interval = Duration::from_secs(interval_secs.max(MIN_INTERVAL_SECS));
+    start(project, dir, interval)
+}
+
+#[tauri::command]
+pub async fn disable_autosave(project: String) -> Result<(), String> {
+    if let Ok(mut autosaves) = AUTOSAVES.lock() {
+        if let Some((task, _)) = autosaves.remove(&project) {
+            task.abort();
+        }
+    }
+    Ok(())
+}
+
+/// List a project's snapshots, newest first
+#[tauri::command]
+pub async fn list_snapshots(project: String) -> Result<Vec<Snapshot>, String> {
+    let dir = project::project_dir(&project)?;
+    let mut snapshots = Vec::new();
+    for id in snapshot_ids(&dir).await {
+        let files = read_snapshot(&dir, &id)
+            .await
+            .map(|snapshot| snapshot.files.len())
+            .unwrap_or(0);
+        snapshots.push(Snapshot {
+            created_ms: id.parse().unwrap_or(0),
+            id,
+            files,
+        });
+    }
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.created_ms));
+    Ok(snapshots)
+}
+
+/// Replace the project's files with a snapshot. The current files are
+/// snapshotted first, so a restore can itself be undone.
+#[tauri::command]
+pub async fn restore_snapshot(project: String, snapshot: String) -> Result<(), String> {
+    let dir = project::project_dir(&project)?;
+    let source = read_snapshot(&dir, &snapshot).await?;
+    let objects = objects_dir(&dir);
+    if let Some((path, _)) = source
+        .files
+        .iter()
+        .find(|(_, hash)| !objects.join(hash).is_file())
+    {
+        return Err(format!("Snapshot {} is missing {}", snapshot, path));
+    }
+    take_snapshot(&dir).await?;
+
+    // Only top-level entries; removing a folder removes what's inside
+    for (entry, path) in project::walk(&dir).await? {
+        if entry.path.contains('/') {
+            continue;
+        }
+        let result = if entry.is_dir {
+            fs::remove_dir_all(&path).await
+        } else {
+            fs::remove_file(&path).await
+        };
+        result.map_err(|e| format!("Failed to remove {}: {}", entry.path, e))?;
+    }
+
+    for path in &source.dirs {
+        fs::create_dir_all(project::resolve_in(&dir, path)?)
+            .await
+            .map_err(|e| format!("Failed to restore {}: {}", path, e))?;
+    }
+    for (path, hash) in &source.files {
+        let dest = project::resolve_in(&dir, path)?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Failed to restore {}: {}", path, e))?;
+        }
+        fs::copy(objects.join(hash), &dest)
+            .await
+            .map_err(|e| format!("Failed to restore {}: {}", path, e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,332 @@
+//! Dependency graph of a document: the files it pulls in with \input,
+//! \include, \subfile and \includegraphics
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use crate::tex_path;
+
+/// Extensions tried, in order, for \includegraphics without one
+const GRAPHICS_EXTENSIONS: &[&str] = &["pdf", "png", "jpg", "jpeg", "eps"];
+
+/// Deeper nesting than this is treated as a runaway include
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IncludeNode {
+    /// Relative to the root file's folder, with forward slashes
+    path: String,
+    /// "root", "input", "include", "subfile" or "graphics"
+    kind: String,
+    /// Line of the command in the including file; 0 for the root
+    line: usize,
+    children: Vec<IncludeNode>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MissingInclude {
+    /// As written in the source
+    name: String,
+    kind: String,
+    /// File containing the command
+    file: String,
+    line: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IncludeGraph {
+    root: IncludeNode,
+    missing: Vec<MissingInclude>,
+    /// Every file the document depends on, root first
+    files: Vec<String>,
+}
+
+struct Walker {
+    base: PathBuf,
+    include_re: Regex,
+    bare_input_re: Regex,
+    graphicspath_re: Regex,
+    graphics_paths: Vec<String>,
+    missing: Vec<MissingInclude>,
+    files: Vec<String>,
+}
+
+/// Resolve `name` against a folder given as a relative path, collapsing
+/// "." and ".." so the same file always gets the same path
+fn join_relative(dir: &str, name: &str) -> String {
+    let mut parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+    for part in name.split('/') {
+        match part {
+            ".." if parts.last().is_some_and(|p| *p != "..") => {
+                parts.pop();
+            }
+            "." | "" => {}
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+/// The part of a line before its comment, if any
+pub fn strip_comment(line: &str) -> &str {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            '%' if !escaped => return &line[..i],
+            _ => escaped = false,
+        }
+    }
+    line
+}
+
+impl Walker {
+    fn new(base: PathBuf) -> Self {
+        Walker {
+            base,
+            include_re: Regex::new(
+                r"\\(input|include|subfile|includegraphics)\*?\s*(?:\[[^\]]*\]\s*)*\{([^}]+)\}",
+            )
+            .unwrap(),
+            bare_input_re: Regex::new(r"\\input\s+([^\s{}\\]+)").unwrap(),
+            graphicspath_re: Regex::new(r"\\graphicspath\s*\{((?:\s*\{[^}]*\})*)\s*\}").unwrap(),
+            graphics_paths: Vec::new(),
+            missing: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        !path.is_empty() && self.base.join(path).is_file()
+    }
+
+    /// Project-relative path of an included file, trying the extensions
+    /// LaTeX would
+    fn resolve(&self, kind: &str, name: &str, dir: &str) -> Option<String> {
+        let candidates: Vec<String> = match kind {
+            "include" => vec![format!("{}.tex", name)],
+            "graphics" => {
+                let prefixes =
+                    std::iter::once("").chain(self.graphics_paths.iter().map(String::as_str));
+                prefixes
+                    .flat_map(|prefix| {
+                        let name = format!("{}{}", prefix, name);
+                        std::iter::once(name.clone()).chain(
+                            GRAPHICS_EXTENSIONS
+                                .iter()
+                                .map(move |ext| format!("{}.{}", name, ext)),
+                        )
+                    })
+                    .collect()
+            }
+            _ => vec![format!("{}.tex", name), name.to_string()],
+        };
+        candidates
+            .iter()
+            .map(|candidate| join_relative(dir, candidate))
+            .find(|path| self.exists(path))
+    }
+
+    /// Children of a .tex file, recursing into the files it inputs
+    fn visit(&mut self, path: &str, stack: &mut Vec<String>) -> Vec<IncludeNode> {
+        let Ok(content) = std::fs::read_to_string(self.base.join(path)) else {
+            return Vec::new();
+        };
+        stack.push(path.to_string());
+
+        let mut children = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line_number = index + 1;
+            let line = strip_comment(line);
+
+            if let Some(cap) = self.graphicspath_re.captures(line) {
+                self.graphics_paths = cap[1]
+                    .split('}')
+                    .map(|p| p.trim().trim_start_matches('{').trim())
+                    .filter(|p| !p.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+
+            let braced = self.include_re.captures_iter(line).map(|cap| {
+                let kind = match &cap[1] {
+                    "includegraphics" => "graphics",
+                    command => command,
+                };
+                (
+                    cap.get(0).map_or(0, |m| m.start()),
+                    kind.to_string(),
+                    cap[2].trim().to_string(),
+                )
+            });
+            let bare = self.bare_input_re.captures_iter(line).map(|cap| {
+                (
+                    cap.get(0).map_or(0, |m| m.start()),
+                    "input".to_string(),
+                    cap[1].to_string(),
+                )
+            });
+            let mut commands: Vec<(usize, String, String)> = braced.chain(bare).collect();
+            commands.sort_by_key(|(start, _, _)| *start);
+
+            for (_, kind, name) in commands {
+                // \subfile paths are relative to the file they're in; the rest
+                // to the folder the document is compiled from
+                let dir = if kind == "subfile" {
+                    parent_of(path)
+                } else {
+                    ""
+                };
+                let Some(resolved) = self.resolve(&kind, &name, dir) else {
+                    self.missing.push(MissingInclude {
+                        name,
+                        kind,
+                        file: path.to_string(),
+                        line: line_number,
+                    });
+                    continue;
+                };
+                if !self.files.contains(&resolved) {
+                    self.files.push(resolved.clone());
+                }
+                let grandchildren = if kind != "graphics"
+                    && !stack.contains(&resolved)
+                    && stack.len() < MAX_DEPTH
+                {
+                    self.visit(&resolved, stack)
+                } else {
+                    Vec::new()
+                };
+                children.push(IncludeNode {
+                    path: resolved,
+                    kind,
+                    line: line_number,
+                    children: grandchildren,
+                });
+            }
+        }
+
+        stack.pop();
+        children
+    }
+
+    /// Text of a .tex file with the files it inputs spliced in, comments removed
+    fn inline(&self, path: &str, stack: &mut Vec<String>, out: &mut String) {
+        let Ok(content) = std::fs::read_to_string(self.base.join(path)) else {
+            return;
+        };
+        stack.push(path.to_string());
+        for line in content.lines() {
+            let line = strip_comment(line);
+            let mut last = 0;
+            for cap in self.include_re.captures_iter(line) {
+                let kind = &cap[1];
+                if kind == "includegraphics" {
+                    continue;
+                }
+                let dir = if kind == "subfile" {
+                    parent_of(path)
+                } else {
+                    ""
+                };
+                let Some(resolved) = self.resolve(kind, cap[2].trim(), dir) else {
+                    continue;
+                };
+                if stack.contains(&resolved) || stack.len() >= MAX_DEPTH {
+                    continue;
+                }
+                let command = cap.get(0).map_or(0..0, |m| m.range());
+                out.push_str(&line[last..command.start]);
+                out.push('\n');
+                self.inline(&resolved, stack, out);
+                last = command.end;
+            }
+            out.push_str(&line[last..]);
+            out.push('\n');
+        }
+        stack.pop();
+    }
+}
+
+/// The whole document as one text, following \input, \include and
+/// \subfile from the root file, with comments removed
+pub fn flatten(root: &Path) -> String {
+    let base = root.parent().map(Path::to_path_buf).unwrap_or_default();
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut out = String::new();
+    Walker::new(base).inline(&name, &mut Vec::new(), &mut out);
+    out
+}
+
+/// Whether kpathsea finds a file in the TeX installation, for inputs like
+/// \input{glyphtounicode} that aren't part of the project
+async fn in_tex_tree(name: &str) -> bool {
+    tex_path::command("kpsewhich")
+        .arg(name)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Follow the includes of a root .tex file on disk, returning the file
+/// hierarchy and the includes that point at nothing
+#[tauri::command]
+pub async fn analyze_includes(root: String) -> Result<IncludeGraph, String> {
+    let root_path = PathBuf::from(&root);
+    if !root_path.is_file() {
+        return Err(format!("Root file not found: {}", root));
+    }
+    let base = root_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let root_name = root_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut walker = Walker::new(base);
+    walker.files.push(root_name.clone());
+    let children = walker.visit(&root_name, &mut Vec::new());
+
+    let mut missing = Vec::new();
+    let mut installed = HashSet::new();
+    for include in walker.missing {
+        if include.kind == "input" {
+            let name = if Path::new(&include.name).extension().is_some() {
+                include.name.clone()
+            } else {
+                format!("{}.tex", include.name)
+            };
+            if installed.contains(&name) || in_tex_tree(&name).await {
+                installed.insert(name);
+                continue;
+            }
+        }
+        missing.push(include);
+    }
+
+    Ok(IncludeGraph {
+        root: IncludeNode {
+            path: root_name,
+            kind: "root".to_string(),
+            line: 0,
+            children,
+        },
+        missing,
+        files: walker.files,
+    })
+}
@@ -0,0 +1,549 @@
+//! Compile job manager: queued/running/finished jobs and their processes
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+
+use crate::container::{self, ContainerSettings};
+use crate::limits::{self, LimitExceeded, ResourceLimits};
+use crate::{output, packages, processes, tex_path, CompilationResult};
+
+/// Finished jobs kept around for status queries, with their published PDFs
+const MAX_FINISHED_JOBS: usize = 10;
+
+/// Finished jobs are kept at least this long, whatever their number, so a
+/// batch of compiles can't drop a result before its poller has read it
+const FINISHED_JOB_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileJob {
+    job_id: String,
+    status: JobStatus,
+    result: Option<CompilationResult>,
+    error: Option<String>,
+}
+
+struct JobState {
+    seq: u64,
+    project_id: Option<String>,
+    /// Queue the job waits in: its build directory's, or the shared one
+    queue: Option<String>,
+    status: JobStatus,
+    pid: Option<u32>,
+    cancelled: bool,
+    limits: Option<ResourceLimits>,
+    /// Limit the job's last process ran into, from its measured usage
+    limit_exceeded: Option<LimitExceeded>,
+    /// Run the job's processes in a container instead of on the host
+    container: Option<ContainerSettings>,
+    /// Container the job is currently running, if any
+    container_name: Option<String>,
+    result: Option<CompilationResult>,
+    error: Option<String>,
+    finished_at: Option<Instant>,
+}
+
+impl JobState {
+    fn to_job(&self, job_id: &str, with_result: bool) -> CompileJob {
+        CompileJob {
+            job_id: job_id.to_string(),
+            status: self.status,
+            result: if with_result {
+                self.result.clone()
+            } else {
+                None
+            },
+            error: self.error.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_seq: u64,
+    states: HashMap<String, JobState>,
+}
+
+lazy_static::lazy_static! {
+    static ref JOBS: Mutex<Jobs> = Mutex::new(Jobs::default());
+    /// Compiles sharing a build directory run one at a time, in submission
+    /// order; compiles in throwaway directories share the `None` queue.
+    /// Entries go when their last job is done.
+    static ref QUEUES: Mutex<HashMap<Option<String>, Arc<tokio::sync::Mutex<()>>>> =
+        Mutex::new(HashMap::new());
+    /// Parallel batch compiles run at most one per CPU
+    static ref PARALLEL: tokio::sync::Semaphore = tokio::sync::Semaphore::new(
+        std::thread::available_parallelism().map_or(2, |n| n.get())
+    );
+}
+
+/// Add a job to a queue and return its new ID. Jobs with the same `queue`
+/// key, e.g. a project's build directory, run one after another.
+pub fn enqueue(project_id: Option<&str>, queue: Option<&str>) -> String {
+    let job_id = uuid::Uuid::new_v4().to_string();
+    if let Ok(mut jobs) = JOBS.lock() {
+        jobs.next_seq += 1;
+        let seq = jobs.next_seq;
+        jobs.states.insert(
+            job_id.clone(),
+            JobState {
+                seq,
+                project_id: project_id.map(str::to_string),
+                queue: queue.map(str::to_string),
+                status: JobStatus::Queued,
+                pid: None,
+                cancelled: false,
+                limits: None,
+                limit_exceeded: None,
+                container: None,
+                container_name: None,
+                result: None,
+                error: None,
+                finished_at: None,
+            },
+        );
+    }
+    job_id
+}
+
+/// A job's turn in its queue, which passes to the next job when dropped
+pub struct QueueTurn {
+    key: Option<String>,
+    queue: Arc<tokio::sync::Mutex<()>>,
+    guard: Option<tokio::sync::OwnedMutexGuard<()>>,
+}
+
+impl Drop for QueueTurn {
+    fn drop(&mut self) {
+        self.guard.take();
+        // Only the map and this turn still refer to an idle queue
+        if let Ok(mut queues) = QUEUES.lock() {
+            if Arc::strong_count(&self.queue) == 2 {
+                queues.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Wait for the job's turn in its queue, then mark it running. Returns
+/// false if it was cancelled while queued; the returned turn holds the
+/// queue until dropped.
+pub async fn start(job_id: &str) -> (bool, QueueTurn) {
+    let turn = take_turn(queue_of(job_id)).await;
+    (start_unqueued(job_id), turn)
+}
+
+fn queue_of(job_id: &str) -> Option<String> {
+    let jobs = JOBS.lock().ok()?;
+    jobs.states.get(job_id)?.queue.clone()
+}
+
+/// Wait until the jobs ahead in a queue are done
+async fn take_turn(key: Option<String>) -> QueueTurn {
+    let queue = match QUEUES.lock() {
+        Ok(mut queues) => queues.entry(key.clone()).or_default().clone(),
+        Err(_) => Arc::default(),
+    };
+    let guard = queue.clone().lock_owned().await;
+    QueueTurn {
+        key,
+        queue,
+        guard: Some(guard),
+    }
+}
+
+/// Wait for one of the slots parallel batch compiles share, then mark the
+/// job running alongside whatever the shared queue is running. A job that
+/// builds in a folder's own directory still waits for that folder's queue.
+/// Returns false if it was cancelled while waiting; the returned turn and
+/// permit hold the queue and the slot.
+pub async fn start_parallel(
+    job_id: &str,
+) -> (
+    bool,
+    Option<QueueTurn>,
+    Option<tokio::sync::SemaphorePermit<'static>>,
+) {
+    let turn = match queue_of(job_id) {
+        Some(key) => Some(take_turn(Some(key)).await),
+        None => None,
+    };
+    let permit = PARALLEL.acquire().await.ok();
+    (start_unqueued(job_id), turn, permit)
+}
+
+/// Mark a job running right away. Returns false if it was cancelled.
+pub fn start_unqueued(job_id: &str) -> bool {
+    if let Ok(mut jobs) = JOBS.lock() {
+        if let Some(job) = jobs.states.get_mut(job_id) {
+            if !job.cancelled {
+                job.status = JobStatus::Running;
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Record the outcome of a job
+pub fn finish(job_id: &str, outcome: Result<CompilationResult, String>) {
+    let Ok(mut jobs) = JOBS.lock() else {
+        return;
+    };
+
+    if let Some(job) = jobs.states.get_mut(job_id) {
+        job.pid = None;
+        job.finished_at = Some(Instant::now());
+        match outcome {
+            _ if job.cancelled => {
+                job.status = JobStatus::Cancelled;
+                // Nobody can ask for the PDF of a cancelled job
+                if let Some(pdf_path) = outcome.ok().and_then(|result| result.pdf_path) {
+                    output::discard(Path::new(&pdf_path));
+                }
+            }
+            Ok(result) => {
+                job.status = JobStatus::Finished;
+                job.result = Some(result);
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e);
+            }
+        }
+    }
+
+    // Drop the oldest finished jobs beyond the retention limit, once they
+    // are old enough
+    let mut done: Vec<(u64, String, bool)> = jobs
+        .states
+        .iter()
+        .filter(|(_, j)| !matches!(j.status, JobStatus::Queued | JobStatus::Running))
+        .map(|(id, j)| {
+            let expired = j
+                .finished_at
+                .is_none_or(|at| at.elapsed() >= FINISHED_JOB_TTL);
+            (j.seq, id.clone(), expired)
+        })
+        .collect();
+    if done.len() > MAX_FINISHED_JOBS {
+        done.sort();
+        let excess = done.len() - MAX_FINISHED_JOBS;
+        for (_, id, _) in done
+            .into_iter()
+            .take(excess)
+            .filter(|(_, _, expired)| *expired)
+        {
+            let dropped = jobs.states.remove(&id);
+            if let Some(pdf_path) = dropped.and_then(|job| job.result?.pdf_path) {
+                output::discard(Path::new(&pdf_path));
+            }
+        }
+    }
+}
+
+/// Drop a job that isn't a compile, e.g. a snippet preview, once it is done
+pub fn forget(job_id: &str) {
+    if let Ok(mut jobs) = JOBS.lock() {
+        jobs.states.remove(job_id);
+    }
+}
+
+pub fn status(job_id: &str) -> Option<JobStatus> {
+    let jobs = JOBS.lock().ok()?;
+    jobs.states.get(job_id).map(|j| j.status)
+}
+
+/// Get a job, including its result when finished
+pub fn get(job_id: &str) -> Option<CompileJob> {
+    let jobs = JOBS.lock().ok()?;
+    jobs.states.get(job_id).map(|j| j.to_job(job_id, true))
+}
+
+/// The PDF a finished job produced
+pub fn pdf(job_id: &str) -> Option<Vec<u8>> {
+    std::fs::read(pdf_path(job_id)?).ok()
+}
+
+/// Where a finished job's PDF was published
+pub fn pdf_path(job_id: &str) -> Option<PathBuf> {
+    let jobs = JOBS.lock().ok()?;
+    let result = jobs.states.get(job_id)?.result.as_ref()?;
+    result.pdf_path.as_ref().map(PathBuf::from)
+}
+
+/// List all known jobs in submission order, without their results
+pub fn list() -> Vec<CompileJob> {
+    let Ok(jobs) = JOBS.lock() else {
+        return vec![];
+    };
+    let mut states: Vec<_> = jobs.states.iter().collect();
+    states.sort_by_key(|(_, j)| j.seq);
+    states
+        .into_iter()
+        .map(|(id, j)| j.to_job(id, false))
+        .collect()
+}
+
+/// Check whether a job has been cancelled
+pub fn is_cancelled(job_id: &str) -> bool {
+    JOBS.lock()
+        .map(|jobs| {
+            jobs.states
+                .get(job_id)
+                .map(|j| j.cancelled)
+                .unwrap_or(false)
+        })
+        .unwrap_or(false)
+}
+
+/// Apply resource limits to every process the job runs from now on
+pub fn set_limits(job_id: &str, limits: ResourceLimits) {
+    if let Ok(mut jobs) = JOBS.lock() {
+        if let Some(job) = jobs.states.get_mut(job_id) {
+            job.limits = Some(limits);
+        }
+    }
+}
+
+fn limits_of(job_id: &str) -> Option<ResourceLimits> {
+    JOBS.lock()
+        .ok()
+        .and_then(|jobs| jobs.states.get(job_id).and_then(|j| j.limits))
+}
+
+fn set_limit_exceeded(job_id: &str, exceeded: Option<LimitExceeded>) {
+    if let Ok(mut jobs) = JOBS.lock() {
+        if let Some(job) = jobs.states.get_mut(job_id) {
+            job.limit_exceeded = exceeded;
+        }
+    }
+}
+
+/// Which limit, if any, the job's last process ran into
+pub fn limit_exceeded(job_id: &str) -> Option<LimitExceeded> {
+    JOBS.lock()
+        .ok()
+        .and_then(|jobs| jobs.states.get(job_id).and_then(|j| j.limit_exceeded))
+}
+
+/// Run every process the job starts from now on in a container
+pub fn set_container(job_id: &str, settings: ContainerSettings) {
+    if let Ok(mut jobs) = JOBS.lock() {
+        if let Some(job) = jobs.states.get_mut(job_id) {
+            job.container = Some(settings);
+        }
+    }
+}
+
+fn container_of(job_id: &str) -> Option<ContainerSettings> {
+    JOBS.lock()
+        .ok()
+        .and_then(|jobs| jobs.states.get(job_id).and_then(|j| j.container.clone()))
+}
+
+fn set_container_name(job_id: &str, name: Option<String>) {
+    if let Ok(mut jobs) = JOBS.lock() {
+        if let Some(job) = jobs.states.get_mut(job_id) {
+            job.container_name = name;
+        }
+    }
+}
+
+fn set_pid(job_id: &str, pid: Option<u32>) {
+    if let Ok(mut jobs) = JOBS.lock() {
+        if let Some(job) = jobs.states.get_mut(job_id) {
+            job.pid = pid;
+        }
+    }
+}
+
+/// Run a command as part of a job, keeping track of its process
+pub async fn run_command(job_id: &str, command: &mut Command) -> io::Result<Output> {
+    run_command_streaming(job_id, command, |_| {}).await
+}
+
+/// Run a command as part of a job, passing each stdout line to `on_line` as it is produced
+pub async fn run_command_streaming<F>(
+    job_id: &str,
+    command: &mut Command,
+    mut on_line: F,
+) -> io::Result<Output>
+where
+    F: FnMut(&str) + Send,
+{
+    if is_cancelled(job_id) {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "job cancelled"));
+    }
+
+    let job_limits = limits_of(job_id);
+    let container = container_of(job_id);
+    // Usage inside a container isn't visible from here
+    let measured_limits = job_limits.filter(|_| container.is_none());
+    let mut wrapped;
+    let command = match container {
+        // The container runtime enforces the limits itself
+        Some(settings) => {
+            let name = format!("offleaf-{}", uuid::Uuid::new_v4());
+            wrapped = container::wrap(command, &settings, &name, job_limits);
+            set_container_name(job_id, Some(name));
+            &mut wrapped
+        }
+        None => {
+            if let Some(job_limits) = job_limits {
+                limits::apply(command, job_limits);
+            }
+            command
+        }
+    };
+
+    tex_path::apply(command);
+    // Own process group so the whole tree can be killed at once
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    set_pid(job_id, child.id());
+    let tracked = processes::Tracked::new(child.id(), command);
+
+    // Held until the process is done; dropping it kills what is left
+    #[cfg(windows)]
+    let job_object = job_limits.and_then(|l| limits::attach(&child, l));
+
+    // Drain stderr concurrently so a full pipe cannot block the process
+    let stderr = child.stderr.take();
+    let stderr_task = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_end(&mut buf).await;
+        }
+        buf
+    });
+
+    let result = async {
+        let mut stdout = Vec::new();
+        if let Some(out) = child.stdout.take() {
+            let mut reader = BufReader::new(out);
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line).await? > 0 {
+                on_line(String::from_utf8_lossy(&line).trim_end());
+                stdout.append(&mut line);
+            }
+        }
+        #[cfg(unix)]
+        let (status, usage) = match child.id().filter(|_| measured_limits.is_some()) {
+            Some(pid) => limits::wait(pid).await?,
+            None => (child.wait().await?, limits::Usage::default()),
+        };
+        #[cfg(windows)]
+        let (status, usage) = {
+            let status = child.wait().await?;
+            let usage = job_object.as_ref().map(limits::usage);
+            (status, usage.unwrap_or_default())
+        };
+        #[cfg(not(any(unix, windows)))]
+        let (status, usage) = (child.wait().await?, limits::Usage::default());
+
+        set_limit_exceeded(
+            job_id,
+            measured_limits.and_then(|l| limits::exceeded(&status, &usage, &l)),
+        );
+        Ok(Output {
+            status,
+            stdout,
+            stderr: stderr_task.await.unwrap_or_default(),
+        })
+    }
+    .await;
+    tracked.finished();
+
+    set_pid(job_id, None);
+    set_container_name(job_id, None);
+    result
+}
+
+/// Kill a job's current process, and its container if it runs in one
+fn kill_job_process(pid: Option<u32>, container: Option<(ContainerSettings, String)>) {
+    if let Some(pid) = pid {
+        processes::kill_tree(pid);
+    }
+    if let Some((settings, name)) = container {
+        container::kill(&settings, &name);
+    }
+}
+
+/// Cancel a queued or running job, killing its process. Returns false if
+/// the job is unknown or already done.
+pub fn cancel(job_id: &str) -> bool {
+    let (pid, container) = match JOBS.lock() {
+        Ok(mut jobs) => match jobs.states.get_mut(job_id) {
+            Some(job) if matches!(job.status, JobStatus::Queued | JobStatus::Running) => {
+                job.cancelled = true;
+                if job.status == JobStatus::Queued {
+                    job.status = JobStatus::Cancelled;
+                }
+                (
+                    job.pid,
+                    job.container.clone().zip(job.container_name.clone()),
+                )
+            }
+            _ => return false,
+        },
+        Err(_) => return false,
+    };
+
+    kill_job_process(pid, container);
+    packages::cancel_job_installs(job_id);
+    true
+}
+
+/// Cancel every queued or running job of a project
+pub fn cancel_project(project_id: &str) {
+    let job_ids: Vec<String> = match JOBS.lock() {
+        Ok(jobs) => jobs
+            .states
+            .iter()
+            .filter(|(_, j)| j.project_id.as_deref() == Some(project_id))
+            .map(|(id, _)| id.clone())
+            .collect(),
+        Err(_) => return,
+    };
+    for job_id in job_ids {
+        cancel(&job_id);
+    }
+}
+
+/// Kill the process a job is currently running without cancelling the job
+pub fn kill_running(job_id: &str) {
+    let (pid, container) = match JOBS.lock() {
+        Ok(mut jobs) => match jobs.states.get_mut(job_id) {
+            Some(job) => (
+                job.pid.take(),
+                job.container.clone().zip(job.container_name.take()),
+            ),
+            None => return,
+        },
+        Err(_) => return,
+    };
+
+    kill_job_process(pid, container);
+}
@@ -1,21 +1,93 @@
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, RunEvent};
 use tempfile::TempDir;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 
-#[derive(Debug, Serialize, Deserialize)]
+mod annotations;
+mod archive;
+mod assets;
+mod backup;
+mod bibliography;
+mod container;
+mod encoding;
+mod export;
+mod external;
+mod fonts;
+mod formatter;
+mod history;
+mod includes;
+mod jobs;
+mod limits;
+mod local_styles;
+mod log_parser;
+mod mirrors;
+mod output;
+mod overleaf;
+mod packages;
+mod pdf;
+mod pdf_metadata;
+mod preamble;
+mod print;
+mod processes;
+mod project;
+mod proxy;
+mod recent;
+mod recovery;
+mod remote;
+mod save;
+mod search;
+mod settings;
+mod snippet;
+mod stats;
+mod templates;
+mod tex_install;
+mod tex_path;
+mod tinytex;
+mod trash;
+mod watcher;
+mod wordcount;
+mod zotero;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CompilationResult {
+    job_id: String,
     success: bool,
+    /// Copy of the PDF in the managed output folder
     pdf_path: Option<String>,
-    pdf_data: Option<Vec<u8>>,
+    /// Where the webview loads the PDF from, see `output`; its size and page
+    /// count are in `metrics`
+    pdf_url: Option<String>,
+    /// DVI/XDV/PS output when requested
+    output_path: Option<String>,
+    /// One SVG document per page for SVG output
+    svg_pages: Option<Vec<String>>,
     log: String,
     errors: Vec<CompilationError>,
     warnings: Vec<CompilationWarning>,
+    metrics: CompileMetrics,
+    /// Pages (1-based) that differ from the previous compile's PDF, None
+    /// when there is nothing to compare against
+    changed_pages: Option<Vec<u32>>,
+}
+
+/// Timing and size figures for a build summary
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CompileMetrics {
+    /// Wall time of each engine pass, in milliseconds
+    pass_ms: Vec<u64>,
+    passes: u32,
+    /// Wall time of the whole compile including tools, in milliseconds
+    total_ms: u64,
+    pdf_size: Option<u64>,
+    page_count: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -23,6 +95,26 @@ pub struct CompilationError {
     line: i32,
     message: String,
     file: Option<String>,
+    /// Source excerpt TeX showed at the error position
+    context: Option<String>,
+    /// Set when the engine could not find a font
+    missing_font: Option<fonts::MissingFont>,
+    /// Set when a process was stopped by a resource limit
+    limit_exceeded: Option<limits::LimitExceeded>,
+}
+
+impl CompilationError {
+    /// An error that isn't tied to a place in the sources
+    pub fn message(message: impl Into<String>) -> Self {
+        CompilationError {
+            line: 0,
+            message: message.into(),
+            file: None,
+            context: None,
+            missing_font: None,
+            limit_exceeded: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -30,19 +122,98 @@ pub struct CompilationWarning {
     line: i32,
     message: String,
     file: Option<String>,
+    /// Set for overfull/underfull box warnings
+    bad_box: Option<BadBox>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BadBoxKind {
+    Overfull,
+    Underfull,
+}
+
+/// Location and severity of an overfull or underfull box
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BadBox {
+    kind: BadBoxKind,
+    /// "hbox" or "vbox"
+    box_type: String,
+    /// Last line of the paragraph or alignment; the warning's line is the first
+    end_line: Option<i32>,
+    /// Badness of an underfull box, up to 10000
+    badness: Option<u32>,
+    /// How far an overfull box sticks out, in pt
+    overflow_pt: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompileRequest {
     content: String,
     files: HashMap<String, String>,
-    engine: Option<String>,     // "xelatex", "pdflatex", "lualatex"
-    auto_install: Option<bool>, // Auto-install missing packages
+    /// Figures and other non-text files, written to the build directory as is
+    #[serde(default)]
+    binary_files: HashMap<String, Vec<u8>>,
+    /// Compile straight from this folder on disk instead of `content` and
+    /// `files`, building in its `.offleaf/build` subfolder
+    source_dir: Option<String>,
+    /// "xelatex" (default), "pdflatex", "lualatex", "tectonic" or "context".
+    /// A `% !TEX program` comment takes precedence unless `engine_override`.
+    engine: Option<String>,
+    /// The user chose `engine`, so it beats the document's magic comment
+    engine_override: Option<bool>,
+    /// Auto-install missing packages
+    auto_install: Option<bool>,
+    /// Compile through texliveonfly, which installs every file the engine
+    /// stops on, e.g. .fd or .bst files \usepackage parsing can't see.
+    /// TeX Live only, on this machine, and only once the user agreed to it
+    /// in the install policy.
+    install_on_the_fly: Option<bool>,
+    /// "makeindex" (default) or "xindy"
+    index_tool: Option<String>,
+    /// "bibtex" or "biber" instead of the one the sources call for
+    bibliography_tool: Option<String>,
+    /// Kill the engine after this many seconds
+    timeout_secs: Option<u64>,
+    /// Keep build files between compiles of this project
+    project_id: Option<String>,
+    /// Single-pass draft preview with image placeholders
+    fast: Option<bool>,
+    /// Appended after the project's own extra flags
+    extra_args: Option<Vec<String>>,
+    /// Requires the project to allow shell escape
+    shell_escape: Option<bool>,
+    /// "pdf" (default), "dvi", "xdv" or "ps"
+    output_format: Option<String>,
+    /// File compiled as the document root: "main.tex" (default) or a key of `files`
+    root_file: Option<String>,
+    /// Upper bound on engine passes when the log asks for reruns (default 5)
+    max_passes: Option<u32>,
+    /// Compile against a cached format of the preamble (pdflatex/xelatex)
+    precompile_preamble: Option<bool>,
+    /// CPU, memory and output-size limits for the engine and tools; none
+    /// unless set
+    limits: Option<limits::ResourceLimits>,
+    /// Compile in a Docker/Podman container; overrides the project setting
+    container: Option<container::ContainerSettings>,
+    /// Compile on a remote compile server; overrides the project setting
+    remote: Option<remote::RemoteServer>,
+    /// Cancel the project's earlier compiles instead of queueing behind them
+    supersede: Option<bool>,
+    /// Attach the .tex and .bib sources to the PDF as embedded files
+    embed_sources: Option<bool>,
+    /// Build in a throwaway directory even for a project, as parallel batch
+    /// targets do so they don't share one
+    #[serde(skip)]
+    temp_build: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DetectedPackage {
+    /// Style or class file name, as in \usepackage
     name: String,
+    /// TeX Live package to install it from
+    package: String,
     installed: bool,
     options: Option<String>,
 }
@@ -59,15 +230,95 @@ pub struct AutoInstallResult {
     success: bool,
     installed: Vec<String>,
     failed: Vec<String>,
+    /// Packages the install policy holds back until the user confirms them
+    pending: Vec<String>,
     message: String,
 }
 
-// Essential packages that should be pre-installed
+lazy_static::lazy_static! {
+    /// Last successful compile, with its build directory kept alive for SyncTeX
+    static ref LAST_BUILD: Mutex<Option<LastBuild>> = Mutex::new(None);
+}
+
+/// Build directory and jobname of the last successful compile
+struct LastBuild {
+    dir: BuildDir,
+    jobname: String,
+    /// See `annotations::pdf_identity`
+    pdf_identity: Vec<u8>,
+}
+
+/// Directory a compile runs in
+enum BuildDir {
+    /// Fresh directory removed when dropped
+    Temp(TempDir),
+    /// Per-project directory kept between compiles for incremental builds
+    Persistent(PathBuf),
+}
+
+impl BuildDir {
+    /// Open (or create) the persistent build directory of a project
+    async fn persistent(project_id: &str) -> Result<Self, String> {
+        let key: String = project_id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        if key.is_empty() {
+            return Err("Invalid project ID".to_string());
+        }
+
+        let dir = project::cached_build_dir(&key);
+        fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| format!("Failed to create build directory: {}", e))?;
+
+        Ok(BuildDir::Persistent(dir))
+    }
+
+    /// Open (or create) the build directory kept inside a folder project
+    async fn in_folder(source_dir: &Path) -> Result<Self, String> {
+        let dir = source_dir.join(".offleaf").join("build");
+        fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| format!("Failed to create build directory: {}", e))?;
+        Ok(BuildDir::Persistent(dir))
+    }
+
+    /// Remove outputs of a previous compile so they can't be mistaken for new ones
+    async fn remove_stale_outputs(&self, jobname: &str) {
+        for ext in ["pdf", "dvi", "xdv", "ps", "idx", "glo", "acn"] {
+            let _ = fs::remove_file(self.path().join(format!("{}.{}", jobname, ext))).await;
+        }
+        for (_, path) in svg_page_files(self.path(), jobname).await {
+            let _ = fs::remove_file(path).await;
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            BuildDir::Temp(dir) => dir.path(),
+            BuildDir::Persistent(dir) => dir,
+        }
+    }
+}
+
+// CJK packages as spelled by their style files, which the installed check
+// looks up with kpsewhich, case-sensitively
+const CJK_PACKAGE: &str = "CJK";
+const XECJK_PACKAGE: &str = "xeCJK";
+
+// Style files of the essential packages that should be pre-installed
 pub const ESSENTIAL_PACKAGES: &[&str] = &[
     // Korean/CJK support
-    "kotex-utf",
-    "cjk",
-    "xecjk",
+    "kotex",
+    CJK_PACKAGE,
+    XECJK_PACKAGE,
     // Math
     "amsmath",
     "amssymb",
@@ -99,6 +350,24 @@ pub const ESSENTIAL_PACKAGES: &[&str] = &[
     "float",
 ];
 
+/// Parse LaTeX content to extract the document class, from \documentclass
+/// or a class's \LoadClass
+fn parse_document_classes(content: &str) -> Vec<(String, Option<String>)> {
+    let re = Regex::new(
+        r"\\(?:documentclass|LoadClass(?:WithOptions)?)\s*(?:\[([^\]]*)\])?\s*\{([^}]+)\}",
+    )
+    .unwrap();
+    re.captures_iter(content)
+        .map(|cap| {
+            (
+                cap[2].trim().to_string(),
+                cap.get(1).map(|m| m.as_str().to_string()),
+            )
+        })
+        .filter(|(name, _)| !name.is_empty())
+        .collect()
+}
+
 /// Parse LaTeX content to extract \usepackage commands
 fn parse_usepackages(content: &str) -> Vec<(String, Option<String>)> {
     let mut packages = Vec::new();
@@ -135,44 +404,202 @@ fn parse_usepackages(content: &str) -> Vec<(String, Option<String>)> {
     packages
 }
 
-/// Check if a package is installed using kpsewhich
-async fn is_package_installed(package: &str) -> bool {
-    let result = Command::new("kpsewhich")
-        .arg(format!("{}.sty", package))
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .await
-        .map(|s| s.success())
-        .unwrap_or(false);
+/// TeXShop-style `% !TEX root = ...` / `% !TEX program = ...` comments
+#[derive(Debug, Default)]
+struct MagicComments {
+    root: Option<String>,
+    program: Option<String>,
+}
 
-    if result {
-        return true;
+/// Parse magic comments from the comment block at the top of a document
+fn parse_magic_comments(content: &str) -> MagicComments {
+    let re = Regex::new(r"(?i)^%\s*!\s*TeX\s+(TS-program|program|root)\s*=\s*(.+?)\s*$").unwrap();
+    let mut magic = MagicComments::default();
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        // Only the leading comments count, as in other editors
+        if !line.starts_with('%') {
+            break;
+        }
+        if let Some(cap) = re.captures(line) {
+            if cap[1].eq_ignore_ascii_case("root") {
+                magic
+                    .root
+                    .get_or_insert_with(|| cap[2].trim_start_matches("./").to_string());
+            } else {
+                magic.program.get_or_insert_with(|| cap[2].to_lowercase());
+            }
+        }
     }
 
-    // Try .cls for document classes
-    Command::new("kpsewhich")
-        .arg(format!("{}.cls", package))
+    magic
+}
+
+/// Packages that only work with -shell-escape
+pub const SHELL_ESCAPE_PACKAGES: &[&str] = &[
+    "minted",
+    "gnuplottex",
+    "pythontex",
+    "svg",
+    "sagetex",
+    "bashful",
+];
+
+/// Find packages in the document that need shell escape
+fn packages_requiring_shell_escape(content: &str) -> Vec<String> {
+    let mut found: Vec<String> = parse_usepackages(content)
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| SHELL_ESCAPE_PACKAGES.contains(&name.as_str()))
+        .collect();
+    found.sort();
+    found.dedup();
+    found
+}
+
+/// Engine flags a project or request may add. Anything else could run
+/// programs, e.g. -shell-escape, -cnf-line=shell_escape=t or xelatex's
+/// -output-driver, or move the outputs the compile looks for (-jobname).
+const SAFE_ENGINE_FLAGS: &[&str] = &[
+    "8bit",
+    "draftmode",
+    "enc",
+    "etex",
+    "file-line-error",
+    "no-file-line-error",
+    "halt-on-error",
+    "interaction",
+    "kanji",
+    "kanji-internal",
+    "mltex",
+    "no-shell-escape",
+    "nosocket",
+    "papersize",
+    "recorder",
+    "safer",
+    "shell-restricted",
+    "src-specials",
+    "synctex",
+    "translate-file",
+];
+
+/// Whether an engine flag is on the allowlist, with or without a value
+fn is_safe_engine_flag(arg: &str) -> bool {
+    let Some(flag) = arg.strip_prefix('-') else {
+        return false;
+    };
+    let flag = flag.strip_prefix('-').unwrap_or(flag);
+    let name = flag.split_once('=').map_or(flag, |(name, _)| name);
+    SAFE_ENGINE_FLAGS.contains(&name)
+}
+
+/// Check if kpsewhich finds a file, remembering the answer until packages
+/// are installed or removed
+async fn kpsewhich_finds(file: &str) -> bool {
+    if let Some(found) = packages::cached_lookup(file).await {
+        return found;
+    }
+    let status = tex_path::command("kpsewhich")
+        .arg(file)
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .status()
-        .await
-        .map(|s| s.success())
-        .unwrap_or(false)
+        .await;
+    // Not remembered without TeX installed, which may be about to change
+    let Ok(status) = status else {
+        return false;
+    };
+    let found = status.success();
+    packages::record_lookup(file, found).await;
+    found
+}
+
+/// Check if a package is installed using kpsewhich
+async fn is_package_installed(package: &str) -> bool {
+    kpsewhich_finds(&format!("{}.sty", package)).await
+        // Try .cls for document classes
+        || kpsewhich_finds(&format!("{}.cls", package)).await
+}
+
+/// Check if a document class is installed using kpsewhich
+async fn is_class_installed(class: &str) -> bool {
+    kpsewhich_finds(&format!("{}.cls", class)).await
+}
+
+/// Classes and packages a document loads, as (name, options, is class)
+fn parse_dependencies(content: &str) -> Vec<(String, Option<String>, bool)> {
+    let classes = parse_document_classes(content)
+        .into_iter()
+        .map(|(name, options)| (name, options, true));
+    let packages = parse_usepackages(content)
+        .into_iter()
+        .map(|(name, options)| (name, options, false));
+    classes.chain(packages).collect()
+}
+
+/// Whether a class or package is available to a document compiled in one
+/// of `dirs`: TeX takes a project's own copy before an installed one
+async fn is_dependency_installed(name: &str, is_class: bool, dirs: &[&Path]) -> bool {
+    let file = format!("{}.{}", name, if is_class { "cls" } else { "sty" });
+    if dirs.iter().any(|dir| dir.join(&file).is_file()) {
+        return true;
+    }
+    if is_class {
+        is_class_installed(name).await
+    } else {
+        is_package_installed(name).await
+    }
 }
 
-/// Install missing packages
-async fn install_missing_packages(packages: &[String]) -> AutoInstallResult {
+/// Install the packages that ship the missing style files. Without
+/// `policy` the user asked for the install; with it, only what the policy
+/// allows is installed and the rest is returned as pending.
+async fn install_missing_packages(
+    install: &packages::Install,
+    files: &[String],
+    policy: Option<&packages::InstallPolicy>,
+) -> AutoInstallResult {
     let mut installed = Vec::new();
     let mut failed = Vec::new();
+    let mut pending = Vec::new();
+    let manager = packages::manager().await;
+
+    // Several files can come from one package, e.g. graphicx and color
+    let mut packages: Vec<String> = Vec::new();
+    for file in files {
+        let package = match manager {
+            Ok(manager) => packages::package_providing(manager, file).await,
+            Err(_) => file.clone(),
+        };
+        if !packages.contains(&package) {
+            packages.push(package);
+        }
+    }
 
-    for pkg in packages {
-        let output = Command::new("tlmgr")
-            .args(["install", pkg])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .await;
+    if let Some(policy) = policy {
+        if policy.mode == packages::AutoInstallMode::Never {
+            return AutoInstallResult {
+                success: false,
+                installed,
+                failed,
+                pending,
+                message: format!(
+                    "Auto-install is turned off; missing packages: {}",
+                    packages.join(", ")
+                ),
+            };
+        }
+        (packages, pending) = packages.into_iter().partition(|p| policy.allows(p));
+    }
+
+    for pkg in &packages {
+        let output = match manager {
+            Ok(manager) => install.run(manager, packages::Action::Install(pkg)).await,
+            Err(ref e) => Err(e.clone()),
+        };
 
         match output {
             Ok(out) if out.status.success() => {
@@ -185,7 +612,19 @@ async fn install_missing_packages(packages: &[String]) -> AutoInstallResult {
     }
 
     let success = failed.is_empty();
-    let message = if success {
+    let message = if packages.is_empty() {
+        format!(
+            "{} packages need confirmation: {}",
+            pending.len(),
+            pending.join(", ")
+        )
+    } else if install.is_cancelled() {
+        format!(
+            "Installation cancelled after {} of {} packages",
+            installed.len(),
+            packages.len()
+        )
+    } else if success {
         format!("Successfully installed {} packages", installed.len())
     } else {
         format!(
@@ -200,160 +639,1586 @@ async fn install_missing_packages(packages: &[String]) -> AutoInstallResult {
         success,
         installed,
         failed,
+        pending,
         message,
     }
 }
 
-fn parse_latex_log(log: &str) -> (Vec<CompilationError>, Vec<CompilationWarning>) {
+/// Parse a ConTeXt (mtxrun) log into errors and warnings
+fn parse_context_log(log: &str) -> (Vec<CompilationError>, Vec<CompilationWarning>) {
     let mut errors = Vec::new();
     let mut warnings = Vec::new();
 
+    // e.g. "tex error       > tex error on line 5 in file ./main.tex: Undefined control sequence"
+    let tex_error_re =
+        Regex::new(r"tex error\s*>\s*tex error on line (\d+) in file (.+?): (.*)$").unwrap();
+
     for line in log.lines() {
-        // Match error patterns
-        if line.starts_with('!') {
+        if let Some(cap) = tex_error_re.captures(line) {
+            let file = cap[2].trim_start_matches("./").to_string();
             errors.push(CompilationError {
-                line: 0,
-                message: line.trim_start_matches('!').trim().to_string(),
-                file: None,
+                line: cap[1].parse().unwrap_or(0),
+                message: cap[3].trim().trim_start_matches('!').trim().to_string(),
+                file: Some(file),
+                context: None,
+                missing_font: None,
+                limit_exceeded: None,
             });
+        } else if line.contains("fatal error") || line.contains("> error") {
+            errors.push(CompilationError::message(line.trim()));
+        } else if let Some((category, message)) = line.split_once('>') {
+            // Warnings are reported as "category > ... warning ..."
+            if !category.trim().is_empty() && message.to_lowercase().contains("warning") {
+                warnings.push(CompilationWarning {
+                    line: 0,
+                    message: line.trim().to_string(),
+                    file: None,
+                    bad_box: None,
+                });
+            }
+        }
+    }
+
+    (errors, warnings)
+}
+
+/// Typesetting engine used for a compile
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Engine {
+    Xelatex,
+    Pdflatex,
+    Lualatex,
+    Tectonic,
+    Context,
+}
+
+impl Engine {
+    const ALL: [Engine; 5] = [
+        Engine::Xelatex,
+        Engine::Pdflatex,
+        Engine::Lualatex,
+        Engine::Tectonic,
+        Engine::Context,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "xelatex" => Some(Engine::Xelatex),
+            "pdflatex" => Some(Engine::Pdflatex),
+            "lualatex" => Some(Engine::Lualatex),
+            "tectonic" => Some(Engine::Tectonic),
+            "context" => Some(Engine::Context),
+            _ => None,
+        }
+    }
+
+    fn command(&self) -> &'static str {
+        match self {
+            Engine::Xelatex => "xelatex",
+            Engine::Pdflatex => "pdflatex",
+            Engine::Lualatex => "lualatex",
+            Engine::Tectonic => "tectonic",
+            Engine::Context => "context",
+        }
+    }
+
+    /// Tectonic and ConTeXt rerun themselves and run their own
+    /// bibliography/index processing
+    fn runs_own_passes(&self) -> bool {
+        matches!(self, Engine::Tectonic | Engine::Context)
+    }
+}
+
+/// Final output requested from a compile
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Pdf,
+    Dvi,
+    Xdv,
+    Ps,
+    Svg,
+}
+
+impl OutputFormat {
+    fn for_engine(engine: Engine, name: Option<&str>) -> Result<Self, String> {
+        let format = match name.unwrap_or("pdf") {
+            "pdf" => OutputFormat::Pdf,
+            "dvi" => OutputFormat::Dvi,
+            "xdv" => OutputFormat::Xdv,
+            "ps" => OutputFormat::Ps,
+            "svg" => OutputFormat::Svg,
+            other => return Err(format!("Unsupported output format: {}", other)),
+        };
+
+        match (engine, format) {
+            (_, OutputFormat::Pdf) => Ok(format),
+            (Engine::Tectonic | Engine::Context, _) => {
+                Err(format!("{} only produces PDF output", engine.command()))
+            }
+            (Engine::Xelatex, _) => Ok(format),
+            (_, OutputFormat::Xdv) => Err("XDV output requires xelatex".to_string()),
+            _ => Ok(format),
+        }
+    }
+
+    /// Extension of the DVI-like file the engine writes, if not producing PDF directly
+    fn intermediate_extension(&self, engine: Engine) -> Option<&'static str> {
+        match (self, engine) {
+            (OutputFormat::Pdf, _) => None,
+            (_, Engine::Xelatex) => Some("xdv"),
+            _ => Some("dvi"),
+        }
+    }
+}
+
+/// Per-page SVGs written by dvisvgm as `<jobname>-<page>.svg`, in page order
+async fn svg_page_files(build_dir: &Path, jobname: &str) -> Vec<(u32, PathBuf)> {
+    let mut pages = Vec::new();
+    let prefix = format!("{}-", jobname);
+    if let Ok(mut entries) = fs::read_dir(build_dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let page = name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".svg"))
+                .and_then(|page| page.parse().ok());
+            if let Some(page) = page {
+                pages.push((page, entry.path()));
+            }
         }
-        // Match line number errors like "l.42 ..."
-        else if let Some(stripped) = line.strip_prefix("l.") {
-            if let Some(num_end) = stripped.find(' ') {
-                if let Ok(line_num) = stripped[..num_end].parse::<i32>() {
-                    let msg = stripped[num_end..].trim().to_string();
-                    if !msg.is_empty() {
-                        errors.push(CompilationError {
-                            line: line_num,
-                            message: msg,
-                            file: None,
-                        });
-                    }
+    }
+    pages.sort();
+    pages
+}
+
+/// Convert the engine's DVI/XDV output to PDF, going through dvips for
+/// PostScript; SVG output also gets one dvisvgm file per page
+async fn run_dvi_postprocess(
+    job_id: &str,
+    format: OutputFormat,
+    jobname: &str,
+    ext: &str,
+    build_dir: &Path,
+    env: &HashMap<String, String>,
+) -> (String, Vec<CompilationError>) {
+    let input = format!("{}.{}", jobname, ext);
+    let ps = format!("{}.ps", jobname);
+    let pdf = format!("{}.pdf", jobname);
+    let steps: Vec<(&str, Vec<String>)> = match format {
+        OutputFormat::Ps => vec![
+            ("dvips", vec!["-o".into(), ps.clone(), input]),
+            ("ps2pdf", vec![ps, pdf]),
+        ],
+        OutputFormat::Svg => vec![
+            (
+                "dvisvgm",
+                vec![
+                    "--page=1-".into(),
+                    "--no-fonts".into(),
+                    "-o".into(),
+                    format!("{}-%p.svg", jobname),
+                    input.clone(),
+                ],
+            ),
+            ("dvipdfmx", vec!["-o".into(), pdf, input]),
+        ],
+        _ => vec![("dvipdfmx", vec!["-o".into(), pdf, input])],
+    };
+
+    let mut log = String::new();
+    let mut errors = Vec::new();
+
+    for (tool, args) in steps {
+        let output = jobs::run_command(
+            job_id,
+            Command::new(tool)
+                .args(&args)
+                .envs(env)
+                .current_dir(build_dir),
+        )
+        .await;
+        match output {
+            Ok(out) => {
+                log.push_str(&String::from_utf8_lossy(&out.stdout));
+                log.push('\n');
+                log.push_str(&String::from_utf8_lossy(&out.stderr));
+                log.push('\n');
+                if !out.status.success() {
+                    errors.push(CompilationError::message(format!(
+                        "{} exited with {}",
+                        tool, out.status
+                    )));
+                    break;
                 }
             }
+            Err(e) => {
+                let message = format!("Failed to run {}: {}", tool, e);
+                log.push_str(&message);
+                errors.push(CompilationError::message(message));
+                break;
+            }
         }
-        // Match warnings
-        else if line.contains("Warning:") {
-            warnings.push(CompilationWarning {
-                line: 0,
-                message: line.to_string(),
-                file: None,
-            });
+    }
+
+    (log, errors)
+}
+
+/// Engine passes allowed when the request doesn't set a maximum
+const DEFAULT_MAX_PASSES: u32 = 5;
+
+/// Whether the engine output asks for another pass to settle cross-references
+fn log_requests_rerun(log: &str) -> bool {
+    let re = Regex::new(r"(?i)rerun to get|label\(s\) may have changed|rerun latex").unwrap();
+    re.is_match(log)
+}
+
+/// Generated lists that only show up in the document on the next pass
+async fn read_toc_files(build_dir: &Path, jobname: &str) -> Vec<Option<Vec<u8>>> {
+    let mut contents = Vec::new();
+    for ext in ["toc", "lof", "lot"] {
+        contents.push(
+            fs::read(build_dir.join(format!("{}.{}", jobname, ext)))
+                .await
+                .ok(),
+        );
+    }
+    contents
+}
+
+/// Jobname TeX derives from the root file, which names all of its outputs
+fn jobname_of(root_file: &str) -> String {
+    Path::new(root_file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "main".to_string())
+}
+
+/// Build the engine invocation for one pass
+#[allow(clippy::too_many_arguments)]
+fn engine_command(
+    engine: Engine,
+    build_path: &Path,
+    root_file: &str,
+    output_format: OutputFormat,
+    fast: bool,
+    shell_escape: bool,
+    extra_args: &[String],
+    format: Option<&str>,
+) -> Command {
+    let mut command = Command::new(engine.command());
+    command.current_dir(build_path);
+
+    if engine == Engine::Tectonic {
+        command
+            .args([
+                "--synctex",
+                "--keep-logs",
+                "--keep-intermediates",
+                "--outdir",
+            ])
+            .arg(build_path);
+        if shell_escape {
+            command.args(["-Z", "shell-escape"]);
         }
-        // Match LaTeX errors with file info
-        else if line.contains("LaTeX Error:") {
-            errors.push(CompilationError {
-                line: 0,
-                message: line.to_string(),
-                file: None,
-            });
+        command.args(extra_args).arg(root_file);
+        return command;
+    }
+
+    if engine == Engine::Context {
+        // ConTeXt writes its outputs next to the input, i.e. the build dir
+        command
+            .args(["--nonstopmode", "--synctex"])
+            .args(extra_args)
+            .arg(root_file);
+        return command;
+    }
+
+    command
+        .args([
+            "-interaction=nonstopmode",
+            "-halt-on-error",
+            "-file-line-error",
+            "-synctex=1",
+            "-output-directory",
+        ])
+        .arg(build_path);
+    if let Some(format) = format {
+        command.arg(format!("-fmt={}", format));
+    }
+    match output_format.intermediate_extension(engine) {
+        Some("xdv") => {
+            command.arg("-no-pdf");
+        }
+        Some(_) => {
+            command.arg("-output-format=dvi");
+        }
+        None => {}
+    }
+    if shell_escape {
+        command.arg("-shell-escape");
+    }
+    command.args(extra_args);
+
+    if fast {
+        // -draftmode skips writing the PDF altogether, so image
+        // placeholders come from graphicx's draft option instead
+        command
+            .arg(format!("-jobname={}", jobname_of(root_file)))
+            .arg(format!(
+                "\\PassOptionsToPackage{{draft}}{{graphicx}}\\input{{{}}}",
+                root_file
+            ));
+    } else {
+        command.arg(root_file);
+    }
+
+    command
+}
+
+/// Whether the project ships its own latexmk configuration
+fn has_latexmkrc(files: &HashMap<String, String>) -> bool {
+    files.contains_key(".latexmkrc") || files.contains_key("latexmkrc")
+}
+
+/// Build a latexmk invocation that runs all passes and tools itself.
+/// latexmk picks up the project's rc file from the build directory.
+fn latexmk_command(
+    engine: Engine,
+    build_path: &Path,
+    root_file: &str,
+    output_format: OutputFormat,
+    shell_escape: bool,
+    extra_args: &[String],
+) -> Command {
+    let mut command = Command::new("latexmk");
+    command.current_dir(build_path);
+
+    let mode = match (output_format.intermediate_extension(engine), engine) {
+        (Some("xdv"), _) => "-xdv",
+        (Some(_), Engine::Lualatex) => "-dvilua",
+        (Some(_), _) => "-dvi",
+        (None, Engine::Xelatex) => "-xelatex",
+        (None, Engine::Lualatex) => "-lualatex",
+        (None, _) => "-pdf",
+    };
+    command.args([
+        mode,
+        "-interaction=nonstopmode",
+        "-halt-on-error",
+        "-file-line-error",
+        "-synctex=1",
+    ]);
+    if shell_escape {
+        command.arg("-shell-escape");
+    }
+    for arg in extra_args {
+        command.arg(format!("-latexoption={}", arg));
+    }
+    command.arg(root_file);
+
+    command
+}
+
+/// Whether texliveonfly, a TeX Live package of its own, is installed
+async fn has_texliveonfly() -> bool {
+    processes::output(
+        Command::new("texliveonfly")
+            .arg("--help")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null()),
+    )
+    .await
+    .is_ok_and(|output| output.status.success())
+}
+
+/// Wrap an engine invocation in texliveonfly, which installs the file the
+/// engine stops on with tlmgr and reruns it. None when the arguments can't
+/// go through texliveonfly's single, whitespace-split argument string.
+fn on_the_fly_command(command: &Command, build_path: &Path) -> Option<Command> {
+    let command = command.as_std();
+    let mut args: Vec<String> = command
+        .get_args()
+        .map(|arg| {
+            // The engine runs in the build dir, whose path may hold spaces
+            if Path::new(arg) == build_path {
+                ".".to_string()
+            } else {
+                arg.to_string_lossy().to_string()
+            }
+        })
+        .collect();
+    let file = args.pop()?;
+    if args.iter().any(|arg| arg.contains(char::is_whitespace)) {
+        return None;
+    }
+
+    let mut wrapped = proxy::command("texliveonfly");
+    wrapped
+        .arg(format!(
+            "--compiler={}",
+            command.get_program().to_string_lossy()
+        ))
+        .arg(format!("--arguments={}", args.join(" ")))
+        .arg(file);
+    if let Some(dir) = command.get_current_dir() {
+        wrapped.current_dir(dir);
+    }
+    Some(wrapped)
+}
+
+/// Bibliography processor required by a document
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BibBackend {
+    Bibtex,
+    Biber,
+}
+
+impl BibBackend {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bibtex" => Some(BibBackend::Bibtex),
+            "biber" => Some(BibBackend::Biber),
+            _ => None,
+        }
+    }
+
+    fn command(&self) -> &'static str {
+        match self {
+            BibBackend::Bibtex => "bibtex",
+            BibBackend::Biber => "biber",
+        }
+    }
+}
+
+/// Detect the bibliography backend from the document sources
+fn detect_bib_backend(content: &str, files: &HashMap<String, String>) -> Option<BibBackend> {
+    let sources = std::iter::once(content).chain(
+        files
+            .iter()
+            .filter(|(name, _)| name.ends_with(".tex"))
+            .map(|(_, text)| text.as_str()),
+    );
+
+    let biblatex_re =
+        Regex::new(r"\\usepackage\s*(?:\[([^\]]*)\])?\s*\{[^}]*\bbiblatex\b").unwrap();
+    let bibliography_re = Regex::new(r"\\(?:bibliography\s*\{|addbibresource\b)").unwrap();
+    let mut uses_bibliography = false;
+    let mut biblatex_backend = None;
+
+    for source in sources {
+        // Ignore comments, but not escaped \% signs
+        let source: String = source
+            .lines()
+            .map(includes::strip_comment)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if let Some(cap) = biblatex_re.captures(&source) {
+            let options = cap.get(1).map(|m| m.as_str()).unwrap_or("");
+            biblatex_backend = if options.contains("backend=bibtex") {
+                Some(BibBackend::Bibtex)
+            } else {
+                Some(BibBackend::Biber)
+            };
+        }
+
+        if bibliography_re.is_match(&source) {
+            uses_bibliography = true;
+        }
+    }
+
+    match (biblatex_backend, uses_bibliography) {
+        (Some(backend), _) => Some(backend),
+        (None, true) => Some(BibBackend::Bibtex),
+        (None, false) => None,
+    }
+}
+
+/// Parse bibtex/biber output into errors and warnings
+fn parse_bibliography_log(
+    backend: BibBackend,
+    log: &str,
+) -> (Vec<CompilationError>, Vec<CompilationWarning>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    match backend {
+        BibBackend::Bibtex => {
+            // e.g. "I was expecting a `,' or a `}'---line 12 of file refs.bib"
+            let line_re = Regex::new(r"^(.*)---line (\d+) of file (.+)$").unwrap();
+
+            for line in log.lines() {
+                if let Some(cap) = line_re.captures(line) {
+                    errors.push(CompilationError {
+                        line: cap[2].parse().unwrap_or(0),
+                        message: cap[1].trim().to_string(),
+                        file: Some(cap[3].trim().to_string()),
+                        context: None,
+                        missing_font: None,
+                        limit_exceeded: None,
+                    });
+                } else if line.starts_with("I couldn't open") || line.starts_with("I found no") {
+                    errors.push(CompilationError::message(line.trim()));
+                } else if let Some(msg) = line.strip_prefix("Warning--") {
+                    warnings.push(CompilationWarning {
+                        line: 0,
+                        message: msg.trim().to_string(),
+                        file: None,
+                        bad_box: None,
+                    });
+                }
+            }
+        }
+        BibBackend::Biber => {
+            let line_re = Regex::new(r"line (\d+)").unwrap();
+
+            for line in log.lines() {
+                if let Some(pos) = line.find("ERROR - ") {
+                    let msg = line[pos + 8..].trim().to_string();
+                    let line_num = line_re
+                        .captures(&msg)
+                        .and_then(|cap| cap[1].parse().ok())
+                        .unwrap_or(0);
+                    errors.push(CompilationError {
+                        line: line_num,
+                        message: msg,
+                        file: None,
+                        context: None,
+                        missing_font: None,
+                        limit_exceeded: None,
+                    });
+                } else if let Some(pos) = line.find("WARN - ") {
+                    warnings.push(CompilationWarning {
+                        line: 0,
+                        message: line[pos + 7..].trim().to_string(),
+                        file: None,
+                        bad_box: None,
+                    });
+                }
+            }
         }
     }
 
     (errors, warnings)
 }
 
-#[tauri::command]
-async fn compile_latex(request: CompileRequest) -> Result<CompilationResult, String> {
-    // Create temporary directory
-    let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
-    let temp_path = temp_dir.path();
+/// Run bibtex or biber on the job in the build directory
+async fn run_bibliography_tool(
+    job_id: &str,
+    backend: BibBackend,
+    jobname: &str,
+    build_dir: &Path,
+    env: &HashMap<String, String>,
+) -> (String, Vec<CompilationError>, Vec<CompilationWarning>) {
+    let tool = backend.command();
+    let output = jobs::run_command(
+        job_id,
+        Command::new(tool)
+            .arg(jobname)
+            .envs(env)
+            .current_dir(build_dir),
+    )
+    .await;
+
+    match output {
+        Ok(out) => {
+            let log = format!(
+                "{}\n{}",
+                String::from_utf8_lossy(&out.stdout),
+                String::from_utf8_lossy(&out.stderr)
+            );
+            let (mut errors, warnings) = parse_bibliography_log(backend, &log);
+            if !out.status.success() && errors.is_empty() {
+                errors.push(CompilationError::message(format!(
+                    "{} exited with {}",
+                    tool, out.status
+                )));
+            }
+            (log, errors, warnings)
+        }
+        Err(e) => {
+            let message = format!("Failed to run {}: {}", tool, e);
+            (
+                message.clone(),
+                vec![CompilationError::message(message)],
+                vec![],
+            )
+        }
+    }
+}
+
+/// Index processor used for documents with \makeindex
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum IndexTool {
+    Makeindex,
+    Xindy,
+}
+
+impl IndexTool {
+    fn from_name(name: Option<&str>) -> Self {
+        match name {
+            Some("xindy") => IndexTool::Xindy,
+            _ => IndexTool::Makeindex,
+        }
+    }
+
+    fn command(&self) -> &'static str {
+        match self {
+            IndexTool::Makeindex => "makeindex",
+            IndexTool::Xindy => "xindy",
+        }
+    }
+}
+
+/// Parse makeindex/xindy output into errors
+fn parse_index_log(tool: IndexTool, log: &str) -> Vec<CompilationError> {
+    let mut errors = Vec::new();
+
+    match tool {
+        IndexTool::Makeindex => {
+            // e.g. "!! Input index error (file = main.idx, line = 5):"
+            //      "   -- Extra `!' at position 12 of first argument."
+            let error_re = Regex::new(r"^!! (.*) \(file = (.+), line = (\d+)\):").unwrap();
+            let mut lines = log.lines().peekable();
+
+            while let Some(line) = lines.next() {
+                if let Some(cap) = error_re.captures(line) {
+                    let detail = lines
+                        .peek()
+                        .map(|next| next.trim().trim_start_matches("--").trim())
+                        .unwrap_or("");
+                    errors.push(CompilationError {
+                        line: cap[3].parse().unwrap_or(0),
+                        message: format!("{}: {}", &cap[1], detail),
+                        file: Some(cap[2].to_string()),
+                        context: None,
+                        missing_font: None,
+                        limit_exceeded: None,
+                    });
+                }
+            }
+        }
+        IndexTool::Xindy => {
+            for line in log.lines() {
+                if let Some(msg) = line.trim().strip_prefix("ERROR:") {
+                    errors.push(CompilationError::message(msg.trim()));
+                }
+            }
+        }
+    }
 
+    errors
+}
+
+/// Run makeindex or xindy on the job's .idx file in the build directory
+async fn run_index_tool(
+    job_id: &str,
+    tool: IndexTool,
+    jobname: &str,
+    build_dir: &Path,
+    env: &HashMap<String, String>,
+) -> (String, Vec<CompilationError>) {
+    let idx = format!("{}.idx", jobname);
+    let mut command = match tool {
+        IndexTool::Makeindex => {
+            let mut cmd = Command::new(tool.command());
+            cmd.arg(&idx);
+            cmd
+        }
+        IndexTool::Xindy => {
+            let mut cmd = Command::new(tool.command());
+            cmd.args(["-M", "texindy", "-C", "utf8", "-o"])
+                .arg(format!("{}.ind", jobname))
+                .arg(&idx);
+            cmd
+        }
+    };
+    let name = tool.command();
+
+    let output = jobs::run_command(job_id, command.envs(env).current_dir(build_dir)).await;
+
+    match output {
+        Ok(out) => {
+            let log = format!(
+                "{}\n{}",
+                String::from_utf8_lossy(&out.stdout),
+                String::from_utf8_lossy(&out.stderr)
+            );
+            let mut errors = parse_index_log(tool, &log);
+            if !out.status.success() && errors.is_empty() {
+                errors.push(CompilationError::message(format!(
+                    "{} exited with {}",
+                    name, out.status
+                )));
+            }
+            (log, errors)
+        }
+        Err(e) => {
+            let message = format!("Failed to run {}: {}", name, e);
+            (message.clone(), vec![CompilationError::message(message)])
+        }
+    }
+}
+
+/// Run makeglossaries for documents using the glossaries package
+async fn run_glossaries_tool(
+    job_id: &str,
+    jobname: &str,
+    build_dir: &Path,
+    env: &HashMap<String, String>,
+) -> (String, Vec<CompilationError>) {
+    let output = jobs::run_command(
+        job_id,
+        Command::new("makeglossaries")
+            .arg(jobname)
+            .envs(env)
+            .current_dir(build_dir),
+    )
+    .await;
+
+    match output {
+        Ok(out) => {
+            let log = format!(
+                "{}\n{}",
+                String::from_utf8_lossy(&out.stdout),
+                String::from_utf8_lossy(&out.stderr)
+            );
+            // makeglossaries drives makeindex, so its errors use the same format
+            let mut errors = parse_index_log(IndexTool::Makeindex, &log);
+            if !out.status.success() && errors.is_empty() {
+                errors.push(CompilationError::message(format!(
+                    "makeglossaries exited with {}",
+                    out.status
+                )));
+            }
+            (log, errors)
+        }
+        Err(e) => {
+            let message = format!("Failed to run makeglossaries: {}", e);
+            (message.clone(), vec![CompilationError::message(message)])
+        }
+    }
+}
+
+/// Write main.tex and the additional project files into a build directory
+async fn write_sources(
+    build_path: &Path,
+    content: &str,
+    files: &HashMap<String, String>,
+    binary_files: &HashMap<String, Vec<u8>>,
+) -> Result<(), String> {
     // Write main.tex file
-    let main_tex_path = temp_path.join("main.tex");
+    let main_tex_path = build_path.join("main.tex");
     let mut file = fs::File::create(&main_tex_path)
         .await
         .map_err(|e| format!("Failed to create main.tex: {}", e))?;
-    file.write_all(request.content.as_bytes())
+    file.write_all(content.as_bytes())
         .await
         .map_err(|e| format!("Failed to write main.tex: {}", e))?;
 
     // Write additional files
-    for (filename, content) in &request.files {
-        let file_path = temp_path.join(filename);
+    let text_files = files.iter().map(|(name, text)| (name, text.as_bytes()));
+    let binary_files = binary_files
+        .iter()
+        .map(|(name, data)| (name, data.as_slice()));
+    for (filename, content) in text_files.chain(binary_files) {
+        // Names come from the frontend; "../" or an absolute path would
+        // write outside the build directory
+        let file_path = project::resolve_in(build_path, filename)?;
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent).await.ok();
         }
         let mut f = fs::File::create(&file_path)
             .await
             .map_err(|e| format!("Failed to create {}: {}", filename, e))?;
-        f.write_all(content.as_bytes())
+        f.write_all(content)
             .await
             .map_err(|e| format!("Failed to write {}: {}", filename, e))?;
     }
 
-    // Determine the LaTeX engine
-    let engine = request.engine.unwrap_or_else(|| "xelatex".to_string());
+    Ok(())
+}
+
+/// Prepare the build directory of a folder project, whose sources stay
+/// where they are: its folders are mirrored, since TeX writes the .aux of
+/// an \include{chapters/intro} into chapters/ under the build directory.
+/// Returns the project's .tex files by relative path.
+async fn mirror_folder(
+    source_dir: &Path,
+    build_path: &Path,
+) -> Result<HashMap<String, String>, String> {
+    let mut tex_files = HashMap::new();
+    for (entry, path) in project::walk(source_dir).await? {
+        if entry.is_dir {
+            let dir = project::resolve_in(build_path, &entry.path)?;
+            fs::create_dir_all(&dir)
+                .await
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        } else if entry.path.ends_with(".tex") {
+            if let Ok(text) = fs::read_to_string(&path).await {
+                tex_files.insert(entry.path, text);
+            }
+        }
+    }
+    Ok(tex_files)
+}
+
+/// Page count from the engine's "Output written on main.pdf (3 pages, ...)"
+/// line, falling back to counting page objects in the PDF itself
+fn count_pages(log: &str, pdf_data: &[u8]) -> Option<u32> {
+    let log_re = Regex::new(r"Output written on .*?\((\d+) pages?").unwrap();
+    if let Some(cap) = log_re.captures(log) {
+        return cap[1].parse().ok();
+    }
+
+    // Misses pages inside compressed object streams
+    let page_re = regex::bytes::Regex::new(r"/Type\s*/Page\b").unwrap();
+    match page_re.find_iter(pdf_data).count() {
+        0 => None,
+        n => Some(n as u32),
+    }
+}
+
+/// Manifest of the project being compiled, if it has one
+async fn project_manifest(request: &CompileRequest) -> Option<project::ProjectManifest> {
+    let dir = match (&request.source_dir, &request.project_id) {
+        (Some(dir), _) => PathBuf::from(dir),
+        (None, Some(project_id)) => project::project_dir(project_id).ok()?,
+        (None, None) => return None,
+    };
+    project::read_manifest(&dir).await
+}
+
+/// Run a compile job to completion
+async fn run_compile(
+    app: &AppHandle,
+    job_id: &str,
+    mut request: CompileRequest,
+) -> Result<CompilationResult, String> {
+    let started = std::time::Instant::now();
+    let mut metrics = CompileMetrics::default();
+    let resource_limits = request.limits;
+    if let Some(resource_limits) = resource_limits {
+        jobs::set_limits(job_id, resource_limits);
+    }
+
+    let project_settings = match &request.project_id {
+        Some(project_id) => settings::project(project_id).await,
+        None => settings::ProjectSettings::default(),
+    };
+
+    // Options saved in the project manifest fill in whatever the request
+    // leaves unset, so the frontend needn't resend them every time
+    let manifest = project_manifest(&request).await.unwrap_or_default();
+    let defaults = manifest.compile;
+    request.shell_escape = request.shell_escape.or(defaults.shell_escape);
+    request.auto_install = request.auto_install.or(defaults.auto_install);
+    request.bibliography_tool = request.bibliography_tool.or(defaults.bibliography_tool);
+    request.index_tool = request.index_tool.or(defaults.index_tool);
+    // A manifest may come with an opened folder, a cloned repository or an
+    // Overleaf pull, so its engine flags need the user's trust
+    if project_settings.trusted && !defaults.extra_args.is_empty() {
+        let mut extra_args = defaults.extra_args;
+        extra_args.extend(request.extra_args.unwrap_or_default());
+        request.extra_args = Some(extra_args);
+    }
+
+    if let Some(server) = request
+        .remote
+        .clone()
+        .or_else(|| project_settings.remote.clone())
+    {
+        return remote::compile(app, job_id, request, &server).await;
+    }
+
+    let container = request
+        .container
+        .clone()
+        .or_else(|| project_settings.container.clone());
+    let in_container = container.is_some();
+    if let Some(settings) = container {
+        jobs::set_container(job_id, settings);
+    }
+
+    // Shell escape runs arbitrary programs, so it is only passed for projects
+    // the user has explicitly allowed it for
+    let shell_escape = request.shell_escape.unwrap_or(false);
+    if shell_escape && !project_settings.shell_escape_allowed {
+        return Err("Shell escape has not been allowed for this project".to_string());
+    }
+
+    // Folder projects are read from disk instead of being sent along
+    let source_dir = request.source_dir.as_deref().map(Path::new);
+    let main_content = match source_dir {
+        Some(dir) => fs::read_to_string(dir.join("main.tex"))
+            .await
+            .unwrap_or_default(),
+        None => request.content.clone(),
+    };
+
+    // Magic comments fill in whatever the request leaves unset
+    let main_magic = parse_magic_comments(&main_content);
+
+    // `content` is main.tex; any other project file may be compiled as the root
+    let root_file = request
+        .root_file
+        .clone()
+        .or(main_magic.root)
+        .unwrap_or(manifest.root_file);
+    // A magic comment comes from the document itself, so keep it inside the
+    // project and away from the engine's options
+    if root_file.starts_with('-') {
+        return Err(format!("Invalid root file: {}", root_file));
+    }
+    let root_path = project::resolve_in(source_dir.unwrap_or(Path::new("")), &root_file)?;
+    let root_content = match source_dir {
+        Some(_) => fs::read_to_string(&root_path)
+            .await
+            .map_err(|_| format!("Root file not found: {}", root_file))?,
+        None if root_file == "main.tex" => main_content.clone(),
+        None => request
+            .files
+            .get(&root_file)
+            .cloned()
+            .ok_or_else(|| format!("Root file not found: {}", root_file))?,
+    };
+    let jobname = jobname_of(&root_file);
+    let root_magic = parse_magic_comments(&root_content);
+
+    // Reuse the project's build directory when one is given, so auxiliary
+    // files survive between compiles; otherwise use a throwaway temp dir
+    let build_dir = match (source_dir, &request.project_id) {
+        (Some(dir), _) => BuildDir::in_folder(dir).await?,
+        (None, Some(project_id)) if !request.temp_build => BuildDir::persistent(project_id).await?,
+        (None, _) => {
+            BuildDir::Temp(TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?)
+        }
+    };
+    let build_path = build_dir.path();
+    build_dir.remove_stale_outputs(&jobname).await;
+
+    let folder_files = match source_dir {
+        Some(dir) => mirror_folder(dir, build_path).await?,
+        None => {
+            write_sources(
+                build_path,
+                &request.content,
+                &request.files,
+                &request.binary_files,
+            )
+            .await?;
+            HashMap::new()
+        }
+    };
+    // The project's files for looking into, wherever they come from
+    let source_files = match source_dir {
+        Some(_) => &folder_files,
+        None => &request.files,
+    };
+
+    // Install the packages the sources load before the engine trips over them
+    if request.auto_install.unwrap_or(false) {
+        let sources = std::iter::once(root_content.as_str())
+            .chain(
+                source_files
+                    .iter()
+                    .filter(|(name, _)| name.ends_with(".tex"))
+                    .map(|(_, text)| text.as_str()),
+            )
+            .collect::<Vec<_>>()
+            .join("\n");
+        // The project's own classes and packages are in the build dir, or
+        // found in its folder through TEXINPUTS. Cancelling the job cancels
+        // the install.
+        let dirs: Vec<&Path> = std::iter::once(build_path).chain(source_dir).collect();
+        auto_install(app, &sources, &dirs, Some(job_id), None).await;
+    }
+
+    // Determine the LaTeX engine. The frontend always sends its default
+    // engine, so only an explicit choice beats the document's own.
+    let magic_program = root_magic.program.or(main_magic.program);
+    let engine_name = if request.engine_override.unwrap_or(false) {
+        request.engine.clone().or(magic_program)
+    } else {
+        magic_program.or(request.engine.clone())
+    };
+    let engine_name = engine_name
+        .or(manifest.engine)
+        .unwrap_or_else(|| "xelatex".to_string());
+    let engine = Engine::from_name(&engine_name)
+        .ok_or_else(|| format!("Unsupported engine: {}", engine_name))?;
+    let output_format = OutputFormat::for_engine(engine, request.output_format.as_deref())?;
+    // Sources outside the build directory are only found through kpathsea
+    if source_dir.is_some() && matches!(engine, Engine::Tectonic | Engine::Context) {
+        return Err(format!(
+            "{} can't compile folder projects in place",
+            engine_name
+        ));
+    }
+
+    // Detect whether bibtex/biber has to run between passes; a chosen tool
+    // also runs when detection finds nothing, e.g. a \bibliography in a
+    // macro
+    let bib_backend = request
+        .bibliography_tool
+        .as_deref()
+        .and_then(BibBackend::from_name)
+        .or_else(|| detect_bib_backend(&main_content, source_files));
+    let index_tool = IndexTool::from_name(request.index_tool.as_deref());
+    let fast = request.fast.unwrap_or(false);
+
+    // Extra engine flags: project settings first, then the request's own.
+    // Only allowlisted flags pass, so shell escape can't be smuggled in.
+    let extra_args: Vec<String> = project_settings
+        .extra_args
+        .iter()
+        .chain(request.extra_args.iter().flatten())
+        .filter(|arg| is_safe_engine_flag(arg))
+        .cloned()
+        .collect();
+
+    // Project environment overrides apply to the engine and every tool
+    let mut env = project_settings.compile_env();
+    if let Some(dir) = source_dir {
+        // The engine and tools find a folder project's sources through their
+        // search paths, ahead of any the project sets
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        for var in ["TEXINPUTS", "BIBINPUTS", "BSTINPUTS"] {
+            let rest = env.get(var).cloned().unwrap_or_default();
+            env.insert(
+                var.to_string(),
+                format!("{}{}{}", dir.display(), separator, rest),
+            );
+        }
+    }
+
+    // A project .latexmkrc (written into the build dir with the other files)
+    // means latexmk drives the build so custom rules keep working. latexmk
+    // can't find the sources of a folder project, which has no `files`.
+    // The rc is Perl that latexmk runs, so it takes a project the user
+    // trusts or has allowed to run programs anyway.
+    let rc_trusted = project_settings.trusted || project_settings.shell_escape_allowed;
+    let latexmk = !fast && !engine.runs_own_passes() && has_latexmkrc(&request.files) && rc_trusted;
+    if has_latexmkrc(&request.files) && !rc_trusted {
+        let _ = app.emit(
+            "compile-log",
+            "Ignoring the project's .latexmkrc: the project isn't trusted",
+        );
+    }
+
+    // texliveonfly stands in for the engine on the first pass, which is
+    // where anything missing shows up. It installs without going through
+    // the install policy, so the user must have agreed to it there; a
+    // container's TeX Live isn't the one the policy is about.
+    let install_policy = settings::load().await.install_policy;
+    let on_the_fly = request.install_on_the_fly.unwrap_or(false)
+        && install_policy.on_the_fly
+        && install_policy.mode != packages::AutoInstallMode::Never
+        && !in_container
+        && !fast
+        && !latexmk
+        && !engine.runs_own_passes()
+        && packages::manager()
+            .await
+            .is_ok_and(|manager| manager.program() == "tlmgr")
+        && has_texliveonfly().await;
+
+    // Heavy preambles are dumped into a cached format once and skipped on later compiles
+    let format = if request.precompile_preamble.unwrap_or(false)
+        && !fast
+        && !latexmk
+        && preamble::supports(engine)
+    {
+        preamble::prepare(job_id, engine, &root_file, &root_content, build_path, &env).await
+    } else {
+        None
+    };
 
-    // Run LaTeX compiler (twice for references)
+    // Run LaTeX compiler, rerunning while the log asks for it, the table of
+    // contents changed, or a bibliography, index or glossary was generated
+    // after the first pass. A fast preview, or an engine or latexmk that
+    // handles reruns, gets a single pass.
     let mut log_output = String::new();
+    let mut tool_log = String::new();
+    let mut tool_errors = Vec::new();
+    let mut tool_warnings = Vec::new();
+    let single_pass = fast || engine.runs_own_passes() || latexmk;
+    let max_passes = request.max_passes.unwrap_or(DEFAULT_MAX_PASSES).max(1);
+    let mut passes = 1;
+    let mut pass = 0;
+    let mut engine_ok = false;
+
+    if !shell_escape {
+        for pkg in packages_requiring_shell_escape(&root_content) {
+            tool_warnings.push(CompilationWarning {
+                line: 0,
+                message: format!(
+                    "Package {} requires shell escape, which is disabled for this compile",
+                    pkg
+                ),
+                file: None,
+                bad_box: None,
+            });
+        }
+    }
 
-    for pass in 1..=2 {
-        let output = Command::new(&engine)
-            .args([
-                "-interaction=nonstopmode",
-                "-halt-on-error",
-                "-file-line-error",
-                "-output-directory",
-                temp_path.to_str().unwrap(),
-                main_tex_path.to_str().unwrap(),
-            ])
-            .current_dir(temp_path)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .await
-            .map_err(|e| format!("Failed to run {}: {}. Is TeX Live installed?", engine, e))?;
+    // The timeout covers all passes of the compile together
+    let deadline = request
+        .timeout_secs
+        .map(|secs| tokio::time::Instant::now() + Duration::from_secs(secs));
+
+    while pass < passes {
+        pass += 1;
+        let pass_started = std::time::Instant::now();
+        let toc_before = read_toc_files(build_path, &jobname).await;
+        let mut command = if latexmk {
+            latexmk_command(
+                engine,
+                build_path,
+                &root_file,
+                output_format,
+                shell_escape,
+                &extra_args,
+            )
+        } else {
+            engine_command(
+                engine,
+                build_path,
+                &root_file,
+                output_format,
+                fast,
+                shell_escape,
+                &extra_args,
+                format.as_deref(),
+            )
+        };
+        if on_the_fly && pass == 1 {
+            if let Some(wrapped) = on_the_fly_command(&command, build_path) {
+                command = wrapped;
+            }
+        }
+        command.envs(&env);
+
+        let mut live_log = String::new();
+        let run = jobs::run_command_streaming(job_id, &mut command, |line| {
+            live_log.push_str(line);
+            live_log.push('\n');
+            let _ = app.emit("compile-log", line);
+        });
+
+        let output = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, run).await {
+                Ok(output) => output,
+                Err(_) => {
+                    jobs::kill_running(job_id);
+                    let secs = request.timeout_secs.unwrap_or_default();
+                    metrics.total_ms = started.elapsed().as_millis() as u64;
+                    return Ok(CompilationResult {
+                        job_id: job_id.to_string(),
+                        success: false,
+                        pdf_path: None,
+                        pdf_url: None,
+                        output_path: None,
+                        svg_pages: None,
+                        log: live_log,
+                        errors: vec![CompilationError::message(format!(
+                            "Compilation timed out after {} seconds (pass {})",
+                            secs, pass
+                        ))],
+                        warnings: vec![],
+                        metrics,
+                        changed_pages: None,
+                    });
+                }
+            },
+            None => run.await,
+        };
+
+        // A temporary build dir is cleaned up when it goes out of scope
+        if jobs::is_cancelled(job_id) {
+            return Err(format!("Compilation {} was cancelled", job_id));
+        }
+
+        let output = output.map_err(|e| {
+            format!(
+                "Failed to run {}: {}. Is TeX Live installed?",
+                engine.command(),
+                e
+            )
+        })?;
+        if on_the_fly && pass == 1 {
+            // tlmgr may have installed packages the lookup cache missed
+            packages::clear_lookups().await;
+        }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
         let stderr = String::from_utf8_lossy(&output.stderr);
 
-        if pass == 2 || !output.status.success() {
-            log_output = format!("{}\n{}", stdout, stderr);
+        log_output = format!("{}\n{}", stdout, stderr);
+        engine_ok = output.status.success();
+        metrics
+            .pass_ms
+            .push(pass_started.elapsed().as_millis() as u64);
+        metrics.passes = pass;
+
+        // If a pass failed, don't continue
+        if !output.status.success() {
+            if let (Some(exceeded), Some(resource_limits)) =
+                (jobs::limit_exceeded(job_id), resource_limits)
+            {
+                tool_errors.push(CompilationError {
+                    limit_exceeded: Some(exceeded),
+                    ..CompilationError::message(exceeded.message(&resource_limits))
+                });
+            }
+            break;
         }
 
-        // If first pass failed, don't continue
-        if !output.status.success() && pass == 1 {
+        if single_pass {
             break;
         }
+        let mut rerun =
+            log_requests_rerun(&stdout) || read_toc_files(build_path, &jobname).await != toc_before;
+
+        // Generate bibliography, index and glossaries between the first and second pass
+        if pass == 1 {
+            if let Some(backend) = bib_backend {
+                let (log, errors, warnings) =
+                    run_bibliography_tool(job_id, backend, &jobname, build_path, &env).await;
+                tool_log.push_str(&log);
+                tool_errors.extend(errors);
+                tool_warnings.extend(warnings);
+                rerun = true;
+            }
+
+            if build_path.join(format!("{}.idx", jobname)).exists() {
+                let (log, errors) =
+                    run_index_tool(job_id, index_tool, &jobname, build_path, &env).await;
+                tool_log.push_str(&log);
+                tool_errors.extend(errors);
+                rerun = true;
+            }
+
+            if build_path.join(format!("{}.glo", jobname)).exists()
+                || build_path.join(format!("{}.acn", jobname)).exists()
+            {
+                let (log, errors) = run_glossaries_tool(job_id, &jobname, build_path, &env).await;
+                tool_log.push_str(&log);
+                tool_errors.extend(errors);
+                rerun = true;
+            }
+        }
+
+        if rerun {
+            if pass < max_passes {
+                passes += 1;
+            } else {
+                tool_warnings.push(CompilationWarning {
+                    line: 0,
+                    message: format!(
+                        "Cross-references may be out of date after the maximum of {} passes",
+                        max_passes
+                    ),
+                    file: None,
+                    bad_box: None,
+                });
+            }
+        }
+    }
+
+    // Turn DVI/XDV output into PostScript, SVG and/or the preview PDF
+    let mut output_path = None;
+    let mut svg_pages = None;
+    if let Some(ext) = output_format.intermediate_extension(engine) {
+        if engine_ok {
+            let (log, errors) =
+                run_dvi_postprocess(job_id, output_format, &jobname, ext, build_path, &env).await;
+            tool_log.push_str(&log);
+            tool_errors.extend(errors);
+
+            let ext = if output_format == OutputFormat::Ps {
+                "ps"
+            } else {
+                ext
+            };
+            let path = build_path.join(format!("{}.{}", jobname, ext));
+            if path.exists() {
+                output_path = Some(path.to_string_lossy().to_string());
+            }
+
+            if output_format == OutputFormat::Svg {
+                let mut pages = Vec::new();
+                for (_, path) in svg_page_files(build_path, &jobname).await {
+                    let svg = fs::read_to_string(&path)
+                        .await
+                        .map_err(|e| format!("Failed to read SVG: {}", e))?;
+                    pages.push(svg);
+                }
+                svg_pages = Some(pages);
+            }
+        }
     }
 
     // Check for PDF output
-    let pdf_path = temp_path.join("main.pdf");
-    let (errors, warnings) = parse_latex_log(&log_output);
+    let pdf_path = build_path.join(format!("{}.pdf", jobname));
+    let (mut errors, mut warnings) = match engine {
+        Engine::Context => parse_context_log(&log_output),
+        _ => log_parser::parse_latex_log(&log_output),
+    };
+    errors.extend(tool_errors);
+    warnings.extend(tool_warnings);
+    // Report files the way the frontend names them so it can open the right buffer
+    log_parser::attribute_to_project(&mut errors, &mut warnings, build_path);
+    if let Some(dir) = source_dir {
+        log_parser::attribute_to_project(&mut errors, &mut warnings, dir);
+    }
+    fonts::suggest_alternatives(&mut errors).await;
+
+    if !tool_log.is_empty() {
+        log_output = format!("{}\n{}", log_output, tool_log);
+    }
+
+    if pdf_path.exists() && request.embed_sources.unwrap_or(false) {
+        let sources = embeddable_sources(&request, source_dir, &main_content).await;
+        if let Err(e) = pdf::embed_files(app, &pdf_path, sources).await {
+            warnings.push(CompilationWarning {
+                line: 0,
+                message: format!("Sources were not embedded in the PDF: {}", e),
+                file: None,
+                bad_box: None,
+            });
+        }
+    }
 
     if pdf_path.exists() {
         // Read PDF data
         let pdf_data = fs::read(&pdf_path)
             .await
             .map_err(|e| format!("Failed to read PDF: {}", e))?;
+        metrics.pdf_size = Some(pdf_data.len() as u64);
+        metrics.page_count = count_pages(&log_output, &pdf_data);
+        let document = queue_key(&request).unwrap_or(&root_file);
+        let changed_pages = pdf::changed_pages(document, &pdf_data).await;
+        let (published, pdf_url) = output::publish(&pdf_path).await?;
+        metrics.total_ms = started.elapsed().as_millis() as u64;
+
+        // Keep the build directory so SyncTeX queries can resolve against it
+        if let Ok(mut last) = LAST_BUILD.lock() {
+            *last = Some(LastBuild {
+                dir: build_dir,
+                jobname,
+                pdf_identity: annotations::pdf_identity(&pdf_data),
+            });
+        }
 
         Ok(CompilationResult {
+            job_id: job_id.to_string(),
             success: true,
-            pdf_path: Some(pdf_path.to_string_lossy().to_string()),
-            pdf_data: Some(pdf_data),
+            pdf_path: Some(published.to_string_lossy().to_string()),
+            pdf_url: Some(pdf_url),
+            output_path,
+            svg_pages,
             log: log_output,
             errors,
             warnings,
+            metrics,
+            changed_pages,
         })
     } else {
+        metrics.total_ms = started.elapsed().as_millis() as u64;
         Ok(CompilationResult {
+            job_id: job_id.to_string(),
             success: false,
             pdf_path: None,
-            pdf_data: None,
+            pdf_url: None,
+            output_path,
+            svg_pages,
             log: log_output,
             errors,
             warnings,
+            metrics,
+            changed_pages: None,
         })
     }
 }
 
+/// Queue a compile's jobs wait in: the one of the folder or project build
+/// directory it uses, or None for a throwaway directory
+fn queue_key(request: &CompileRequest) -> Option<&str> {
+    match &request.source_dir {
+        Some(dir) => Some(dir),
+        None => request
+            .project_id
+            .as_deref()
+            .filter(|_| !request.temp_build),
+    }
+}
+
+/// Queue a compile and return its job ID immediately. The outcome is
+/// available via get_compile_status once the "compile-finished" event fires.
+#[tauri::command]
+async fn compile_latex(app: AppHandle, request: CompileRequest) -> Result<String, String> {
+    if let (Some(project_id), Some(true)) = (&request.project_id, request.supersede) {
+        jobs::cancel_project(project_id);
+    }
+    let job_id = jobs::enqueue(request.project_id.as_deref(), queue_key(&request));
+
+    let id = job_id.clone();
+    tauri::async_runtime::spawn(async move {
+        let (runnable, _queue) = jobs::start(&id).await;
+        let outcome = if runnable {
+            run_compile(&app, &id, request).await
+        } else {
+            Err(format!("Compilation {} was cancelled", id))
+        };
+        jobs::finish(&id, outcome);
+        let _ = app.emit("compile-finished", &id);
+    });
+
+    Ok(job_id)
+}
+
+/// The .tex and .bib files of a compile, by project-relative name, for
+/// embedding in its PDF
+async fn embeddable_sources(
+    request: &CompileRequest,
+    source_dir: Option<&Path>,
+    main_content: &str,
+) -> Vec<(String, Vec<u8>)> {
+    let is_source = |name: &str| name.ends_with(".tex") || name.ends_with(".bib");
+    let Some(dir) = source_dir else {
+        let mut sources = vec![("main.tex".to_string(), main_content.as_bytes().to_vec())];
+        sources.extend(
+            request
+                .files
+                .iter()
+                .filter(|(name, _)| is_source(name))
+                .map(|(name, text)| (name.clone(), text.as_bytes().to_vec())),
+        );
+        return sources;
+    };
+
+    let mut sources = Vec::new();
+    for (entry, path) in project::walk(dir).await.unwrap_or_default() {
+        if entry.is_dir || !is_source(&entry.path) {
+            continue;
+        }
+        if let Ok(data) = fs::read(&path).await {
+            sources.push((entry.path, data));
+        }
+    }
+    sources
+}
+
+/// Result for a batch target whose compile could not run at all
+fn failed_result(job_id: &str, message: String) -> CompilationResult {
+    CompilationResult {
+        job_id: job_id.to_string(),
+        success: false,
+        pdf_path: None,
+        pdf_url: None,
+        output_path: None,
+        svg_pages: None,
+        log: String::new(),
+        errors: vec![CompilationError::message(message)],
+        warnings: vec![],
+        metrics: CompileMetrics::default(),
+        changed_pages: None,
+    }
+}
+
+/// Compile several root documents over a shared file set and return one
+/// result per target, in order. Each target's own files override the shared
+/// ones.
+#[tauri::command]
+async fn compile_batch(
+    app: AppHandle,
+    roots: Vec<CompileRequest>,
+    files: Option<HashMap<String, String>>,
+    parallel: Option<bool>,
+) -> Result<Vec<CompilationResult>, String> {
+    let shared = files.unwrap_or_default();
+    let parallel = parallel.unwrap_or(false);
+
+    let mut targets = Vec::new();
+    for mut request in roots {
+        let mut merged = shared.clone();
+        merged.extend(request.files);
+        request.files = merged;
+        // Parallel targets must not share a persistent build directory;
+        // they keep the project's settings
+        request.temp_build = parallel;
+        let job_id = jobs::enqueue(request.project_id.as_deref(), queue_key(&request));
+        targets.push((job_id, request));
+    }
+
+    let mut results = Vec::new();
+    if parallel {
+        let handles: Vec<_> = targets
+            .into_iter()
+            .map(|(job_id, request)| {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    let (runnable, _turn, _slot) = jobs::start_parallel(&job_id).await;
+                    let outcome = if runnable {
+                        run_compile(&app, &job_id, request).await
+                    } else {
+                        Err(format!("Compilation {} was cancelled", job_id))
+                    };
+                    jobs::finish(&job_id, outcome.clone());
+                    let _ = app.emit("compile-finished", &job_id);
+                    outcome.unwrap_or_else(|e| failed_result(&job_id, e))
+                })
+            })
+            .collect();
+        for handle in handles {
+            results.push(
+                handle
+                    .await
+                    .map_err(|e| format!("Batch compile task failed: {}", e))?,
+            );
+        }
+    } else {
+        for (job_id, request) in targets {
+            let (runnable, _queue) = jobs::start(&job_id).await;
+            let outcome = if runnable {
+                run_compile(&app, &job_id, request).await
+            } else {
+                Err(format!("Compilation {} was cancelled", job_id))
+            };
+            jobs::finish(&job_id, outcome.clone());
+            let _ = app.emit("compile-finished", &job_id);
+            results.push(outcome.unwrap_or_else(|e| failed_result(&job_id, e)));
+        }
+    }
+
+    Ok(results)
+}
+
+/// Get the status of a compile job, with its result once finished
+#[tauri::command]
+async fn get_compile_status(job_id: String) -> Result<jobs::CompileJob, String> {
+    jobs::get(&job_id).ok_or_else(|| format!("Unknown compile job: {}", job_id))
+}
+
+/// List queued, running and recently finished compile jobs
+#[tauri::command]
+async fn list_compile_jobs() -> Vec<jobs::CompileJob> {
+    jobs::list()
+}
+
+/// Cancel a running compile, killing the engine or tool process tree
+#[tauri::command]
+async fn cancel_compile(job_id: String) -> Result<bool, String> {
+    Ok(jobs::cancel(&job_id))
+}
+
 #[tauri::command]
 async fn check_latex_installation() -> Result<HashMap<String, bool>, String> {
     let mut result = HashMap::new();
 
-    for engine in ["xelatex", "pdflatex", "lualatex"] {
-        let available = Command::new(engine)
+    for engine in Engine::ALL.iter().map(Engine::command) {
+        let available = tex_path::command(engine)
             .arg("--version")
             .stdout(Stdio::null())
             .stderr(Stdio::null())
@@ -369,23 +2234,22 @@ async fn check_latex_installation() -> Result<HashMap<String, bool>, String> {
 
 #[tauri::command]
 async fn save_project(path: String, content: String) -> Result<(), String> {
-    fs::write(&path, content)
-        .await
-        .map_err(|e| format!("Failed to save project: {}", e))
+    save::write_atomic(Path::new(&path), content.as_bytes()).await
 }
 
 #[tauri::command]
 async fn load_project(path: String) -> Result<String, String> {
-    fs::read_to_string(&path)
+    let data = fs::read(&path)
         .await
-        .map_err(|e| format!("Failed to load project: {}", e))
+        .map_err(|e| format!("Failed to load project: {}", e))?;
+    save::remember(Path::new(&path)).await;
+    // Legacy sources (EUC-KR, Latin-1) come back as UTF-8
+    Ok(encoding::decode(&data).0)
 }
 
 #[tauri::command]
 async fn get_projects_dir() -> Result<String, String> {
-    let dir = dirs::document_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("OffLeaf");
+    let dir = project::projects_dir();
 
     fs::create_dir_all(&dir)
         .await
@@ -401,7 +2265,210 @@ async fn save_pdf(pdf_data: Vec<u8>, path: String) -> Result<(), String> {
         .map_err(|e| format!("Failed to save PDF: {}", e))
 }
 
-// ============ Package Manager (tlmgr) Commands ============
+/// Auxiliary files removed by clean_project
+const AUX_SUFFIXES: &[&str] = &[
+    ".aux",
+    ".log",
+    ".out",
+    ".toc",
+    ".lof",
+    ".lot",
+    ".bbl",
+    ".blg",
+    ".bcf",
+    ".run.xml",
+    ".synctex.gz",
+    ".fls",
+    ".fdb_latexmk",
+    ".idx",
+    ".ind",
+    ".ilg",
+    ".glo",
+    ".gls",
+    ".glg",
+    ".acn",
+    ".acr",
+    ".alg",
+    ".ist",
+    ".nav",
+    ".snm",
+    ".vrb",
+];
+
+/// Cache files removed in deep mode: preamble formats and TikZ externalization
+const CACHE_SUFFIXES: &[&str] = &[".fmt", ".md5", ".dpth", ".auxlock"];
+
+/// Cache directories removed in deep mode (minted, svg package)
+const CACHE_DIR_PREFIXES: &[&str] = &["_minted", "svg-inkscape"];
+
+/// Whether a file was written by a compile of `jobname`: "main.aux",
+/// "main-figure0.md5", or a cached preamble format
+fn is_build_output(name: &str, jobname: &str) -> bool {
+    name.starts_with("offleaf-") && name.ends_with(".fmt")
+        || name
+            .strip_prefix(jobname)
+            .is_some_and(|rest| rest.starts_with('.') || rest.starts_with('-'))
+}
+
+/// The build directory to clean for a path: a folder project's
+/// `.offleaf/build`, or a build directory itself. Anything else, e.g. a
+/// project's own sources, is refused.
+fn clean_target(path: &Path) -> Result<PathBuf, String> {
+    let in_folder = path.join(".offleaf").join("build");
+    let dir = if in_folder.is_dir() {
+        in_folder
+    } else {
+        path.to_path_buf()
+    };
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let builds = project::cached_build_dir("")
+        .canonicalize()
+        .unwrap_or_default();
+    let is_folder_build = dir.ends_with(Path::new(".offleaf").join("build"));
+    let is_cached_build = dir.parent().is_some_and(|parent| parent == builds);
+    if !is_folder_build && !is_cached_build {
+        return Err(format!("Not a build directory: {}", path.display()));
+    }
+    Ok(dir)
+}
+
+/// Remove the auxiliary files of `jobname` (default "main") from a build
+/// directory, and in deep mode its format and externalization caches too.
+/// Returns the removed paths.
+#[tauri::command]
+async fn clean_project(
+    path: String,
+    jobname: Option<String>,
+    deep: bool,
+) -> Result<Vec<String>, String> {
+    let root = clean_target(Path::new(&path))?;
+    let jobname = jobname.unwrap_or_else(|| "main".to_string());
+
+    let mut removed = Vec::new();
+    let mut dirs = vec![root];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let entry_path = entry.path();
+            // Symlinks are never followed or removed
+            let Ok(file_type) = entry.file_type().await else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                if name.starts_with('.') {
+                    // Hidden and VCS folders, e.g. .git
+                    continue;
+                }
+                if deep && CACHE_DIR_PREFIXES.iter().any(|p| name.starts_with(p)) {
+                    fs::remove_dir_all(&entry_path)
+                        .await
+                        .map_err(|e| format!("Failed to remove {}: {}", name, e))?;
+                    removed.push(entry_path.to_string_lossy().to_string());
+                } else {
+                    dirs.push(entry_path);
+                }
+            } else if file_type.is_file() && is_build_output(&name, &jobname) {
+                let is_aux = AUX_SUFFIXES.iter().any(|s| name.ends_with(s));
+                let is_cache = deep && CACHE_SUFFIXES.iter().any(|s| name.ends_with(s));
+                if is_aux || is_cache {
+                    fs::remove_file(&entry_path)
+                        .await
+                        .map_err(|e| format!("Failed to remove {}: {}", name, e))?;
+                    removed.push(entry_path.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+// ============ SyncTeX Commands ============
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncTexPosition {
+    page: u32,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+/// Parse the first record of `synctex view` output
+fn parse_synctex_view(output: &str) -> Option<SyncTexPosition> {
+    let mut page = None;
+    let mut x = None;
+    let mut y = None;
+    let mut width = 0.0;
+    let mut height = 0.0;
+
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key {
+            // A second record starts; only the first one is reported
+            "Page" if page.is_some() => break,
+            "Page" => page = value.parse().ok(),
+            "x" => x = value.parse().ok(),
+            "y" => y = value.parse().ok(),
+            "W" => width = value.parse().unwrap_or(0.0),
+            "H" => height = value.parse().unwrap_or(0.0),
+            _ => {}
+        }
+    }
+
+    Some(SyncTexPosition {
+        page: page?,
+        x: x?,
+        y: y?,
+        width,
+        height,
+    })
+}
+
+/// Forward search: map a source position to a PDF page and coordinates
+#[tauri::command]
+async fn synctex_forward(
+    line: u32,
+    column: u32,
+    file: Option<String>,
+) -> Result<SyncTexPosition, String> {
+    let (build_dir, jobname) = LAST_BUILD
+        .lock()
+        .map_err(|e| format!("Failed to access build directory: {}", e))?
+        .as_ref()
+        .map(|last| (last.dir.path().to_path_buf(), last.jobname.clone()))
+        .ok_or_else(|| "No compiled document available".to_string())?;
+
+    let file = file.unwrap_or_else(|| "main.tex".to_string());
+    let input = build_dir.join(&file);
+
+    let output = tex_path::command("synctex")
+        .arg("view")
+        .arg("-i")
+        .arg(format!("{}:{}:{}", line, column, input.to_string_lossy()))
+        .arg("-o")
+        .arg(build_dir.join(format!("{}.pdf", jobname)))
+        .current_dir(&build_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run synctex: {}", e))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_synctex_view(&stdout).ok_or_else(|| format!("No SyncTeX match for {}:{}", file, line))
+}
+
+// ============ Package Manager Commands ============
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PackageInfo {
@@ -410,6 +2477,8 @@ pub struct PackageInfo {
     installed: bool,
     version: Option<String>,
     size: Option<String>,
+    /// Packages it depends on, when known (only from get_package_info)
+    depends: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -425,63 +2494,28 @@ pub struct InstallResult {
     installed_packages: Vec<String>,
 }
 
-/// Check if tlmgr is available
+/// Check if a package manager (tlmgr or MiKTeX's) is available
 #[tauri::command]
 async fn check_tlmgr() -> Result<bool, String> {
-    let result = Command::new("tlmgr")
-        .arg("--version")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
+    Ok(packages::manager().await.is_ok())
+}
+
+/// Name of the detected package manager, e.g. "TeX Live (tlmgr)"
+#[tauri::command]
+async fn get_package_manager() -> Option<String> {
+    packages::manager()
         .await
-        .map(|s| s.success())
-        .unwrap_or(false);
-    Ok(result)
+        .ok()
+        .map(|manager| manager.name().to_string())
 }
 
 /// Search for packages
 #[tauri::command]
 async fn search_packages(query: String) -> Result<PackageSearchResult, String> {
-    let output = Command::new("tlmgr")
-        .args(["search", "--global", &query])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run tlmgr search: {}", e))?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut packages = Vec::new();
-
-    for line in stdout.lines() {
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-
-        // Parse tlmgr search output format: "package_name - description" or just "package_name:"
-        if let Some(sep_pos) = line.find(" - ") {
-            let name = line[..sep_pos].trim().to_string();
-            let description = line[sep_pos + 3..].trim().to_string();
-            packages.push(PackageInfo {
-                name,
-                description,
-                installed: false,
-                version: None,
-                size: None,
-            });
-        } else if line.ends_with(':') {
-            let name = line.trim_end_matches(':').to_string();
-            packages.push(PackageInfo {
-                name,
-                description: String::new(),
-                installed: false,
-                version: None,
-                size: None,
-            });
-        }
-    }
+    let manager = packages::manager().await?;
+    let output = packages::run(manager, packages::Action::Search(&query)).await?;
 
+    let packages = manager.parse_search(&String::from_utf8_lossy(&output.stdout), &query);
     let total = packages.len();
     Ok(PackageSearchResult { packages, total })
 }
@@ -489,113 +2523,48 @@ async fn search_packages(query: String) -> Result<PackageSearchResult, String> {
 /// Get list of installed packages
 #[tauri::command]
 async fn list_installed_packages() -> Result<Vec<PackageInfo>, String> {
-    let output = Command::new("tlmgr")
-        .args(["list", "--only-installed"])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run tlmgr list: {}", e))?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut packages = Vec::new();
-
-    for line in stdout.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with("tlmgr") {
-            continue;
-        }
-
-        // Parse format: "i package_name: description"
-        if let Some(rest) = line.strip_prefix('i') {
-            let rest = rest.trim();
-            if let Some(colon_pos) = rest.find(':') {
-                let name = rest[..colon_pos].trim().to_string();
-                let description = rest[colon_pos + 1..].trim().to_string();
-                packages.push(PackageInfo {
-                    name,
-                    description,
-                    installed: true,
-                    version: None,
-                    size: None,
-                });
-            }
-        }
-    }
+    let manager = packages::manager().await?;
+    let output = packages::run(manager, packages::Action::ListInstalled).await?;
 
-    Ok(packages)
+    Ok(manager.parse_installed(&String::from_utf8_lossy(&output.stdout)))
 }
 
 /// Get detailed info about a package
 #[tauri::command]
 async fn get_package_info(package_name: String) -> Result<PackageInfo, String> {
-    let output = Command::new("tlmgr")
-        .args(["info", &package_name])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run tlmgr info: {}", e))?;
+    let manager = packages::manager().await?;
+    let output = packages::run(manager, packages::Action::Info(&package_name)).await?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-
-    let mut name = package_name.clone();
-    let mut description = String::new();
-    let mut installed = false;
-    let mut version = None;
-    let mut size = None;
-
-    for line in stdout.lines() {
-        let line = line.trim();
-        if line.starts_with("package:") {
-            name = line.replace("package:", "").trim().to_string();
-        } else if line.starts_with("shortdesc:") {
-            description = line.replace("shortdesc:", "").trim().to_string();
-        } else if line.starts_with("installed:") {
-            installed = line.contains("Yes");
-        } else if line.starts_with("revision:") {
-            version = Some(line.replace("revision:", "").trim().to_string());
-        } else if line.starts_with("sizes:") {
-            size = Some(line.replace("sizes:", "").trim().to_string());
-        }
-    }
-
-    Ok(PackageInfo {
-        name,
-        description,
-        installed,
-        version,
-        size,
-    })
+    Ok(manager.parse_info(&String::from_utf8_lossy(&output.stdout), &package_name))
 }
 
-/// Install a package
+/// Install a package, reporting progress as "package-install-progress"
+/// events. The install's ID, for `cancel_package_install`, comes first in
+/// a "package-install-started" event.
 #[tauri::command]
-async fn install_package(package_name: String) -> Result<InstallResult, String> {
-    let output = Command::new("tlmgr")
-        .args(["install", &package_name])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
+async fn install_package(app: AppHandle, package_name: String) -> Result<InstallResult, String> {
+    let manager = packages::manager().await?;
+    let install = packages::Install::begin(&app, None);
+    let output = match install
+        .run(manager, packages::Action::Install(&package_name))
         .await
-        .map_err(|e| format!("Failed to run tlmgr install: {}", e))?;
+    {
+        Ok(output) => output,
+        Err(_) if install.is_cancelled() => {
+            return Ok(InstallResult {
+                success: false,
+                message: format!("Installation of {} cancelled", package_name),
+                installed_packages: vec![],
+            })
+        }
+        Err(e) => return Err(e),
+    };
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
     let success = output.status.success();
 
-    let mut installed_packages = Vec::new();
-
-    // Parse installed packages from output
-    for line in stdout.lines() {
-        if line.contains("Installing") || line.contains("install:") {
-            // Extract package name
-            if let Some(pkg) = line.split_whitespace().last() {
-                installed_packages.push(pkg.trim_matches(|c| c == ':' || c == '.').to_string());
-            }
-        }
-    }
-
+    let mut installed_packages = manager.parse_install(&stdout);
     if installed_packages.is_empty() && success {
         installed_packages.push(package_name.clone());
     }
@@ -614,13 +2583,8 @@ async fn install_package(package_name: String) -> Result<InstallResult, String>
 /// Remove a package
 #[tauri::command]
 async fn remove_package(package_name: String) -> Result<InstallResult, String> {
-    let output = Command::new("tlmgr")
-        .args(["remove", &package_name])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run tlmgr remove: {}", e))?;
+    let manager = packages::manager().await?;
+    let output = packages::run(manager, packages::Action::Remove(&package_name)).await?;
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
@@ -637,27 +2601,84 @@ async fn remove_package(package_name: String) -> Result<InstallResult, String> {
     })
 }
 
-/// Update all packages
+/// Whether tlmgr refused to update packages because it is out of date
+fn needs_tlmgr_update(output: &std::process::Output) -> bool {
+    let needle = "tlmgr itself needs to be updated";
+    String::from_utf8_lossy(&output.stdout).contains(needle)
+        || String::from_utf8_lossy(&output.stderr).contains(needle)
+}
+
+/// Run `tlmgr update --self`
+async fn run_tlmgr_self_update() -> Result<std::process::Output, String> {
+    processes::output(
+        proxy::command("tlmgr")
+            .args(["update", "--self"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .await
+    .map_err(|e| format!("Failed to run tlmgr update --self: {}", e))
+}
+
+/// Update tlmgr itself and the TeX Live infrastructure
+#[tauri::command]
+async fn update_tlmgr_self() -> Result<InstallResult, String> {
+    packages::require_tlmgr("tlmgr can only be updated").await?;
+    let output = run_tlmgr_self_update().await?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let success = output.status.success();
+
+    Ok(InstallResult {
+        success,
+        message: if success {
+            format!("tlmgr updated:\n{}", stdout)
+        } else {
+            format!("tlmgr update failed: {}", stderr)
+        },
+        installed_packages: vec![],
+    })
+}
+
+/// Update all packages, updating tlmgr first when it asks for that
 #[tauri::command]
 async fn update_packages() -> Result<InstallResult, String> {
-    let output = Command::new("tlmgr")
-        .args(["update", "--all"])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .await
-        .map_err(|e| format!("Failed to run tlmgr update: {}", e))?;
+    let manager = packages::manager().await?;
+    let mut output = packages::run(manager, packages::Action::UpdateAll).await?;
+
+    let mut self_updated = false;
+    if !output.status.success() && manager.program() == "tlmgr" && needs_tlmgr_update(&output) {
+        let self_update = run_tlmgr_self_update().await?;
+        if !self_update.status.success() {
+            return Ok(InstallResult {
+                success: false,
+                message: format!(
+                    "tlmgr needs to be updated first, but that failed: {}",
+                    String::from_utf8_lossy(&self_update.stderr)
+                ),
+                installed_packages: vec![],
+            });
+        }
+        self_updated = true;
+        output = packages::run(manager, packages::Action::UpdateAll).await?;
+    }
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
     let success = output.status.success();
+    let prefix = if self_updated {
+        "tlmgr was updated first.\n"
+    } else {
+        ""
+    };
 
     Ok(InstallResult {
         success,
         message: if success {
-            format!("Update completed:\n{}", stdout)
+            format!("{}Update completed:\n{}", prefix, stdout)
         } else {
-            format!("Update failed: {}", stderr)
+            format!("{}Update failed: {}", prefix, stderr)
         },
         installed_packages: vec![],
     })
@@ -673,20 +2694,23 @@ async fn get_recommended_packages() -> Vec<PackageInfo> {
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
         PackageInfo {
-            name: "cjk".to_string(),
+            name: CJK_PACKAGE.to_string(),
             description: "CJK (Chinese, Japanese, Korean) language support".to_string(),
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
         PackageInfo {
-            name: "xecjk".to_string(),
+            name: XECJK_PACKAGE.to_string(),
             description: "CJK support for XeLaTeX".to_string(),
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
         PackageInfo {
             name: "amsmath".to_string(),
@@ -694,6 +2718,7 @@ async fn get_recommended_packages() -> Vec<PackageInfo> {
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
         PackageInfo {
             name: "graphicx".to_string(),
@@ -701,6 +2726,7 @@ async fn get_recommended_packages() -> Vec<PackageInfo> {
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
         PackageInfo {
             name: "hyperref".to_string(),
@@ -708,6 +2734,7 @@ async fn get_recommended_packages() -> Vec<PackageInfo> {
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
         PackageInfo {
             name: "tikz".to_string(),
@@ -715,6 +2742,7 @@ async fn get_recommended_packages() -> Vec<PackageInfo> {
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
         PackageInfo {
             name: "biblatex".to_string(),
@@ -722,6 +2750,7 @@ async fn get_recommended_packages() -> Vec<PackageInfo> {
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
         PackageInfo {
             name: "listings".to_string(),
@@ -729,6 +2758,7 @@ async fn get_recommended_packages() -> Vec<PackageInfo> {
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
         PackageInfo {
             name: "booktabs".to_string(),
@@ -736,24 +2766,32 @@ async fn get_recommended_packages() -> Vec<PackageInfo> {
             installed: false,
             version: None,
             size: None,
+            depends: None,
         },
     ]
 }
 
 // ============ Auto Package Detection Commands ============
 
-/// Detect packages used in LaTeX content
+/// Detect the document class and packages used in LaTeX content, compiled
+/// from `project_dir` if given
 #[tauri::command]
-async fn detect_packages(content: String) -> Result<PackageDetectionResult, String> {
-    let parsed = parse_usepackages(&content);
+async fn detect_packages(
+    content: String,
+    project_dir: Option<String>,
+) -> Result<PackageDetectionResult, String> {
+    let dirs: Vec<&Path> = project_dir.iter().map(Path::new).collect();
+    let manager = packages::manager().await.unwrap_or(&packages::TexLive);
+    let parsed = parse_dependencies(&content);
     let mut packages = Vec::new();
     let mut missing = Vec::new();
     let mut installed_list = Vec::new();
 
-    for (name, options) in parsed {
-        let is_installed = is_package_installed(&name).await;
+    for (name, options, is_class) in parsed {
+        let is_installed = is_dependency_installed(&name, is_class, &dirs).await;
 
         packages.push(DetectedPackage {
+            package: packages::package_of(manager, &name).to_string(),
             name: name.clone(),
             installed: is_installed,
             options,
@@ -773,33 +2811,66 @@ async fn detect_packages(content: String) -> Result<PackageDetectionResult, Stri
     })
 }
 
-/// Auto-install missing packages from content
+/// Auto-install missing packages from content, reporting progress like
+/// `install_package`. Unless `confirmed`, the install policy decides which
+/// packages are installed.
 #[tauri::command]
-async fn auto_install_missing(content: String) -> Result<AutoInstallResult, String> {
-    let parsed = parse_usepackages(&content);
+async fn auto_install_missing(
+    app: AppHandle,
+    content: String,
+    project_dir: Option<String>,
+    confirmed: Option<bool>,
+) -> Result<AutoInstallResult, String> {
+    let dirs: Vec<&Path> = project_dir.iter().map(Path::new).collect();
+    Ok(auto_install(&app, &content, &dirs, None, confirmed).await)
+}
+
+/// Install what `content` loads and neither `dirs` nor the TeX installation
+/// has, as part of compile job `job_id` if given
+async fn auto_install(
+    app: &AppHandle,
+    content: &str,
+    dirs: &[&Path],
+    job_id: Option<&str>,
+    confirmed: Option<bool>,
+) -> AutoInstallResult {
+    let parsed = parse_dependencies(content);
     let mut missing = Vec::new();
 
-    for (name, _) in parsed {
-        if !is_package_installed(&name).await {
+    for (name, _, is_class) in parsed {
+        if !is_dependency_installed(&name, is_class, dirs).await {
             missing.push(name);
         }
     }
 
     if missing.is_empty() {
-        return Ok(AutoInstallResult {
+        return AutoInstallResult {
             success: true,
             installed: vec![],
             failed: vec![],
+            pending: vec![],
             message: "All packages are already installed".to_string(),
-        });
+        };
     }
 
-    Ok(install_missing_packages(&missing).await)
+    let policy = match confirmed {
+        Some(true) => None,
+        _ => Some(settings::load().await.install_policy),
+    };
+    let install = packages::Install::begin(app, job_id);
+    install_missing_packages(&install, &missing, policy.as_ref()).await
+}
+
+/// Detect packages that need shell escape, so the frontend can ask for confirmation
+#[tauri::command]
+async fn detect_shell_escape_packages(content: String) -> Vec<String> {
+    packages_requiring_shell_escape(&content)
 }
 
-/// Install essential packages for OffLeaf
+/// Install essential packages for OffLeaf, reporting progress like
+/// `install_package`
 #[tauri::command]
-async fn install_essential_packages() -> Result<AutoInstallResult, String> {
+async fn install_essential_packages(app: AppHandle) -> Result<AutoInstallResult, String> {
     let mut missing = Vec::new();
 
     for pkg in ESSENTIAL_PACKAGES {
@@ -813,22 +2884,26 @@ async fn install_essential_packages() -> Result<AutoInstallResult, String> {
             success: true,
             installed: vec![],
             failed: vec![],
+            pending: vec![],
             message: "All essential packages are already installed".to_string(),
         });
     }
 
-    Ok(install_missing_packages(&missing).await)
+    let install = packages::Install::begin(&app, None);
+    Ok(install_missing_packages(&install, &missing, None).await)
 }
 
 /// Get list of essential packages and their status
 #[tauri::command]
 async fn get_essential_packages() -> Vec<DetectedPackage> {
     let mut result = Vec::new();
+    let manager = packages::manager().await.unwrap_or(&packages::TexLive);
 
     for pkg in ESSENTIAL_PACKAGES {
         let installed = is_package_installed(pkg).await;
         result.push(DetectedPackage {
             name: pkg.to_string(),
+            package: packages::package_of(manager, pkg).to_string(),
             installed,
             options: None,
         });
@@ -839,46 +2914,230 @@ async fn get_essential_packages() -> Vec<DetectedPackage> {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Engines left running by a crashed session would hold files and CPU
+    processes::reap_orphans();
+    // Their jobs died with the last session
+    output::clear();
+    // TeX installations OffLeaf set up aren't on the system PATH
+    tex_path::init();
+    // Through a proxy, if the network needs one to reach CTAN
+    proxy::init();
+
     let result = tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_os::init())
+        .register_asynchronous_uri_scheme_protocol(output::SCHEME, |_, request, responder| {
+            tauri::async_runtime::spawn(async move {
+                responder.respond(output::serve(request).await);
+            });
+        })
         .invoke_handler(tauri::generate_handler![
             compile_latex,
+            compile_batch,
+            cancel_compile,
+            get_compile_status,
+            list_compile_jobs,
             check_latex_installation,
+            tex_install::install_texlive,
+            tinytex::get_tinytex_status,
+            tinytex::install_tinytex,
+            tinytex::set_tinytex_enabled,
+            tinytex::remove_tinytex,
             save_project,
             load_project,
+            encoding::convert_file_encoding,
+            save::check_external_changes,
             get_projects_dir,
+            project::create_project,
+            project::list_projects,
+            project::open_project,
+            project::update_project_manifest,
+            project::delete_project,
+            project::duplicate_project,
+            project::rename_project,
+            recent::get_recent_projects,
+            recent::remove_recent_project,
+            templates::list_templates,
+            templates::create_project_from_template,
+            project::list_project_files,
+            project::list_folder_files,
+            search::search_project,
+            project::create_file,
+            project::create_folder,
+            project::rename_entry,
+            project::move_entry,
+            project::delete_entry,
+            trash::list_trash,
+            trash::restore_from_trash,
+            trash::empty_trash,
+            history::enable_autosave,
+            history::disable_autosave,
+            history::list_snapshots,
+            history::restore_snapshot,
+            recovery::record_dirty_buffer,
+            recovery::clear_dirty_buffer,
+            recovery::get_recovery_state,
+            recovery::discard_recovery_state,
+            settings::get_project_settings,
+            settings::set_project_settings,
             save_pdf,
+            clean_project,
+            synctex_forward,
+            snippet::render_snippet,
+            export::export_svg,
+            export::export_html,
+            export::export_pdfa,
+            export::optimize_pdf,
+            export::export_pdf_pages,
+            export::export_pdf_as_images,
+            export::crop_pdf,
+            external::open_pdf_external,
+            archive::export_project_zip,
+            archive::import_project_zip,
+            assets::import_asset,
+            assets::save_clipboard_image,
+            assets::import_dropped_files,
+            backup::get_backup_settings,
+            backup::set_backup_settings,
+            backup::run_backup_now,
+            includes::analyze_includes,
+            stats::get_project_stats,
+            watcher::watch_folder,
+            watcher::set_watch_compile,
+            watcher::unwatch_folder,
+            overleaf::overleaf_set_token,
+            overleaf::overleaf_clear_token,
+            overleaf::overleaf_has_token,
+            overleaf::overleaf_clone,
+            overleaf::overleaf_pull,
+            overleaf::overleaf_push,
+            pdf::generate_thumbnails,
+            pdf::render_pdf_page,
+            pdf::search_pdf,
+            pdf_metadata::set_pdf_metadata,
+            annotations::extract_pdf_annotations,
+            print::print_pdf,
+            wordcount::count_words,
+            formatter::format_document,
             // Package manager commands
             check_tlmgr,
+            get_package_manager,
             search_packages,
             list_installed_packages,
             get_package_info,
             install_package,
+            packages::cancel_package_install,
+            packages::open_package_docs,
+            packages::refresh_package_cache,
+            packages::get_package_dependencies,
             remove_package,
             update_packages,
+            update_tlmgr_self,
+            packages::check_package_updates,
             get_recommended_packages,
+            mirrors::get_package_repository,
+            mirrors::set_package_repository,
+            mirrors::pick_ctan_mirror,
+            mirrors::pin_historic_repository,
             // Auto-detection commands
             detect_packages,
             auto_install_missing,
+            detect_shell_escape_packages,
+            fonts::detect_missing_fonts,
+            fonts::list_system_fonts,
+            local_styles::install_local_style,
+            packages::get_install_policy,
+            packages::set_install_policy,
+            proxy::get_proxy_settings,
+            proxy::set_proxy_settings,
+            bibliography::parse_bibliography,
+            bibliography::validate_bib_entries,
+            bibliography::get_citation_keys,
+            bibliography::get_online_lookups,
+            bibliography::set_online_lookups,
+            bibliography::fetch_bibtex,
+            zotero::sync_zotero_bibliography,
+            zotero::pick_zotero_citation,
             install_essential_packages,
             get_essential_packages,
         ])
-        .run(tauri::generate_context!());
-
-    if let Err(e) = result {
-        eprintln!("Error while running tauri application: {}", e);
-        #[cfg(target_os = "windows")]
-        {
-            use std::io::Write;
-            let _ = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("offleaf_error.log")
-                .and_then(|mut f| writeln!(f, "Error: {}", e));
-        }
-        std::process::exit(1);
+        .build(tauri::generate_context!());
+
+    match result {
+        Ok(app) => app.run(|_, event| match event {
+            RunEvent::Ready => {
+                tauri::async_runtime::spawn(backup::start());
+                tauri::async_runtime::spawn(packages::detect_at_startup());
+            }
+            RunEvent::Exit => processes::kill_all(),
+            _ => {}
+        }),
+        Err(e) => {
+            eprintln!("Error while running tauri application: {}", e);
+            #[cfg(target_os = "windows")]
+            {
+                use std::io::Write;
+                let _ = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open("offleaf_error.log")
+                    .and_then(|mut f| writeln!(f, "Error: {}", e));
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// main.blg
+    const BIBER_LOG: &str = r#"[0] Config.pm:307> INFO - This is Biber 2.19
+[101] Biber.pm:415> INFO - Reading 'main.bcf'
+[180] Utils.pm:411> WARN - I didn't find a database entry for 'knuth1984' (section 0)
+[190] Utils.pm:411> ERROR - BibTeX subsystem: /tmp/offleaf/refs.bib_1234.utf8, line 8, syntax error: found "author", expected end of entry ("}" or ")") (skipping to next "@")
+[195] Biber.pm:133> INFO - WARNINGS: 1
+"#;
+
+    #[test]
+    fn detects_the_bibliography_backend() {
+        let none = HashMap::new();
+        let natbib = "\\usepackage{natbib}\n\\bibliography {refs}\n";
+        assert_eq!(detect_bib_backend(natbib, &none), Some(BibBackend::Bibtex));
+
+        let biblatex = "\\usepackage[style=apa]{biblatex}\n\\addbibresource{refs.bib}\n";
+        assert_eq!(detect_bib_backend(biblatex, &none), Some(BibBackend::Biber));
+
+        let chapter = HashMap::from([(
+            "chapters/end.tex".to_string(),
+            "\\bibliography{refs}".to_string(),
+        )]);
+        assert_eq!(detect_bib_backend("", &chapter), Some(BibBackend::Bibtex));
+    }
+
+    #[test]
+    fn ignores_comments_but_not_escaped_percent_signs() {
+        let none = HashMap::new();
+        let commented = "% \\bibliography{refs}\nText\n";
+        assert_eq!(detect_bib_backend(commented, &none), None);
+
+        let escaped = "50\\% of cases \\bibliography{refs}\n";
+        assert_eq!(detect_bib_backend(escaped, &none), Some(BibBackend::Bibtex));
+    }
+
+    #[test]
+    fn reads_biber_errors_and_warnings() {
+        let (errors, warnings) = parse_bibliography_log(BibBackend::Biber, BIBER_LOG);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 8);
+        assert!(errors[0].message.starts_with("BibTeX subsystem:"));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].message,
+            "I didn't find a database entry for 'knuth1984' (section 0)"
+        );
     }
 }
@@ -0,0 +1,270 @@
+//! CPU time, memory and output-size limits for compile processes: setrlimit
+//! on Unix, Job Objects on Windows
+
+use serde::{Deserialize, Serialize};
+use std::process::ExitStatus;
+#[cfg(windows)]
+use tokio::process::Child;
+use tokio::process::Command;
+
+const MB: u64 = 1024 * 1024;
+
+/// Limits per process; 0 means unlimited
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    pub cpu_secs: u64,
+    pub memory_mb: u64,
+    /// Largest file a process may write (Unix only)
+    pub output_mb: u64,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        ResourceLimits {
+            cpu_secs: 600,
+            memory_mb: 4096,
+            output_mb: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitExceeded {
+    CpuTime,
+    Memory,
+    OutputSize,
+}
+
+/// Set the limits in the child between fork and exec. Best effort: a limit
+/// above the user's own hard limit is left as it is.
+#[cfg(unix)]
+pub fn apply(command: &mut Command, limits: ResourceLimits) {
+    fn rlimit(value: u64, slack: u64) -> libc::rlimit {
+        libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: (value + slack) as libc::rlim_t,
+        }
+    }
+
+    // SAFETY: the closure only calls setrlimit, which is async-signal-safe
+    unsafe {
+        command.pre_exec(move || {
+            // The soft CPU limit raises SIGXCPU; the hard one a second later kills
+            if limits.cpu_secs > 0 {
+                libc::setrlimit(libc::RLIMIT_CPU, &rlimit(limits.cpu_secs, 1));
+            }
+            if limits.memory_mb > 0 {
+                libc::setrlimit(libc::RLIMIT_AS, &rlimit(limits.memory_mb * MB, 0));
+            }
+            if limits.output_mb > 0 {
+                libc::setrlimit(libc::RLIMIT_FSIZE, &rlimit(limits.output_mb * MB, 0));
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+pub fn apply(_command: &mut Command, _limits: ResourceLimits) {}
+
+/// Job Object holding a child process and its descendants; closing it kills them
+#[cfg(windows)]
+pub struct JobObject(windows_sys::Win32::Foundation::HANDLE);
+
+#[cfg(windows)]
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        // SAFETY: the handle came from CreateJobObjectW and is closed only here
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.0);
+        }
+    }
+}
+
+// Job Object handles may be used from any thread
+#[cfg(windows)]
+unsafe impl Send for JobObject {}
+
+/// Put a spawned child into a Job Object enforcing the limits
+#[cfg(windows)]
+pub fn attach(child: &Child, limits: ResourceLimits) -> Option<JobObject> {
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE, JOB_OBJECT_LIMIT_PROCESS_MEMORY,
+        JOB_OBJECT_LIMIT_PROCESS_TIME,
+    };
+
+    let process = child.raw_handle()?;
+    // SAFETY: plain Win32 calls on a handle we own and a zeroed POD struct
+    unsafe {
+        let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+        if handle.is_null() {
+            return None;
+        }
+        let job = JobObject(handle);
+
+        let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+        info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        if limits.cpu_secs > 0 {
+            // In 100ns units
+            info.BasicLimitInformation.PerProcessUserTimeLimit =
+                (limits.cpu_secs * 10_000_000) as i64;
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+        }
+        if limits.memory_mb > 0 {
+            info.ProcessMemoryLimit = (limits.memory_mb * MB) as usize;
+            info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+        }
+
+        let ok = SetInformationJobObject(
+            job.0,
+            JobObjectExtendedLimitInformation,
+            &info as *const _ as *const std::ffi::c_void,
+            std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+        );
+        if ok == 0 || AssignProcessToJobObject(job.0, process as _) == 0 {
+            return None;
+        }
+        Some(job)
+    }
+}
+
+/// What a process used, measured once it exited
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    /// User plus system time
+    pub cpu_secs: f64,
+    pub peak_memory_mb: u64,
+}
+
+/// Wait for a child and collect its resource usage. Reaps the child, so
+/// its tokio handle must not be waited on afterwards.
+#[cfg(unix)]
+pub async fn wait(pid: u32) -> std::io::Result<(ExitStatus, Usage)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    tokio::task::spawn_blocking(move || loop {
+        let mut status = 0;
+        // SAFETY: wait4 fills a zeroed POD struct
+        let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+        let result = unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut rusage) };
+        if result == -1 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+        let seconds = |t: libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+        // ru_maxrss is in bytes on macOS and kilobytes elsewhere
+        let peak_bytes = if cfg!(target_os = "macos") {
+            rusage.ru_maxrss as u64
+        } else {
+            rusage.ru_maxrss as u64 * 1024
+        };
+        return Ok((
+            ExitStatus::from_raw(status),
+            Usage {
+                cpu_secs: seconds(rusage.ru_utime) + seconds(rusage.ru_stime),
+                peak_memory_mb: peak_bytes / MB,
+            },
+        ));
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+/// What the processes in a Job Object have used so far
+#[cfg(windows)]
+pub fn usage(job: &JobObject) -> Usage {
+    use windows_sys::Win32::System::JobObjects::{
+        JobObjectBasicAccountingInformation, JobObjectExtendedLimitInformation,
+        QueryInformationJobObject, JOBOBJECT_BASIC_ACCOUNTING_INFORMATION,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+    };
+
+    // SAFETY: queries into zeroed POD structs of the size passed
+    unsafe {
+        let mut accounting: JOBOBJECT_BASIC_ACCOUNTING_INFORMATION = std::mem::zeroed();
+        let mut extended: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+        QueryInformationJobObject(
+            job.0,
+            JobObjectBasicAccountingInformation,
+            &mut accounting as *mut _ as *mut std::ffi::c_void,
+            std::mem::size_of::<JOBOBJECT_BASIC_ACCOUNTING_INFORMATION>() as u32,
+            std::ptr::null_mut(),
+        );
+        QueryInformationJobObject(
+            job.0,
+            JobObjectExtendedLimitInformation,
+            &mut extended as *mut _ as *mut std::ffi::c_void,
+            std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            std::ptr::null_mut(),
+        );
+        // The Job Object limits user time only; times are in 100ns units
+        Usage {
+            cpu_secs: accounting.TotalUserTime as f64 / 1e7,
+            peak_memory_mb: extended.PeakProcessMemoryUsed as u64 / MB,
+        }
+    }
+}
+
+/// Which limit, if any, ended a process that used `usage`. A process that
+/// failed is blamed on a limit only when it actually reached it.
+pub fn exceeded(
+    status: &ExitStatus,
+    usage: &Usage,
+    limits: &ResourceLimits,
+) -> Option<LimitExceeded> {
+    if status.success() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        // Only the rlimits raise these, and written output isn't measured
+        match status.signal() {
+            Some(libc::SIGXFSZ) if limits.output_mb > 0 => return Some(LimitExceeded::OutputSize),
+            Some(libc::SIGXCPU) if limits.cpu_secs > 0 => return Some(LimitExceeded::CpuTime),
+            _ => {}
+        }
+    }
+    // The kernel accounts CPU time in ticks, so allow for rounding
+    if limits.cpu_secs > 0 && usage.cpu_secs >= limits.cpu_secs as f64 * 0.99 {
+        return Some(LimitExceeded::CpuTime);
+    }
+    // Allocations fail before the peak reaches the limit itself, which
+    // also counts address space that was never touched
+    if limits.memory_mb > 0 && usage.peak_memory_mb * 10 >= limits.memory_mb * 9 {
+        return Some(LimitExceeded::Memory);
+    }
+    None
+}
+
+impl LimitExceeded {
+    pub fn message(&self, limits: &ResourceLimits) -> String {
+        match self {
+            LimitExceeded::CpuTime => {
+                format!(
+                    "Compilation exceeded the CPU time limit of {} seconds",
+                    limits.cpu_secs
+                )
+            }
+            LimitExceeded::Memory => {
+                format!(
+                    "Compilation exceeded the memory limit of {} MB",
+                    limits.memory_mb
+                )
+            }
+            LimitExceeded::OutputSize => {
+                format!(
+                    "Compilation exceeded the output size limit of {} MB",
+                    limits.output_mb
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Style and class files the user installs once, e.g. a university thesis
+//! class, into their own texmf tree (TEXMFHOME) so every project finds them
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::{packages, processes};
+
+/// Where each kind of file goes under the texmf tree, following the TDS
+const STYLE_DIRS: &[(&str, &str)] = &[
+    ("sty", "tex/latex"),
+    ("cls", "tex/latex"),
+    ("clo", "tex/latex"),
+    ("cfg", "tex/latex"),
+    ("def", "tex/latex"),
+    ("fd", "tex/latex"),
+    ("bbx", "tex/latex"),
+    ("cbx", "tex/latex"),
+    ("bst", "bibtex/bst"),
+];
+
+/// The user texmf tree, e.g. `~/texmf`
+async fn texmf_home() -> Result<PathBuf, String> {
+    let output = processes::output(
+        Command::new("kpsewhich")
+            .arg("-var-value=TEXMFHOME")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null()),
+    )
+    .await
+    .map_err(|e| format!("Failed to run kpsewhich: {}", e))?;
+    let home = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || home.is_empty() {
+        return Err("Failed to find the user texmf tree".to_string());
+    }
+    Ok(PathBuf::from(home))
+}
+
+/// Refresh the file name database: texhash for TeX Live, initexmf for
+/// MiKTeX
+async fn refresh_file_database(home: &Path) -> Result<(), String> {
+    let output = match processes::output(
+        Command::new("texhash")
+            .arg(home)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped()),
+    )
+    .await
+    {
+        Ok(output) => output,
+        Err(_) => processes::output(
+            Command::new("initexmf")
+                .arg("--update-fndb")
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped()),
+        )
+        .await
+        .map_err(|e| format!("Failed to run texhash: {}", e))?,
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to refresh the file database: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Copy a .sty/.cls (or .bst, ...) file into the user texmf tree and
+/// refresh the file database. Returns where it was installed.
+#[tauri::command]
+pub async fn install_local_style(path: String) -> Result<String, String> {
+    let source = Path::new(&path);
+    if !source.is_file() {
+        return Err(format!("File not found: {}", path));
+    }
+    let file_name = source
+        .file_name()
+        .ok_or_else(|| format!("Invalid file: {}", path))?;
+    let extension = source
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    let dir = STYLE_DIRS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, dir)| *dir)
+        .ok_or_else(|| format!("Not a style or class file: {}", path))?;
+    let stem = source
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("local");
+
+    let home = texmf_home().await?;
+    // Each file gets its own folder, as packages do in the TeX tree
+    let target_dir = home.join(dir).join(stem);
+    tokio::fs::create_dir_all(&target_dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", target_dir.display(), e))?;
+    let target = target_dir.join(file_name);
+    tokio::fs::copy(source, &target)
+        .await
+        .map_err(|e| format!("Failed to copy {}: {}", path, e))?;
+
+    refresh_file_database(&home).await?;
+    // The file may have been looked up and not found before
+    packages::clear_lookups().await;
+    Ok(target.to_string_lossy().to_string())
+}
@@ -0,0 +1,508 @@
+//! Stateful parser for LaTeX engine logs. Tracks the files TeX has open so
+//! every error and warning can be attributed to a file and line.
+
+use regex::Regex;
+use std::path::Path;
+
+use crate::{fonts, BadBox, BadBoxKind, CompilationError, CompilationWarning};
+
+/// TeX hard-wraps log lines at this many characters (max_print_line)
+const LOG_LINE_WIDTH: usize = 79;
+
+/// How far after an error to look for its "l.<line>" context
+const CONTEXT_LOOKAHEAD: usize = 12;
+
+/// Join log lines TeX wrapped at the line width back together
+fn unwrap_lines(log: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for line in log.lines() {
+        current.push_str(line);
+        if line.chars().count() != LOG_LINE_WIDTH {
+            lines.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Open parentheses in the log. TeX prints "(file" when it opens a file and
+/// ")" when it closes it; other parentheses are kept so they balance.
+#[derive(Default)]
+struct FileStack {
+    entries: Vec<Option<String>>,
+}
+
+impl FileStack {
+    fn update(&mut self, line: &str, file_re: &Regex) {
+        let mut rest = line;
+        while let Some(pos) = rest.find(['(', ')']) {
+            if rest[pos..].starts_with(')') {
+                self.entries.pop();
+                rest = &rest[pos + 1..];
+                continue;
+            }
+
+            let after = &rest[pos + 1..];
+            let end = after
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .unwrap_or(after.len());
+            let token = &after[..end];
+            self.entries.push(if file_re.is_match(token) {
+                Some(token.to_string())
+            } else {
+                None
+            });
+            rest = &after[end..];
+        }
+    }
+
+    /// Innermost open file
+    fn current(&self) -> Option<String> {
+        self.entries.iter().rev().flatten().next().cloned()
+    }
+}
+
+/// The "l.<line> <text>" lines TeX prints after an error, split at the error
+/// position; the second part is indented on the following line
+fn error_context(lines: &[String], start: usize) -> Option<(usize, i32, String)> {
+    let context_re = Regex::new(r"^l\.(\d+) (.*)$").unwrap();
+    let end = (start + CONTEXT_LOOKAHEAD).min(lines.len());
+
+    for i in start..end {
+        // Don't run into the next error
+        if lines[i].starts_with('!') {
+            break;
+        }
+        if let Some(cap) = context_re.captures(&lines[i]) {
+            let mut excerpt = cap[2].to_string();
+            if let Some(next) = lines.get(i + 1) {
+                excerpt.push_str(next.trim_start());
+            }
+            return Some((i, cap[1].parse().unwrap_or(0), excerpt));
+        }
+    }
+    None
+}
+
+/// Parse LaTeX log output into errors and warnings
+pub fn parse_latex_log(log: &str) -> (Vec<CompilationError>, Vec<CompilationWarning>) {
+    let mut errors: Vec<CompilationError> = Vec::new();
+    let mut warnings = Vec::new();
+
+    let file_re =
+        Regex::new(r"^(?:\./|\.\./|/|[A-Za-z]:)?[^\s()\d][^\s()]*\.[A-Za-z0-9]+$").unwrap();
+    // -file-line-error output, e.g. "./chapters/intro.tex:12: Undefined control sequence."
+    let file_line_re = Regex::new(r"^(.+?\.[A-Za-z0-9]+):(\d+): (.*)$").unwrap();
+    // e.g. "Package hyperref Warning: Token not allowed ... on input line 5."
+    let warning_re =
+        Regex::new(r"^(?:LaTeX|LaTeX (Font)|Package (\S+)|Class (\S+)) Warning: ").unwrap();
+    let input_line_re = Regex::new(r"on input line (\d+)").unwrap();
+    // e.g. "Overfull \hbox (12.3pt too wide) in paragraph at lines 40--42"
+    //      "Underfull \vbox (badness 10000) has occurred while \output is active"
+    let bad_box_re = Regex::new(
+        r"^(Overfull|Underfull) \\([hv]box) \((?:badness (\d+)|([\d.]+)pt too \w+)\)(?:.*? at lines? (\d+)(?:--(\d+))?)?",
+    )
+    .unwrap();
+
+    let lines = unwrap_lines(log);
+    let mut stack = FileStack::default();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i].as_str();
+        // Tectonic prefixes its diagnostics
+        let tectonic_error = line.strip_prefix("error: ");
+        let error_text = tectonic_error.or_else(|| line.strip_prefix('!').map(str::trim));
+
+        let header = match (error_text, file_line_re.captures(line)) {
+            (_, Some(cap)) => Some((
+                Some(cap[1].to_string()),
+                cap[2].parse().unwrap_or(0),
+                cap[3].to_string(),
+            )),
+            (Some(text), None) => match file_line_re.captures(text) {
+                Some(cap) => Some((
+                    Some(cap[1].to_string()),
+                    cap[2].parse().unwrap_or(0),
+                    cap[3].to_string(),
+                )),
+                None => Some((stack.current(), 0, text.to_string())),
+            },
+            (None, None) => None,
+        };
+
+        if let Some((file, mut line_num, mut message)) = header {
+            // The message may continue until a blank line or TeX's context
+            let mut j = i + 1;
+            while j < lines.len() {
+                let next = lines[j].trim();
+                if next.is_empty() || next.starts_with("l.") || next.starts_with('<') {
+                    break;
+                }
+                // Drop the "(package)" prefix of continuation lines
+                let next = next
+                    .strip_prefix('(')
+                    .and_then(|rest| rest.split_once(')'))
+                    .map(|(_, rest)| rest.trim())
+                    .unwrap_or(next);
+                message.push(' ');
+                message.push_str(next);
+                j += 1;
+            }
+
+            let mut context = None;
+            if let Some((end, context_line, excerpt)) = error_context(&lines, i + 1) {
+                if line_num == 0 {
+                    line_num = context_line;
+                }
+                context = Some(excerpt);
+                j = j.max(end + 2);
+            }
+
+            let halt_notice = message.contains("==> Fatal error occurred");
+            let emergency_stop = message == "Emergency stop." && !errors.is_empty();
+            if !halt_notice && !emergency_stop {
+                let missing_font = fonts::missing_font(&message);
+                errors.push(CompilationError {
+                    line: line_num,
+                    message,
+                    file,
+                    context,
+                    missing_font,
+                    limit_exceeded: None,
+                });
+            }
+            i = j;
+            continue;
+        }
+
+        if let Some(cap) = warning_re.captures(line) {
+            // Continuation lines are prefixed with "(package)", "(class)" or "(Font)"
+            let name = cap.get(1).or(cap.get(2)).or(cap.get(3));
+            let prefix = name.map(|n| format!("({})", n.as_str()));
+            let mut message = line.to_string();
+            let mut j = i + 1;
+            while let (Some(prefix), Some(next)) = (&prefix, lines.get(j)) {
+                match next.strip_prefix(prefix.as_str()) {
+                    Some(rest) => {
+                        message.push(' ');
+                        message.push_str(rest.trim());
+                        j += 1;
+                    }
+                    None => break,
+                }
+            }
+
+            let line_num = input_line_re
+                .captures(&message)
+                .and_then(|c| c[1].parse().ok())
+                .unwrap_or(0);
+            warnings.push(CompilationWarning {
+                line: line_num,
+                message,
+                file: stack.current(),
+                bad_box: None,
+            });
+            i = j;
+            continue;
+        }
+
+        if let Some(cap) = bad_box_re.captures(line) {
+            let kind = if &cap[1] == "Overfull" {
+                BadBoxKind::Overfull
+            } else {
+                BadBoxKind::Underfull
+            };
+            let start = cap.get(5).and_then(|m| m.as_str().parse().ok());
+            let end = cap.get(6).and_then(|m| m.as_str().parse().ok());
+            warnings.push(CompilationWarning {
+                line: start.unwrap_or(0),
+                message: line.to_string(),
+                file: stack.current(),
+                bad_box: Some(BadBox {
+                    kind,
+                    box_type: cap[2].to_string(),
+                    end_line: end.or(start),
+                    badness: cap.get(3).and_then(|m| m.as_str().parse().ok()),
+                    overflow_pt: cap.get(4).and_then(|m| m.as_str().parse().ok()),
+                }),
+            });
+
+            // Skip the box contents TeX dumps after the warning; their
+            // parentheses would throw off the file stack
+            i += 1;
+            while i < lines.len() && !lines[i].trim().is_empty() {
+                i += 1;
+            }
+            continue;
+        }
+
+        if let Some(msg) = line.strip_prefix("warning: ") {
+            warnings.push(CompilationWarning {
+                line: 0,
+                message: msg.to_string(),
+                file: None,
+                bad_box: None,
+            });
+        } else if line.contains("Warning:") {
+            warnings.push(CompilationWarning {
+                line: 0,
+                message: line.to_string(),
+                file: stack.current(),
+                bad_box: None,
+            });
+        } else {
+            stack.update(line, &file_re);
+        }
+        i += 1;
+    }
+
+    (errors, warnings)
+}
+
+/// Turn a path from the log into the project-relative form used as a key of
+/// the request's files, e.g. "./chapters/intro.tex" -> "chapters/intro.tex".
+/// Files outside the build directory (classes, packages) are left as they are.
+pub fn project_relative_path(file: &str, build_dirs: &[&Path]) -> String {
+    let path = Path::new(file);
+    let relative = if path.is_absolute() {
+        match build_dirs
+            .iter()
+            .find_map(|dir| path.strip_prefix(dir).ok())
+        {
+            Some(relative) => relative.to_string_lossy().to_string(),
+            None => return file.to_string(),
+        }
+    } else {
+        file.to_string()
+    };
+
+    let relative = relative.replace('\\', "/");
+    relative.trim_start_matches("./").to_string()
+}
+
+/// Rewrite the files of parsed errors and warnings relative to the project
+pub fn attribute_to_project(
+    errors: &mut [CompilationError],
+    warnings: &mut [CompilationWarning],
+    build_dir: &Path,
+) {
+    // Temp dirs may be reported through a symlink (e.g. /private/var on macOS)
+    let canonical = std::fs::canonicalize(build_dir).ok();
+    let mut build_dirs = vec![build_dir];
+    build_dirs.extend(canonical.as_deref());
+
+    for error in errors.iter_mut() {
+        error.file = error
+            .file
+            .take()
+            .map(|file| project_relative_path(&file, &build_dirs));
+    }
+    for warning in warnings.iter_mut() {
+        warning.file = warning
+            .file
+            .take()
+            .map(|file| project_relative_path(&file, &build_dirs));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// pdflatex without -file-line-error: files come from the stack
+    const PDFLATEX_LOG: &str = r#"This is pdfTeX, Version 3.141592653-2.6-1.40.25 (TeX Live 2023) (preloaded format=pdflatex 2023.5.1)  2 MAR 2024 10:15
+entering extended mode
+ restricted \write18 enabled.
+**main.tex
+(./main.tex
+LaTeX2e <2022-11-01> patch level 1
+L3 programming layer <2023-02-22>
+(/usr/share/texlive/texmf-dist/tex/latex/base/article.cls
+Document Class: article 2022/07/02 v1.4n Standard LaTeX document class
+(/usr/share/texlive/texmf-dist/tex/latex/base/size10.clo
+File: size10.clo 2022/07/02 v1.4n Standard LaTeX file (size option)
+))
+(./main.aux) (./chapters/intro.tex
+! Undefined control sequence.
+l.3 Some text \foo
+                  bar.
+The control sequence at the end of the top line
+of your error message was never \def'ed.
+
+)
+Package biblatex Warning: Please (re)run Biber on the file:
+(biblatex)                main
+(biblatex)                and rerun LaTeX afterwards.
+
+! Missing $ inserted.
+<inserted text> 
+                $
+l.27 The value of x_
+                    1 is positive.
+"#;
+
+    /// xelatex with -file-line-error
+    const XELATEX_LOG: &str = r#"This is XeTeX, Version 3.141592653-2.6-0.999995 (TeX Live 2023) (preloaded format=xelatex)
+(./main.tex
+LaTeX2e <2022-11-01> patch level 1
+(./sections/method.tex
+./sections/method.tex:12: LaTeX Error: Environment foo undefined.
+
+See the LaTeX manual or LaTeX Companion for explanation.
+Type  H <return>  for immediate help.
+ ...                                              
+                                                  
+l.12 \begin{foo}
+                
+)
+./main.tex:40: Emergency stop.
+"#;
+
+    /// A file name and a warning TeX wrapped at 79 characters
+    const WRAPPED_LOG: &str = r#"(./main.tex
+(./chapters/evaluation/benchmarks/results-for-the-complete-benchmark-suite-appe
+ndix.tex
+LaTeX Warning: Reference `fig:results-overview-of-the-benchmark-suite' on page 
+3 undefined on input line 42.
+
+)
+LaTeX Warning: There were undefined references.
+
+)
+"#;
+
+    const BAD_BOXES_LOG: &str = r#"(./main.tex
+Overfull \hbox (15.0pt too wide) in paragraph at lines 40--42
+[]\T1/cmr/m/n/10 A sentence with (parentheses) that runs past the mar-
+gin|
+ []
+
+
+Underfull \vbox (badness 10000) has occurred while \output is active []
+
+ [1{/var/lib/texmf/fonts/map/pdftex/updmap/pdftex.map}]
+Underfull \hbox (badness 1533) in paragraph at lines 57--58
+[]\T1/cmr/m/n/10 Short line
+ []
+
+)
+"#;
+
+    #[test]
+    fn attributes_errors_to_the_innermost_open_file() {
+        let (errors, _) = parse_latex_log(PDFLATEX_LOG);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].message, "Undefined control sequence.");
+        assert_eq!(errors[0].file.as_deref(), Some("./chapters/intro.tex"));
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[0].context.as_deref(), Some("Some text \\foobar."));
+        // The chapter was closed, so the next error is back in main.tex
+        assert_eq!(errors[1].message, "Missing $ inserted.");
+        assert_eq!(errors[1].file.as_deref(), Some("./main.tex"));
+        assert_eq!(errors[1].line, 27);
+    }
+
+    #[test]
+    fn joins_package_warning_continuations() {
+        let (_, warnings) = parse_latex_log(PDFLATEX_LOG);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].message,
+            "Package biblatex Warning: Please (re)run Biber on the file: main and rerun LaTeX afterwards."
+        );
+        assert_eq!(warnings[0].file.as_deref(), Some("./main.tex"));
+    }
+
+    #[test]
+    fn reads_file_line_errors() {
+        let (errors, _) = parse_latex_log(XELATEX_LOG);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].message, "LaTeX Error: Environment foo undefined.");
+        assert_eq!(errors[0].file.as_deref(), Some("./sections/method.tex"));
+        assert_eq!(errors[0].line, 12);
+        assert_eq!(errors[0].context.as_deref(), Some("\\begin{foo}"));
+    }
+
+    #[test]
+    fn unwraps_long_lines() {
+        let (errors, warnings) = parse_latex_log(WRAPPED_LOG);
+        assert!(errors.is_empty());
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[0].message,
+            "LaTeX Warning: Reference `fig:results-overview-of-the-benchmark-suite' on page 3 undefined on input line 42."
+        );
+        assert_eq!(warnings[0].line, 42);
+        assert_eq!(
+            warnings[0].file.as_deref(),
+            Some("./chapters/evaluation/benchmarks/results-for-the-complete-benchmark-suite-appendix.tex")
+        );
+        assert_eq!(
+            warnings[1].message,
+            "LaTeX Warning: There were undefined references."
+        );
+        assert_eq!(warnings[1].file.as_deref(), Some("./main.tex"));
+    }
+
+    #[test]
+    fn makes_paths_project_relative() {
+        let build = Path::new("/tmp/offleaf-build");
+        assert_eq!(
+            project_relative_path("/tmp/offleaf-build/chapters/intro.tex", &[build]),
+            "chapters/intro.tex"
+        );
+        assert_eq!(project_relative_path("./main.tex", &[build]), "main.tex");
+        assert_eq!(
+            project_relative_path(
+                "/usr/share/texlive/texmf-dist/tex/latex/base/article.cls",
+                &[build]
+            ),
+            "/usr/share/texlive/texmf-dist/tex/latex/base/article.cls"
+        );
+    }
+
+    #[test]
+    fn attributes_errors_to_project_files() {
+        let build = tempfile::tempdir().unwrap();
+        let log = format!(
+            "({dir}/main.tex\n({dir}/chapters/intro.tex\n! Undefined control sequence.\nl.3 \\foo\n\n)\n./main.tex:9: Missing $ inserted.\n",
+            dir = build.path().display()
+        );
+        let (mut errors, mut warnings) = parse_latex_log(&log);
+        attribute_to_project(&mut errors, &mut warnings, build.path());
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].file.as_deref(), Some("chapters/intro.tex"));
+        assert_eq!(errors[0].line, 3);
+        assert_eq!(errors[1].file.as_deref(), Some("main.tex"));
+        assert_eq!(errors[1].line, 9);
+    }
+
+    #[test]
+    fn reads_bad_boxes() {
+        let (errors, warnings) = parse_latex_log(BAD_BOXES_LOG);
+        assert!(errors.is_empty());
+        assert_eq!(warnings.len(), 3);
+
+        let overfull = warnings[0].bad_box.as_ref().unwrap();
+        assert_eq!(overfull.kind, BadBoxKind::Overfull);
+        assert_eq!(overfull.box_type, "hbox");
+        assert_eq!(overfull.overflow_pt, Some(15.0));
+        assert_eq!(warnings[0].line, 40);
+        assert_eq!(overfull.end_line, Some(42));
+
+        let output = warnings[1].bad_box.as_ref().unwrap();
+        assert_eq!(output.kind, BadBoxKind::Underfull);
+        assert_eq!(output.box_type, "vbox");
+        assert_eq!(output.badness, Some(10000));
+        assert_eq!(warnings[1].line, 0);
+
+        // The parentheses in the box contents don't close main.tex
+        assert_eq!(warnings[2].file.as_deref(), Some("./main.tex"));
+        assert_eq!(warnings[2].line, 57);
+        assert_eq!(warnings[2].bad_box.as_ref().unwrap().badness, Some(1533));
+    }
+}
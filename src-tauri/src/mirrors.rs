@@ -0,0 +1,225 @@
+//! The repository tlmgr installs from: picking a fast CTAN mirror instead of
+//! the redirector, which hands out a different (sometimes slow or stale)
+//! mirror on every request, or pinning to a frozen historic repository
+
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+use crate::{packages, processes, proxy};
+
+/// CTAN's redirector, which sends each request to a nearby mirror
+const REDIRECTOR: &str = "https://mirror.ctan.org/systems/texlive/tlnet";
+
+/// Frozen repositories of past TeX Live releases
+const HISTORIC: &str = "https://ftp.math.utah.edu/pub/tex/historic/systems/texlive";
+
+/// Well-connected mirrors speed-tested along with the redirector's pick
+const KNOWN_MIRRORS: [&str; 8] = [
+    "https://mirror.kakao.com/CTAN/systems/texlive/tlnet",
+    "https://ftp.kaist.ac.kr/tex-archive/systems/texlive/tlnet",
+    "https://ftp.jaist.ac.jp/pub/CTAN/systems/texlive/tlnet",
+    "https://mirrors.tuna.tsinghua.edu.cn/CTAN/systems/texlive/tlnet",
+    "https://mirror.aarnet.edu.au/pub/CTAN/systems/texlive/tlnet",
+    "https://ftp.fau.de/ctan/systems/texlive/tlnet",
+    "https://mirrors.mit.edu/CTAN/systems/texlive/tlnet",
+    "https://ctan.math.illinois.edu/systems/texlive/tlnet",
+];
+
+/// Bytes of the package database downloaded to time a mirror
+const SPEED_TEST_BYTES: u32 = 256 * 1024;
+
+/// Mirrors slower than this are given up on
+const SPEED_TEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MirrorTiming {
+    url: String,
+    /// None when the mirror couldn't be reached in time
+    millis: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MirrorChoice {
+    /// The mirror now set as the repository
+    url: String,
+    /// Every mirror tried, fastest first; empty without a speed test
+    timings: Vec<MirrorTiming>,
+}
+
+fn null_device() -> &'static str {
+    if cfg!(windows) {
+        "NUL"
+    } else {
+        "/dev/null"
+    }
+}
+
+/// Repository settings are tlmgr's; MiKTeX manages its own
+const TLMGR_ONLY: &str = "Package repositories can only be set";
+
+async fn tlmgr(args: &[&str]) -> Result<String, String> {
+    let output = processes::output(
+        proxy::command("tlmgr")
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .await
+    .map_err(|e| format!("Failed to run tlmgr: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "tlmgr failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+async fn curl(args: &[&str]) -> Option<String> {
+    let output = processes::output(
+        proxy::command("curl")
+            .args(["-sS", "--max-time"])
+            .arg(SPEED_TEST_TIMEOUT.as_secs().to_string())
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null()),
+    )
+    .await
+    .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The mirror the redirector currently picks for this machine
+async fn nearby_mirror() -> Option<String> {
+    let location = curl(&[
+        "-o",
+        null_device(),
+        "-w",
+        "%{redirect_url}",
+        &format!("{}/", REDIRECTOR),
+    ])
+    .await?;
+    let location = location.trim_end_matches('/');
+    location.starts_with("http").then(|| location.to_string())
+}
+
+/// Time downloading the start of a mirror's package database
+async fn time_mirror(url: String) -> MirrorTiming {
+    let range = format!("0-{}", SPEED_TEST_BYTES - 1);
+    let database = format!("{}/tlpkg/texlive.tlpdb.xz", url);
+    let millis = curl(&[
+        "-f",
+        "-r",
+        &range,
+        "-o",
+        null_device(),
+        "-w",
+        "%{time_total}",
+        &database,
+    ])
+    .await
+    .and_then(|seconds| seconds.parse::<f64>().ok())
+    .map(|seconds| (seconds * 1000.0) as u64);
+    MirrorTiming { url, millis }
+}
+
+/// The repository tlmgr installs from
+#[tauri::command]
+pub async fn get_package_repository() -> Result<String, String> {
+    packages::require_tlmgr(TLMGR_ONLY).await?;
+    let output = tlmgr(&["option", "repository"]).await?;
+    // "Default package repository (repository): https://..."
+    output
+        .lines()
+        .find(|line| line.contains("(repository)"))
+        .and_then(|line| line.split_once("): "))
+        .map(|(_, url)| url.trim().to_string())
+        .ok_or_else(|| format!("Unexpected tlmgr output: {}", output.trim()))
+}
+
+/// Set the repository tlmgr installs from: a URL, a local path, or "ctan"
+/// for the redirector
+#[tauri::command]
+pub async fn set_package_repository(url: String) -> Result<(), String> {
+    packages::require_tlmgr(TLMGR_ONLY).await?;
+    let url = url.trim().trim_end_matches('/');
+    if url.is_empty() {
+        return Err("Repository is empty".to_string());
+    }
+    tlmgr(&["option", "repository", url]).await.map(|_| ())
+}
+
+/// Set a fixed mirror as the repository: the one the redirector picks for
+/// this machine, or with `speed_test` the fastest of it and a list of known
+/// mirrors
+#[tauri::command]
+pub async fn pick_ctan_mirror(speed_test: bool) -> Result<MirrorChoice, String> {
+    packages::require_tlmgr(TLMGR_ONLY).await?;
+    let nearby = nearby_mirror().await;
+
+    let (url, timings) = if speed_test {
+        let mut candidates: Vec<String> = nearby.into_iter().collect();
+        for mirror in KNOWN_MIRRORS {
+            if !candidates.iter().any(|c| c == mirror) {
+                candidates.push(mirror.to_string());
+            }
+        }
+        let mut tests = JoinSet::new();
+        for candidate in candidates {
+            tests.spawn(time_mirror(candidate));
+        }
+        let mut timings = Vec::new();
+        while let Some(timing) = tests.join_next().await {
+            timings.extend(timing.ok());
+        }
+        timings.sort_by_key(|t| t.millis.unwrap_or(u64::MAX));
+        let fastest = timings
+            .iter()
+            .find(|t| t.millis.is_some())
+            .map(|t| t.url.clone())
+            .ok_or_else(|| "No CTAN mirror could be reached".to_string())?;
+        (fastest, timings)
+    } else {
+        let nearby = nearby.ok_or_else(|| "Failed to reach the CTAN redirector".to_string())?;
+        (nearby, Vec::new())
+    };
+
+    tlmgr(&["option", "repository", &url]).await?;
+    Ok(MirrorChoice { url, timings })
+}
+
+/// Pin the repository to the frozen final state of the installed TeX Live
+/// release, so installs keep matching it after the next release is out.
+/// Returns the repository URL.
+#[tauri::command]
+pub async fn pin_historic_repository() -> Result<String, String> {
+    packages::require_tlmgr(TLMGR_ONLY).await?;
+    // "TeX Live (https://tug.org/texlive) version 2023"
+    let version = tlmgr(&["--version"]).await?;
+    let release = version
+        .lines()
+        .find_map(|line| line.strip_prefix("TeX Live ")?.rsplit_once("version "))
+        .map(|(_, year)| year.trim().to_string())
+        .filter(|year| year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()))
+        .ok_or_else(|| "Failed to read the TeX Live release".to_string())?;
+
+    let url = format!("{}/{}/tlnet-final", HISTORIC, release);
+    let database = format!("{}/tlpkg/texlive.tlpdb.sha512", url);
+    if curl(&["-f", "-I", "-o", null_device(), &database])
+        .await
+        .is_none()
+    {
+        return Err(format!(
+            "No frozen repository for TeX Live {} yet; it appears once the next release is out",
+            release
+        ));
+    }
+
+    tlmgr(&["option", "repository", &url]).await?;
+    Ok(url)
+}
@@ -0,0 +1,105 @@
+//! Compiled PDFs kept in a managed folder and served to the webview over
+//! the `pdf` URI scheme, instead of being sent through IPC as JSON arrays
+
+use std::path::{Path, PathBuf};
+use tauri::http::{header, Request, Response, StatusCode};
+use tokio::fs;
+
+/// URI scheme the PDFs are served under
+pub const SCHEME: &str = "pdf";
+
+/// Folder of the published PDFs; cleared at startup, so nothing the user
+/// keeps may be written there
+pub fn dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("OffLeaf")
+        .join("output")
+}
+
+/// A new file name for a published PDF. Job IDs aren't used: client-chosen
+/// ones may hold anything, and two could sanitize to the same name.
+fn file_name() -> String {
+    format!("{}.pdf", uuid::Uuid::new_v4())
+}
+
+/// Webview URL of a published PDF. Windows and Android serve custom
+/// schemes over http://<scheme>.localhost.
+fn url_of(name: &str) -> String {
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{}.localhost/{}", SCHEME, name)
+    } else {
+        format!("{}://localhost/{}", SCHEME, name)
+    }
+}
+
+/// Remove the PDFs of an earlier session, whose jobs are gone; run at
+/// startup
+pub fn clear() {
+    let _ = std::fs::remove_dir_all(dir());
+}
+
+/// Whether a path is in the managed folder, e.g. an export the user
+/// pointed there
+pub fn contains(path: &Path) -> bool {
+    let dir = dir();
+    let resolved = path
+        .parent()
+        .and_then(|parent| parent.canonicalize().ok())
+        .zip(path.file_name())
+        .map(|(parent, name)| parent.join(name))
+        .unwrap_or_else(|| path.to_path_buf());
+    path.starts_with(&dir)
+        || dir
+            .canonicalize()
+            .is_ok_and(|dir| resolved.starts_with(dir))
+}
+
+/// Remove a published PDF once its job is dropped, so a job's `pdf_path`
+/// and `pdf_url` stay valid for as long as the job is kept
+pub fn discard(path: &Path) {
+    if path.parent() == Some(dir().as_path()) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Copy a compiled PDF out of the build folder, which the next compile
+/// overwrites. Returns the copy's path and webview URL.
+pub async fn publish(pdf_path: &Path) -> Result<(PathBuf, String), String> {
+    let dir = dir();
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Failed to create output folder: {}", e))?;
+    let name = file_name();
+    let target = dir.join(&name);
+    fs::copy(pdf_path, &target)
+        .await
+        .map_err(|e| format!("Failed to store PDF: {}", e))?;
+    Ok((target, url_of(&name)))
+}
+
+/// Handler of the `pdf` scheme: `pdf://localhost/<name>.pdf` returns the
+/// published PDF of that name. Runs off the webview's thread, which a large
+/// PDF would otherwise block.
+pub async fn serve(request: Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let name = request.uri().path().trim_start_matches('/');
+    let valid = name.ends_with(".pdf")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.contains("..");
+    let data = if valid {
+        fs::read(dir().join(name)).await.ok()
+    } else {
+        None
+    };
+
+    let response = Response::builder().header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+    let response = match data {
+        Some(data) => response
+            .header(header::CONTENT_TYPE, "application/pdf")
+            .body(data),
+        None => response.status(StatusCode::NOT_FOUND).body(Vec::new()),
+    };
+    response.unwrap_or_else(|_| Response::new(Vec::new()))
+}
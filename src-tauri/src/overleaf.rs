@@ -0,0 +1,252 @@
+//! Overleaf Git bridge: clone an Overleaf project, pull its updates and push
+//! local changes back. The Git token is kept in the OS keychain.
+
+use std::io::Write;
+use std::path::Path;
+use tokio::fs;
+
+use crate::{archive, processes, project, proxy};
+
+const KEYRING_SERVICE: &str = "OffLeaf";
+const KEYRING_USER: &str = "overleaf-git";
+
+const GIT_HOST: &str = "https://git.overleaf.com";
+
+/// Answers Git's prompts for the Overleaf host: Overleaf wants "git" as the
+/// user name and the token as the password. The token comes through the
+/// environment so it never touches the disk. Prompts for any other host,
+/// e.g. a redirect or a submodule, get nothing.
+fn askpass_script() -> String {
+    let host = GIT_HOST.trim_start_matches("https://");
+    format!(
+        "#!/bin/sh
+case \"$1\" in
+  \"Username for 'https://{host}'\"*) printf '%s\\n' git ;;
+  \"Password for 'https://git@{host}'\"*) printf '%s\\n' \"$OFFLEAF_GIT_TOKEN\" ;;
+  *) exit 1 ;;
+esac
+"
+    )
+}
+
+fn keyring_entry() -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+        .map_err(|e| format!("Failed to access keychain: {}", e))
+}
+
+/// Keychain backends block, so they run off the async threads
+async fn with_keyring<T, F>(f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(keyring::Entry) -> Result<T, String> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || f(keyring_entry()?))
+        .await
+        .map_err(|e| format!("Failed to access keychain: {}", e))?
+}
+
+async fn stored_token() -> Result<Option<String>, String> {
+    with_keyring(|entry| match entry.get_password() {
+        Ok(token) => Ok(Some(token)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read Overleaf token: {}", e)),
+    })
+    .await
+}
+
+/// Store the Overleaf Git token (Account Settings > Git Integration)
+#[tauri::command]
+pub async fn overleaf_set_token(token: String) -> Result<(), String> {
+    let token = token.trim().to_string();
+    if token.is_empty() {
+        return Err("Token is empty".to_string());
+    }
+    with_keyring(move |entry| {
+        entry
+            .set_password(&token)
+            .map_err(|e| format!("Failed to store Overleaf token: {}", e))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn overleaf_clear_token() -> Result<(), String> {
+    with_keyring(|entry| match entry.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!("Failed to remove Overleaf token: {}", e)),
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn overleaf_has_token() -> Result<bool, String> {
+    Ok(stored_token().await?.is_some())
+}
+
+/// Git remote for a project URL (editor or Git link) or a bare project ID.
+/// Only Overleaf's own Git host is accepted, since the token goes to it.
+fn remote_url(project: &str) -> Result<String, String> {
+    let project = project.trim().trim_end_matches('/');
+    let id = project
+        .strip_prefix("https://www.overleaf.com/project/")
+        .or_else(|| project.strip_prefix("https://overleaf.com/project/"))
+        .or_else(|| {
+            project
+                .strip_prefix(GIT_HOST)
+                .and_then(|rest| rest.strip_prefix('/'))
+        })
+        .unwrap_or(project);
+    if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Ok(format!("{}/{}", GIT_HOST, id));
+    }
+    Err(format!("Not an Overleaf project URL or ID: {}", project))
+}
+
+/// Run git with the stored token available to it, returning its stdout
+async fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let token = stored_token().await?.unwrap_or_default();
+
+    let mut script = tempfile::Builder::new()
+        .prefix("offleaf-askpass")
+        .suffix(".sh")
+        .tempfile()
+        .map_err(|e| format!("Failed to prepare Git credentials: {}", e))?;
+    script
+        .write_all(askpass_script().as_bytes())
+        .map_err(|e| format!("Failed to prepare Git credentials: {}", e))?;
+    // Closed before git runs it; an open file can't be executed on Linux
+    let script = script.into_temp_path();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o700))
+            .map_err(|e| format!("Failed to prepare Git credentials: {}", e))?;
+    }
+
+    let mut command = proxy::command("git");
+    command
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_ASKPASS", &*script)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("OFFLEAF_GIT_TOKEN", token);
+    let output = processes::output(&mut command)
+        .await
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        // Name the subcommand, past any "-c key=value" options
+        let subcommand = args
+            .iter()
+            .step_by(2)
+            .find(|arg| **arg != "-c")
+            .unwrap_or(&"");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git {} failed: {}", subcommand, stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Directory of a project cloned from Overleaf
+fn repo_dir(id: &str) -> Result<std::path::PathBuf, String> {
+    let dir = project::project_dir(id)?;
+    if !dir.join(".git").is_dir() {
+        return Err(format!("Project {} is not linked to Overleaf", id));
+    }
+    Ok(dir)
+}
+
+/// Clone an Overleaf project into a new project
+#[tauri::command]
+pub async fn overleaf_clone(
+    project: String,
+    name: Option<String>,
+) -> Result<project::ProjectSummary, String> {
+    let url = remote_url(&project)?;
+    let name = name.unwrap_or_else(|| {
+        let id = url.rsplit('/').next().unwrap_or_default();
+        format!("Overleaf {}", id)
+    });
+
+    let projects = project::projects_dir();
+    fs::create_dir_all(&projects)
+        .await
+        .map_err(|e| format!("Failed to create projects directory: {}", e))?;
+    let dir = projects.join(project::new_project_id(&name));
+    let target = dir.to_string_lossy().to_string();
+    git(&projects, &["clone", "--", &url, &target]).await?;
+
+    // OffLeaf's own files stay out of the Overleaf project
+    let exclude = dir.join(".git").join("info").join("exclude");
+    let mut excluded = fs::read_to_string(&exclude).await.unwrap_or_default();
+    if !excluded.is_empty() && !excluded.ends_with('\n') {
+        excluded.push('\n');
+    }
+    excluded.push_str(&format!("/{}\n/.offleaf/\n", project::MANIFEST));
+    if let Some(parent) = exclude.parent() {
+        let _ = fs::create_dir_all(parent).await;
+    }
+    fs::write(&exclude, excluded)
+        .await
+        .map_err(|e| format!("Failed to update Git excludes: {}", e))?;
+
+    match archive::register_folder(&dir, name).await {
+        Ok(summary) => Ok(summary),
+        Err(e) => {
+            let _ = fs::remove_dir_all(&dir).await;
+            Err(e)
+        }
+    }
+}
+
+/// Merge the changes made on Overleaf into a cloned project
+#[tauri::command]
+pub async fn overleaf_pull(id: String) -> Result<(), String> {
+    let dir = repo_dir(&id)?;
+    git(&dir, &["pull", "--no-rebase", "--no-edit"]).await?;
+    Ok(())
+}
+
+/// Commit the local changes, if any, and push them to Overleaf. Returns
+/// whether a commit was made.
+#[tauri::command]
+pub async fn overleaf_push(id: String, message: Option<String>) -> Result<bool, String> {
+    let dir = repo_dir(&id)?;
+    // Staging would mark conflicted files resolved, markers and all
+    let unmerged = git(&dir, &["diff", "--name-only", "--diff-filter=U"]).await?;
+    if dir.join(".git").join("MERGE_HEAD").exists() || !unmerged.trim().is_empty() {
+        let files: Vec<&str> = unmerged.lines().collect();
+        return Err(if files.is_empty() {
+            "Finish or abort the merge with Overleaf before pushing".to_string()
+        } else {
+            format!(
+                "Resolve the conflicts with Overleaf before pushing: {}",
+                files.join(", ")
+            )
+        });
+    }
+    git(&dir, &["add", "-A"]).await?;
+    let changed = !git(&dir, &["status", "--porcelain"])
+        .await?
+        .trim()
+        .is_empty();
+    if changed {
+        let message = message
+            .filter(|m| !m.trim().is_empty())
+            .unwrap_or_else(|| "Update from OffLeaf".to_string());
+        // Overleaf ignores the author, but git refuses to commit without one
+        let has_identity = git(&dir, &["config", "user.email"]).await.is_ok();
+        let mut args = Vec::new();
+        if !has_identity {
+            args.extend([
+                "-c",
+                "user.name=OffLeaf",
+                "-c",
+                "user.email=offleaf@localhost",
+            ]);
+        }
+        args.extend(["commit", "-m", &message]);
+        git(&dir, &args).await?;
+    }
+    git(&dir, &["push"]).await?;
+    Ok(changed)
+}
@@ -0,0 +1,1191 @@
+//! Package management through whichever TeX distribution is installed:
+//! tlmgr for TeX Live, `miktex packages` (or the older mpm) for MiKTeX
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::process::{Output, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+
+use crate::{external, processes, proxy, save, settings, tex_path, PackageInfo};
+
+lazy_static::lazy_static! {
+    /// Installs in progress by ID, with their current process
+    static ref INSTALLS: Mutex<HashMap<String, InstallState>> = Mutex::new(HashMap::new());
+    /// Whether kpsewhich finds a file, by file name; None until read from disk
+    static ref LOOKUPS: tokio::sync::Mutex<Option<HashMap<String, bool>>> =
+        tokio::sync::Mutex::new(None);
+    /// Whether writing the lookups to disk is already scheduled
+    static ref LOOKUPS_SAVE_PENDING: Mutex<bool> = Mutex::new(false);
+    /// tlmgr's "update:   pgf  [7093k]: local:    65553, source:    67456" lines
+    static ref TLMGR_UPDATE: Regex =
+        Regex::new(r"^(update|auto-install|auto-remove):\s+(\S+?)(?:\s+\[([^\]]+)\])?:\s*(.*)$").unwrap();
+    /// tlmgr's "[2/5, 00:03/00:10] install: pgf [7093k]" lines
+    static ref TLMGR_PROGRESS: Regex =
+        Regex::new(r"^\[(\d+)/(\d+)[^\]]*\]\s+(?:install|update):\s+(\S+)(?:\s+\[([^\]]+)\])?").unwrap();
+}
+
+/// Found files are written to disk this long after the first unsaved one,
+/// so the lookups of a whole document are saved at once
+const LOOKUPS_SAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// Style and class files whose TeX Live package has another name, by file
+/// name without extension. Files not listed are looked up with tlmgr.
+const TEXLIVE_FILE_PACKAGES: &[(&str, &str)] = &[
+    // LaTeX base
+    ("alltt", "latex"),
+    ("article", "latex"),
+    ("book", "latex"),
+    ("fontenc", "latex"),
+    ("ifthen", "latex"),
+    ("inputenc", "latex"),
+    ("latexsym", "latex"),
+    ("letter", "latex"),
+    ("makeidx", "latex"),
+    ("report", "latex"),
+    ("textcomp", "latex"),
+    // Graphics bundle
+    ("color", "graphics"),
+    ("epsfig", "graphics"),
+    ("graphicx", "graphics"),
+    ("keyval", "graphics"),
+    ("lscape", "graphics"),
+    ("trig", "graphics"),
+    // Tools bundle
+    ("afterpage", "tools"),
+    ("array", "tools"),
+    ("bm", "tools"),
+    ("calc", "tools"),
+    ("dcolumn", "tools"),
+    ("enumerate", "tools"),
+    ("hhline", "tools"),
+    ("indentfirst", "tools"),
+    ("longtable", "tools"),
+    ("multicol", "tools"),
+    ("showkeys", "tools"),
+    ("tabularx", "tools"),
+    ("theorem", "tools"),
+    ("varioref", "tools"),
+    ("verbatim", "tools"),
+    ("xr", "tools"),
+    ("xspace", "tools"),
+    // AMS
+    ("amsart", "amscls"),
+    ("amsbook", "amscls"),
+    ("amscd", "amsmath"),
+    ("amssymb", "amsfonts"),
+    ("amsthm", "amscls"),
+    ("eucal", "amsfonts"),
+    ("eufrak", "amsfonts"),
+    // Korean and CJK
+    ("CJK", "cjk"),
+    ("CJKutf8", "cjk"),
+    ("kotex", "kotex-utf"),
+    ("xeCJK", "xecjk"),
+    // Fonts
+    ("bbm", "bbm-macros"),
+    ("dsfont", "doublestroke"),
+    ("helvet", "psnfss"),
+    ("mathpazo", "psnfss"),
+    ("mathptmx", "psnfss"),
+    ("mathrsfs", "jknapltx"),
+    ("newtxmath", "newtx"),
+    ("newtxtext", "newtx"),
+    ("pifont", "psnfss"),
+    ("times", "psnfss"),
+    // Document classes
+    ("IEEEtran", "ieeetran"),
+    ("amsproc", "amscls"),
+    ("ctexart", "ctex"),
+    ("ctexbook", "ctex"),
+    ("ctexrep", "ctex"),
+    ("ltjsarticle", "luatexja"),
+    ("minimal", "latex"),
+    ("oblivoir", "kotex-oblivoir"),
+    ("revtex4-2", "revtex"),
+    ("scrartcl", "koma-script"),
+    ("scrbook", "koma-script"),
+    ("scrlayer-scrpage", "koma-script"),
+    ("scrlttr2", "koma-script"),
+    ("scrreprt", "koma-script"),
+    ("tufte-book", "tufte-latex"),
+    ("tufte-handout", "tufte-latex"),
+    ("typearea", "koma-script"),
+    // Others
+    ("algorithm", "algorithms"),
+    ("algorithmic", "algorithms"),
+    ("algpseudocode", "algorithmicx"),
+    ("bigdelim", "multirow"),
+    ("bigstrut", "multirow"),
+    ("empheq", "mathtools"),
+    ("epstopdf", "epstopdf-pkg"),
+    ("etex", "etex-pkg"),
+    ("expl3", "l3kernel"),
+    ("ifluatex", "iftex"),
+    ("ifpdf", "iftex"),
+    ("ifxetex", "iftex"),
+    ("nicefrac", "units"),
+    ("pgfplotstable", "pgfplots"),
+    ("subcaption", "caption"),
+    ("tikz", "pgf"),
+    ("xfrac", "l3packages"),
+    ("xparse", "l3packages"),
+];
+
+/// Style and class files whose MiKTeX package has another name. MiKTeX
+/// can't look files up, so files not listed ship in a package of their name.
+const MIKTEX_FILE_PACKAGES: &[(&str, &str)] = &[
+    ("color", "graphics"),
+    ("graphicx", "graphics"),
+    ("keyval", "graphics"),
+    ("lscape", "graphics"),
+    ("amssymb", "amsfonts"),
+    ("amsthm", "amscls"),
+    ("array", "tools"),
+    ("bm", "tools"),
+    ("calc", "tools"),
+    ("longtable", "tools"),
+    ("multicol", "tools"),
+    ("tabularx", "tools"),
+    ("verbatim", "tools"),
+    ("xspace", "tools"),
+    ("tikz", "pgf"),
+    ("xparse", "l3packages"),
+];
+
+/// A style or class file's name without its extension
+fn file_stem(file: &str) -> &str {
+    file.strip_suffix(".sty")
+        .or_else(|| file.strip_suffix(".cls"))
+        .unwrap_or(file)
+}
+
+/// The package that ships a style or class file as far as the manager's
+/// table knows, e.g. "graphics" for graphicx
+pub fn package_of<'a>(manager: &dyn PackageManager, file: &'a str) -> &'a str {
+    let name = file_stem(file);
+    manager
+        .file_packages()
+        .iter()
+        .find(|(f, _)| *f == name)
+        .map(|(_, package)| *package)
+        .unwrap_or(name)
+}
+
+/// The package that ships a style or class file, asking the manager's
+/// repository about files its table doesn't list
+pub async fn package_providing(manager: &dyn PackageManager, file: &str) -> String {
+    let name = file_stem(file);
+    let listed = manager.file_packages().iter().any(|(f, _)| *f == name);
+    if let (false, Some(args)) = (listed, manager.find_file_args(name)) {
+        let output = processes::output(
+            proxy::command(manager.program())
+                .args(args)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .await;
+        if let Some(output) = output.ok().filter(|o| o.status.success()) {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            if let Some(package) = manager.parse_find_file(&stdout, name) {
+                return package;
+            }
+        }
+    }
+    package_of(manager, file).to_string()
+}
+
+fn lookups_path() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("OffLeaf")
+        .join("installed-files.json")
+}
+
+/// Whether kpsewhich found a file last time it was asked
+pub async fn cached_lookup(file: &str) -> Option<bool> {
+    let mut lookups = LOOKUPS.lock().await;
+    if lookups.is_none() {
+        let saved: Option<HashMap<String, bool>> = tokio::fs::read_to_string(lookups_path())
+            .await
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok());
+        // Files missing in an earlier session may have been installed since
+        let found = saved
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, found)| *found);
+        *lookups = Some(found.collect());
+    }
+    lookups.as_ref()?.get(file).copied()
+}
+
+/// Remember whether kpsewhich found a file. Files it found are also kept
+/// on disk for later sessions; missing ones only until the app exits.
+pub async fn record_lookup(file: &str, found: bool) {
+    LOOKUPS
+        .lock()
+        .await
+        .get_or_insert_with(HashMap::new)
+        .insert(file.to_string(), found);
+    if !found {
+        return;
+    }
+    match LOOKUPS_SAVE_PENDING.lock() {
+        Ok(mut pending) if !*pending => *pending = true,
+        _ => return,
+    }
+    tauri::async_runtime::spawn(async {
+        tokio::time::sleep(LOOKUPS_SAVE_DELAY).await;
+        save_lookups().await;
+    });
+}
+
+/// Write the files kpsewhich found to disk
+async fn save_lookups() {
+    if let Ok(mut pending) = LOOKUPS_SAVE_PENDING.lock() {
+        *pending = false;
+    }
+    let text = {
+        let lookups = LOOKUPS.lock().await;
+        let found: HashMap<&String, bool> = lookups
+            .iter()
+            .flatten()
+            .filter(|(_, found)| **found)
+            .map(|(file, found)| (file, *found))
+            .collect();
+        serde_json::to_string(&found)
+    };
+    let path = lookups_path();
+    if let Some(parent) = path.parent() {
+        let _ = tokio::fs::create_dir_all(parent).await;
+    }
+    if let Ok(text) = text {
+        let _ = save::write_atomic(&path, text.as_bytes()).await;
+    }
+}
+
+/// Forget every lookup, e.g. because packages were installed or removed
+pub async fn clear_lookups() {
+    *LOOKUPS.lock().await = Some(HashMap::new());
+    let _ = tokio::fs::remove_file(lookups_path()).await;
+}
+
+/// Whether an operation can change which files are installed
+fn changes_files(action: Action) -> bool {
+    matches!(
+        action,
+        Action::Install(_) | Action::Remove(_) | Action::UpdateAll
+    )
+}
+
+/// Forget which packages are installed, for when they were installed or
+/// removed outside of OffLeaf
+#[tauri::command]
+pub async fn refresh_package_cache() {
+    clear_lookups().await;
+}
+
+/// Whether packages a document loads are installed without asking
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoInstallMode {
+    /// Every install waits for the user to confirm it
+    #[default]
+    Ask,
+    /// Packages on the allowlist are installed, the rest wait for the user
+    Allowlist,
+    /// Nothing is installed automatically
+    Never,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstallPolicy {
+    pub mode: AutoInstallMode,
+    /// TeX Live package names installed without asking in allowlist mode
+    pub allowlist: Vec<String>,
+    /// User agreed to compiles going through texliveonfly, which installs
+    /// whatever the engine stops on without asking
+    pub on_the_fly: bool,
+}
+
+impl InstallPolicy {
+    /// Whether a package may be installed without asking
+    pub fn allows(&self, package: &str) -> bool {
+        self.mode == AutoInstallMode::Allowlist && self.allowlist.iter().any(|p| p == package)
+    }
+}
+
+#[tauri::command]
+pub async fn get_install_policy() -> Result<InstallPolicy, String> {
+    Ok(settings::load().await.install_policy)
+}
+
+#[tauri::command]
+pub async fn set_install_policy(policy: InstallPolicy) -> Result<(), String> {
+    let mut app_settings = settings::load().await;
+    app_settings.install_policy = InstallPolicy {
+        allowlist: policy
+            .allowlist
+            .iter()
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect(),
+        ..policy
+    };
+    settings::save(&app_settings).await
+}
+
+/// A package operation, turned into a command line by each manager
+#[derive(Debug, Clone, Copy)]
+pub enum Action<'a> {
+    Search(&'a str),
+    ListInstalled,
+    Info(&'a str),
+    Install(&'a str),
+    Remove(&'a str),
+    UpdateAll,
+}
+
+/// A distribution's package manager: how to run each operation and how to
+/// read its output
+pub trait PackageManager: Sync {
+    /// Shown in the package manager, e.g. "TeX Live (tlmgr)"
+    fn name(&self) -> &'static str;
+    fn program(&self) -> &'static str;
+    fn args(&self, action: Action) -> Vec<String>;
+    fn parse_search(&self, output: &str, query: &str) -> Vec<PackageInfo>;
+    fn parse_installed(&self, output: &str) -> Vec<PackageInfo>;
+    fn parse_info(&self, output: &str, package: &str) -> PackageInfo;
+    /// Names of the packages an install pulled in, dependencies included
+    fn parse_install(&self, _output: &str) -> Vec<String> {
+        Vec::new()
+    }
+    /// The package an output line of an install starts on
+    fn parse_progress(&self, _line: &str) -> Option<InstallStep> {
+        None
+    }
+    /// Files, by name without extension, whose package has another name
+    fn file_packages(&self) -> &'static [(&'static str, &'static str)];
+    /// Arguments to find the packages shipping a .sty or .cls file named
+    /// `name`, if the manager can look files up
+    fn find_file_args(&self, _name: &str) -> Option<Vec<String>> {
+        None
+    }
+    /// The package in `find_file_args` output that ships `name`
+    fn parse_find_file(&self, _output: &str, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+/// One package of an install: the `current`th of `total`
+#[derive(Debug, Clone, Serialize)]
+pub struct InstallStep {
+    package: String,
+    current: u32,
+    total: u32,
+    /// Download size as the manager reports it, e.g. "7093k"
+    size: Option<String>,
+}
+
+/// Payload of the "package-install-started" event, giving the ID that
+/// progress events carry and `cancel_package_install` takes
+#[derive(Debug, Clone, Serialize)]
+struct InstallStarted<'a> {
+    install_id: &'a str,
+    /// Compile job the install is part of, if any
+    job_id: Option<&'a str>,
+}
+
+/// Payload of "package-install-progress" events, one per output line
+#[derive(Debug, Clone, Serialize)]
+struct InstallProgress<'a> {
+    install_id: &'a str,
+    line: &'a str,
+    step: Option<InstallStep>,
+}
+
+fn package(name: String, description: String, installed: bool) -> PackageInfo {
+    PackageInfo {
+        name,
+        description,
+        installed,
+        version: None,
+        size: None,
+        depends: None,
+    }
+}
+
+pub struct TexLive;
+
+impl PackageManager for TexLive {
+    fn name(&self) -> &'static str {
+        "TeX Live (tlmgr)"
+    }
+
+    fn program(&self) -> &'static str {
+        "tlmgr"
+    }
+
+    fn args(&self, action: Action) -> Vec<String> {
+        let args: &[&str] = match action {
+            Action::Search(query) => &["search", "--global", query],
+            Action::ListInstalled => &["list", "--only-installed"],
+            Action::Info(name) => &["info", name],
+            Action::Install(name) => &["install", name],
+            Action::Remove(name) => &["remove", name],
+            Action::UpdateAll => &["update", "--all"],
+        };
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn parse_search(&self, output: &str, _query: &str) -> Vec<PackageInfo> {
+        let mut packages = Vec::new();
+        for line in output.lines() {
+            let line = line.trim();
+            // "package_name - description" or just "package_name:"
+            if let Some(sep_pos) = line.find(" - ") {
+                let name = line[..sep_pos].trim().to_string();
+                let description = line[sep_pos + 3..].trim().to_string();
+                packages.push(package(name, description, false));
+            } else if line.ends_with(':') {
+                let name = line.trim_end_matches(':').to_string();
+                packages.push(package(name, String::new(), false));
+            }
+        }
+        packages
+    }
+
+    fn parse_installed(&self, output: &str) -> Vec<PackageInfo> {
+        let mut packages = Vec::new();
+        for line in output.lines() {
+            let line = line.trim();
+            if line.starts_with("tlmgr") {
+                continue;
+            }
+            // "i package_name: description"
+            if let Some(rest) = line.strip_prefix('i') {
+                let rest = rest.trim();
+                if let Some(colon_pos) = rest.find(':') {
+                    let name = rest[..colon_pos].trim().to_string();
+                    let description = rest[colon_pos + 1..].trim().to_string();
+                    packages.push(package(name, description, true));
+                }
+            }
+        }
+        packages
+    }
+
+    fn parse_info(&self, output: &str, package_name: &str) -> PackageInfo {
+        let mut info = package(package_name.to_string(), String::new(), false);
+        for line in output.lines() {
+            let line = line.trim();
+            if line.starts_with("package:") {
+                info.name = line.replace("package:", "").trim().to_string();
+            } else if line.starts_with("shortdesc:") {
+                info.description = line.replace("shortdesc:", "").trim().to_string();
+            } else if line.starts_with("installed:") {
+                info.installed = line.contains("Yes");
+            } else if line.starts_with("revision:") {
+                info.version = Some(line.replace("revision:", "").trim().to_string());
+            } else if line.starts_with("sizes:") {
+                info.size = Some(line.replace("sizes:", "").trim().to_string());
+            } else if let Some(depends) = line.strip_prefix("depends:") {
+                info.depends = Some(
+                    depends
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|d| !d.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                );
+            }
+        }
+        // tlmgr leaves the field out for packages without dependencies
+        info.depends.get_or_insert_with(Vec::new);
+        info
+    }
+
+    fn parse_install(&self, output: &str) -> Vec<String> {
+        output
+            .lines()
+            .filter(|line| line.contains("Installing") || line.contains("install:"))
+            .filter_map(|line| line.split_whitespace().last())
+            .map(|pkg| pkg.trim_matches(|c| c == ':' || c == '.').to_string())
+            .collect()
+    }
+
+    fn parse_progress(&self, line: &str) -> Option<InstallStep> {
+        let cap = TLMGR_PROGRESS.captures(line.trim())?;
+        Some(InstallStep {
+            package: cap[3].to_string(),
+            current: cap[1].parse().ok()?,
+            total: cap[2].parse().ok()?,
+            size: cap.get(4).map(|size| size.as_str().to_string()),
+        })
+    }
+
+    fn file_packages(&self) -> &'static [(&'static str, &'static str)] {
+        TEXLIVE_FILE_PACKAGES
+    }
+
+    fn find_file_args(&self, name: &str) -> Option<Vec<String>> {
+        // A pattern over the repository's file paths
+        let pattern = format!("/{}.", name);
+        Some(
+            ["search", "--global", "--file", &pattern]
+                .map(str::to_string)
+                .to_vec(),
+        )
+    }
+
+    fn parse_find_file(&self, output: &str, name: &str) -> Option<String> {
+        // "graphics:" lines, each followed by the package's matching files
+        let mut package = None;
+        for line in output.lines() {
+            let header = line.strip_suffix(':');
+            if let Some(header) = header.filter(|_| !line.starts_with(char::is_whitespace)) {
+                package = Some(header);
+                continue;
+            }
+            let file = line.trim();
+            let matches = [".sty", ".cls"]
+                .iter()
+                .any(|ext| file.ends_with(&format!("/{}{}", name, ext)));
+            // Binaries of architecture-specific packages are e.g. "pdftex.x86_64-linux"
+            if let Some(package) = package.filter(|p| matches && !p.contains('.')) {
+                return Some(package.to_string());
+            }
+        }
+        None
+    }
+}
+
+/// MiKTeX through its `miktex` console, or mpm on releases before 21.x
+pub enum MiKTeX {
+    Console,
+    Legacy,
+}
+
+/// Fields `miktex packages list` prints per package, tab-separated
+const MIKTEX_TEMPLATE: &str = "{id}\t{isInstalled}\t{title}\t{version}";
+
+impl MiKTeX {
+    /// Packages from the tab-separated template output, or from the
+    /// "i  files  size  name" columns of `mpm --list`
+    fn parse_list(&self, output: &str) -> Vec<PackageInfo> {
+        let mut packages = Vec::new();
+        for line in output.lines() {
+            match self {
+                MiKTeX::Console => {
+                    let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+                    let [id, installed, title, version] = fields[..] else {
+                        continue;
+                    };
+                    let mut info = package(
+                        id.to_string(),
+                        title.to_string(),
+                        matches!(installed.to_lowercase().as_str(), "true" | "yes" | "1"),
+                    );
+                    info.version = Some(version.to_string()).filter(|v| !v.is_empty());
+                    packages.push(info);
+                }
+                MiKTeX::Legacy => {
+                    let columns: Vec<&str> = line.split_whitespace().collect();
+                    if columns.len() < 2 || !matches!(columns[0], "i" | "-") {
+                        continue;
+                    }
+                    let name = columns[columns.len() - 1].to_string();
+                    packages.push(package(name, String::new(), columns[0] == "i"));
+                }
+            }
+        }
+        packages
+    }
+}
+
+impl PackageManager for MiKTeX {
+    fn name(&self) -> &'static str {
+        match self {
+            MiKTeX::Console => "MiKTeX (miktex packages)",
+            MiKTeX::Legacy => "MiKTeX (mpm)",
+        }
+    }
+
+    fn program(&self) -> &'static str {
+        match self {
+            MiKTeX::Console => "miktex",
+            MiKTeX::Legacy => "mpm",
+        }
+    }
+
+    fn file_packages(&self) -> &'static [(&'static str, &'static str)] {
+        MIKTEX_FILE_PACKAGES
+    }
+
+    fn args(&self, action: Action) -> Vec<String> {
+        match self {
+            MiKTeX::Console => {
+                let mut args = vec!["packages".to_string()];
+                match action {
+                    // There's no search command; the whole list is filtered
+                    Action::Search(_) => {
+                        args.extend(["list".into(), "--template".into(), MIKTEX_TEMPLATE.into()])
+                    }
+                    Action::ListInstalled => args.extend([
+                        "list".into(),
+                        "--only-installed".into(),
+                        "--template".into(),
+                        MIKTEX_TEMPLATE.into(),
+                    ]),
+                    Action::Info(name) => args.extend([
+                        "info".into(),
+                        "--template".into(),
+                        MIKTEX_TEMPLATE.into(),
+                        name.into(),
+                    ]),
+                    Action::Install(name) => args.extend(["install".into(), name.into()]),
+                    Action::Remove(name) => args.extend(["remove".into(), name.into()]),
+                    Action::UpdateAll => args.push("update".into()),
+                }
+                args
+            }
+            MiKTeX::Legacy => vec![match action {
+                Action::Search(_) | Action::ListInstalled => "--list".to_string(),
+                Action::Info(name) => format!("--print-package-info={}", name),
+                Action::Install(name) => format!("--install={}", name),
+                Action::Remove(name) => format!("--uninstall={}", name),
+                Action::UpdateAll => "--update".to_string(),
+            }],
+        }
+    }
+
+    fn parse_search(&self, output: &str, query: &str) -> Vec<PackageInfo> {
+        let query = query.trim().to_lowercase();
+        self.parse_list(output)
+            .into_iter()
+            .filter(|p| {
+                p.name.to_lowercase().contains(&query)
+                    || p.description.to_lowercase().contains(&query)
+            })
+            .collect()
+    }
+
+    fn parse_installed(&self, output: &str) -> Vec<PackageInfo> {
+        self.parse_list(output)
+            .into_iter()
+            .filter(|p| p.installed)
+            .collect()
+    }
+
+    fn parse_info(&self, output: &str, package_name: &str) -> PackageInfo {
+        if let Some(info) = self.parse_list(output).into_iter().next() {
+            return info;
+        }
+        // mpm prints "key: value" lines
+        let mut info = package(package_name.to_string(), String::new(), false);
+        for line in output.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "name" => info.name = value.to_string(),
+                "title" => info.description = value.to_string(),
+                "version" if !value.is_empty() => info.version = Some(value.to_string()),
+                "installed on" | "installed" => {
+                    info.installed = !value.is_empty() && !value.eq_ignore_ascii_case("no")
+                }
+                "archive file size" | "size" => info.size = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        info
+    }
+}
+
+/// Tried in this order; a MiKTeX install has no tlmgr and vice versa
+static MANAGERS: [&dyn PackageManager; 3] = [&TexLive, &MiKTeX::Console, &MiKTeX::Legacy];
+
+static MANAGER: OnceCell<&'static dyn PackageManager> = OnceCell::const_new();
+
+async fn detect() -> Result<&'static dyn PackageManager, String> {
+    for manager in MANAGERS {
+        let available = tex_path::command(manager.program())
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .map(|s| s.success())
+            .unwrap_or(false);
+        if available {
+            return Ok(manager);
+        }
+    }
+    Err("No TeX package manager found (tlmgr, miktex or mpm)".to_string())
+}
+
+/// The installed distribution's package manager. Detected once; a failed
+/// detection is retried, so a distribution installed later is picked up.
+pub async fn manager() -> Result<&'static dyn PackageManager, String> {
+    MANAGER.get_or_try_init(detect).await.copied()
+}
+
+/// Detect the package manager at startup, ahead of the first package command
+pub async fn detect_at_startup() {
+    let _ = manager().await;
+}
+
+/// The package manager, if it is TeX Live's tlmgr; otherwise an error
+/// starting with `what`, e.g. "Package repositories can only be set"
+pub async fn require_tlmgr(what: &str) -> Result<&'static dyn PackageManager, String> {
+    let manager = manager().await?;
+    if manager.program() == "tlmgr" {
+        Ok(manager)
+    } else {
+        Err(format!("{} for TeX Live, not {}", what, manager.name()))
+    }
+}
+
+/// Run a package operation and capture its output
+pub async fn run(manager: &dyn PackageManager, action: Action<'_>) -> Result<Output, String> {
+    let output = processes::output(
+        proxy::command(manager.program())
+            .args(manager.args(action))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .await;
+    if changes_files(action) {
+        clear_lookups().await;
+    }
+    output.map_err(|e| format!("Failed to run {}: {}", manager.program(), e))
+}
+
+#[derive(Default)]
+struct InstallState {
+    pid: Option<u32>,
+    cancelled: bool,
+    job_id: Option<String>,
+}
+
+/// An install that streams its output as "package-install-progress" events
+/// and can be cancelled by ID while it runs
+pub struct Install {
+    app: AppHandle,
+    id: String,
+}
+
+impl Install {
+    /// Start tracking an install under a new ID, announced in a
+    /// "package-install-started" event. One started by a compile job is
+    /// cancelled with the job.
+    pub fn begin(app: &AppHandle, job_id: Option<&str>) -> Self {
+        let id = uuid::Uuid::new_v4().to_string();
+        if let Ok(mut installs) = INSTALLS.lock() {
+            installs.insert(
+                id.clone(),
+                InstallState {
+                    job_id: job_id.map(str::to_string),
+                    ..InstallState::default()
+                },
+            );
+        }
+        let _ = app.emit(
+            "package-install-started",
+            InstallStarted {
+                install_id: &id,
+                job_id,
+            },
+        );
+        Install {
+            app: app.clone(),
+            id,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        INSTALLS
+            .lock()
+            .map(|installs| installs.get(&self.id).is_some_and(|i| i.cancelled))
+            .unwrap_or(false)
+    }
+
+    fn set_pid(&self, pid: Option<u32>) {
+        if let Ok(mut installs) = INSTALLS.lock() {
+            if let Some(install) = installs.get_mut(&self.id) {
+                install.pid = pid;
+            }
+        }
+    }
+
+    /// Run a package operation, emitting each line of its output
+    pub async fn run(
+        &self,
+        manager: &dyn PackageManager,
+        action: Action<'_>,
+    ) -> Result<Output, String> {
+        let mut command = proxy::command(manager.program());
+        command.args(manager.args(action));
+        let output = self.run_command(&mut command, manager).await;
+        if changes_files(action) {
+            clear_lookups().await;
+        }
+        output
+    }
+
+    /// Run a command, emitting each line of its output along with the
+    /// progress `parser` reads from it
+    pub async fn run_command(
+        &self,
+        command: &mut Command,
+        parser: &dyn PackageManager,
+    ) -> Result<Output, String> {
+        if self.is_cancelled() {
+            return Err("Installation cancelled".to_string());
+        }
+        let program = command.as_std().get_program().to_string_lossy().to_string();
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        tex_path::apply(command);
+        // Own process group so the whole tree can be killed at once
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+        self.set_pid(child.id());
+        let tracked = processes::Tracked::new(child.id(), command);
+
+        // Drain stderr concurrently so a full pipe cannot block the process
+        let stderr = child.stderr.take();
+        let stderr_task = tokio::spawn(async move {
+            let mut buf = Vec::new();
+            if let Some(mut stderr) = stderr {
+                let _ = stderr.read_to_end(&mut buf).await;
+            }
+            buf
+        });
+
+        let mut stdout = Vec::new();
+        if let Some(out) = child.stdout.take() {
+            let mut reader = BufReader::new(out);
+            let mut line = Vec::new();
+            while reader
+                .read_until(b'\n', &mut line)
+                .await
+                .map_err(|e| format!("Failed to read {} output: {}", program, e))?
+                > 0
+            {
+                let text = String::from_utf8_lossy(&line);
+                let text = text.trim_end();
+                let _ = self.app.emit(
+                    "package-install-progress",
+                    InstallProgress {
+                        install_id: &self.id,
+                        line: text,
+                        step: parser.parse_progress(text),
+                    },
+                );
+                stdout.append(&mut line);
+            }
+        }
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+        tracked.finished();
+        self.set_pid(None);
+
+        if self.is_cancelled() {
+            return Err("Installation cancelled".to_string());
+        }
+        Ok(Output {
+            status,
+            stdout,
+            stderr: stderr_task.await.unwrap_or_default(),
+        })
+    }
+}
+
+impl Drop for Install {
+    fn drop(&mut self) {
+        if let Ok(mut installs) = INSTALLS.lock() {
+            installs.remove(&self.id);
+        }
+    }
+}
+
+/// Cancel a running install, killing the package manager. Returns false if
+/// no install of that ID is running.
+#[tauri::command]
+pub fn cancel_package_install(install_id: String) -> bool {
+    let pid = match INSTALLS.lock() {
+        Ok(mut installs) => match installs.get_mut(&install_id) {
+            Some(install) => {
+                install.cancelled = true;
+                install.pid
+            }
+            None => return false,
+        },
+        Err(_) => return false,
+    };
+    if let Some(pid) = pid {
+        processes::kill_tree(pid);
+    }
+    true
+}
+
+/// Cancel the installs a compile job started
+pub fn cancel_job_installs(job_id: &str) {
+    let install_ids: Vec<String> = match INSTALLS.lock() {
+        Ok(installs) => installs
+            .iter()
+            .filter(|(_, install)| install.job_id.as_deref() == Some(job_id))
+            .map(|(id, _)| id.clone())
+            .collect(),
+        Err(_) => return,
+    };
+    for install_id in install_ids {
+        cancel_package_install(install_id);
+    }
+}
+
+/// A package manual found by texdoc
+#[derive(Debug, Serialize)]
+pub struct PackageDoc {
+    path: String,
+    language: Option<String>,
+    description: Option<String>,
+}
+
+/// The best-scored documentation file in `texdoc --list --machine` output,
+/// whose lines are "name, score, path, language, description", tab-separated
+fn best_doc(output: &str) -> Option<PackageDoc> {
+    let mut docs: Vec<(f32, PackageDoc)> = output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let field = |i: usize| {
+                fields
+                    .get(i)
+                    .map(|f| f.trim())
+                    .filter(|f| !f.is_empty())
+                    .map(str::to_string)
+            };
+            Some((
+                fields.get(1)?.trim().parse().ok()?,
+                PackageDoc {
+                    path: field(2)?,
+                    language: field(3),
+                    description: field(4),
+                },
+            ))
+        })
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+    // PDFs before README and source files of the same score
+    docs.sort_by(|(a, a_doc), (b, b_doc)| {
+        b.total_cmp(a).then_with(|| {
+            b_doc
+                .path
+                .ends_with(".pdf")
+                .cmp(&a_doc.path.ends_with(".pdf"))
+        })
+    });
+    docs.into_iter().next().map(|(_, doc)| doc)
+}
+
+/// Find a package's manual with texdoc, and open it in the system viewer
+/// unless `open` is false, e.g. to show it in the app instead
+#[tauri::command]
+pub async fn open_package_docs(name: String, open: Option<bool>) -> Result<PackageDoc, String> {
+    let name = name.trim();
+    if name.is_empty() || name.starts_with('-') {
+        return Err(format!("Invalid package name: {}", name));
+    }
+    let output = processes::output(
+        Command::new("texdoc")
+            .args(["--list", "--machine", name])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .await
+    .map_err(|e| format!("Failed to run texdoc: {}", e))?;
+
+    let doc = best_doc(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| format!("No documentation found for {}", name))?;
+    if open.unwrap_or(true) {
+        external::open_path(std::path::Path::new(&doc.path))
+            .map_err(|e| format!("Failed to open {}: {}", doc.path, e))?;
+    }
+    Ok(doc)
+}
+
+/// Stop resolving dependencies beyond this many packages
+const MAX_DEPENDENCIES: usize = 5000;
+
+#[derive(Debug, Serialize)]
+pub struct Dependency {
+    name: String,
+    /// The package that pulls this one in
+    required_by: String,
+    installed: bool,
+    /// Download size in bytes, as tlmgr reports it
+    size: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageDependencies {
+    package: String,
+    /// Every package installing it pulls in, nearest first
+    dependencies: Vec<Dependency>,
+    /// Size of the package and the dependencies not installed yet
+    download_size: u64,
+}
+
+/// One line of `tlmgr info --data name,installed,size,depends`: fields are
+/// comma-separated, the dependencies colon-separated
+fn parse_info_data(line: &str) -> Option<(String, bool, Option<u64>, Vec<String>)> {
+    let mut fields = line.trim().splitn(4, ',');
+    let name = fields.next().filter(|n| !n.is_empty())?.to_string();
+    let installed = matches!(fields.next()?.trim(), "1" | "true" | "yes");
+    let size = fields.next()?.trim().parse().ok();
+    let depends = fields
+        .next()
+        .unwrap_or_default()
+        .split(':')
+        .map(str::trim)
+        // Binaries come as "<name>.ARCH", resolved by tlmgr per platform
+        .filter(|d| !d.is_empty() && !d.ends_with(".ARCH"))
+        .map(str::to_string)
+        .collect();
+    Some((name, installed, size, depends))
+}
+
+/// Resolve everything installing a TeX Live package pulls in, to show why
+/// e.g. a collection downloads hundreds of megabytes
+#[tauri::command]
+pub async fn get_package_dependencies(name: String) -> Result<PackageDependencies, String> {
+    require_tlmgr("Dependencies can only be resolved").await?;
+    let name = name.trim().to_string();
+    if name.is_empty() || name.starts_with('-') {
+        return Err(format!("Invalid package name: {}", name));
+    }
+
+    let mut seen: HashSet<String> = HashSet::from([name.clone()]);
+    let mut required_by: HashMap<String, String> = HashMap::new();
+    let mut dependencies = Vec::new();
+    let mut download_size = 0;
+    let mut found = false;
+    // One tlmgr run per level of the dependency tree
+    let mut level = vec![name.clone()];
+    while !level.is_empty() {
+        let output = processes::output(
+            proxy::command("tlmgr")
+                .args(["info", "--data", "name,installed,size,depends"])
+                .args(&level)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .await
+        .map_err(|e| format!("Failed to run tlmgr info: {}", e))?;
+
+        let mut next = Vec::new();
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let Some((package, installed, size, depends)) = parse_info_data(line) else {
+                continue;
+            };
+            if !installed {
+                download_size += size.unwrap_or(0);
+            }
+            if package == name {
+                found = true;
+            } else if let Some(parent) = required_by.get(&package) {
+                dependencies.push(Dependency {
+                    name: package.clone(),
+                    required_by: parent.clone(),
+                    installed,
+                    size,
+                });
+            }
+            for depend in depends {
+                if seen.len() < MAX_DEPENDENCIES && seen.insert(depend.clone()) {
+                    required_by.insert(depend.clone(), package.clone());
+                    next.push(depend);
+                }
+            }
+        }
+        level = next;
+    }
+
+    if !found {
+        return Err(format!("Package not found: {}", name));
+    }
+    Ok(PackageDependencies {
+        package: name,
+        dependencies,
+        download_size,
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageUpdate {
+    name: String,
+    /// "update", or "install"/"remove" for packages added to or dropped
+    /// from an installed collection
+    action: String,
+    local_revision: Option<u32>,
+    remote_revision: Option<u32>,
+    /// Download size as tlmgr reports it, e.g. "7093k"
+    size: Option<String>,
+}
+
+fn parse_update_list(output: &str) -> Vec<PackageUpdate> {
+    let revision = |rest: &str, key: &str| {
+        rest.split(',')
+            .filter_map(|part| part.trim().strip_prefix(key))
+            .find_map(|value| value.trim().parse().ok())
+    };
+    output
+        .lines()
+        .filter_map(|line| TLMGR_UPDATE.captures(line.trim()))
+        .map(|cap| PackageUpdate {
+            name: cap[2].to_string(),
+            action: cap[1].trim_start_matches("auto-").to_string(),
+            local_revision: revision(&cap[4], "local:"),
+            remote_revision: revision(&cap[4], "source:"),
+            size: cap.get(3).map(|size| size.as_str().to_string()),
+        })
+        .collect()
+}
+
+/// The updates `update_packages` would install, without installing them
+#[tauri::command]
+pub async fn check_package_updates() -> Result<Vec<PackageUpdate>, String> {
+    require_tlmgr("Updates can only be listed").await?;
+    let output = processes::output(
+        proxy::command("tlmgr")
+            .args(["update", "--list"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .await
+    .map_err(|e| format!("Failed to run tlmgr update: {}", e))?;
+
+    let updates = parse_update_list(&String::from_utf8_lossy(&output.stdout));
+    if !output.status.success() && updates.is_empty() {
+        return Err(format!(
+            "Failed to list updates: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(updates)
+}
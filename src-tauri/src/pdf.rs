@@ -0,0 +1,559 @@
+//! Rendering and searching pages of compiled PDFs with pdfium, for previews
+//! that don't go through the webview's PDF viewer
+
+use lopdf::{Dictionary, Object, ObjectId};
+use pdfium_render::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tauri::ipc::Response;
+use tauri::{AppHandle, Manager};
+
+use crate::jobs;
+
+/// Largest zoom factor a page is rendered at
+const MAX_SCALE: f32 = 8.0;
+
+/// Searching stops after this many hits
+const MAX_HITS: usize = 1000;
+
+/// Widths thumbnails may be requested at, in pixels
+const MIN_THUMBNAIL_WIDTH: u32 = 32;
+const MAX_THUMBNAIL_WIDTH: u32 = 1024;
+
+/// Thumbnail sets of this many PDFs are kept on disk
+const CACHED_THUMBNAIL_SETS: usize = 20;
+
+/// Page hashes are kept for this many documents
+const CACHED_PAGE_HASHES: usize = 16;
+
+lazy_static::lazy_static! {
+    /// Bound on first use and kept until the app exits. pdfium isn't
+    /// reentrant, so calls go through the lock.
+    static ref PDFIUM: Mutex<Option<&'static Pdfium>> = Mutex::new(None);
+    /// The PDF last previewed, with the modification time it was loaded at.
+    /// Only used under the PDFIUM lock.
+    static ref DOCUMENT: Mutex<Option<(PathBuf, SystemTime, PdfDocument<'static>)>> =
+        Mutex::new(None);
+    /// Hash of every page of the last PDF compiled for each document, least
+    /// recently compiled first
+    static ref PAGE_HASHES: Mutex<Vec<(String, Vec<u64>)>> = Mutex::new(Vec::new());
+}
+
+/// Area of a page in points, measured from its top-left corner
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfRectangle {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfHit {
+    /// 1-based
+    page: u16,
+    /// One rectangle per line the hit spans
+    rects: Vec<PdfRectangle>,
+    text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Thumbnail {
+    /// 1-based
+    page: u16,
+    /// PNG file in the thumbnail cache
+    path: String,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PdfSearchResult {
+    hits: Vec<PdfHit>,
+    /// Whether the search stopped at `MAX_HITS`
+    truncated: bool,
+}
+
+/// Load the pdfium library shipped with the app, or else one installed on
+/// the system
+fn bind(app: &AppHandle) -> Result<Pdfium, String> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Ok(resources) = app.path().resource_dir() {
+        dirs.push(resources);
+    }
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(PathBuf::from))
+    {
+        dirs.push(exe_dir);
+    }
+
+    for dir in dirs {
+        // Bundled resources keep their folder
+        let library = Pdfium::pdfium_platform_library_name_at_path(&dir.join("pdfium"));
+        if let Ok(bindings) = Pdfium::bind_to_library(&library) {
+            return Ok(Pdfium::new(bindings));
+        }
+        let library = Pdfium::pdfium_platform_library_name_at_path(&dir);
+        if let Ok(bindings) = Pdfium::bind_to_library(&library) {
+            return Ok(Pdfium::new(bindings));
+        }
+    }
+    Pdfium::bind_to_system_library()
+        .map(Pdfium::new)
+        .map_err(|e| format!("PDF renderer is unavailable: {}", e))
+}
+
+/// Run `f` with the pdfium library, binding it first if needed
+pub fn with_pdfium<T>(
+    app: &AppHandle,
+    f: impl FnOnce(&'static Pdfium) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut pdfium = PDFIUM
+        .lock()
+        .map_err(|_| "PDF renderer is unavailable".to_string())?;
+    if pdfium.is_none() {
+        *pdfium = Some(Box::leak(Box::new(bind(app)?)));
+    }
+    match *pdfium {
+        Some(pdfium) => f(pdfium),
+        None => Err("PDF renderer is unavailable".to_string()),
+    }
+}
+
+/// Run `f` with a PDF on disk, reusing the loaded document while the file
+/// is unchanged, so paging through a preview doesn't reparse it each time
+fn with_document<T>(
+    app: &AppHandle,
+    path: &Path,
+    f: impl FnOnce(&PdfDocument) -> Result<T, String>,
+) -> Result<T, String> {
+    with_pdfium(app, |pdfium| {
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| format!("Failed to open PDF: {}", e))?;
+        let mut cached = DOCUMENT
+            .lock()
+            .map_err(|_| "PDF renderer is unavailable".to_string())?;
+        let fresh = matches!(&*cached, Some((cached_path, cached_modified, _))
+            if cached_path == path && *cached_modified == modified);
+        if !fresh {
+            *cached = None;
+            // Loaded from memory: pdfium would keep the file open, and a
+            // published PDF is deleted once its job is gone
+            let data = std::fs::read(path).map_err(|e| format!("Failed to open PDF: {}", e))?;
+            let document = pdfium
+                .load_pdf_from_byte_vec(data, None)
+                .map_err(|e| format!("Failed to open PDF: {}", e))?;
+            *cached = Some((path.to_path_buf(), modified, document));
+        }
+        match cached.as_ref() {
+            Some((_, _, document)) => f(document),
+            None => Err("PDF renderer is unavailable".to_string()),
+        }
+    })
+}
+
+/// Mix the images and forms of a resource dictionary into `hasher`.
+/// Fonts are left out: a new glyph anywhere changes a subset font, not
+/// the pages that don't use it.
+fn hash_xobjects(
+    document: &lopdf::Document,
+    resources: &Dictionary,
+    seen: &mut HashMap<ObjectId, u64>,
+    hasher: &mut DefaultHasher,
+) {
+    let xobjects = resources
+        .get(b"XObject")
+        .and_then(|object| document.dereference(object))
+        .and_then(|(_, object)| object.as_dict());
+    let Ok(xobjects) = xobjects else {
+        return;
+    };
+    for (name, object) in xobjects.iter() {
+        name.hash(hasher);
+        let Object::Reference(id) = object else {
+            continue;
+        };
+        if let Some(hash) = seen.get(id) {
+            hash.hash(hasher);
+            continue;
+        }
+        // Placeholder against forms that draw themselves
+        seen.insert(*id, 0);
+        let mut xobject_hasher = DefaultHasher::new();
+        if let Ok(stream) = document.get_object(*id).and_then(Object::as_stream) {
+            stream.content.hash(&mut xobject_hasher);
+            // Forms, e.g. included PDF pages, have images of their own
+            if let Ok((_, nested)) = stream
+                .dict
+                .get(b"Resources")
+                .and_then(|object| document.dereference(object))
+            {
+                if let Ok(nested) = nested.as_dict() {
+                    hash_xobjects(document, nested, seen, &mut xobject_hasher);
+                }
+            }
+        }
+        let hash = xobject_hasher.finish();
+        seen.insert(*id, hash);
+        hash.hash(hasher);
+    }
+}
+
+/// Hash of each page's content streams and the images and forms it draws
+fn page_hashes(data: &[u8]) -> Result<Vec<u64>, String> {
+    let document =
+        lopdf::Document::load_mem(data).map_err(|e| format!("Failed to open PDF: {}", e))?;
+    let mut seen = HashMap::new();
+    document
+        .get_pages()
+        .into_values()
+        .map(|page_id| {
+            let mut hasher = DefaultHasher::new();
+            document
+                .get_page_content(page_id)
+                .map_err(|e| format!("Failed to read page: {}", e))?
+                .hash(&mut hasher);
+            if let Ok((own, inherited)) = document.get_page_resources(page_id) {
+                let inherited = inherited
+                    .into_iter()
+                    .filter_map(|id| document.get_dictionary(id).ok());
+                for resources in own.into_iter().chain(inherited) {
+                    hash_xobjects(&document, resources, &mut seen, &mut hasher);
+                }
+            }
+            Ok(hasher.finish())
+        })
+        .collect()
+}
+
+/// Pages (1-based) of a freshly compiled PDF that differ from the previous
+/// compile of the same document, so the preview can jump to the edit. The
+/// key names the document: builds land in a fresh folder each time, so the
+/// PDF path can't. None on the first compile or when the PDF can't be read.
+pub async fn changed_pages(key: &str, data: &[u8]) -> Option<Vec<u32>> {
+    let data = data.to_vec();
+    let hashes = tokio::task::spawn_blocking(move || page_hashes(&data))
+        .await
+        .ok()?
+        .ok()?;
+
+    let previous = {
+        let mut cache = PAGE_HASHES.lock().ok()?;
+        let previous = cache
+            .iter()
+            .position(|(cached, _)| cached == key)
+            .map(|index| cache.remove(index).1);
+        cache.push((key.to_string(), hashes.clone()));
+        if cache.len() > CACHED_PAGE_HASHES {
+            cache.remove(0);
+        }
+        previous?
+    };
+    Some(
+        hashes
+            .iter()
+            .enumerate()
+            .filter(|(i, hash)| previous.get(*i) != Some(hash))
+            .map(|(i, _)| i as u32 + 1)
+            .collect(),
+    )
+}
+
+/// Attach files to a PDF on disk as embedded files, e.g. the sources it
+/// was compiled from
+pub async fn embed_files(
+    app: &AppHandle,
+    pdf_path: &Path,
+    files: Vec<(String, Vec<u8>)>,
+) -> Result<(), String> {
+    let (app, pdf_path) = (app.clone(), pdf_path.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        with_pdfium(&app, |pdfium| {
+            let mut document = pdfium
+                .load_pdf_from_file(&pdf_path, None)
+                .map_err(|e| format!("Failed to open PDF: {}", e))?;
+            for (name, data) in &files {
+                document
+                    .attachments_mut()
+                    .create_attachment_from_bytes(name, data)
+                    .map_err(|e| format!("Failed to attach {}: {}", name, e))?;
+            }
+            // pdfium reads the file lazily, so it can't be overwritten in place
+            let data = document
+                .save_to_bytes()
+                .map_err(|e| format!("Failed to save PDF: {}", e))?;
+            drop(document);
+            std::fs::write(&pdf_path, data).map_err(|e| format!("Failed to save PDF: {}", e))
+        })
+    })
+    .await
+    .map_err(|e| format!("Failed to embed files: {}", e))?
+}
+
+/// Render one page (1-based) of a compile job's PDF as a PNG. At scale 1.0
+/// a point of the page is a pixel. Sent as raw bytes rather than a JSON
+/// array of numbers.
+#[tauri::command]
+pub async fn render_pdf_page(
+    app: AppHandle,
+    job_id: String,
+    page: u16,
+    scale: f32,
+) -> Result<Response, String> {
+    if !(scale > 0.0 && scale <= MAX_SCALE) {
+        return Err(format!("Scale must be between 0 and {}", MAX_SCALE));
+    }
+    let path = jobs::pdf_path(&job_id).ok_or_else(|| format!("No PDF for job {}", job_id))?;
+
+    tokio::task::spawn_blocking(move || {
+        with_document(&app, &path, |document| {
+            let pages = document.pages();
+            if page == 0 || page > pages.len() {
+                return Err(format!("Page {} is out of range (1-{})", page, pages.len()));
+            }
+            let image = pages
+                .get(page - 1)
+                .map_err(|e| format!("Failed to load page {}: {}", page, e))?
+                .render_with_config(&PdfRenderConfig::new().scale_page_by_factor(scale))
+                .map_err(|e| format!("Failed to render page {}: {}", page, e))?
+                .as_image();
+
+            let mut png = Vec::new();
+            image
+                .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+                .map_err(|e| format!("Failed to encode page {}: {}", page, e))?;
+            Ok(Response::new(png))
+        })
+    })
+    .await
+    .map_err(|e| format!("Failed to render page {}: {}", page, e))?
+}
+
+/// Find a text in a compile job's PDF, ignoring case, with the areas of the
+/// pages to highlight for each hit
+#[tauri::command]
+pub async fn search_pdf(
+    app: AppHandle,
+    job_id: String,
+    query: String,
+) -> Result<PdfSearchResult, String> {
+    if query.trim().is_empty() {
+        return Err("Search query is empty".to_string());
+    }
+    let path = jobs::pdf_path(&job_id).ok_or_else(|| format!("No PDF for job {}", job_id))?;
+
+    tokio::task::spawn_blocking(move || {
+        with_document(&app, &path, |document| {
+            let options = PdfSearchOptions::new();
+            let mut hits = Vec::new();
+            for (index, page) in document.pages().iter().enumerate() {
+                let page_height = page.height().value;
+                let text = page
+                    .text()
+                    .map_err(|e| format!("Failed to read page {}: {}", index + 1, e))?;
+                let search = text
+                    .search(&query, &options)
+                    .map_err(|e| format!("Failed to search page {}: {}", index + 1, e))?;
+                for segments in search.iter(PdfSearchDirection::SearchForward) {
+                    if hits.len() == MAX_HITS {
+                        return Ok(PdfSearchResult {
+                            hits,
+                            truncated: true,
+                        });
+                    }
+                    let rects = segments
+                        .iter()
+                        .map(|segment| {
+                            let bounds = segment.bounds();
+                            PdfRectangle {
+                                x: bounds.left().value,
+                                y: page_height - bounds.top().value,
+                                width: bounds.width().value,
+                                height: bounds.height().value,
+                            }
+                        })
+                        .collect();
+                    hits.push(PdfHit {
+                        page: index as u16 + 1,
+                        rects,
+                        text: segments
+                            .iter()
+                            .map(|s| s.text())
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    });
+                }
+            }
+            Ok(PdfSearchResult {
+                hits,
+                truncated: false,
+            })
+        })
+    })
+    .await
+    .map_err(|e| format!("Failed to search PDF: {}", e))?
+}
+
+fn thumbnails_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("OffLeaf")
+        .join("thumbnails")
+}
+
+/// Remove all but the most recently made thumbnail sets
+fn prune_thumbnails(root: &Path) {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return;
+    };
+    let mut sets: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    sets.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, dir) in sets.into_iter().skip(CACHED_THUMBNAIL_SETS) {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
+
+/// Render every page of a PDF `max_width` pixels wide into `dir`
+fn render_thumbnails(
+    pdfium: &Pdfium,
+    data: &[u8],
+    max_width: u32,
+    dir: &Path,
+) -> Result<Vec<Thumbnail>, String> {
+    let document = pdfium
+        .load_pdf_from_byte_slice(data, None)
+        .map_err(|e| format!("Failed to open PDF: {}", e))?;
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create folder: {}", e))?;
+    let config = PdfRenderConfig::new().set_target_width(max_width as Pixels);
+
+    let mut thumbnails = Vec::new();
+    for (index, page) in document.pages().iter().enumerate() {
+        let number = index as u16 + 1;
+        let image = page
+            .render_with_config(&config)
+            .map_err(|e| format!("Failed to render page {}: {}", number, e))?
+            .as_image();
+        let path = dir.join(format!("page-{}.png", number));
+        image
+            .save(&path)
+            .map_err(|e| format!("Failed to save thumbnail: {}", e))?;
+        thumbnails.push(Thumbnail {
+            page: number,
+            path: path.to_string_lossy().to_string(),
+            width: image.width(),
+            height: image.height(),
+        });
+    }
+    Ok(thumbnails)
+}
+
+/// Small PNGs of every page of a compile job's PDF for the thumbnail strip.
+/// They are cached by the PDF's content, so an unchanged document is only
+/// rendered once.
+#[tauri::command]
+pub async fn generate_thumbnails(
+    app: AppHandle,
+    job_id: String,
+    max_width: u32,
+) -> Result<Vec<Thumbnail>, String> {
+    let max_width = max_width.clamp(MIN_THUMBNAIL_WIDTH, MAX_THUMBNAIL_WIDTH);
+    let data = jobs::pdf(&job_id).ok_or_else(|| format!("No PDF for job {}", job_id))?;
+
+    // Kept across runs, so named by a digest that doesn't change between
+    // Rust releases
+    let root = thumbnails_dir();
+    let dir = root.join(format!("{:x}-{}", Sha256::digest(&data), max_width));
+    let index = dir.join("pages.json");
+    if let Ok(content) = tokio::fs::read_to_string(&index).await {
+        if let Ok(thumbnails) = serde_json::from_str(&content) {
+            return Ok(thumbnails);
+        }
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let thumbnails = with_pdfium(&app, |pdfium| {
+            render_thumbnails(pdfium, &data, max_width, &dir)
+        })
+        .inspect_err(|_| {
+            let _ = std::fs::remove_dir_all(&dir);
+        })?;
+        // Written last, so a set is only used once it is complete
+        let json = serde_json::to_string(&thumbnails)
+            .map_err(|e| format!("Failed to serialize thumbnails: {}", e))?;
+        std::fs::write(&index, json)
+            .map_err(|e| format!("Failed to write thumbnail index: {}", e))?;
+        prune_thumbnails(&root);
+        Ok(thumbnails)
+    })
+    .await
+    .map_err(|e| format!("Failed to generate thumbnails: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Document, Stream};
+
+    /// A PDF with one page per content stream
+    fn pdf(pages: &[&str]) -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let kids: Vec<Object> = pages
+            .iter()
+            .map(|content| {
+                let content_id =
+                    document.add_object(Stream::new(dictionary! {}, content.as_bytes().to_vec()));
+                document
+                    .add_object(dictionary! {
+                        "Type" => "Page",
+                        "Parent" => pages_id,
+                        "Contents" => content_id,
+                        "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+                    })
+                    .into()
+            })
+            .collect();
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Count" => kids.len() as i64,
+                "Kids" => kids,
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        let mut data = Vec::new();
+        document.save_to(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn compares_compiles_of_the_same_document() {
+        let first = pdf(&["0 0 m 10 10 l S", "0 0 m 20 20 l S", "0 0 m 30 30 l S"]);
+        let second = pdf(&["0 0 m 10 10 l S", "0 0 m 25 25 l S", "0 0 m 30 30 l S"]);
+        tauri::async_runtime::block_on(async {
+            assert_eq!(changed_pages("changed/main.tex", &first).await, None);
+            assert_eq!(
+                changed_pages("changed/main.tex", &second).await,
+                Some(vec![2])
+            );
+            assert_eq!(changed_pages("other/main.tex", &second).await, None);
+        });
+    }
+}
@@ -0,0 +1,184 @@
+//! Editing a PDF's title, author, subject and keywords, in both the
+//! document info dictionary and the XMP metadata readers prefer
+
+use lopdf::{decode_text_string, text_string, Dictionary, Document, Object, Stream};
+use regex::Regex;
+use std::path::PathBuf;
+
+/// Info dictionary keys the editable fields are stored under
+const FIELDS: [&[u8]; 4] = [b"Title", b"Author", b"Subject", b"Keywords"];
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Properties of the old XMP packet worth keeping, such as the PDF/A
+/// identification and the creation dates, as (prefix, name, value)
+fn kept_properties(old: &str) -> Vec<(String, String, String)> {
+    let re =
+        Regex::new(r#"\b(pdfaid|xmp):(\w+)(?:="([^"]*)"|>([^<]*)</(?:pdfaid|xmp):\w+>)"#).unwrap();
+    let mut kept: Vec<(String, String, String)> = Vec::new();
+    for cap in re.captures_iter(old) {
+        let Some(value) = cap.get(3).or(cap.get(4)).map(|v| v.as_str().trim()) else {
+            continue;
+        };
+        let seen = kept.iter().any(|(p, n, _)| *p == cap[1] && *n == cap[2]);
+        if !value.is_empty() && !seen {
+            kept.push((cap[1].to_string(), cap[2].to_string(), value.to_string()));
+        }
+    }
+    kept
+}
+
+/// An XMP packet with the document's info fields
+fn xmp_packet(info: &Dictionary, kept: &[(String, String, String)]) -> String {
+    let field = |key: &[u8]| {
+        info.get(key)
+            .ok()
+            .and_then(|value| decode_text_string(value).ok())
+            .filter(|value| !value.is_empty())
+            .map(|value| escape_xml(&value))
+    };
+
+    let mut properties = String::new();
+    if let Some(title) = field(b"Title") {
+        properties.push_str(&format!(
+            "   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n",
+            title
+        ));
+    }
+    if let Some(author) = field(b"Author") {
+        properties.push_str(&format!(
+            "   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n",
+            author
+        ));
+    }
+    if let Some(subject) = field(b"Subject") {
+        properties.push_str(&format!(
+            "   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+            subject
+        ));
+    }
+    if let Some(keywords) = field(b"Keywords") {
+        properties.push_str(&format!("   <pdf:Keywords>{}</pdf:Keywords>\n", keywords));
+    }
+    if let Some(producer) = field(b"Producer") {
+        properties.push_str(&format!("   <pdf:Producer>{}</pdf:Producer>\n", producer));
+    }
+    for (prefix, name, value) in kept {
+        properties.push_str(&format!(
+            "   <{0}:{1}>{2}</{0}:{1}>\n",
+            prefix,
+            name,
+            escape_xml(value)
+        ));
+    }
+
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\"\n\
+         \x20   xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n\
+         \x20   xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n\
+         \x20   xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n\
+         \x20   xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\">\n\
+         {}\
+         </rdf:Description>\n\
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        properties
+    )
+}
+
+/// Set a PDF's title, author, subject and keywords, e.g. to replace the
+/// "main.tex" title many documents ship with. None leaves a field as it
+/// is; an empty string removes it.
+#[tauri::command]
+pub async fn set_pdf_metadata(
+    path: String,
+    title: Option<String>,
+    author: Option<String>,
+    subject: Option<String>,
+    keywords: Option<String>,
+) -> Result<(), String> {
+    let pdf_path = PathBuf::from(&path);
+    let data = tokio::fs::read(&pdf_path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+
+    let updated = tokio::task::spawn_blocking(move || {
+        let mut document =
+            Document::load_mem(&data).map_err(|e| format!("Failed to open PDF: {}", e))?;
+        if document.is_encrypted() {
+            return Err("Encrypted PDFs can't be edited".to_string());
+        }
+
+        // The info dictionary, created if the PDF has none
+        let info_id = match document.trailer.get(b"Info").and_then(Object::as_reference) {
+            Ok(id) => id,
+            Err(_) => {
+                let id = document.add_object(Dictionary::new());
+                document.trailer.set("Info", Object::Reference(id));
+                id
+            }
+        };
+        let info = document
+            .get_dictionary_mut(info_id)
+            .map_err(|e| format!("Failed to read PDF metadata: {}", e))?;
+        for (key, value) in FIELDS.iter().zip([title, author, subject, keywords]) {
+            match value.as_deref().map(str::trim) {
+                Some("") => {
+                    info.remove(key);
+                }
+                Some(value) => info.set(*key, text_string(value)),
+                None => {}
+            }
+        }
+        let info = info.clone();
+
+        let catalog = document
+            .catalog()
+            .map_err(|e| format!("Failed to read PDF catalog: {}", e))?;
+        let old_metadata = catalog.get(b"Metadata").and_then(Object::as_reference).ok();
+        let kept = old_metadata
+            .and_then(|id| document.get_object(id).ok())
+            .and_then(|object| object.as_stream().ok())
+            .and_then(|stream| stream.get_plain_content().ok())
+            .map(|content| kept_properties(&String::from_utf8_lossy(&content)))
+            .unwrap_or_default();
+
+        // Left uncompressed, as PDF/A requires of metadata
+        let mut dict = Dictionary::new();
+        dict.set("Type", Object::Name(b"Metadata".to_vec()));
+        dict.set("Subtype", Object::Name(b"XML".to_vec()));
+        let stream = Stream::new(dict, xmp_packet(&info, &kept).into_bytes());
+        let metadata = match old_metadata {
+            Some(id) => {
+                document.objects.insert(id, Object::Stream(stream));
+                id
+            }
+            None => document.add_object(stream),
+        };
+        document
+            .catalog_mut()
+            .map_err(|e| format!("Failed to read PDF catalog: {}", e))?
+            .set("Metadata", Object::Reference(metadata));
+
+        let mut updated = Vec::new();
+        document
+            .save_to(&mut updated)
+            .map_err(|e| format!("Failed to save PDF: {}", e))?;
+        Ok(updated)
+    })
+    .await
+    .map_err(|e| format!("Failed to update PDF metadata: {}", e))??;
+
+    tokio::fs::write(&pdf_path, updated)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
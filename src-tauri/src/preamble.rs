@@ -0,0 +1,125 @@
+//! Precompiled preamble formats (mylatexformat), cached by a hash of the preamble
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::{includes, jobs, processes, tex_path, Engine};
+
+/// Text before \begin{document}, if the document has one outside a comment
+fn preamble_of(content: &str) -> Option<&str> {
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if let Some(start) = includes::strip_comment(line).find("\\begin{document}") {
+            return Some(&content[..offset + start]);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// What identifies an engine's installation: its version banner and when
+/// its own format was last rebuilt, which TeX Live updates do
+async fn engine_version(engine: Engine) -> String {
+    let mut version = tex_path::command(engine.command());
+    version.arg("--version");
+    let banner = processes::output(&mut version)
+        .await
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+        .unwrap_or_default();
+
+    let base_engine = match engine {
+        Engine::Xelatex => "xetex",
+        _ => "pdftex",
+    };
+    let mut kpsewhich = tex_path::command("kpsewhich");
+    kpsewhich
+        .arg(format!("-engine={}", base_engine))
+        .arg(format!("{}.fmt", engine.command()));
+    let mut built = String::new();
+    if let Ok(output) = processes::output(&mut kpsewhich).await {
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if let Ok(modified) = fs::metadata(&path).await.and_then(|m| m.modified()) {
+            built = format!("{:?}", modified);
+        }
+    }
+    format!("{}\n{}", banner, built)
+}
+
+/// Format name for a preamble; a changed preamble, engine or TeX
+/// installation gets a new format
+fn format_name(engine: Engine, version: &str, preamble: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [engine.command(), version, preamble] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    let hash = format!("{:x}", hasher.finalize());
+    format!("offleaf-{}", &hash[..16])
+}
+
+fn formats_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("OffLeaf")
+        .join("formats")
+}
+
+/// Whether preamble formats work with an engine. LuaTeX can't dump the Lua
+/// state fontspec relies on, and the others build their own formats.
+pub fn supports(engine: Engine) -> bool {
+    matches!(engine, Engine::Pdflatex | Engine::Xelatex)
+}
+
+/// Make a format with the root file's preamble available in the build
+/// directory, building and caching it if needed. Returns the format name to
+/// compile against, or None to compile normally.
+pub async fn prepare(
+    job_id: &str,
+    engine: Engine,
+    root_file: &str,
+    content: &str,
+    build_dir: &Path,
+    env: &HashMap<String, String>,
+) -> Option<String> {
+    let preamble = preamble_of(content)?;
+    let name = format_name(engine, &engine_version(engine).await, preamble);
+    let fmt_file = format!("{}.fmt", name);
+    let cached = formats_dir().join(&fmt_file);
+    let local = build_dir.join(&fmt_file);
+
+    if !cached.exists() {
+        // The engine reads the preamble up to \begin{document} and dumps it
+        let output = jobs::run_command(
+            job_id,
+            Command::new(engine.command())
+                .args(["-ini", "-interaction=nonstopmode"])
+                .arg(format!("-jobname={}", name))
+                .arg(format!("&{}", engine.command()))
+                .arg("mylatexformat.ltx")
+                .arg(root_file)
+                .envs(env)
+                .current_dir(build_dir),
+        )
+        .await
+        .ok()?;
+        if !output.status.success() || !local.exists() {
+            return None;
+        }
+
+        fs::create_dir_all(formats_dir()).await.ok()?;
+        fs::copy(&local, &cached).await.ok()?;
+    } else if !local.exists() {
+        fs::copy(&cached, &local).await.ok()?;
+    }
+
+    Some(name)
+}
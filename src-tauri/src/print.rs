@@ -0,0 +1,197 @@
+//! Printing compiled PDFs without an external viewer: through CUPS (lp or
+//! lpr) on Unix, and through the shell's print verb on Windows
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::AppHandle;
+
+#[cfg(windows)]
+use crate::external;
+#[cfg(not(windows))]
+use crate::processes;
+#[cfg(not(windows))]
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Duplex {
+    OneSided,
+    LongEdge,
+    ShortEdge,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PrintOptions {
+    /// Pages to print, e.g. "1-4,7"; all of them when unset
+    pages: Option<String>,
+    /// The printer's default when unset
+    duplex: Option<Duplex>,
+    copies: Option<u32>,
+}
+
+/// Page ranges in the "1-4,7" form lp and pdfium both take
+fn page_ranges(pages: &str) -> Result<String, String> {
+    let ranges: String = pages.chars().filter(|c| !c.is_whitespace()).collect();
+    let re = Regex::new(r"^\d+(-\d+)?(,\d+(-\d+)?)*$").unwrap();
+    if re.is_match(&ranges) {
+        Ok(ranges)
+    } else {
+        Err(format!("Invalid page range: {}", pages))
+    }
+}
+
+/// Send a PDF to a printer, or to the default printer when none is named
+#[tauri::command]
+pub async fn print_pdf(
+    app: AppHandle,
+    path: String,
+    printer: Option<String>,
+    options: Option<PrintOptions>,
+) -> Result<(), String> {
+    if !Path::new(&path).is_file() {
+        return Err(format!("PDF not found: {}", path));
+    }
+    let options = options.unwrap_or_default();
+    let pages = options
+        .pages
+        .as_deref()
+        .filter(|pages| !pages.trim().is_empty())
+        .map(page_ranges)
+        .transpose()?;
+    let printer = printer.filter(|printer| !printer.trim().is_empty());
+    let copies = options.copies.unwrap_or(1).max(1);
+
+    send(
+        &app,
+        &path,
+        printer.as_deref(),
+        pages.as_deref(),
+        options.duplex,
+        copies,
+    )
+    .await
+}
+
+#[cfg(not(windows))]
+async fn send(
+    _app: &AppHandle,
+    path: &str,
+    printer: Option<&str>,
+    pages: Option<&str>,
+    duplex: Option<Duplex>,
+    copies: u32,
+) -> Result<(), String> {
+    let sides = duplex.map(|duplex| match duplex {
+        Duplex::OneSided => "sides=one-sided",
+        Duplex::LongEdge => "sides=two-sided-long-edge",
+        Duplex::ShortEdge => "sides=two-sided-short-edge",
+    });
+
+    let mut lp = Command::new("lp");
+    if let Some(printer) = printer {
+        lp.args(["-d", printer]);
+    }
+    lp.arg("-n").arg(copies.to_string());
+    if let Some(pages) = pages {
+        lp.args(["-P", pages]);
+    }
+    if let Some(sides) = sides {
+        lp.args(["-o", sides]);
+    }
+    lp.arg(path);
+
+    // BSD-style fallback for systems without lp
+    let mut lpr = Command::new("lpr");
+    if let Some(printer) = printer {
+        lpr.args(["-P", printer]);
+    }
+    lpr.arg(format!("-#{}", copies));
+    if let Some(pages) = pages {
+        lpr.arg("-o").arg(format!("page-ranges={}", pages));
+    }
+    if let Some(sides) = sides {
+        lpr.args(["-o", sides]);
+    }
+    lpr.arg(path);
+
+    // lp may be a stub that fails where lpr works, so a failing lp isn't
+    // the final word
+    let mut failures = Vec::new();
+    for (tool, mut command) in [("lp", lp), ("lpr", lpr)] {
+        match processes::output(&mut command).await {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => failures.push(format!(
+                "{}: {}",
+                tool,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) => failures.push(format!("{}: {}", tool, e)),
+        }
+    }
+    Err(format!("Printing failed ({})", failures.join("; ")))
+}
+
+/// Print through the PDF handler's "printto" verb, so the OS driver does
+/// the rendering. Duplex is left to the printer's defaults, which the
+/// shell verbs don't expose.
+#[cfg(windows)]
+async fn send(
+    app: &AppHandle,
+    path: &str,
+    printer: Option<&str>,
+    pages: Option<&str>,
+    _duplex: Option<Duplex>,
+    copies: u32,
+) -> Result<(), String> {
+    let mut target = std::path::PathBuf::from(path);
+    if let Some(pages) = pages {
+        let data = tokio::fs::read(path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let app = app.clone();
+        let pages = pages.to_string();
+        let selection = tokio::task::spawn_blocking(move || {
+            crate::pdf::with_pdfium(&app, |pdfium| select_pages(pdfium, &data, &pages))
+        })
+        .await
+        .map_err(|e| format!("Printing failed: {}", e))??;
+        target = std::env::temp_dir().join(format!("offleaf-print-{}.pdf", uuid::Uuid::new_v4()));
+        tokio::fs::write(&target, selection)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+    }
+
+    // The verbs print one copy each
+    let printer = printer.map(|printer| format!("\"{}\"", printer));
+    for _ in 0..copies {
+        let result = match &printer {
+            Some(printer) => external::shell_execute("printto", &target, Some(printer)),
+            None => external::shell_execute("print", &target, None),
+        };
+        result.map_err(|e| format!("Printing failed: {}", e))?;
+    }
+    Ok(())
+}
+
+/// A copy of a PDF with only the given pages
+#[cfg(windows)]
+fn select_pages(
+    pdfium: &pdfium_render::prelude::Pdfium,
+    data: &[u8],
+    pages: &str,
+) -> Result<Vec<u8>, String> {
+    let source = pdfium
+        .load_pdf_from_byte_slice(data, None)
+        .map_err(|e| format!("Failed to open PDF: {}", e))?;
+    let mut selection = pdfium
+        .create_new_pdf()
+        .map_err(|e| format!("Failed to create PDF: {}", e))?;
+    selection
+        .pages_mut()
+        .copy_pages_from_document(&source, pages, 0)
+        .map_err(|e| format!("Invalid page range {}: {}", pages, e))?;
+    selection
+        .save_to_bytes()
+        .map_err(|e| format!("Failed to create PDF: {}", e))
+}
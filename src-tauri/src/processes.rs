@@ -0,0 +1,208 @@
+//! Registry of the TeX processes OffLeaf has started. It is persisted per
+//! app instance so processes left behind by a crash can be killed on the
+//! next start, without touching those of another instance still running.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use std::sync::Mutex;
+use tokio::process::Command;
+
+use crate::tex_path;
+
+lazy_static::lazy_static! {
+    /// Running processes by PID, with the program each was started as
+    static ref RUNNING: Mutex<HashMap<u32, String>> = Mutex::new(HashMap::new());
+}
+
+/// Folder of the registries, one per instance, named by the instance's PID
+fn registry_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("OffLeaf")
+        .join("processes")
+}
+
+fn registry_path() -> PathBuf {
+    registry_dir().join(format!("{}.json", std::process::id()))
+}
+
+fn save(running: &HashMap<u32, String>) {
+    let path = registry_path();
+    if running.is_empty() {
+        let _ = std::fs::remove_file(path);
+        return;
+    }
+    let _ = std::fs::create_dir_all(registry_dir());
+    if let Ok(data) = serde_json::to_vec(running) {
+        let _ = std::fs::write(path, data);
+    }
+}
+
+fn register(pid: u32, program: String) {
+    if let Ok(mut running) = RUNNING.lock() {
+        running.insert(pid, program);
+        save(&running);
+    }
+}
+
+fn unregister(pid: u32) {
+    if let Ok(mut running) = RUNNING.lock() {
+        running.remove(&pid);
+        save(&running);
+    }
+}
+
+/// Kill a process and all of its children
+pub fn kill_tree(pid: u32) {
+    #[cfg(unix)]
+    let _ = std::process::Command::new("kill")
+        .args(["-KILL", &format!("-{}", pid)])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    #[cfg(windows)]
+    let _ = std::process::Command::new("taskkill")
+        .args(["/PID", &pid.to_string(), "/T", "/F"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// "xelatex" for "/usr/bin/xelatex" or "xelatex.exe"
+fn program_stem(program: &str) -> String {
+    Path::new(program)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Name of the program a PID is running, if the process exists
+fn process_name(pid: u32) -> Option<String> {
+    #[cfg(unix)]
+    let output = std::process::Command::new("ps")
+        .args(["-p", &pid.to_string(), "-o", "comm="])
+        .output()
+        .ok()?;
+
+    // e.g. "xelatex.exe","1234","Console","1","12,345 K"
+    #[cfg(windows)]
+    let output = std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+        .output()
+        .ok()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let name = stdout.trim().split(',').next()?.trim_matches('"');
+    if !output.status.success() || name.is_empty() || name.starts_with("INFO:") {
+        return None;
+    }
+    Some(program_stem(name))
+}
+
+/// Kill every process that is still registered; called when the app exits
+pub fn kill_all() {
+    let pids: Vec<u32> = match RUNNING.lock() {
+        Ok(mut running) => {
+            let pids = running.keys().copied().collect();
+            running.clear();
+            save(&running);
+            pids
+        }
+        Err(_) => return,
+    };
+    for pid in pids {
+        kill_tree(pid);
+    }
+}
+
+/// Kill processes registered by earlier instances that did not exit
+/// cleanly. Registries of instances that still run are left alone. A PID
+/// is only killed if it still runs the program it was started as, since
+/// the OS may have reused it.
+pub fn reap_orphans() {
+    let Ok(entries) = std::fs::read_dir(registry_dir()) else {
+        return;
+    };
+    let app = std::env::current_exe()
+        .map(|exe| program_stem(&exe.to_string_lossy()))
+        .unwrap_or_default();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let owner = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u32>().ok());
+        let Some(owner) = owner else {
+            continue;
+        };
+        // The owner's PID may have been reused too, by some other program
+        let owner_alive = owner == std::process::id() || process_name(owner) == Some(app.clone());
+        if owner_alive {
+            continue;
+        }
+        let orphans: HashMap<u32, String> = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        for (pid, program) in orphans {
+            if process_name(pid).is_some_and(|name| name == program_stem(&program)) {
+                kill_tree(pid);
+            }
+        }
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Keeps a process registered while it runs. Dropping it before `finished`,
+/// e.g. because the future waiting on the process was abandoned, kills the
+/// process tree.
+pub struct Tracked {
+    pid: Option<u32>,
+    running: bool,
+}
+
+impl Tracked {
+    pub fn new(pid: Option<u32>, command: &Command) -> Self {
+        if let Some(pid) = pid {
+            register(
+                pid,
+                command.as_std().get_program().to_string_lossy().to_string(),
+            );
+        }
+        Tracked { pid, running: true }
+    }
+
+    /// The process has exited; stop tracking it
+    pub fn finished(mut self) {
+        self.running = false;
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid {
+            unregister(pid);
+            if self.running {
+                kill_tree(pid);
+            }
+        }
+    }
+}
+
+/// Run a command outside of a compile job, tracking its process like the
+/// processes jobs run
+pub async fn output(command: &mut Command) -> io::Result<Output> {
+    tex_path::apply(command);
+    // Own process group so the whole tree can be killed at once
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let child = command.spawn()?;
+    let tracked = Tracked::new(child.id(), command);
+    let output = child.wait_with_output().await;
+    tracked.finished();
+    output
+}
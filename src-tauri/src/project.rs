@@ -0,0 +1,621 @@
+//! Projects on disk: a directory in the projects folder with an offleaf.json
+//! manifest next to the sources
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+
+use crate::{encoding, history, recent, save, settings, trash};
+
+pub const MANIFEST: &str = "offleaf.json";
+
+const MAIN_TEMPLATE: &str = "\\documentclass{article}\n\
+\\begin{document}\n\
+\n\
+\\end{document}\n";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectManifest {
+    pub name: String,
+    /// File compiled by default
+    pub root_file: String,
+    pub engine: Option<String>,
+    /// Compile options used when a compile request leaves them unset
+    pub compile: CompileDefaults,
+    /// Editor preferences the frontend keeps with the project. Anything that
+    /// needs the user's trust (e.g. allowing shell escape) stays in the app
+    /// settings instead.
+    pub settings: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompileDefaults {
+    /// Compile with shell escape; the app settings must still allow it
+    pub shell_escape: Option<bool>,
+    /// Engine flags, after the app settings' and before the request's own;
+    /// ignored unless the user trusts the project in the app settings
+    pub extra_args: Vec<String>,
+    pub auto_install: Option<bool>,
+    /// "bibtex" or "biber"; detected from the sources when unset
+    pub bibliography_tool: Option<String>,
+    /// "makeindex" or "xindy"
+    pub index_tool: Option<String>,
+}
+
+impl Default for ProjectManifest {
+    fn default() -> Self {
+        ProjectManifest {
+            name: String::new(),
+            root_file: "main.tex".to_string(),
+            engine: None,
+            compile: CompileDefaults::default(),
+            settings: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectSummary {
+    id: String,
+    path: String,
+    manifest: ProjectManifest,
+}
+
+/// A project with all of its files, keyed by path relative to the project
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Project {
+    id: String,
+    path: String,
+    manifest: ProjectManifest,
+    files: HashMap<String, String>,
+    binary_files: HashMap<String, Vec<u8>>,
+}
+
+pub fn projects_dir() -> PathBuf {
+    dirs::document_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("OffLeaf")
+}
+
+/// Directory of a project; IDs are single path components
+pub fn project_dir(id: &str) -> Result<PathBuf, String> {
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+        return Err(format!("Invalid project ID: {}", id));
+    }
+    Ok(projects_dir().join(id))
+}
+
+/// Resolve a project-relative path, refusing anything that could leave the
+/// project (absolute paths, "..") and the manifest itself
+pub fn entry_path(id: &str, relative: &str) -> Result<PathBuf, String> {
+    resolve_in(&project_dir(id)?, relative)
+}
+
+/// Like `entry_path`, for a project folder given by its path
+pub fn resolve_in(dir: &Path, relative: &str) -> Result<PathBuf, String> {
+    let path = Path::new(relative);
+    let valid = !relative.is_empty()
+        && relative != MANIFEST
+        && path.components().all(|c| matches!(c, Component::Normal(_)));
+    if !valid {
+        return Err(format!("Invalid path: {}", relative));
+    }
+    Ok(dir.join(path))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Relative to the project, with "/" separators
+    pub path: String,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// Folders of OffLeaf itself and of version control, never part of a project
+const SKIPPED_DIRS: &[&str] = &[".offleaf", ".git", ".hg", ".svn", ".bzr"];
+
+/// Every file and folder in a project except OffLeaf's and version
+/// control's folders and the manifest. Other dotfiles, e.g. a .latexmkrc,
+/// belong to the project.
+pub async fn walk(dir: &Path) -> Result<Vec<(FileEntry, PathBuf)>, String> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut entries = fs::read_dir(&current)
+            .await
+            .map_err(|e| format!("Failed to read project: {}", e))?;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let name = relative.to_string_lossy().replace('\\', "/");
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            // Atomic saves in progress, see `save::write_atomic`
+            let is_temp = file_name.ends_with(".offleaf-tmp");
+            if SKIPPED_DIRS.contains(&file_name.as_str()) || is_temp || name == MANIFEST {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(path.clone());
+            }
+            found.push((
+                FileEntry {
+                    path: name,
+                    is_dir: metadata.is_dir(),
+                    size: metadata.len(),
+                },
+                path,
+            ));
+        }
+    }
+    found.sort_by(|(a, _), (b, _)| a.path.cmp(&b.path));
+    Ok(found)
+}
+
+/// Build directory kept between compiles of a project, see `BuildDir`
+pub fn cached_build_dir(key: &str) -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("OffLeaf")
+        .join("builds")
+        .join(key)
+}
+
+/// "My Thesis (draft)" -> "my-thesis-draft"
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "project".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Unused project ID for a name; the same name as an existing project gets
+/// a numbered directory
+pub fn new_project_id(name: &str) -> String {
+    let base = slug(name);
+    let mut id = base.clone();
+    let mut n = 2;
+    while projects_dir().join(&id).exists() {
+        id = format!("{}-{}", base, n);
+        n += 1;
+    }
+    id
+}
+
+pub async fn read_manifest(dir: &Path) -> Option<ProjectManifest> {
+    let text = fs::read_to_string(dir.join(MANIFEST)).await.ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+pub async fn write_manifest(dir: &Path, manifest: &ProjectManifest) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+    fs::write(dir.join(MANIFEST), text)
+        .await
+        .map_err(|e| format!("Failed to write manifest: {}", e))
+}
+
+/// Create a project with a manifest and an empty main.tex
+#[tauri::command]
+pub async fn create_project(name: String, engine: Option<String>) -> Result<Project, String> {
+    let manifest = ProjectManifest {
+        name,
+        engine,
+        ..ProjectManifest::default()
+    };
+    let files = HashMap::from([(
+        manifest.root_file.clone(),
+        MAIN_TEMPLATE.as_bytes().to_vec(),
+    )]);
+    create_with_files(manifest, files).await
+}
+
+/// Create a project directory with a manifest and the given files, keyed by
+/// project-relative path
+pub async fn create_with_files(
+    manifest: ProjectManifest,
+    files: HashMap<String, Vec<u8>>,
+) -> Result<Project, String> {
+    fs::create_dir_all(projects_dir())
+        .await
+        .map_err(|e| format!("Failed to create projects directory: {}", e))?;
+
+    let id = new_project_id(&manifest.name);
+    let dir = project_dir(&id)?;
+    fs::create_dir(&dir)
+        .await
+        .map_err(|e| format!("Failed to create project: {}", e))?;
+
+    write_manifest(&dir, &manifest).await?;
+    for (name, data) in files {
+        let path = entry_path(&id, &name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Failed to create folder: {}", e))?;
+        }
+        fs::write(&path, data)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", name, e))?;
+    }
+
+    open_project(id).await
+}
+
+/// Summary of the project in a directory, if it has a manifest
+pub async fn summary(dir: &Path) -> Option<ProjectSummary> {
+    let manifest = read_manifest(dir).await?;
+    Some(ProjectSummary {
+        id: dir.file_name()?.to_string_lossy().to_string(),
+        path: dir.to_string_lossy().to_string(),
+        manifest,
+    })
+}
+
+/// List the projects in the projects folder, sorted by name. Directories
+/// without a manifest are not projects and are skipped.
+#[tauri::command]
+pub async fn list_projects() -> Result<Vec<ProjectSummary>, String> {
+    let mut projects = Vec::new();
+    let Ok(mut entries) = fs::read_dir(projects_dir()).await else {
+        return Ok(projects);
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        projects.extend(summary(&entry.path()).await);
+    }
+    projects.sort_by_key(|p| p.manifest.name.to_lowercase());
+    Ok(projects)
+}
+
+/// Load a project's manifest and every file in it. Sources in a legacy
+/// encoding are converted to UTF-8; other files that aren't valid UTF-8
+/// (images, PDFs) come back as binary files.
+#[tauri::command]
+pub async fn open_project(id: String) -> Result<Project, String> {
+    let dir = project_dir(&id)?;
+    let manifest = read_manifest(&dir)
+        .await
+        .ok_or_else(|| format!("Not an OffLeaf project: {}", id))?;
+
+    let mut files = HashMap::new();
+    let mut binary_files = HashMap::new();
+    for (entry, path) in walk(&dir).await? {
+        if entry.is_dir {
+            continue;
+        }
+        let data = fs::read(&path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", entry.path, e))?;
+        // Edits made elsewhere from now on are noticed before saving
+        save::remember(&path).await;
+        match String::from_utf8(data) {
+            Ok(text) => {
+                files.insert(entry.path, text);
+            }
+            Err(e) => {
+                let is_text = Path::new(&entry.path).extension().is_some_and(|ext| {
+                    encoding::TEXT_EXTENSIONS.contains(&ext.to_string_lossy().as_ref())
+                });
+                if is_text {
+                    files.insert(entry.path, encoding::decode(e.as_bytes()).0);
+                } else {
+                    binary_files.insert(entry.path, e.into_bytes());
+                }
+            }
+        }
+    }
+
+    let path = dir.to_string_lossy().to_string();
+    let _ = recent::record(
+        path.clone(),
+        manifest.name.clone(),
+        Some(manifest.root_file.clone()),
+    )
+    .await;
+
+    Ok(Project {
+        id,
+        path,
+        manifest,
+        files,
+        binary_files,
+    })
+}
+
+/// Replace a project's manifest, e.g. after renaming it or changing its root file
+#[tauri::command]
+pub async fn update_project_manifest(id: String, manifest: ProjectManifest) -> Result<(), String> {
+    let dir = project_dir(&id)?;
+    if read_manifest(&dir).await.is_none() {
+        return Err(format!("Not an OffLeaf project: {}", id));
+    }
+    write_manifest(&dir, &manifest).await
+}
+
+/// Delete a project with all of its files and its cached build directory
+#[tauri::command]
+pub async fn delete_project(id: String) -> Result<(), String> {
+    let dir = project_dir(&id)?;
+    if read_manifest(&dir).await.is_none() {
+        return Err(format!("Not an OffLeaf project: {}", id));
+    }
+    fs::remove_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Failed to delete project: {}", e))?;
+
+    let _ = fs::remove_dir_all(cached_build_dir(&id)).await;
+    Ok(())
+}
+
+/// Whether two existing paths are the same entry, as "Intro.tex" and
+/// "intro.tex" are on a case-insensitive file system
+fn same_entry(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Folder a project with a new name goes in: a new ID for projects in the
+/// projects folder, a sibling folder named after it for any other
+fn renamed_dir(dir: &Path, new_name: &str) -> Result<PathBuf, String> {
+    if dir.parent() == Some(projects_dir().as_path()) {
+        return Ok(projects_dir().join(new_project_id(new_name)));
+    }
+    let valid =
+        !new_name.is_empty() && !new_name.starts_with('.') && !new_name.contains(['/', '\\']);
+    if !valid {
+        return Err(format!("Invalid name: {}", new_name));
+    }
+    let target = dir.with_file_name(new_name);
+    if target.exists() && !same_entry(dir, &target) {
+        return Err(format!("{} already exists", target.display()));
+    }
+    Ok(target)
+}
+
+/// Copy a project into a new one with the given name. OffLeaf's folder (the
+/// history, build files) and a Git checkout stay behind.
+#[tauri::command]
+pub async fn duplicate_project(src: String, new_name: String) -> Result<ProjectSummary, String> {
+    let source = PathBuf::from(&src);
+    if !source.is_dir() {
+        return Err(format!("Not a folder: {}", src));
+    }
+    let target = renamed_dir(&source, &new_name)?;
+    if same_entry(&source, &target) {
+        return Err(format!("{} already exists", target.display()));
+    }
+    fs::create_dir_all(&target)
+        .await
+        .map_err(|e| format!("Failed to create project: {}", e))?;
+
+    let copied = async {
+        for (entry, path) in walk(&source).await? {
+            let dest = target.join(&entry.path);
+            if entry.is_dir {
+                fs::create_dir_all(&dest)
+                    .await
+                    .map_err(|e| format!("Failed to create {}: {}", entry.path, e))?;
+                continue;
+            }
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| format!("Failed to create folder: {}", e))?;
+            }
+            fs::copy(&path, &dest)
+                .await
+                .map_err(|e| format!("Failed to copy {}: {}", entry.path, e))?;
+        }
+        let mut manifest = read_manifest(&source).await.unwrap_or_default();
+        manifest.name = new_name;
+        write_manifest(&target, &manifest).await
+    };
+    if let Err(e) = copied.await {
+        let _ = fs::remove_dir_all(&target).await;
+        return Err(e);
+    }
+
+    summary(&target)
+        .await
+        .ok_or_else(|| "Failed to register duplicated project".to_string())
+}
+
+/// Rename a project: its manifest name and its folder, carrying along its
+/// build cache and settings, and its place in the recent projects
+#[tauri::command]
+pub async fn rename_project(path: String, new_name: String) -> Result<ProjectSummary, String> {
+    let source = PathBuf::from(&path);
+    let mut manifest = read_manifest(&source)
+        .await
+        .ok_or_else(|| format!("Not an OffLeaf project: {}", path))?;
+    manifest.name = new_name.clone();
+
+    // Projects in the projects folder keep their ID when it already fits the
+    // new name, e.g. "thesis-2" for "Thesis"
+    let managed = source.parent() == Some(projects_dir().as_path());
+    let id = source
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let unchanged = if managed {
+        id.strip_prefix(&slug(&new_name)).is_some_and(|rest| {
+            rest.is_empty()
+                || rest
+                    .strip_prefix('-')
+                    .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        })
+    } else {
+        id == new_name
+    };
+    let target = if unchanged {
+        source.clone()
+    } else {
+        let target = renamed_dir(&source, &new_name)?;
+        fs::rename(&source, &target)
+            .await
+            .map_err(|e| format!("Failed to rename project folder: {}", e))?;
+        target
+    };
+    // Written after the move, so a failed move leaves the project as it was
+    if let Err(e) = write_manifest(&target, &manifest).await {
+        if target != source {
+            let _ = fs::rename(&target, &source).await;
+        }
+        return Err(e);
+    }
+
+    // The project has moved by now, so what follows is best effort: at worst
+    // autosave stops or the project's settings stay under its old ID
+    if managed && target != source {
+        let new_id = target
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let _ = fs::rename(cached_build_dir(&id), cached_build_dir(&new_id)).await;
+        let _ = history::rename(&id, &new_id);
+        let mut app_settings = settings::load().await;
+        if let Some(project_settings) = app_settings.projects.remove(&id) {
+            app_settings.projects.insert(new_id, project_settings);
+            let _ = settings::save(&app_settings).await;
+        }
+    }
+
+    let target_path = target.to_string_lossy().to_string();
+    let _ = recent::rename(&path, target_path, new_name).await;
+    summary(&target)
+        .await
+        .ok_or_else(|| "Failed to register renamed project".to_string())
+}
+
+/// List a project's files and folders for the file explorer
+#[tauri::command]
+pub async fn list_project_files(id: String) -> Result<Vec<FileEntry>, String> {
+    let dir = project_dir(&id)?;
+    Ok(walk(&dir)
+        .await?
+        .into_iter()
+        .map(|(entry, _)| entry)
+        .collect())
+}
+
+/// List the files of a folder opened from disk, to be compiled in place
+#[tauri::command]
+pub async fn list_folder_files(path: String) -> Result<Vec<FileEntry>, String> {
+    let dir = PathBuf::from(path);
+    if !dir.is_dir() {
+        return Err(format!("Not a folder: {}", dir.display()));
+    }
+    let name = dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let _ = recent::record(dir.to_string_lossy().to_string(), name, None).await;
+
+    Ok(walk(&dir)
+        .await?
+        .into_iter()
+        .map(|(entry, _)| entry)
+        .collect())
+}
+
+/// Create a file, optionally with content; fails if it already exists
+#[tauri::command]
+pub async fn create_file(id: String, path: String, content: Option<String>) -> Result<(), String> {
+    let file_path = entry_path(&id, &path)?;
+    if file_path.exists() {
+        return Err(format!("{} already exists", path));
+    }
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create folder: {}", e))?;
+    }
+    fs::write(&file_path, content.unwrap_or_default())
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", path, e))
+}
+
+#[tauri::command]
+pub async fn create_folder(id: String, path: String) -> Result<(), String> {
+    fs::create_dir_all(entry_path(&id, &path)?)
+        .await
+        .map_err(|e| format!("Failed to create folder {}: {}", path, e))
+}
+
+/// Move a file or folder to another project-relative path, refusing to
+/// overwrite an existing entry
+async fn move_within(id: &str, from: &str, to: &str) -> Result<(), String> {
+    let source = entry_path(id, from)?;
+    let target = entry_path(id, to)?;
+    if !source.exists() {
+        return Err(format!("{} does not exist", from));
+    }
+    // A case-only rename finds the source itself there
+    let case_only = same_entry(&source, &target);
+    if target.exists() && !case_only {
+        return Err(format!("{} already exists", to));
+    }
+    if target.starts_with(&source) && !case_only {
+        return Err(format!("Cannot move {} into itself", from));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create folder: {}", e))?;
+    }
+    fs::rename(&source, &target)
+        .await
+        .map_err(|e| format!("Failed to move {}: {}", from, e))
+}
+
+/// Rename a file or folder in place
+#[tauri::command]
+pub async fn rename_entry(id: String, path: String, new_name: String) -> Result<(), String> {
+    if new_name.contains(['/', '\\']) {
+        return Err(format!("Invalid name: {}", new_name));
+    }
+    let target = match path.rsplit_once('/') {
+        Some((parent, _)) => format!("{}/{}", parent, new_name),
+        None => new_name,
+    };
+    move_within(&id, &path, &target).await
+}
+
+/// Move a file or folder into another folder; "" is the project root
+#[tauri::command]
+pub async fn move_entry(id: String, path: String, destination: String) -> Result<(), String> {
+    let name = path.rsplit('/').next().unwrap_or(&path);
+    let target = if destination.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", destination.trim_end_matches('/'), name)
+    };
+    move_within(&id, &path, &target).await
+}
+
+/// Delete a file, or a folder with everything in it, by moving it to the
+/// project's trash
+#[tauri::command]
+pub async fn delete_entry(id: String, path: String) -> Result<(), String> {
+    trash::move_to_trash(&id, &path).await
+}
@@ -0,0 +1,93 @@
+//! Recently opened projects, stored as JSON in the app data directory
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+/// Older entries are dropped beyond this many
+const MAX_RECENT: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentProject {
+    path: String,
+    name: String,
+    /// Milliseconds since the Unix epoch
+    last_opened_ms: u64,
+    root_file: Option<String>,
+}
+
+fn recent_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("OffLeaf")
+        .join("recent.json")
+}
+
+async fn load() -> Vec<RecentProject> {
+    match fs::read_to_string(recent_path()).await {
+        Ok(text) => serde_json::from_str(&text).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn save(recent: &[RecentProject]) -> Result<(), String> {
+    let path = recent_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create data directory: {}", e))?;
+    }
+    let text = serde_json::to_string_pretty(recent)
+        .map_err(|e| format!("Failed to serialize recent projects: {}", e))?;
+    fs::write(&path, text)
+        .await
+        .map_err(|e| format!("Failed to save recent projects: {}", e))
+}
+
+/// Move a project to the top of the list, adding it if needed
+pub async fn record(path: String, name: String, root_file: Option<String>) -> Result<(), String> {
+    let last_opened_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+
+    let mut recent = load().await;
+    recent.retain(|p| p.path != path);
+    recent.insert(
+        0,
+        RecentProject {
+            path,
+            name,
+            last_opened_ms,
+            root_file,
+        },
+    );
+    recent.truncate(MAX_RECENT);
+    save(&recent).await
+}
+
+/// Point an entry at a project's new folder and name after a rename,
+/// keeping its place in the list
+pub async fn rename(old_path: &str, path: String, name: String) -> Result<(), String> {
+    let mut recent = load().await;
+    let Some(entry) = recent.iter_mut().find(|p| p.path == old_path) else {
+        return Ok(());
+    };
+    entry.path = path;
+    entry.name = name;
+    save(&recent).await
+}
+
+/// Recently opened projects, most recent first
+#[tauri::command]
+pub async fn get_recent_projects() -> Result<Vec<RecentProject>, String> {
+    Ok(load().await)
+}
+
+#[tauri::command]
+pub async fn remove_recent_project(path: String) -> Result<(), String> {
+    let mut recent = load().await;
+    recent.retain(|p| p.path != path);
+    save(&recent).await
+}
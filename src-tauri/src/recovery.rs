@@ -0,0 +1,142 @@
+//! Crash recovery: a journal of editor buffers with unsaved changes, offered
+//! for restoring on the next start
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Changes are written once the buffers have been quiet this long
+const DEBOUNCE: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirtyBuffer {
+    project: String,
+    path: String,
+    content: String,
+    /// Milliseconds since the Unix epoch
+    modified_ms: u64,
+}
+
+#[derive(Default)]
+struct Journal {
+    buffers: HashMap<(String, String), DirtyBuffer>,
+    /// Bumped on every change; a pending write only goes ahead if it is current
+    generation: u64,
+}
+
+lazy_static::lazy_static! {
+    // Starts from whatever the last session left, so new edits don't
+    // overwrite it before the frontend asked for it
+    static ref JOURNAL: Mutex<Journal> = Mutex::new(load());
+}
+
+fn journal_path() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("OffLeaf")
+        .join("recovery.json")
+}
+
+fn load() -> Journal {
+    let buffers: Vec<DirtyBuffer> = std::fs::read(journal_path())
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default();
+    Journal {
+        buffers: buffers
+            .into_iter()
+            .map(|b| ((b.project.clone(), b.path.clone()), b))
+            .collect(),
+        generation: 0,
+    }
+}
+
+/// Write the journal through a temp file so a crash mid-write can't corrupt it
+fn save(journal: &Journal) -> Result<(), String> {
+    let path = journal_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create recovery directory: {}", e))?;
+    }
+    let buffers: Vec<&DirtyBuffer> = journal.buffers.values().collect();
+    let data = serde_json::to_vec(&buffers)
+        .map_err(|e| format!("Failed to serialize recovery journal: {}", e))?;
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, data).map_err(|e| format!("Failed to write recovery journal: {}", e))?;
+    std::fs::rename(&temp, &path).map_err(|e| format!("Failed to write recovery journal: {}", e))
+}
+
+/// Apply a change to the journal and schedule a write
+fn update<F: FnOnce(&mut HashMap<(String, String), DirtyBuffer>)>(change: F) -> Result<(), String> {
+    let generation = {
+        let mut journal = JOURNAL
+            .lock()
+            .map_err(|_| "Recovery journal is unavailable".to_string())?;
+        change(&mut journal.buffers);
+        journal.generation += 1;
+        journal.generation
+    };
+
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(DEBOUNCE).await;
+        if let Ok(journal) = JOURNAL.lock() {
+            if journal.generation == generation {
+                let _ = save(&journal);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Record the current content of a buffer with unsaved changes. Called on
+/// every edit; the journal is written once edits pause.
+#[tauri::command]
+pub async fn record_dirty_buffer(
+    project: String,
+    path: String,
+    content: String,
+) -> Result<(), String> {
+    let modified_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    update(move |buffers| {
+        buffers.insert(
+            (project.clone(), path.clone()),
+            DirtyBuffer {
+                project,
+                path,
+                content,
+                modified_ms,
+            },
+        );
+    })
+}
+
+/// Forget a buffer once it has been saved or its changes discarded
+#[tauri::command]
+pub async fn clear_dirty_buffer(project: String, path: String) -> Result<(), String> {
+    update(move |buffers| {
+        buffers.remove(&(project, path));
+    })
+}
+
+/// Unsaved buffers left by the last session, oldest first; the frontend
+/// calls this on startup to offer restoring them
+#[tauri::command]
+pub async fn get_recovery_state() -> Result<Vec<DirtyBuffer>, String> {
+    let journal = JOURNAL
+        .lock()
+        .map_err(|_| "Recovery journal is unavailable".to_string())?;
+    let mut buffers: Vec<DirtyBuffer> = journal.buffers.values().cloned().collect();
+    buffers.sort_by_key(|b| b.modified_ms);
+    Ok(buffers)
+}
+
+/// Drop all recovered buffers, after restoring them or declining to
+#[tauri::command]
+pub async fn discard_recovery_state() -> Result<(), String> {
+    update(|buffers| buffers.clear())
+}
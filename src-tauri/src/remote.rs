@@ -0,0 +1,188 @@
+//! Compiling on a remote OffLeaf compile server, over HTTP(S) or SSH
+//!
+//! Either way the server receives the CompileRequest as JSON and answers with
+//! one JSON event per line: `{"log": "..."}` while compiling, then
+//! `{"result": {...}}` with the CompilationResult, or `{"error": "..."}`.
+
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use tauri::{AppHandle, Emitter};
+use tempfile::TempDir;
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::{jobs, output, proxy, CompilationResult, CompileRequest};
+
+/// Command started on SSH hosts unless the server config names another
+const DEFAULT_SSH_COMMAND: &str = "offleaf-server --stdio";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum RemoteServer {
+    /// Compile server reached over HTTP(S); requests go to `<url>/compile`
+    Http { url: String, token: Option<String> },
+    /// Host reached over SSH that has the compile server installed
+    Ssh {
+        host: String,
+        command: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RemoteEvent {
+    Log(String),
+    Result(Box<RemoteResult>),
+    Error(String),
+}
+
+/// The server's result, which carries the PDF itself
+#[derive(Debug, Deserialize)]
+struct RemoteResult {
+    #[serde(flatten)]
+    result: CompilationResult,
+    pdf_data: Option<Vec<u8>>,
+}
+
+/// Split an SSH host, `[user@]hostname[:port]`, into the destination and
+/// port. Anything else, e.g. a leading `-` ssh would read as an option, is
+/// rejected.
+fn ssh_destination(host: &str) -> Result<(String, Option<u16>), String> {
+    let invalid = || format!("Invalid SSH host: {}", host);
+    let (destination, port) = match host.trim().rsplit_once(':') {
+        Some((destination, port)) => (
+            destination,
+            Some(port.parse::<u16>().map_err(|_| invalid())?),
+        ),
+        None => (host.trim(), None),
+    };
+    let (user, hostname) = match destination.split_once('@') {
+        Some((user, hostname)) => (Some(user), hostname),
+        None => (None, destination),
+    };
+    let valid = |part: &str, extra: &[char]| {
+        !part.is_empty()
+            && !part.starts_with('-')
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || extra.contains(&c))
+    };
+    if !valid(hostname, &[]) || user.is_some_and(|user| !valid(user, &['_'])) {
+        return Err(invalid());
+    }
+    Ok((destination.to_string(), port))
+}
+
+/// Compile the request on a remote server, streaming its log as "compile-log"
+/// events like a local compile
+pub async fn compile(
+    app: &AppHandle,
+    job_id: &str,
+    mut request: CompileRequest,
+    server: &RemoteServer,
+) -> Result<CompilationResult, String> {
+    if request.source_dir.is_some() {
+        return Err("Folder projects can't be compiled remotely".to_string());
+    }
+
+    // Project settings and backends are local to this machine
+    request.project_id = None;
+    request.container = None;
+    request.remote = None;
+    // The server's own install policy decides what it installs
+    request.install_on_the_fly = None;
+
+    let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let request_path = temp_dir.path().join("request.json");
+    let body =
+        serde_json::to_vec(&request).map_err(|e| format!("Failed to serialize request: {}", e))?;
+    fs::write(&request_path, body)
+        .await
+        .map_err(|e| format!("Failed to write request: {}", e))?;
+
+    let mut command = match server {
+        RemoteServer::Http { url, token } => {
+            let mut command = proxy::command("curl");
+            command.args(["-sS", "-N", "--fail-with-body", "-X", "POST"]);
+            command.args(["-H", "Content-Type: application/json"]);
+            // Through a file so the token doesn't show up in the process list
+            if let Some(token) = token {
+                let headers_path = temp_dir.path().join("headers.txt");
+                fs::write(&headers_path, format!("Authorization: Bearer {}\n", token))
+                    .await
+                    .map_err(|e| format!("Failed to write request: {}", e))?;
+                command
+                    .arg("-H")
+                    .arg(format!("@{}", headers_path.display()));
+            }
+            command
+                .arg("--data-binary")
+                .arg(format!("@{}", request_path.display()))
+                .arg(format!("{}/compile", url.trim_end_matches('/')));
+            command
+        }
+        RemoteServer::Ssh {
+            host,
+            command: remote_command,
+        } => {
+            let (destination, port) = ssh_destination(host)?;
+            let request_file = std::fs::File::open(&request_path)
+                .map_err(|e| format!("Failed to read request: {}", e))?;
+            let mut command = Command::new("ssh");
+            command.args(["-o", "BatchMode=yes"]);
+            if let Some(port) = port {
+                command.args(["-p", &port.to_string()]);
+            }
+            command
+                .args(["--", &destination])
+                .arg(remote_command.as_deref().unwrap_or(DEFAULT_SSH_COMMAND))
+                .stdin(Stdio::from(request_file));
+            command
+        }
+    };
+
+    let mut result = None;
+    let mut server_error = None;
+    let output =
+        jobs::run_command_streaming(job_id, &mut command, |line| {
+            match serde_json::from_str::<RemoteEvent>(line) {
+                Ok(RemoteEvent::Log(text)) => {
+                    let _ = app.emit("compile-log", text.as_str());
+                }
+                Ok(RemoteEvent::Result(r)) => result = Some(*r),
+                Ok(RemoteEvent::Error(e)) => server_error = Some(e),
+                Err(_) => {}
+            }
+        })
+        .await
+        .map_err(|e| format!("Failed to reach compile server: {}", e))?;
+
+    if let Some(e) = server_error {
+        return Err(format!("Compile server error: {}", e));
+    }
+    match result {
+        Some(remote) => {
+            let mut result = remote.result;
+            result.job_id = job_id.to_string();
+            // Paths and URLs on the server mean nothing here; the PDF comes
+            // as data and is published like a local one
+            result.pdf_path = None;
+            result.pdf_url = None;
+            result.output_path = None;
+            if let Some(data) = remote.pdf_data {
+                let pdf_path = temp_dir.path().join("output.pdf");
+                fs::write(&pdf_path, data)
+                    .await
+                    .map_err(|e| format!("Failed to write PDF: {}", e))?;
+                let (published, pdf_url) = output::publish(&pdf_path).await?;
+                result.pdf_path = Some(published.to_string_lossy().to_string());
+                result.pdf_url = Some(pdf_url);
+            }
+            Ok(result)
+        }
+        None => Err(format!(
+            "Compile server returned no result: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
@@ -0,0 +1,94 @@
+//! Saving files safely: atomic writes, and detecting when another program changed a file since OffLeaf last
+//! read or wrote it
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+lazy_static::lazy_static! {
+    /// Modification time of each file as of OffLeaf's last read or write
+    static ref KNOWN_MTIMES: Mutex<HashMap<PathBuf, SystemTime>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExternalChange {
+    /// Modified (or deleted) since OffLeaf last read or wrote it
+    changed: bool,
+    exists: bool,
+    /// Milliseconds since the Unix epoch
+    modified_ms: Option<u64>,
+}
+
+async fn mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).await.and_then(|m| m.modified()).ok()
+}
+
+/// Note a file's current modification time as seen by OffLeaf
+pub async fn remember(path: &Path) {
+    if let Some(modified) = mtime(path).await {
+        if let Ok(mut known) = KNOWN_MTIMES.lock() {
+            known.insert(path.to_path_buf(), modified);
+        }
+    }
+}
+
+/// Replace a file's content without ever leaving it half-written: the data
+/// goes to a temporary file next to it, which is renamed over the original.
+/// Earlier versions are in the project history, not in files next to it.
+pub async fn write_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("Invalid path: {}", path.display()))?
+        .to_string_lossy()
+        .to_string();
+    let temp = path.with_file_name(format!(".{}.offleaf-tmp", name));
+
+    let mut file = fs::File::create(&temp)
+        .await
+        .map_err(|e| format!("Failed to save {}: {}", name, e))?;
+    let written = async {
+        file.write_all(data).await?;
+        file.sync_all().await
+    };
+    if let Err(e) = written.await {
+        let _ = fs::remove_file(&temp).await;
+        return Err(format!("Failed to save {}: {}", name, e));
+    }
+    drop(file);
+
+    if let Err(e) = fs::rename(&temp, path).await {
+        let _ = fs::remove_file(&temp).await;
+        return Err(format!("Failed to save {}: {}", name, e));
+    }
+    remember(path).await;
+    Ok(())
+}
+
+/// Whether a file changed on disk since OffLeaf last loaded or saved it, so
+/// the editor can warn before overwriting someone else's edits
+#[tauri::command]
+pub async fn check_external_changes(path: String) -> Result<ExternalChange, String> {
+    let path = PathBuf::from(path);
+    let current = mtime(&path).await;
+    let known = KNOWN_MTIMES
+        .lock()
+        .map_err(|_| "File state is unavailable".to_string())?
+        .get(&path)
+        .copied();
+
+    Ok(ExternalChange {
+        changed: match (known, current) {
+            (Some(known), Some(current)) => known != current,
+            (Some(_), None) => true,
+            (None, _) => false,
+        },
+        exists: current.is_some(),
+        modified_ms: current
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64),
+    })
+}
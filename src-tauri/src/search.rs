@@ -0,0 +1,102 @@
+//! Project-wide text search across every text file of a project
+
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::fs;
+
+use crate::project;
+
+/// Searching stops after this many matches
+const MAX_MATCHES: usize = 1000;
+
+/// Larger files are unlikely to be sources and are skipped
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchMatch {
+    /// Relative to the project, with "/" separators
+    file: String,
+    /// 1-based
+    line: usize,
+    /// Character offsets of the match within the line
+    start: usize,
+    end: usize,
+    text: String,
+    /// The lines around the match, for showing it in context
+    before: Option<String>,
+    after: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResult {
+    matches: Vec<SearchMatch>,
+    /// Whether the search stopped at `MAX_MATCHES`
+    truncated: bool,
+}
+
+/// Search the text files of a project folder. Without `regex` the query is
+/// matched literally; matches don't span lines.
+#[tauri::command]
+pub async fn search_project(
+    project_path: String,
+    query: String,
+    regex: bool,
+    case_sensitive: bool,
+) -> Result<SearchResult, String> {
+    let dir = PathBuf::from(&project_path);
+    if !dir.is_dir() {
+        return Err(format!("Not a folder: {}", project_path));
+    }
+    if query.is_empty() {
+        return Err("Search query is empty".to_string());
+    }
+    let pattern = if regex { query } else { regex::escape(&query) };
+    let re = RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid regular expression: {}", e))?;
+
+    let mut matches = Vec::new();
+    for (entry, path) in project::walk(&dir).await? {
+        if entry.is_dir || entry.size > MAX_FILE_SIZE {
+            continue;
+        }
+        // Images and other binary files aren't valid UTF-8
+        let Ok(content) = fs::read_to_string(&path).await else {
+            continue;
+        };
+        let lines: Vec<&str> = content.lines().collect();
+        for (index, line) in lines.iter().enumerate() {
+            for found in re.find_iter(line) {
+                if found.start() == found.end() {
+                    continue;
+                }
+                if matches.len() == MAX_MATCHES {
+                    return Ok(SearchResult {
+                        matches,
+                        truncated: true,
+                    });
+                }
+                let start = line[..found.start()].chars().count();
+                matches.push(SearchMatch {
+                    file: entry.path.clone(),
+                    line: index + 1,
+                    start,
+                    end: start + found.as_str().chars().count(),
+                    text: line.to_string(),
+                    before: index
+                        .checked_sub(1)
+                        .and_then(|i| lines.get(i))
+                        .map(|l| l.to_string()),
+                    after: lines.get(index + 1).map(|l| l.to_string()),
+                });
+            }
+        }
+    }
+
+    Ok(SearchResult {
+        matches,
+        truncated: false,
+    })
+}
@@ -0,0 +1,131 @@
+//! Persistent settings, stored as JSON in the user's config directory
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::fs;
+
+use crate::backup::BackupSettings;
+use crate::container::ContainerSettings;
+use crate::packages::InstallPolicy;
+use crate::proxy::ProxySettings;
+use crate::remote::RemoteServer;
+
+/// Compile settings remembered for a single project
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectSettings {
+    /// Extra flags appended to the engine invocation
+    pub extra_args: Vec<String>,
+    /// User confirmed that this project may compile with -shell-escape
+    pub shell_escape_allowed: bool,
+    /// User trusts the project's own build configuration: engine flags in
+    /// its manifest and its .latexmkrc, which is Perl
+    pub trusted: bool,
+    /// Environment overrides for the compile processes, see `COMPILE_ENV_VARS`
+    pub env: HashMap<String, String>,
+    /// Compile in a Docker/Podman container instead of the local TeX install
+    pub container: Option<ContainerSettings>,
+    /// Compile on a remote compile server instead of this machine
+    pub remote: Option<RemoteServer>,
+}
+
+/// Environment variables a project may override. Anything else (e.g.
+/// kpathsea's shell_escape) could bypass the compile's restrictions.
+pub const COMPILE_ENV_VARS: &[&str] = &["TEXINPUTS", "BIBINPUTS", "BSTINPUTS", "TZ"];
+
+impl ProjectSettings {
+    /// Environment to apply to compile processes. Search paths get a trailing
+    /// separator so kpathsea still searches the default TeX tree after them.
+    pub fn compile_env(&self) -> HashMap<String, String> {
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        self.env
+            .iter()
+            .filter(|(name, _)| COMPILE_ENV_VARS.contains(&name.as_str()))
+            .map(|(name, value)| {
+                let mut value = value.clone();
+                if name.ends_with("INPUTS") && !value.ends_with(separator) {
+                    value.push(separator);
+                }
+                (name.clone(), value)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub projects: HashMap<String, ProjectSettings>,
+    pub backup: BackupSettings,
+    /// Bin folders of TeX installations OffLeaf set up, see `tex_path`
+    pub tex_bin_dirs: Vec<String>,
+    /// Which packages documents may install without asking
+    pub install_policy: InstallPolicy,
+    /// Proxy for tlmgr and other network access, see `proxy`
+    pub proxy: Option<ProxySettings>,
+    /// User allowed lookups on the internet, e.g. DOIs in `bibliography`
+    pub online_lookups: bool,
+}
+
+fn settings_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("OffLeaf")
+        .join("settings.json")
+}
+
+/// Load settings, falling back to defaults if the file is missing or invalid
+pub async fn load() -> AppSettings {
+    match fs::read_to_string(settings_path()).await {
+        Ok(text) => serde_json::from_str(&text).unwrap_or_default(),
+        Err(_) => AppSettings::default(),
+    }
+}
+
+pub async fn save(settings: &AppSettings) -> Result<(), String> {
+    let path = settings_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create settings directory: {}", e))?;
+    }
+    let text = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    fs::write(&path, text)
+        .await
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+/// Settings of one project, or defaults if none were saved
+pub async fn project(project_id: &str) -> ProjectSettings {
+    load()
+        .await
+        .projects
+        .get(project_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub async fn get_project_settings(project_id: String) -> Result<ProjectSettings, String> {
+    Ok(project(&project_id).await)
+}
+
+#[tauri::command]
+pub async fn set_project_settings(
+    project_id: String,
+    settings: ProjectSettings,
+) -> Result<(), String> {
+    if let Some(name) = settings
+        .env
+        .keys()
+        .find(|name| !COMPILE_ENV_VARS.contains(&name.as_str()))
+    {
+        return Err(format!("Unsupported environment variable: {}", name));
+    }
+
+    let mut app_settings = load().await;
+    app_settings.projects.insert(project_id, settings);
+    save(&app_settings).await
+}
@@ -0,0 +1,111 @@
+//! Quick previews of math snippets, rendered to SVG without a full compile
+
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::fs;
+
+use crate::limits::ResourceLimits;
+use crate::log_parser::parse_latex_log;
+use crate::{jobs, tex_path};
+
+/// A formula that takes longer than this is runaway macro expansion
+const SNIPPET_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A formula needs a fraction of what a document does
+const SNIPPET_LIMITS: ResourceLimits = ResourceLimits {
+    cpu_secs: 10,
+    memory_mb: 1024,
+    output_mb: 64,
+};
+
+/// Minimal standalone document cropped tightly around the formula
+fn snippet_document(latex: &str, display_mode: bool) -> String {
+    let style = if display_mode { "\\displaystyle " } else { "" };
+    format!(
+        "\\documentclass[preview,border=1pt]{{standalone}}\n\
+         \\usepackage{{amsmath,amssymb}}\n\
+         \\begin{{document}}\n\
+         ${}{}$\n\
+         \\end{{document}}\n",
+        style, latex
+    )
+}
+
+/// Render a formula to SVG for inline previews in the editor
+#[tauri::command]
+pub async fn render_snippet(latex: String, display_mode: bool) -> Result<String, String> {
+    let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let dir = temp_dir.path();
+
+    fs::write(
+        dir.join("snippet.tex"),
+        snippet_document(&latex, display_mode),
+    )
+    .await
+    .map_err(|e| format!("Failed to write snippet: {}", e))?;
+
+    // Run like a compile job, so the processes are tracked, limited and
+    // killed on timeout, but without queueing behind compiles
+    let job_id = jobs::enqueue(None, None);
+    jobs::set_limits(&job_id, SNIPPET_LIMITS);
+    jobs::start_unqueued(&job_id);
+    let result = tokio::time::timeout(SNIPPET_TIMEOUT, render(&job_id, dir)).await;
+    if result.is_err() {
+        jobs::kill_running(&job_id);
+    }
+    jobs::forget(&job_id);
+    result.map_err(|_| "Rendering the snippet timed out".to_string())?
+}
+
+/// Typeset snippet.tex in `dir` and convert it to SVG
+async fn render(job_id: &str, dir: &Path) -> Result<String, String> {
+    // dvisvgm reads DVI; --no-fonts turns glyphs into paths so no fonts are needed
+    let output = jobs::run_command(
+        job_id,
+        tex_path::command("latex")
+            .args([
+                "-interaction=nonstopmode",
+                "-halt-on-error",
+                "-file-line-error",
+                "snippet.tex",
+            ])
+            .current_dir(dir),
+    )
+    .await
+    .map_err(|e| format!("Failed to run latex: {}. Is TeX Live installed?", e))?;
+    if !output.status.success() {
+        let log = String::from_utf8_lossy(&output.stdout);
+        let (errors, _) = parse_latex_log(&log);
+        return Err(errors
+            .into_iter()
+            .next()
+            .map(|e| e.message)
+            .unwrap_or_else(|| "Failed to render snippet".to_string()));
+    }
+
+    let output = jobs::run_command(
+        job_id,
+        tex_path::command("dvisvgm")
+            .args([
+                "--no-fonts",
+                "--exact-bbox",
+                "-o",
+                "snippet.svg",
+                "snippet.dvi",
+            ])
+            .current_dir(dir),
+    )
+    .await
+    .map_err(|e| format!("Failed to run dvisvgm: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "dvisvgm failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    fs::read_to_string(dir.join("snippet.svg"))
+        .await
+        .map_err(|e| format!("Failed to read SVG: {}", e))
+}
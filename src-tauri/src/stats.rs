@@ -0,0 +1,176 @@
+//! Project statistics for a dashboard: words, floats, equations, citations
+//! and pages, overall and per chapter
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::{archive, count_pages, includes, jobname_of, wordcount};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ChapterStats {
+    title: String,
+    /// None without texcount
+    words: Option<u32>,
+    figures: usize,
+    tables: usize,
+    equations: usize,
+    citations: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectStats {
+    /// Words in the running text; None without texcount
+    words: Option<u32>,
+    figures: usize,
+    tables: usize,
+    equations: usize,
+    /// Every \cite'd key, counting repeats
+    citations: usize,
+    unique_citations: usize,
+    /// From the last compiled PDF, if there is one
+    pages: Option<u32>,
+    /// By \chapter, or by \section in documents without chapters
+    chapters: Vec<ChapterStats>,
+}
+
+struct Counters {
+    figure_re: Regex,
+    table_re: Regex,
+    equation_re: Regex,
+    cite_re: Regex,
+}
+
+impl Counters {
+    fn new() -> Self {
+        Counters {
+            figure_re: Regex::new(r"\\begin\s*\{(?:sub)?figure\*?\}").unwrap(),
+            table_re: Regex::new(r"\\begin\s*\{(?:sub)?table\*?\}").unwrap(),
+            // Display math; the environments count once however many lines
+            // they number
+            equation_re: Regex::new(
+                r"\\begin\s*\{(?:equation|align|gather|multline|eqnarray|flalign|alignat|displaymath)\*?\}|\\\[",
+            )
+            .unwrap(),
+            cite_re: Regex::new(r"\\[a-zA-Z]*cite[a-zA-Z]*\*?\s*(?:\[[^\]]*\]\s*)*\{([^}]*)\}")
+                .unwrap(),
+        }
+    }
+
+    /// Figures, tables, equations and the cited keys in a piece of text
+    fn count<'a>(&self, text: &'a str) -> (usize, usize, usize, Vec<&'a str>) {
+        let keys = self
+            .cite_re
+            .captures_iter(text)
+            .filter_map(|cap| cap.get(1))
+            .flat_map(|keys| keys.as_str().split(','))
+            .map(str::trim)
+            // \nocite{*} adds the whole database, not a key
+            .filter(|key| !key.is_empty() && *key != "*")
+            .collect();
+        (
+            self.figure_re.find_iter(text).count(),
+            self.table_re.find_iter(text).count(),
+            self.equation_re.find_iter(text).count(),
+            keys,
+        )
+    }
+}
+
+/// Page count of the PDF the last compile of the root file left behind
+async fn last_pdf_pages(root: &Path) -> Option<u32> {
+    let project_dir = root.parent()?;
+    let jobname = jobname_of(&root.file_name()?.to_string_lossy());
+    for build_dir in archive::build_dirs(project_dir) {
+        let Ok(pdf) = fs::read(build_dir.join(format!("{}.pdf", jobname))).await else {
+            continue;
+        };
+        let log = fs::read_to_string(build_dir.join(format!("{}.log", jobname)))
+            .await
+            .unwrap_or_default();
+        return count_pages(&log, &pdf);
+    }
+    None
+}
+
+/// Statistics of the document rooted at a .tex file on disk, following its
+/// \input and \include files
+#[tauri::command]
+pub async fn get_project_stats(root: String) -> Result<ProjectStats, String> {
+    let root_path = PathBuf::from(&root);
+    if !root_path.is_file() {
+        return Err(format!("Root file not found: {}", root));
+    }
+    let text = includes::flatten(&root_path);
+    let counters = Counters::new();
+
+    let (figures, tables, equations, keys) = counters.count(&text);
+    let unique_citations = keys.iter().collect::<HashSet<_>>().len();
+
+    // Split the document at its chapters, or at its sections if it has none
+    let heading_kind = if text.contains("\\chapter") {
+        "chapter"
+    } else {
+        "section"
+    };
+    let heading_re = Regex::new(&format!(
+        r"\\{}\*?\s*(?:\[[^\]]*\])?\s*\{{([^}}]*)\}}",
+        heading_kind
+    ))
+    .unwrap();
+    let headings: Vec<(usize, String)> = heading_re
+        .captures_iter(&text)
+        .filter_map(|cap| Some((cap.get(0)?.start(), cap[1].trim().to_string())))
+        .collect();
+    let mut chapters: Vec<ChapterStats> = headings
+        .iter()
+        .enumerate()
+        .map(|(i, (start, title))| {
+            let end = headings.get(i + 1).map_or(text.len(), |(next, _)| *next);
+            let (figures, tables, equations, keys) = counters.count(&text[*start..end]);
+            ChapterStats {
+                title: title.clone(),
+                words: None,
+                figures,
+                tables,
+                equations,
+                citations: keys.len(),
+            }
+        })
+        .collect();
+
+    let project_dir = root_path.parent().unwrap_or(Path::new("."));
+    let root_name = root_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let word_count = wordcount::count_file(project_dir, &root_name).await.ok();
+    if let Some(word_count) = &word_count {
+        // texcount names the kinds "Chapter" and "Section"
+        let kind = if heading_kind == "chapter" {
+            "Chapter"
+        } else {
+            "Section"
+        };
+        let words = word_count.section_words(kind);
+        // Only trusted if texcount saw the same chapters
+        if words.len() == chapters.len() {
+            for (chapter, words) in chapters.iter_mut().zip(words) {
+                chapter.words = Some(words);
+            }
+        }
+    }
+
+    Ok(ProjectStats {
+        words: word_count.as_ref().map(|w| w.words_in_text()),
+        figures,
+        tables,
+        equations,
+        citations: keys.len(),
+        unique_citations,
+        pages: last_pdf_pages(&root_path).await,
+        chapters,
+    })
+}
@@ -0,0 +1,310 @@
+//! Project templates: built-in ones plus user templates in the app data dir.
+//! Text files may contain {{title}}, {{author}} and {{date}} placeholders.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::fs;
+
+use crate::project::{self, Project, ProjectManifest};
+
+/// Optional description file in a user template's directory
+const TEMPLATE_INFO: &str = "template.json";
+
+struct BuiltinTemplate {
+    name: &'static str,
+    description: &'static str,
+    engine: &'static str,
+    main: &'static str,
+}
+
+const BUILTIN_TEMPLATES: &[BuiltinTemplate] = &[
+    BuiltinTemplate {
+        name: "article",
+        description: "Plain article",
+        engine: "pdflatex",
+        main: r"\documentclass[11pt,a4paper]{article}
+\usepackage{amsmath,amssymb}
+\usepackage{graphicx}
+\usepackage{hyperref}
+
+\title{{{title}}}
+\author{{{author}}}
+\date{{{date}}}
+
+\begin{document}
+\maketitle
+
+\section{Introduction}
+
+\end{document}
+",
+    },
+    BuiltinTemplate {
+        name: "beamer",
+        description: "Beamer presentation",
+        engine: "pdflatex",
+        main: r"\documentclass{beamer}
+\usetheme{Madrid}
+
+\title{{{title}}}
+\author{{{author}}}
+\date{{{date}}}
+
+\begin{document}
+
+\begin{frame}
+  \titlepage
+\end{frame}
+
+\begin{frame}{Outline}
+  \tableofcontents
+\end{frame}
+
+\section{Introduction}
+\begin{frame}{Introduction}
+\end{frame}
+
+\end{document}
+",
+    },
+    BuiltinTemplate {
+        name: "korean-report",
+        description: "Korean report (kotex)",
+        engine: "xelatex",
+        main: r"\documentclass[11pt,a4paper]{article}
+\usepackage{kotex}
+\usepackage{amsmath,amssymb}
+\usepackage{graphicx}
+\usepackage{hyperref}
+
+\title{{{title}}}
+\author{{{author}}}
+\date{{{date}}}
+
+\begin{document}
+\maketitle
+
+\section{서론}
+
+\section{본론}
+
+\section{결론}
+
+\end{document}
+",
+    },
+    BuiltinTemplate {
+        name: "korean-thesis",
+        description: "Korean thesis with chapters (kotex)",
+        engine: "xelatex",
+        main: r"\documentclass[12pt,a4paper,oneside]{report}
+\usepackage{kotex}
+\usepackage{amsmath,amssymb}
+\usepackage{graphicx}
+\usepackage{setspace}
+\usepackage{hyperref}
+\onehalfspacing
+
+\title{{{title}}}
+\author{{{author}}}
+\date{{{date}}}
+
+\begin{document}
+\maketitle
+
+\begin{abstract}
+\end{abstract}
+
+\tableofcontents
+
+\chapter{서론}
+
+\chapter{관련 연구}
+
+\chapter{결론}
+
+\end{document}
+",
+    },
+    BuiltinTemplate {
+        name: "cv",
+        description: "Curriculum vitae",
+        engine: "pdflatex",
+        main: r"\documentclass[11pt,a4paper]{article}
+\usepackage[margin=2cm]{geometry}
+\usepackage{enumitem}
+\usepackage{hyperref}
+\pagestyle{empty}
+\setlist{nosep}
+
+\begin{document}
+
+\begin{center}
+  {\LARGE\bfseries {{author}}}\\[4pt]
+  {{title}}
+\end{center}
+
+\section*{Education}
+
+\section*{Experience}
+
+\section*{Publications}
+
+\section*{Skills}
+
+\end{document}
+",
+    },
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct UserTemplateInfo {
+    description: String,
+    engine: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TemplateInfo {
+    name: String,
+    description: String,
+    engine: Option<String>,
+    builtin: bool,
+}
+
+fn user_templates_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("OffLeaf")
+        .join("templates")
+}
+
+async fn user_template_info(name: &str) -> UserTemplateInfo {
+    match fs::read_to_string(user_templates_dir().join(name).join(TEMPLATE_INFO)).await {
+        Ok(text) => serde_json::from_str(&text).unwrap_or_default(),
+        Err(_) => UserTemplateInfo::default(),
+    }
+}
+
+/// Escape characters that are special in LaTeX so values can go into the source
+fn escape_latex(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn substitute(text: &str, variables: &HashMap<String, String>) -> String {
+    let mut text = text.to_string();
+    for (name, value) in variables {
+        text = text.replace(&format!("{{{{{}}}}}", name), value);
+    }
+    text
+}
+
+/// List the built-in templates followed by the user's own
+#[tauri::command]
+pub async fn list_templates() -> Result<Vec<TemplateInfo>, String> {
+    let mut templates: Vec<TemplateInfo> = BUILTIN_TEMPLATES
+        .iter()
+        .map(|t| TemplateInfo {
+            name: t.name.to_string(),
+            description: t.description.to_string(),
+            engine: Some(t.engine.to_string()),
+            builtin: true,
+        })
+        .collect();
+
+    let mut user = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(user_templates_dir()).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !entry.path().is_dir() || name.starts_with('.') {
+                continue;
+            }
+            let info = user_template_info(&name).await;
+            user.push(TemplateInfo {
+                name,
+                description: info.description,
+                engine: info.engine,
+                builtin: false,
+            });
+        }
+    }
+    user.sort_by(|a, b| a.name.cmp(&b.name));
+    templates.extend(user);
+    Ok(templates)
+}
+
+/// Create a project from a template. `variables` fill the placeholders; the
+/// project is named after the title.
+#[tauri::command]
+pub async fn create_project_from_template(
+    name: String,
+    variables: HashMap<String, String>,
+) -> Result<Project, String> {
+    let title = variables
+        .get("title")
+        .cloned()
+        .unwrap_or_else(|| "Untitled".to_string());
+    let mut values = HashMap::from([
+        ("title".to_string(), "Untitled".to_string()),
+        ("author".to_string(), String::new()),
+        ("date".to_string(), "\\today".to_string()),
+    ]);
+    for (key, value) in variables {
+        values.insert(key, escape_latex(&value));
+    }
+
+    let (engine, files) = match BUILTIN_TEMPLATES.iter().find(|t| t.name == name) {
+        Some(template) => (
+            Some(template.engine.to_string()),
+            HashMap::from([(
+                "main.tex".to_string(),
+                substitute(template.main, &values).into_bytes(),
+            )]),
+        ),
+        None => {
+            if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+                return Err(format!("Invalid template name: {}", name));
+            }
+            let dir = user_templates_dir().join(&name);
+            if !dir.is_dir() {
+                return Err(format!("Template not found: {}", name));
+            }
+            let mut files = HashMap::new();
+            for (entry, path) in project::walk(&dir).await? {
+                if entry.is_dir || entry.path == TEMPLATE_INFO {
+                    continue;
+                }
+                let data = fs::read(&path)
+                    .await
+                    .map_err(|e| format!("Failed to read template: {}", e))?;
+                let data = match String::from_utf8(data) {
+                    Ok(text) => substitute(&text, &values).into_bytes(),
+                    Err(e) => e.into_bytes(),
+                };
+                files.insert(entry.path, data);
+            }
+            (user_template_info(&name).await.engine, files)
+        }
+    };
+
+    let manifest = ProjectManifest {
+        name: title,
+        engine,
+        ..ProjectManifest::default()
+    };
+    project::create_with_files(manifest, files).await
+}
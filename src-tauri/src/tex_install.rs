@@ -0,0 +1,250 @@
+//! Installing TeX Live for users who have no TeX distribution: the official
+//! installer is downloaded and run without questions, into the app's data
+//! folder, so no administrator rights are needed
+
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tauri::AppHandle;
+use tempfile::TempDir;
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::packages::{self, TexLive};
+use crate::{processes, proxy, tex_path};
+
+/// Where the installer and the packages come from unless a mirror is chosen
+const DEFAULT_REPOSITORY: &str = "https://mirror.ctan.org/systems/texlive/tlnet";
+
+/// TeX Live schemes, smallest first: "basic" is enough for LaTeX, the
+/// missing packages are installed on demand
+const SCHEMES: [&str; 6] = ["infraonly", "minimal", "basic", "small", "medium", "full"];
+
+#[derive(Debug, Serialize)]
+pub struct TexLiveInstallation {
+    texdir: String,
+    /// Put in front of PATH, e.g. `<texdir>/bin/x86_64-linux`
+    bin_dir: String,
+}
+
+fn install_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("OffLeaf")
+        .join("texlive")
+}
+
+fn installer_archive() -> &'static str {
+    if cfg!(windows) {
+        "install-tl.zip"
+    } else {
+        "install-tl-unx.tar.gz"
+    }
+}
+
+/// Installer profile answering every question the installer would ask
+fn profile(scheme: &str, texdir: &Path) -> String {
+    // The installer takes forward slashes on Windows too
+    let texdir = texdir.to_string_lossy().replace('\\', "/");
+    format!(
+        "selected_scheme scheme-{scheme}\n\
+         TEXDIR {texdir}\n\
+         TEXMFLOCAL {texdir}/texmf-local\n\
+         TEXMFSYSVAR {texdir}/texmf-var\n\
+         TEXMFSYSCONFIG {texdir}/texmf-config\n\
+         instopt_adjustpath 0\n\
+         instopt_letter 0\n\
+         tlpdbopt_install_srcfiles 0\n\
+         tlpdbopt_autobackup 0\n\
+         tlpdbopt_desktop_integration 0\n\
+         tlpdbopt_file_assocs 0\n\
+         tlpdbopt_w32_multi_user 0\n"
+    )
+}
+
+/// Hash algorithms downloads are checked with
+#[derive(Debug, Clone, Copy)]
+pub enum Checksum {
+    Sha256,
+    Sha512,
+}
+
+/// Fail unless a downloaded file has the expected hex digest
+pub async fn verify_checksum(
+    path: &Path,
+    algorithm: Checksum,
+    expected: &str,
+) -> Result<(), String> {
+    let path = path.to_path_buf();
+    let actual = tokio::task::spawn_blocking(move || -> std::io::Result<String> {
+        let mut file = std::fs::File::open(&path)?;
+        Ok(match algorithm {
+            Checksum::Sha256 => {
+                let mut hasher = Sha256::new();
+                std::io::copy(&mut file, &mut hasher)?;
+                format!("{:x}", hasher.finalize())
+            }
+            Checksum::Sha512 => {
+                let mut hasher = Sha512::new();
+                std::io::copy(&mut file, &mut hasher)?;
+                format!("{:x}", hasher.finalize())
+            }
+        })
+    })
+    .await
+    .map_err(|e| format!("Failed to check the download: {}", e))?
+    .map_err(|e| format!("Failed to check the download: {}", e))?;
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(
+            "The download is corrupt or was tampered with: its checksum doesn't match".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// The installer's SHA-512 as CTAN publishes it: "<hex>  install-tl-unx.tar.gz".
+/// It comes from CTAN itself even when the installer comes from a mirror.
+async fn installer_checksum() -> Result<String, String> {
+    let output = processes::output(
+        proxy::command("curl")
+            .args(["-fsSL", "--max-time", "60"])
+            .arg(format!(
+                "{}/{}.sha512",
+                DEFAULT_REPOSITORY,
+                installer_archive()
+            ))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .await
+    .map_err(|e| format!("Failed to run curl: {}", e))?;
+    check("Downloading the installer checksum", &output)?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.split_whitespace()
+        .next()
+        .filter(|hash| hash.len() == 128 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_string)
+        .ok_or_else(|| "CTAN returned no installer checksum".to_string())
+}
+
+/// Check a step's output, with the end of its error output on failure
+fn check(step: &str, output: &std::process::Output) -> Result<(), String> {
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let tail: Vec<&str> = stderr.lines().rev().take(10).collect();
+    Err(format!(
+        "{} failed: {}",
+        step,
+        tail.into_iter().rev().collect::<Vec<_>>().join("\n")
+    ))
+}
+
+/// Download and run the TeX Live installer with a scheme ("basic", "small",
+/// ...), from a mirror or CTAN's redirector. Progress is reported like
+/// `install_package`'s.
+#[tauri::command]
+pub async fn install_texlive(
+    app: AppHandle,
+    scheme: String,
+    mirror: Option<String>,
+) -> Result<TexLiveInstallation, String> {
+    let scheme = scheme.trim().trim_start_matches("scheme-");
+    if !SCHEMES.contains(&scheme) {
+        return Err(format!("Unknown TeX Live scheme: {}", scheme));
+    }
+    let engines = crate::check_latex_installation().await?;
+    if engines.values().any(|available| *available) {
+        return Err("A TeX distribution is already installed".to_string());
+    }
+    let mirror = mirror.filter(|m| !m.trim().is_empty());
+    let repository = mirror
+        .as_deref()
+        .unwrap_or(DEFAULT_REPOSITORY)
+        .trim()
+        .trim_end_matches('/');
+
+    let install = packages::Install::begin(&app, None);
+    let work = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let archive = work.path().join(installer_archive());
+
+    let download = install
+        .run_command(
+            proxy::command("curl")
+                .args(["-fsSL", "-o"])
+                .arg(&archive)
+                .arg(format!("{}/{}", repository, installer_archive())),
+            &TexLive,
+        )
+        .await?;
+    check("Downloading the installer", &download)?;
+    verify_checksum(&archive, Checksum::Sha512, &installer_checksum().await?).await?;
+
+    // bsdtar, which Windows 10 and later ship, unpacks zip files too
+    let unpack = install
+        .run_command(
+            Command::new("tar")
+                .arg("-xf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(work.path()),
+            &TexLive,
+        )
+        .await?;
+    check("Unpacking the installer", &unpack)?;
+
+    let mut installer_dir = None;
+    let mut entries = fs::read_dir(work.path())
+        .await
+        .map_err(|e| format!("Failed to read installer: {}", e))?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with("install-tl-")
+            && entry.path().is_dir()
+        {
+            installer_dir = Some(entry.path());
+        }
+    }
+    let installer_dir = installer_dir.ok_or_else(|| "Installer archive is empty".to_string())?;
+
+    let texdir = install_dir();
+    fs::create_dir_all(&texdir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", texdir.display(), e))?;
+    let profile_path = work.path().join("offleaf.profile");
+    fs::write(&profile_path, profile(scheme, &texdir))
+        .await
+        .map_err(|e| format!("Failed to write installer profile: {}", e))?;
+
+    let mut installer = if cfg!(windows) {
+        let mut command = proxy::command(installer_dir.join("install-tl-windows.bat"));
+        command.arg("-no-gui");
+        command
+    } else {
+        let mut command = proxy::command("perl");
+        command.arg(installer_dir.join("install-tl"));
+        command
+    };
+    installer
+        .arg("-profile")
+        .arg(&profile_path)
+        .arg("-repository")
+        .arg(repository)
+        .current_dir(&installer_dir);
+    let output = install.run_command(&mut installer, &TexLive).await?;
+    check("TeX Live installation", &output)?;
+
+    let bin_dir = tex_path::find_bin_dir(&texdir)
+        .await
+        .ok_or_else(|| "TeX Live was installed without binaries".to_string())?;
+    tex_path::register(&bin_dir).await?;
+
+    Ok(TexLiveInstallation {
+        texdir: texdir.to_string_lossy().to_string(),
+        bin_dir: bin_dir.to_string_lossy().to_string(),
+    })
+}
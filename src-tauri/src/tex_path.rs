@@ -0,0 +1,126 @@
+//! TeX installations OffLeaf set up itself, whose bin folders aren't on the
+//! system PATH: they are put in front of the PATH of every engine and tool
+//! it runs
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::process::Command;
+
+use crate::{packages, settings};
+
+lazy_static::lazy_static! {
+    /// Registered bin folders, the first one searched first
+    static ref BIN_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+}
+
+/// The platform folder under a TeX Live tree's `bin`, e.g. `bin/x86_64-linux`
+/// or `bin/windows`
+pub async fn find_bin_dir(texdir: &Path) -> Option<PathBuf> {
+    let mut entries = tokio::fs::read_dir(texdir.join("bin")).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let dir = entry.path();
+        if dir.join("tlmgr").exists() || dir.join("tlmgr.bat").exists() {
+            return Some(dir);
+        }
+    }
+    None
+}
+
+/// Put a folder in front of the others, unless it is there already
+fn prepend(dir: &Path) {
+    if let Ok(mut dirs) = BIN_DIRS.lock() {
+        if !dirs.iter().any(|d| d == dir) {
+            dirs.insert(0, dir.to_path_buf());
+        }
+    }
+}
+
+fn remove(dir: &Path) {
+    if let Ok(mut dirs) = BIN_DIRS.lock() {
+        dirs.retain(|d| d != dir);
+    }
+}
+
+/// The app's PATH with the registered bin folders in front, or None when
+/// there are none
+fn search_path() -> Option<OsString> {
+    let dirs = BIN_DIRS.lock().ok()?;
+    if dirs.is_empty() {
+        return None;
+    }
+    let current = std::env::var_os("PATH").unwrap_or_default();
+    let paths = dirs
+        .iter()
+        .cloned()
+        .chain(std::env::split_paths(&current).filter(|path| !dirs.contains(path)));
+    std::env::join_paths(paths).ok()
+}
+
+/// Let a command find the registered installations' programs, itself
+/// included. A PATH the caller set, e.g. from a project's environment,
+/// is kept.
+pub fn apply(command: &mut Command) -> &mut Command {
+    let has_path = command
+        .as_std()
+        .get_envs()
+        .any(|(name, _)| name.eq_ignore_ascii_case("PATH"));
+    if !has_path {
+        if let Some(path) = search_path() {
+            command.env("PATH", path);
+        }
+    }
+    command
+}
+
+/// A command for a TeX program, found in the registered installations too
+pub fn command(program: impl AsRef<OsStr>) -> Command {
+    let mut command = Command::new(program);
+    apply(&mut command);
+    command
+}
+
+/// Add the bin folders registered in earlier sessions; run at startup
+pub fn init() {
+    let settings = tauri::async_runtime::block_on(settings::load());
+    // The first registered folder ends up in front
+    for dir in settings.tex_bin_dirs.iter().rev() {
+        let dir = Path::new(dir);
+        if dir.is_dir() {
+            prepend(dir);
+        }
+    }
+}
+
+/// Use a TeX installation's bin folder from now on, in this session and
+/// the following ones
+pub async fn register(dir: &Path) -> Result<(), String> {
+    prepend(dir);
+    // Another TeX tree now answers kpsewhich
+    packages::clear_lookups().await;
+    let mut settings = settings::load().await;
+    let dir = dir.to_string_lossy().to_string();
+    settings.tex_bin_dirs.retain(|d| *d != dir);
+    settings.tex_bin_dirs.insert(0, dir);
+    settings::save(&settings).await
+}
+
+/// Stop using a bin folder registered before
+pub async fn unregister(dir: &Path) -> Result<(), String> {
+    remove(dir);
+    packages::clear_lookups().await;
+    let mut settings = settings::load().await;
+    let dir = dir.to_string_lossy().to_string();
+    settings.tex_bin_dirs.retain(|d| *d != dir);
+    settings::save(&settings).await
+}
+
+/// Whether a bin folder is registered
+pub async fn is_registered(dir: &Path) -> bool {
+    let dir = dir.to_string_lossy();
+    settings::load()
+        .await
+        .tex_bin_dirs
+        .iter()
+        .any(|d| *d == dir)
+}
@@ -0,0 +1,227 @@
+//! A private TinyTeX, the portable TeX Live that TinyTeX's authors build,
+//! kept in the app's data folder for machines where no TeX distribution can
+//! or will be installed. Its bin folder is put in front of the app's PATH
+//! while it is enabled, ahead of any system TeX.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Stdio;
+use tauri::AppHandle;
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::packages::{self, TexLive};
+use crate::tex_install::{self, Checksum};
+use crate::{processes, proxy, tex_path};
+
+/// Releases of the prebuilt TinyTeX; "TinyTeX-1" has the packages most
+/// documents need
+const RELEASES: &str = "https://api.github.com/repos/rstudio/tinytex-releases/releases/latest";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+    /// "sha256:<hex>"
+    digest: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TinyTexStatus {
+    installed: bool,
+    /// In front of PATH, so its engines and tlmgr are the ones used
+    enabled: bool,
+    path: String,
+    bin_dir: Option<String>,
+}
+
+fn tinytex_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("OffLeaf")
+        .join("tinytex")
+}
+
+fn archive_extension() -> &'static str {
+    if cfg!(windows) {
+        "zip"
+    } else if cfg!(target_os = "macos") {
+        "tgz"
+    } else {
+        "tar.gz"
+    }
+}
+
+async fn status() -> TinyTexStatus {
+    let dir = tinytex_dir();
+    let bin_dir = tex_path::find_bin_dir(&dir).await;
+    let enabled = match &bin_dir {
+        Some(bin_dir) => tex_path::is_registered(bin_dir).await,
+        None => false,
+    };
+    TinyTexStatus {
+        installed: bin_dir.is_some(),
+        enabled,
+        path: dir.to_string_lossy().to_string(),
+        bin_dir: bin_dir.map(|d| d.to_string_lossy().to_string()),
+    }
+}
+
+/// Download URL and SHA-256 of the latest TinyTeX-1 for this platform
+async fn latest_archive() -> Result<(String, String), String> {
+    let output = processes::output(
+        proxy::command("curl")
+            .args(["-fsSL", "--max-time", "60"])
+            .args(["-H", "Accept: application/vnd.github+json"])
+            .arg(RELEASES)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .await
+    .map_err(|e| format!("Failed to run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(failure("Looking up the latest TinyTeX", &output));
+    }
+    let release: Release = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to read TinyTeX releases: {}", e))?;
+
+    // "TinyTeX-1-v2025.10.tar.gz", not "TinyTeX-2-..." or "TinyTeX-1-....tgz"
+    let suffix = format!(".{}", archive_extension());
+    let asset = release
+        .assets
+        .into_iter()
+        .find(|asset| {
+            asset.name.strip_prefix("TinyTeX-1").is_some_and(|rest| {
+                (rest.starts_with('-') || rest.starts_with('.')) && rest.ends_with(&suffix)
+            })
+        })
+        .ok_or_else(|| "No TinyTeX download for this platform".to_string())?;
+    let sha256 = asset
+        .digest
+        .as_deref()
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+        .ok_or_else(|| "TinyTeX's download comes without a checksum".to_string())?
+        .to_string();
+    Ok((asset.browser_download_url, sha256))
+}
+
+fn failure(step: &str, output: &std::process::Output) -> String {
+    format!(
+        "{} failed: {}",
+        step,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+}
+
+/// Whether the private TinyTeX is installed and in use
+#[tauri::command]
+pub async fn get_tinytex_status() -> TinyTexStatus {
+    status().await
+}
+
+/// Download TinyTeX into the app's data folder, replacing an earlier copy,
+/// and enable it. Progress is reported like `install_package`'s.
+#[tauri::command]
+pub async fn install_tinytex(app: AppHandle) -> Result<TinyTexStatus, String> {
+    let target = tinytex_dir();
+    let parent = target
+        .parent()
+        .ok_or_else(|| "Invalid data folder".to_string())?;
+    fs::create_dir_all(parent)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    // Unpacked next to the target, so moving it in place is a rename
+    let work = tempfile::TempDir::new_in(parent)
+        .map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let archive = work
+        .path()
+        .join(format!("TinyTeX-1.{}", archive_extension()));
+
+    let (url, sha256) = latest_archive().await?;
+    let install = packages::Install::begin(&app, None);
+    let download = install
+        .run_command(
+            proxy::command("curl")
+                .args(["-fsSL", "-o"])
+                .arg(&archive)
+                .arg(&url),
+            &TexLive,
+        )
+        .await?;
+    if !download.status.success() {
+        return Err(failure("Downloading TinyTeX", &download));
+    }
+    tex_install::verify_checksum(&archive, Checksum::Sha256, &sha256).await?;
+    let unpack = install
+        .run_command(
+            Command::new("tar")
+                .arg("-xf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(work.path()),
+            &TexLive,
+        )
+        .await?;
+    if !unpack.status.success() {
+        return Err(failure("Unpacking TinyTeX", &unpack));
+    }
+
+    // The archives hold a "TinyTeX" folder, hidden as ".TinyTeX" on Unix
+    let unpacked = ["TinyTeX", ".TinyTeX"]
+        .iter()
+        .map(|name| work.path().join(name))
+        .find(|dir| dir.is_dir())
+        .ok_or_else(|| "TinyTeX archive is empty".to_string())?;
+
+    if let Some(old_bin) = tex_path::find_bin_dir(&target).await {
+        tex_path::unregister(&old_bin).await?;
+    }
+    if target.exists() {
+        fs::remove_dir_all(&target)
+            .await
+            .map_err(|e| format!("Failed to remove the old TinyTeX: {}", e))?;
+    }
+    fs::rename(&unpacked, &target)
+        .await
+        .map_err(|e| format!("Failed to install TinyTeX: {}", e))?;
+
+    let bin_dir = tex_path::find_bin_dir(&target)
+        .await
+        .ok_or_else(|| "TinyTeX came without binaries".to_string())?;
+    tex_path::register(&bin_dir).await?;
+    Ok(status().await)
+}
+
+/// Use the private TinyTeX or go back to the system's TeX distribution
+#[tauri::command]
+pub async fn set_tinytex_enabled(enabled: bool) -> Result<TinyTexStatus, String> {
+    let bin_dir = tex_path::find_bin_dir(&tinytex_dir())
+        .await
+        .ok_or_else(|| "TinyTeX is not installed".to_string())?;
+    if enabled {
+        tex_path::register(&bin_dir).await?;
+    } else {
+        tex_path::unregister(&bin_dir).await?;
+    }
+    Ok(status().await)
+}
+
+/// Delete the private TinyTeX
+#[tauri::command]
+pub async fn remove_tinytex() -> Result<(), String> {
+    let dir = tinytex_dir();
+    if let Some(bin_dir) = tex_path::find_bin_dir(&dir).await {
+        tex_path::unregister(&bin_dir).await?;
+    }
+    if dir.exists() {
+        fs::remove_dir_all(&dir)
+            .await
+            .map_err(|e| format!("Failed to remove TinyTeX: {}", e))?;
+    }
+    Ok(())
+}
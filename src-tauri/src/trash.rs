@@ -0,0 +1,141 @@
+//! Deleted project files go to .offleaf/trash instead of being unlinked, so
+//! they can be restored until the trash is emptied
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+use crate::project;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashItem {
+    id: String,
+    /// Where the entry was, relative to the project
+    path: String,
+    is_dir: bool,
+    /// Milliseconds since the Unix epoch
+    deleted_ms: u64,
+}
+
+fn trash_dir(project_dir: &Path) -> PathBuf {
+    project_dir.join(".offleaf").join("trash")
+}
+
+/// Each item is a folder holding the deleted entry, with its details in a
+/// JSON file of the same name next to it. IDs are the deletion time in
+/// milliseconds.
+fn item_paths(project_dir: &Path, item: &str) -> Result<(PathBuf, PathBuf), String> {
+    if item.is_empty() || !item.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("Invalid trash item: {}", item));
+    }
+    let dir = trash_dir(project_dir);
+    Ok((dir.join(item), dir.join(format!("{}.json", item))))
+}
+
+async fn read_item(info_path: &Path) -> Option<TrashItem> {
+    let text = fs::read_to_string(info_path).await.ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Move a project file or folder into the trash
+pub async fn move_to_trash(id: &str, path: &str) -> Result<(), String> {
+    let source = project::entry_path(id, path)?;
+    let metadata = fs::metadata(&source)
+        .await
+        .map_err(|e| format!("Failed to delete {}: {}", path, e))?;
+    let project_dir = project::project_dir(id)?;
+
+    let mut deleted_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    // Deletes within the same millisecond get the next free ID
+    let (item_dir, info_path) = loop {
+        let (item_dir, info_path) = item_paths(&project_dir, &deleted_ms.to_string())?;
+        if !item_dir.exists() && !info_path.exists() {
+            break (item_dir, info_path);
+        }
+        deleted_ms += 1;
+    };
+    fs::create_dir_all(&item_dir)
+        .await
+        .map_err(|e| format!("Failed to create trash: {}", e))?;
+
+    let item = TrashItem {
+        id: deleted_ms.to_string(),
+        path: path.to_string(),
+        is_dir: metadata.is_dir(),
+        deleted_ms,
+    };
+    let text = serde_json::to_string_pretty(&item)
+        .map_err(|e| format!("Failed to serialize trash item: {}", e))?;
+    fs::write(&info_path, text)
+        .await
+        .map_err(|e| format!("Failed to write trash item: {}", e))?;
+
+    let name = source.file_name().unwrap_or_default();
+    if let Err(e) = fs::rename(&source, item_dir.join(name)).await {
+        let _ = fs::remove_dir_all(&item_dir).await;
+        let _ = fs::remove_file(&info_path).await;
+        return Err(format!("Failed to delete {}: {}", path, e));
+    }
+    Ok(())
+}
+
+/// List a project's deleted files and folders, most recently deleted first
+#[tauri::command]
+pub async fn list_trash(id: String) -> Result<Vec<TrashItem>, String> {
+    let dir = trash_dir(&project::project_dir(&id)?);
+    let mut items = Vec::new();
+    if let Ok(mut entries) = fs::read_dir(&dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                items.extend(read_item(&path).await);
+            }
+        }
+    }
+    items.sort_by_key(|item| std::cmp::Reverse(item.deleted_ms));
+    Ok(items)
+}
+
+/// Put a deleted entry back where it was; fails if something has taken its place
+#[tauri::command]
+pub async fn restore_from_trash(id: String, item: String) -> Result<(), String> {
+    let project_dir = project::project_dir(&id)?;
+    let (item_dir, info_path) = item_paths(&project_dir, &item)?;
+    let info = read_item(&info_path)
+        .await
+        .ok_or_else(|| format!("Trash item not found: {}", item))?;
+
+    let target = project::entry_path(&id, &info.path)?;
+    if target.exists() {
+        return Err(format!("{} already exists", info.path));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create folder: {}", e))?;
+    }
+    let name = target.file_name().unwrap_or_default();
+    fs::rename(item_dir.join(name), &target)
+        .await
+        .map_err(|e| format!("Failed to restore {}: {}", info.path, e))?;
+
+    let _ = fs::remove_dir_all(&item_dir).await;
+    let _ = fs::remove_file(&info_path).await;
+    Ok(())
+}
+
+/// Permanently delete everything in a project's trash
+#[tauri::command]
+pub async fn empty_trash(id: String) -> Result<(), String> {
+    let dir = trash_dir(&project::project_dir(&id)?);
+    if !dir.exists() {
+        return Ok(());
+    }
+    fs::remove_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Failed to empty trash: {}", e))
+}
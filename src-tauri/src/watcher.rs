@@ -0,0 +1,187 @@
+//! Watching folder projects for edits made outside OffLeaf (e.g. a figure
+//! saved from Inkscape), so the preview can follow them
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::mpsc;
+
+use crate::jobs::{self, JobStatus};
+use crate::CompileRequest;
+
+/// Changes are reported once the folder has been quiet this long, so a
+/// save that touches several files triggers a single compile
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Sources, bibliographies and figures; anything else can't change the PDF
+const WATCHED_EXTENSIONS: &[&str] = &[
+    "tex", "bib", "sty", "cls", "bst", "png", "jpg", "jpeg", "pdf", "eps", "svg",
+];
+
+struct FolderWatch {
+    /// Events stop when the watcher is dropped
+    _watcher: RecommendedWatcher,
+    task: JoinHandle<()>,
+    /// Compile started on changes; the frontend updates it as settings change
+    compile: Arc<Mutex<Option<CompileRequest>>>,
+}
+
+lazy_static::lazy_static! {
+    /// Watched folders by path
+    static ref WATCHES: Mutex<HashMap<String, FolderWatch>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderChange {
+    folder: String,
+    /// Changed files, relative to the folder
+    files: Vec<String>,
+    /// Job of the recompile the change started, if auto-compile is on
+    job_id: Option<String>,
+}
+
+/// Path relative to the folder, if it's a file worth reacting to. Hidden
+/// files and folders are skipped, which keeps the compile's own outputs in
+/// .offleaf/build from triggering another compile.
+fn relevant_path(folder: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(folder).ok()?;
+    let hidden = relative.components().any(|c| match c {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    });
+    let extension = relative.extension()?.to_string_lossy().to_lowercase();
+    if hidden || !WATCHED_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+/// Start the watch's compile for a change, unless one that hasn't started
+/// yet will already see it. A running compile read the files too early and
+/// is cancelled. Returns the job that covers the change.
+async fn compile_change(
+    app: &AppHandle,
+    compile: &Mutex<Option<CompileRequest>>,
+    last_job: Option<String>,
+) -> Option<String> {
+    if let Some(job_id) = last_job {
+        match jobs::status(&job_id) {
+            Some(JobStatus::Queued) => return Some(job_id),
+            Some(JobStatus::Running) => {
+                jobs::cancel(&job_id);
+            }
+            _ => {}
+        }
+    }
+    let mut request = compile.lock().ok()?.clone()?;
+    request.supersede = Some(true);
+    crate::compile_latex(app.clone(), request).await.ok()
+}
+
+/// Watch a folder project and emit "folder-changed" when its sources or
+/// figures change on disk. With `compile`, each change also starts that
+/// compile, or is left to one still queued. Watching again replaces the
+/// previous watch.
+#[tauri::command]
+pub async fn watch_folder(
+    app: AppHandle,
+    path: String,
+    compile: Option<CompileRequest>,
+) -> Result<(), String> {
+    let folder = PathBuf::from(&path);
+    if !folder.is_dir() {
+        return Err(format!("Not a folder: {}", path));
+    }
+    // Events carry resolved paths (e.g. /private/var on macOS)
+    let folder = folder.canonicalize().unwrap_or(folder);
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            if !matches!(event.kind, EventKind::Access(_)) {
+                let _ = sender.send(event.paths);
+            }
+        }
+    })
+    .map_err(|e| format!("Failed to watch {}: {}", path, e))?;
+    watcher
+        .watch(&folder, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch {}: {}", path, e))?;
+
+    let folder_name = path.clone();
+    let compile = Arc::new(Mutex::new(compile));
+    let current = compile.clone();
+    let task = tauri::async_runtime::spawn(async move {
+        let mut last_job = None;
+        while let Some(paths) = receiver.recv().await {
+            let mut files = BTreeSet::new();
+            files.extend(paths.iter().filter_map(|p| relevant_path(&folder, p)));
+            // Gather everything until the folder goes quiet
+            while let Ok(Some(paths)) = tokio::time::timeout(DEBOUNCE, receiver.recv()).await {
+                files.extend(paths.iter().filter_map(|p| relevant_path(&folder, p)));
+            }
+            if files.is_empty() {
+                continue;
+            }
+
+            let job_id = compile_change(&app, &current, last_job.take()).await;
+            last_job = job_id.clone();
+            let _ = app.emit(
+                "folder-changed",
+                FolderChange {
+                    folder: folder_name.clone(),
+                    files: files.into_iter().collect(),
+                    job_id,
+                },
+            );
+        }
+    });
+
+    let mut watches = WATCHES
+        .lock()
+        .map_err(|_| "Watcher state is unavailable".to_string())?;
+    let watch = FolderWatch {
+        _watcher: watcher,
+        task,
+        compile,
+    };
+    if let Some(previous) = watches.insert(path, watch) {
+        previous.task.abort();
+    }
+    Ok(())
+}
+
+/// Replace the compile a watch starts, e.g. after the engine or other
+/// settings changed; None stops compiling on changes
+#[tauri::command]
+pub async fn set_watch_compile(
+    path: String,
+    compile: Option<CompileRequest>,
+) -> Result<(), String> {
+    let watches = WATCHES
+        .lock()
+        .map_err(|_| "Watcher state is unavailable".to_string())?;
+    let watch = watches
+        .get(&path)
+        .ok_or_else(|| format!("Not watching {}", path))?;
+    *watch
+        .compile
+        .lock()
+        .map_err(|_| "Watcher state is unavailable".to_string())? = compile;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unwatch_folder(path: String) -> Result<(), String> {
+    if let Ok(mut watches) = WATCHES.lock() {
+        if let Some(watch) = watches.remove(&path) {
+            watch.task.abort();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Word counts via texcount
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use tempfile::TempDir;
+
+use crate::{tex_path, write_sources};
+
+/// Counts texcount reports for a file or the whole project
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WordCountStats {
+    words_in_text: u32,
+    words_in_headers: u32,
+    words_outside_text: u32,
+    headers: u32,
+    floats: u32,
+    math_inlines: u32,
+    math_displayed: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileWordCount {
+    file: String,
+    stats: WordCountStats,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SectionWordCount {
+    file: String,
+    /// e.g. "Section" or "Subsection"
+    kind: String,
+    title: String,
+    words_in_text: u32,
+    words_in_headers: u32,
+    words_outside_text: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WordCount {
+    total: WordCountStats,
+    files: Vec<FileWordCount>,
+    sections: Vec<SectionWordCount>,
+}
+
+impl WordCount {
+    pub fn words_in_text(&self) -> u32 {
+        self.total.words_in_text
+    }
+
+    /// Words in the text of each section of a kind ("Chapter", "Section"),
+    /// in document order
+    pub fn section_words(&self, kind: &str) -> Vec<u32> {
+        self.sections
+            .iter()
+            .filter(|s| s.kind == kind)
+            .map(|s| s.words_in_text)
+            .collect()
+    }
+}
+
+/// Parse texcount's default report. Each file gets a block starting with
+/// "File:" or "Included file:", and with -inc a "Sum of files:" block
+/// holds the totals.
+fn parse_texcount_output(output: &str) -> WordCount {
+    let stat_re = Regex::new(r"^([^:]+):\s*(\d+)$").unwrap();
+    // e.g. "  120+3+0 (1/0/2/1) Section: Introduction"
+    let section_re =
+        Regex::new(r"^(\d+)\+(\d+)\+(\d+) \(\d+/\d+/\d+/\d+\) ([^:]+):\s*(.*)$").unwrap();
+
+    let mut files: Vec<FileWordCount> = Vec::new();
+    let mut sections = Vec::new();
+    let mut total = None;
+    let mut in_total = false;
+
+    for line in output.lines().map(str::trim) {
+        let file = line
+            .strip_prefix("File:")
+            .or_else(|| line.strip_prefix("Included file:"));
+        if let Some(file) = file {
+            files.push(FileWordCount {
+                file: file.trim().trim_start_matches("./").to_string(),
+                stats: WordCountStats::default(),
+            });
+            in_total = false;
+            continue;
+        }
+        if line.starts_with("Sum of files:") {
+            total = Some(WordCountStats::default());
+            in_total = true;
+            continue;
+        }
+
+        if let Some(cap) = section_re.captures(line) {
+            if let Some(current) = files.last() {
+                sections.push(SectionWordCount {
+                    file: current.file.clone(),
+                    kind: cap[4].trim().to_string(),
+                    title: cap[5].trim().to_string(),
+                    words_in_text: cap[1].parse().unwrap_or(0),
+                    words_in_headers: cap[2].parse().unwrap_or(0),
+                    words_outside_text: cap[3].parse().unwrap_or(0),
+                });
+            }
+            continue;
+        }
+
+        let Some(cap) = stat_re.captures(line) else {
+            continue;
+        };
+        let stats = if in_total {
+            total.as_mut()
+        } else {
+            files.last_mut().map(|f| &mut f.stats)
+        };
+        let Some(stats) = stats else {
+            continue;
+        };
+        let value = cap[2].parse().unwrap_or(0);
+        match cap[1].trim() {
+            "Words in text" => stats.words_in_text = value,
+            "Words in headers" => stats.words_in_headers = value,
+            "Words outside text (captions, etc.)" => stats.words_outside_text = value,
+            "Number of headers" => stats.headers = value,
+            "Number of floats/tables/figures" => stats.floats = value,
+            "Number of math inlines" => stats.math_inlines = value,
+            "Number of math displayed" => stats.math_displayed = value,
+            _ => {}
+        }
+    }
+
+    // Without included files there is no sum block; the only file is the total
+    let total = total
+        .or_else(|| files.first().map(|f| f.stats.clone()))
+        .unwrap_or_default();
+
+    WordCount {
+        total,
+        files,
+        sections,
+    }
+}
+
+/// Count words in the project with texcount, following \input and \include
+#[tauri::command]
+pub async fn count_words(
+    content: String,
+    files: HashMap<String, String>,
+) -> Result<WordCount, String> {
+    let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    write_sources(temp_dir.path(), &content, &files, &HashMap::new()).await?;
+    count_file(temp_dir.path(), "main.tex").await
+}
+
+/// Count words in a document on disk, from its root file in `dir`
+pub async fn count_file(dir: &Path, root_file: &str) -> Result<WordCount, String> {
+    let output = tex_path::command("texcount")
+        .args(["-inc", "-sub=section", "-utf8", root_file])
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run texcount: {}. Is TeX Live installed?", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "texcount failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(parse_texcount_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
@@ -0,0 +1,137 @@
+//! Zotero through the Better BibTeX add-on's local HTTP server: exporting a
+//! collection to a .bib file and picking citation keys with its CAYW
+//! ("cite as you write") dialog. Zotero must be running.
+
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::{bibliography, processes, save};
+
+/// Zotero's connector port, where Better BibTeX listens
+const BETTER_BIBTEX: &str = "http://127.0.0.1:23119/better-bibtex";
+
+/// The user's own library; group libraries have other IDs
+const USER_LIBRARY: u32 = 1;
+
+/// Exports of large libraries take a while
+const EXPORT_TIMEOUT_SECS: u32 = 300;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ZoteroSync {
+    path: String,
+    entries: usize,
+}
+
+/// GET from Better BibTeX. Zotero is local, so any configured proxy is
+/// bypassed.
+async fn get(url: &str, timeout_secs: Option<u32>) -> Result<String, String> {
+    let mut command = Command::new("curl");
+    command.args(["-sSf", "--noproxy", "*"]);
+    if let Some(secs) = timeout_secs {
+        command.args(["--max-time", &secs.to_string()]);
+    }
+    let output = processes::output(
+        command
+            .arg(url)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .await
+    .map_err(|e| format!("Failed to run curl: {}", e))?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        // curl exit code 7: nothing listens on the port
+        return Err(if output.status.code() == Some(7) {
+            "Zotero isn't running, or Better BibTeX isn't installed".to_string()
+        } else {
+            format!("Better BibTeX request failed: {}", error.trim())
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Fails unless Zotero runs with Better BibTeX
+async fn probe() -> Result<(), String> {
+    let answer = get(&format!("{}/cayw?probe=true", BETTER_BIBTEX), Some(5)).await?;
+    if answer.trim() == "ready" {
+        Ok(())
+    } else {
+        Err("Better BibTeX isn't ready; is Zotero still starting?".to_string())
+    }
+}
+
+/// Export a Zotero collection, e.g. "Thesis/Chapter 2", or the whole
+/// library without one, into a .bib file, replacing its content. BibLaTeX
+/// output unless `biblatex` is false.
+#[tauri::command]
+pub async fn sync_zotero_bibliography(
+    collection: Option<String>,
+    target_bib: String,
+    biblatex: Option<bool>,
+) -> Result<ZoteroSync, String> {
+    let target = Path::new(&target_bib);
+    if target.extension().and_then(|e| e.to_str()) != Some("bib") {
+        return Err(format!("Not a .bib file: {}", target_bib));
+    }
+    probe().await?;
+
+    let format = if biblatex.unwrap_or(true) {
+        "biblatex"
+    } else {
+        "bibtex"
+    };
+    let url = match collection.as_deref().map(|c| c.trim_matches('/')) {
+        Some(collection) if !collection.is_empty() => {
+            let path: Vec<String> = collection
+                .split('/')
+                .map(|name| utf8_percent_encode(name, NON_ALPHANUMERIC).to_string())
+                .collect();
+            format!(
+                "{}/export/collection?/{}/{}.{}",
+                BETTER_BIBTEX,
+                USER_LIBRARY,
+                path.join("/"),
+                format
+            )
+        }
+        _ => format!(
+            "{}/export/library?/{}/library.{}",
+            BETTER_BIBTEX, USER_LIBRARY, format
+        ),
+    };
+    let content = get(&url, Some(EXPORT_TIMEOUT_SECS)).await?;
+
+    let parsed = bibliography::parse(&content);
+    if parsed.entries().is_empty() && !content.trim().is_empty() {
+        return Err("Better BibTeX returned something other than BibTeX".to_string());
+    }
+    save::write_atomic(target, content.as_bytes()).await?;
+
+    Ok(ZoteroSync {
+        path: target_bib,
+        entries: parsed.entries().len(),
+    })
+}
+
+/// Open Zotero's citation picker and return the keys chosen, empty when
+/// the picker was cancelled
+#[tauri::command]
+pub async fn pick_zotero_citation() -> Result<Vec<String>, String> {
+    probe().await?;
+    // Waits for as long as the user takes to pick
+    let citation = get(&format!("{}/cayw?format=latex", BETTER_BIBTEX), None).await?;
+    // "\cite{knuth84,lamport94}"
+    let keys = citation
+        .rsplit_once('{')
+        .map(|(_, keys)| keys.trim_end().trim_end_matches('}'))
+        .unwrap_or("");
+    Ok(keys
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+        .collect())
+}
@@ -138,10 +138,21 @@ function App() {
         if (installResult.failed.length > 0) {
           addToast('warning', `패키지 설치 실패: ${installResult.failed.join(', ')}`);
         }
+        if (installResult.pending.length > 0) {
+          addToast('info', `설치하지 않은 패키지: ${installResult.pending.join(', ')}`);
+        }
       };
 
+      const confirmInstall = (packages: string[]) =>
+        window.confirm(`누락된 패키지를 설치할까요?\n${packages.join(', ')}`);
+
       // Compile
-      const result = await latexCompiler.compile(mainContent, files, onAutoInstall);
+      const result = await latexCompiler.compile(
+        mainContent,
+        files,
+        onAutoInstall,
+        confirmInstall
+      );
 
       setCompilationResult(result.log, result.errors, result.warnings);
 
@@ -26,10 +26,13 @@ interface PackageInfo {
   installed: boolean;
   version: string | null;
   size: string | null;
+  depends: string[] | null;
 }
 
 interface DetectedPackage {
   name: string;
+  /** TeX Live package that ships the style file */
+  package: string;
   installed: boolean;
   options: string | null;
 }
@@ -49,6 +52,7 @@ interface AutoInstallResult {
   success: boolean;
   installed: string[];
   failed: string[];
+  pending: string[];
   message: string;
 }
 
@@ -75,6 +79,7 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
   const [essentialPackages, setEssentialPackages] = useState<DetectedPackage[]>([]);
   const [isLoading, setIsLoading] = useState(false);
   const [isTlmgrAvailable, setIsTlmgrAvailable] = useState<boolean | null>(null);
+  const [managerName, setManagerName] = useState<string | null>(null);
   const [installingPackage, setInstallingPackage] = useState<string | null>(null);
   const [isInstallingAll, setIsInstallingAll] = useState(false);
   const [error, setError] = useState<string | null>(null);
@@ -93,6 +98,7 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
       try {
         const available = await invoke<boolean>('check_tlmgr');
         setIsTlmgrAvailable(available);
+        setManagerName(await invoke<string | null>('get_package_manager'));
       } catch {
         setIsTlmgrAvailable(false);
       }
@@ -192,8 +198,10 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
     setIsInstallingAll(true);
 
     try {
+      // Clicking the button confirms the install
       const result = await invoke<AutoInstallResult>('auto_install_missing', {
         content: currentFile.content,
+        confirmed: true,
       });
 
       if (result.success) {
@@ -269,10 +277,10 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
           prev.map((p) => (p.name === packageName ? { ...p, installed: true } : p))
         );
         setDetectedPackages((prev) =>
-          prev.map((p) => (p.name === packageName ? { ...p, installed: true } : p))
+          prev.map((p) => (p.package === packageName ? { ...p, installed: true } : p))
         );
         setEssentialPackages((prev) =>
-          prev.map((p) => (p.name === packageName ? { ...p, installed: true } : p))
+          prev.map((p) => (p.package === packageName ? { ...p, installed: true } : p))
         );
       } else {
         addToast('error', result.message);
@@ -305,10 +313,10 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
           );
         }
         setDetectedPackages((prev) =>
-          prev.map((p) => (p.name === packageName ? { ...p, installed: false } : p))
+          prev.map((p) => (p.package === packageName ? { ...p, installed: false } : p))
         );
         setEssentialPackages((prev) =>
-          prev.map((p) => (p.name === packageName ? { ...p, installed: false } : p))
+          prev.map((p) => (p.package === packageName ? { ...p, installed: false } : p))
         );
       } else {
         addToast('error', result.message);
@@ -377,9 +385,9 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
             <div className="flex items-center gap-2 text-red-400">
               <AlertCircle className="w-5 h-5" />
               <div>
-                <p className="font-medium">tlmgr를 찾을 수 없습니다</p>
+                <p className="font-medium">패키지 관리자를 찾을 수 없습니다</p>
                 <p className="text-sm mt-1">
-                  TeX Live가 설치되어 있는지 확인하세요.
+                  TeX Live(tlmgr) 또는 MiKTeX가 설치되어 있는지 확인하세요.
                 </p>
               </div>
             </div>
@@ -564,6 +572,9 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
                       <div className="flex-1 min-w-0">
                         <div className="flex items-center gap-2">
                           <span className="font-medium text-white">{pkg.name}</span>
+                          {pkg.package !== pkg.name && (
+                            <span className="text-xs text-gray-500">({pkg.package})</span>
+                          )}
                           {pkg.installed ? (
                             <span className="px-2 py-0.5 text-xs bg-green-900/50 text-green-400 rounded">
                               설치됨
@@ -581,12 +592,12 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
                       <div className="flex gap-2 ml-4">
                         {pkg.installed ? (
                           <button
-                            onClick={() => handleRemove(pkg.name)}
-                            disabled={installingPackage === pkg.name}
+                            onClick={() => handleRemove(pkg.package)}
+                            disabled={installingPackage === pkg.package}
                             className="btn btn-secondary p-2 text-red-400 hover:bg-red-900/30"
                             title="제거"
                           >
-                            {installingPackage === pkg.name ? (
+                            {installingPackage === pkg.package ? (
                               <Loader2 className="w-4 h-4 animate-spin" />
                             ) : (
                               <Trash2 className="w-4 h-4" />
@@ -594,12 +605,12 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
                           </button>
                         ) : (
                           <button
-                            onClick={() => handleInstall(pkg.name)}
-                            disabled={installingPackage === pkg.name}
+                            onClick={() => handleInstall(pkg.package)}
+                            disabled={installingPackage === pkg.package}
                             className="btn btn-primary p-2"
                             title="설치"
                           >
-                            {installingPackage === pkg.name ? (
+                            {installingPackage === pkg.package ? (
                               <Loader2 className="w-4 h-4 animate-spin" />
                             ) : (
                               <Download className="w-4 h-4" />
@@ -682,7 +693,7 @@ export function PackageManager({ isOpen, onClose }: PackageManagerProps) {
 
             {/* Footer info */}
             <div className="mt-4 pt-4 border-t border-[#3c3c3c] text-xs text-gray-500">
-              <p>tlmgr (TeX Live Manager)를 사용하여 패키지를 관리합니다.</p>
+              <p>{managerName ?? 'tlmgr (TeX Live Manager)'}를 사용하여 패키지를 관리합니다.</p>
             </div>
           </>
         )}
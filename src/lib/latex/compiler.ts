@@ -17,12 +17,20 @@ async function invoke<T>(cmd: string, args?: Record<string, unknown>): Promise<T
 interface TauriCompilationResult {
   success: boolean;
   pdf_path: string | null;
-  pdf_data: number[] | null;
+  /** Served over the backend's pdf:// scheme instead of through IPC */
+  pdf_url: string | null;
   log: string;
   errors: CompilationError[];
   warnings: CompilationWarning[];
 }
 
+interface TauriCompileJob {
+  job_id: string;
+  status: 'queued' | 'running' | 'finished' | 'failed' | 'cancelled';
+  result: TauriCompilationResult | null;
+  error: string | null;
+}
+
 interface LatexInstallation {
   xelatex: boolean;
   pdflatex: boolean;
@@ -31,6 +39,8 @@ interface LatexInstallation {
 
 interface DetectedPackage {
   name: string;
+  /** TeX Live package that ships the style file */
+  package: string;
   installed: boolean;
   options: string | null;
 }
@@ -45,6 +55,8 @@ interface AutoInstallResult {
   success: boolean;
   installed: string[];
   failed: string[];
+  /** Packages the install policy holds back until the user confirms them */
+  pending: string[];
   message: string;
 }
 
@@ -55,6 +67,8 @@ export type { AutoInstallResult, PackageDetectionResult, DetectedPackage };
 class LaTeXCompiler {
   private installation: LatexInstallation | null = null;
   private currentEngine: LatexEngine = 'xelatex';
+  // Whether the user picked the engine, which then beats % !TEX program
+  private engineChosen: boolean = false;
   private autoInstallEnabled: boolean = true;
 
   setAutoInstall(enabled: boolean): void {
@@ -78,24 +92,26 @@ class LaTeXCompiler {
     }
   }
 
-  async autoInstallMissing(content: string): Promise<AutoInstallResult> {
+  async autoInstallMissing(content: string, confirmed = false): Promise<AutoInstallResult> {
     if (!isTauri()) {
       return {
         success: false,
         installed: [],
         failed: [],
+        pending: [],
         message: 'Auto-install only available in desktop app',
       };
     }
 
     try {
-      return await invoke<AutoInstallResult>('auto_install_missing', { content });
+      return await invoke<AutoInstallResult>('auto_install_missing', { content, confirmed });
     } catch (error) {
       const message = error instanceof Error ? error.message : String(error);
       return {
         success: false,
         installed: [],
         failed: [],
+        pending: [],
         message,
       };
     }
@@ -121,6 +137,7 @@ class LaTeXCompiler {
 
   setEngine(engine: LatexEngine): void {
     this.currentEngine = engine;
+    this.engineChosen = true;
   }
 
   getEngine(): LatexEngine {
@@ -130,7 +147,8 @@ class LaTeXCompiler {
   async compile(
     mainContent: string,
     files?: Map<string, string>,
-    onAutoInstall?: (result: AutoInstallResult) => void
+    onAutoInstall?: (result: AutoInstallResult) => void,
+    confirmInstall?: (packages: string[]) => boolean | Promise<boolean>
   ): Promise<CompilationResult> {
     if (!isTauri()) {
       return this.compileWithSwiftLatex(mainContent, files);
@@ -140,7 +158,15 @@ class LaTeXCompiler {
     if (this.autoInstallEnabled) {
       const detection = await this.detectPackages(mainContent);
       if (detection.missing.length > 0) {
-        const installResult = await this.autoInstallMissing(mainContent);
+        let installResult = await this.autoInstallMissing(mainContent);
+        // The install policy held packages back for the user to confirm
+        if (
+          installResult.pending.length > 0 &&
+          confirmInstall &&
+          (await confirmInstall(installResult.pending))
+        ) {
+          installResult = await this.autoInstallMissing(mainContent, true);
+        }
         if (onAutoInstall) {
           onAutoInstall(installResult);
         }
@@ -163,16 +189,22 @@ class LaTeXCompiler {
         }
       }
 
-      const result = await invoke<TauriCompilationResult>('compile_latex', {
+      const jobId = await invoke<string>('compile_latex', {
         request: {
           content: mainContent,
           files: filesObj,
           engine: this.currentEngine,
+          engine_override: this.engineChosen,
         },
       });
 
-      // Convert number[] to Uint8Array
-      const pdfData = result.pdf_data ? new Uint8Array(result.pdf_data) : undefined;
+      const job = await this.waitForJob(jobId);
+      if (!job.result) {
+        throw new Error(job.error ?? `Compilation ${job.status}`);
+      }
+      const result = job.result;
+
+      const pdfData = result.pdf_url ? await this.fetchPdf(result.pdf_url) : undefined;
 
       return {
         success: result.success,
@@ -192,6 +224,25 @@ class LaTeXCompiler {
     }
   }
 
+  private async fetchPdf(url: string): Promise<Uint8Array> {
+    const response = await fetch(url);
+    if (!response.ok) {
+      throw new Error(`Failed to load PDF (${response.status})`);
+    }
+    return new Uint8Array(await response.arrayBuffer());
+  }
+
+  private async waitForJob(jobId: string): Promise<TauriCompileJob> {
+    // Compiles are queued in the backend; poll until this one is done
+    for (;;) {
+      const job = await invoke<TauriCompileJob>('get_compile_status', { jobId });
+      if (job.status !== 'queued' && job.status !== 'running') {
+        return job;
+      }
+      await new Promise((resolve) => setTimeout(resolve, 200));
+    }
+  }
+
   private async compileWithSwiftLatex(
     mainContent: string,
     files?: Map<string, string>